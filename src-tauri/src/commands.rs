@@ -100,6 +100,13 @@ pub fn cmd_open_in_finder(path: String) -> Result<(), String> {
 pub async fn cmd_propagate_proxy(
     state: tauri::State<'_, SidecarState>,
 ) -> Result<serde_json::Value, String> {
+    let (ok, fail) = propagate_proxy_to_sidecars(&state).await?;
+    Ok(serde_json::json!({ "updated": ok, "failed": fail }))
+}
+
+/// Push the current proxy settings to every running Sidecar via `/api/proxy/set`.
+/// Returns (updated, failed) counts.
+pub async fn propagate_proxy_to_sidecars(state: &SidecarState) -> Result<(u32, u32), String> {
     let payload = match crate::proxy_config::read_proxy_settings() {
         Some(s) => match crate::proxy_config::get_proxy_url(&s) {
             Ok(_) => serde_json::json!({
@@ -139,7 +146,7 @@ pub async fn cmd_propagate_proxy(
     }
 
     log::info!("[proxy-propagate] Done: {} updated, {} failed", ok, fail);
-    Ok(serde_json::json!({ "updated": ok, "failed": fail }))
+    Ok((ok, fail))
 }
 
 #[tauri::command]
@@ -175,29 +182,15 @@ pub async fn cmd_heartbeat_sync(
     agent_id: String,
     state: tauri::State<'_, HeartbeatManagerState>,
 ) -> Result<(), String> {
-    // Read latest agent config from disk
-    let config_path = dirs::home_dir()
-        .ok_or("No home dir")?
-        .join(".soagents")
-        .join("config.json");
-    let content = std::fs::read_to_string(&config_path)
-        .map_err(|e| format!("Read config: {}", e))?;
-    let config: serde_json::Value =
-        serde_json::from_str(&content).map_err(|e| format!("Parse: {}", e))?;
-
-    let agents = config
-        .get("agents")
-        .and_then(|a| a.as_array())
-        .ok_or("No agents array")?;
-
-    let agent = agents
+    // Latest agent config from disk
+    let snapshot = crate::config_service::current();
+    let agent_config = snapshot
+        .agents
         .iter()
-        .find(|a| a.get("id").and_then(|v| v.as_str()) == Some(&agent_id))
+        .find(|a| a.id == agent_id)
+        .cloned()
         .ok_or_else(|| format!("Agent {} not found", agent_id))?;
 
-    let agent_config: crate::im::types::AgentConfigRust =
-        serde_json::from_value(agent.clone()).map_err(|e| format!("Parse agent: {}", e))?;
-
     state
         .sync_agent(
             &agent_id,
@@ -0,0 +1,667 @@
+//! Config service — the single reader of `~/.soagents/config.json`.
+//!
+//! The frontend owns the file (Settings, Agent pages). This module parses it
+//! once per modification into a typed [`AppConfigSnapshot`] shared by the IM
+//! manager, heartbeat scheduler, proxy policy and metrics endpoint, and runs a
+//! watcher that polls the file's mtime, diffs old vs new snapshots and reconciles:
+//!   1. IM channels — start / stop / restart, or hot-patch allowlist, persona and routes
+//!   2. Heartbeat runners — `HeartbeatManager::sync_agent` per changed agent
+//!   3. Proxy — pushed to running Sidecars; Feishu/DingTalk channels (whose HTTP
+//!      clients bake the proxy in) are restarted
+//!   4. IM turn scheduler limits — applied live
+//!
+//! Every applied diff is emitted to the frontend as `config:changed`.
+//!
+//! Reconciliation is idempotent against what is actually running, so it is safe
+//! for the frontend to keep calling the explicit commands (`cmd_start_agent_channel`,
+//! `cmd_heartbeat_sync`, `cmd_propagate_proxy`) right after it saves.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::commands::{HeartbeatManagerState, SidecarState};
+use crate::im::scheduler::SchedulerLimits;
+use crate::im::types::{AgentConfigRust, ChannelConfigRust, ImConfig, ImPlatform};
+use crate::im::ImManagerState;
+use crate::metrics::MetricsEndpointSettings;
+use crate::proxy_config::{self, ProxySettings};
+use crate::{ulog_error, ulog_info, ulog_warn};
+
+/// How often the watcher checks the file's mtime
+const POLL_INTERVAL_SECS: u64 = 2;
+
+/// Tauri event carrying a [`ConfigChangedEvent`]
+pub const CONFIG_CHANGED_EVENT: &str = "config:changed";
+
+// ── Snapshot ──
+
+/// Typed view of the parts of config.json the backend cares about.
+#[derive(Debug, Clone, Default)]
+pub struct AppConfigSnapshot {
+    pub agents: Vec<AgentConfigRust>,
+    /// Raw `proxySettings` (may be disabled — see `proxy_config::read_proxy_settings`)
+    pub proxy_settings: Option<ProxySettings>,
+    /// `imScheduler` — turn concurrency limits shared by all IM channels
+    pub im_scheduler: SchedulerLimits,
+    /// `metricsEndpoint` — localhost Prometheus endpoint (read once at startup)
+    pub metrics_endpoint: Option<MetricsEndpointSettings>,
+}
+
+impl AppConfigSnapshot {
+    /// Parse config.json content. Agents that fail to parse are skipped (and logged)
+    /// so one malformed entry doesn't take every channel down.
+    fn parse(content: &str) -> Result<Self, String> {
+        // Strip UTF-8 BOM if present (Windows editors sometimes inject one).
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+        let json: serde_json::Value =
+            serde_json::from_str(content).map_err(|e| format!("Invalid JSON: {}", e))?;
+
+        let mut agents = Vec::new();
+        if let Some(arr) = json.get("agents").and_then(|a| a.as_array()) {
+            for value in arr {
+                match serde_json::from_value::<AgentConfigRust>(value.clone()) {
+                    Ok(agent) => agents.push(agent),
+                    Err(e) => {
+                        let id = value.get("id").and_then(|v| v.as_str()).unwrap_or("?");
+                        ulog_warn!("[config] Skipping agent {}: {}", id, e);
+                    }
+                }
+            }
+        }
+
+        let proxy_settings = match json.get("proxySettings") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(v) => match serde_json::from_value::<ProxySettings>(v.clone()) {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    ulog_warn!("[config] Ignoring invalid proxySettings: {}", e);
+                    None
+                }
+            },
+        };
+
+        let im_scheduler = match json.get("imScheduler") {
+            None | Some(serde_json::Value::Null) => SchedulerLimits::default(),
+            Some(v) => serde_json::from_value::<SchedulerLimits>(v.clone()).unwrap_or_else(|e| {
+                ulog_warn!("[config] Ignoring invalid imScheduler: {}", e);
+                SchedulerLimits::default()
+            }),
+        };
+
+        let metrics_endpoint = match json.get("metricsEndpoint") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(v) => match serde_json::from_value::<MetricsEndpointSettings>(v.clone()) {
+                Ok(m) => Some(m),
+                Err(e) => {
+                    ulog_warn!("[config] Ignoring invalid metricsEndpoint: {}", e);
+                    None
+                }
+            },
+        };
+
+        Ok(Self {
+            agents,
+            proxy_settings,
+            im_scheduler,
+            metrics_endpoint,
+        })
+    }
+
+    fn find_channel(&self, r: &ChannelRef) -> Option<(&AgentConfigRust, &ChannelConfigRust)> {
+        let agent = self.agents.iter().find(|a| a.id == r.agent_id)?;
+        let channel = agent.channels.iter().find(|c| c.id == r.channel_id)?;
+        Some((agent, channel))
+    }
+
+    /// Proxy URL actually in effect (None = disabled; Err = enabled but invalid)
+    fn effective_proxy(&self) -> Option<Result<String, String>> {
+        self.proxy_settings
+            .as_ref()
+            .filter(|p| p.enabled)
+            .map(proxy_config::get_proxy_url)
+    }
+}
+
+/// Identifies the file version a snapshot was parsed from
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+struct Cache {
+    stamp: Option<FileStamp>,
+    snapshot: Arc<AppConfigSnapshot>,
+    /// Last version that failed to read/parse (logged once, previous snapshot kept)
+    rejected: Option<FileStamp>,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+pub fn config_path() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(".soagents").join("config.json"))
+}
+
+/// Current snapshot. Cheap: re-parses only when the file's mtime/size changed.
+///
+/// A missing or unparsable file keeps the previous snapshot — the frontend's
+/// atomic save briefly renames the file away, and a half-written file must not
+/// look like "every channel was deleted".
+pub fn current() -> Arc<AppConfigSnapshot> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(path) = config_path() else {
+        return Arc::new(AppConfigSnapshot::default());
+    };
+
+    let stamp = std::fs::metadata(&path).ok().and_then(|m| {
+        Some(FileStamp {
+            modified: m.modified().ok()?,
+            len: m.len(),
+        })
+    });
+
+    if let Some(c) = cache.as_ref() {
+        if stamp.is_none() || c.stamp == stamp || c.rejected == stamp {
+            return Arc::clone(&c.snapshot);
+        }
+    }
+
+    let loaded = match std::fs::read_to_string(&path) {
+        Ok(content) => AppConfigSnapshot::parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AppConfigSnapshot::default()),
+        Err(e) => Err(format!("Failed to read: {}. Check file permissions.", e)),
+    };
+
+    match loaded {
+        Ok(snapshot) => {
+            let snapshot = Arc::new(snapshot);
+            *cache = Some(Cache {
+                stamp,
+                snapshot: Arc::clone(&snapshot),
+                rejected: None,
+            });
+            snapshot
+        }
+        Err(e) => {
+            ulog_error!("[config] {:?}: {}", path, e);
+            match cache.as_mut() {
+                Some(c) => {
+                    c.rejected = stamp;
+                    Arc::clone(&c.snapshot)
+                }
+                None => {
+                    let snapshot = Arc::new(AppConfigSnapshot::default());
+                    *cache = Some(Cache {
+                        stamp: None,
+                        snapshot: Arc::clone(&snapshot),
+                        rejected: stamp,
+                    });
+                    snapshot
+                }
+            }
+        }
+    }
+}
+
+// ── Diff ──
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelRef {
+    pub agent_id: String,
+    pub channel_id: String,
+}
+
+/// What changed between two snapshots
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiff {
+    pub channels_added: Vec<ChannelRef>,
+    pub channels_removed: Vec<ChannelRef>,
+    /// Channel or owning-agent settings that feed the channel changed
+    pub channels_changed: Vec<ChannelRef>,
+    /// Agents whose heartbeat / memory auto-update settings changed (incl. removed agents)
+    pub heartbeat_changed: Vec<String>,
+    pub proxy_changed: bool,
+    pub scheduler_changed: bool,
+}
+
+impl ConfigDiff {
+    pub fn between(old: &AppConfigSnapshot, new: &AppConfigSnapshot) -> Self {
+        let mut diff = Self::default();
+
+        let channel_refs = |s: &AppConfigSnapshot| -> Vec<ChannelRef> {
+            s.agents
+                .iter()
+                .flat_map(|a| {
+                    a.channels.iter().map(|c| ChannelRef {
+                        agent_id: a.id.clone(),
+                        channel_id: c.id.clone(),
+                    })
+                })
+                .collect()
+        };
+        let old_refs = channel_refs(old);
+        let new_refs = channel_refs(new);
+        let old_set: HashSet<&ChannelRef> = old_refs.iter().collect();
+        let new_set: HashSet<&ChannelRef> = new_refs.iter().collect();
+
+        for r in &new_refs {
+            if !old_set.contains(r) {
+                diff.channels_added.push(r.clone());
+                continue;
+            }
+            let before = old.find_channel(r).map(|(a, c)| channel_fingerprint(a, c));
+            let after = new.find_channel(r).map(|(a, c)| channel_fingerprint(a, c));
+            if before != after {
+                diff.channels_changed.push(r.clone());
+            }
+        }
+        for r in &old_refs {
+            if !new_set.contains(r) {
+                diff.channels_removed.push(r.clone());
+            }
+        }
+
+        for agent in &new.agents {
+            let before = old.agents.iter().find(|a| a.id == agent.id);
+            if before.map(heartbeat_fingerprint) != Some(heartbeat_fingerprint(agent)) {
+                diff.heartbeat_changed.push(agent.id.clone());
+            }
+        }
+        for agent in &old.agents {
+            if !new.agents.iter().any(|a| a.id == agent.id) {
+                diff.heartbeat_changed.push(agent.id.clone());
+            }
+        }
+
+        diff.proxy_changed = old.effective_proxy() != new.effective_proxy();
+        diff.scheduler_changed = old.im_scheduler != new.im_scheduler;
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels_added.is_empty()
+            && self.channels_removed.is_empty()
+            && self.channels_changed.is_empty()
+            && self.heartbeat_changed.is_empty()
+            && !self.proxy_changed
+            && !self.scheduler_changed
+    }
+}
+
+/// Should this channel be running according to config?
+fn channel_desired(agent: &AgentConfigRust, channel: &ChannelConfigRust) -> bool {
+    agent.enabled && channel.enabled && channel.has_credentials()
+}
+
+fn channel_fingerprint(agent: &AgentConfigRust, channel: &ChannelConfigRust) -> serde_json::Value {
+    serde_json::json!({
+        "desired": channel_desired(agent, channel),
+        "im": channel.to_im_config(agent),
+        "channel": channel,
+    })
+}
+
+fn heartbeat_fingerprint(agent: &AgentConfigRust) -> serde_json::Value {
+    serde_json::json!({
+        "enabled": agent.enabled,
+        "heartbeat": agent.heartbeat,
+        "workspacePath": agent.workspace_path,
+        "memoryAutoUpdate": agent.memory_auto_update,
+    })
+}
+
+/// ImConfig fields that are applied live by `ImManager::update_channel_config`
+/// (or maintained by the adapter itself) and never require a restart.
+const HOT_FIELDS: &[&str] = &[
+    "allowedUsers",
+    "persona",
+    "agentRoutes",
+    "groupPermissions",
+    "allowProactiveSend",
+];
+
+fn restart_fingerprint(config: &ImConfig) -> serde_json::Value {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    if let Some(obj) = value.as_object_mut() {
+        for field in HOT_FIELDS {
+            obj.remove(*field);
+        }
+    }
+    value
+}
+
+fn hot_patch(channel: &ChannelConfigRust) -> serde_json::Value {
+    serde_json::json!({
+        "allowedUsers": channel.allowed_users,
+        "overrides": channel.overrides,
+        "agentRoutes": channel.agent_routes,
+        "allowProactiveSend": channel.allow_proactive_send,
+    })
+}
+
+// ── Watcher ──
+
+/// Payload of the `config:changed` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChangedEvent {
+    #[serde(flatten)]
+    pub diff: ConfigDiff,
+    /// Channels (re)started, stopped or patched by reconciliation
+    pub channels_started: Vec<ChannelRef>,
+    pub channels_stopped: Vec<ChannelRef>,
+    pub channels_updated: Vec<ChannelRef>,
+    /// Reconciliation failures (human readable)
+    pub errors: Vec<String>,
+}
+
+/// Poll config.json and reconcile running services whenever it changes.
+pub fn spawn_config_watcher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut previous = current();
+        loop {
+            tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+            let next = current();
+            if Arc::ptr_eq(&previous, &next) {
+                continue;
+            }
+            let diff = ConfigDiff::between(&previous, &next);
+            let old = std::mem::replace(&mut previous, Arc::clone(&next));
+            if diff.is_empty() {
+                continue;
+            }
+
+            ulog_info!(
+                "[config] config.json changed: +{} -{} ~{} channel(s), {} heartbeat(s), \
+                 proxy={}, scheduler={}",
+                diff.channels_added.len(),
+                diff.channels_removed.len(),
+                diff.channels_changed.len(),
+                diff.heartbeat_changed.len(),
+                diff.proxy_changed,
+                diff.scheduler_changed
+            );
+            let event = reconcile(&app, &old, &next, diff).await;
+            for e in &event.errors {
+                ulog_warn!("[config] {}", e);
+            }
+            let _ = app.emit(CONFIG_CHANGED_EVENT, &event);
+        }
+    });
+}
+
+async fn reconcile(
+    app: &AppHandle,
+    old: &AppConfigSnapshot,
+    new: &AppConfigSnapshot,
+    diff: ConfigDiff,
+) -> ConfigChangedEvent {
+    let mut event = ConfigChangedEvent {
+        diff,
+        channels_started: Vec::new(),
+        channels_stopped: Vec::new(),
+        channels_updated: Vec::new(),
+        errors: Vec::new(),
+    };
+    reconcile_channels(app, old, new, &mut event).await;
+    if event.diff.scheduler_changed {
+        let im_state: ImManagerState = (*app.state::<ImManagerState>()).clone();
+        im_state
+            .lock()
+            .await
+            .set_scheduler_limits(new.im_scheduler.clone());
+    }
+    if event.diff.proxy_changed {
+        reconcile_proxy(app, &mut event).await;
+    }
+    reconcile_heartbeats(app, new, &event.diff.heartbeat_changed).await;
+    event
+}
+
+async fn reconcile_channels(
+    app: &AppHandle,
+    old: &AppConfigSnapshot,
+    new: &AppConfigSnapshot,
+    event: &mut ConfigChangedEvent,
+) {
+    let im_state: ImManagerState = (*app.state::<ImManagerState>()).clone();
+    let sidecar_state: SidecarState = (*app.state::<SidecarState>()).clone();
+
+    for r in &event.diff.channels_removed {
+        let mut manager = im_state.lock().await;
+        if manager.running_config(&r.agent_id, &r.channel_id).is_some() {
+            match manager.stop_channel(&r.agent_id, &r.channel_id).await {
+                Ok(()) => event.channels_stopped.push(r.clone()),
+                Err(e) => event.errors.push(format!("Stop {}: {}", r.channel_id, e)),
+            }
+        }
+    }
+
+    let touched: Vec<ChannelRef> = event
+        .diff
+        .channels_added
+        .iter()
+        .chain(&event.diff.channels_changed)
+        .cloned()
+        .collect();
+    for r in touched {
+        let Some((agent, channel)) = new.find_channel(&r) else {
+            continue;
+        };
+        let previous = old.find_channel(&r);
+        let was_desired = previous.is_some_and(|(a, c)| channel_desired(a, c));
+        let desired = channel_desired(agent, channel);
+        let config = channel.to_im_config(agent);
+
+        let mut manager = im_state.lock().await;
+        let running = manager
+            .running_config(&r.agent_id, &r.channel_id)
+            .map(restart_fingerprint);
+
+        match running {
+            Some(_) if !desired && was_desired => {
+                match manager.stop_channel(&r.agent_id, &r.channel_id).await {
+                    Ok(()) => event.channels_stopped.push(r),
+                    Err(e) => event.errors.push(format!("Stop {}: {}", r.channel_id, e)),
+                }
+            }
+            // Started by hand while disabled in config — leave it alone
+            Some(_) if !desired => {}
+            Some(running) if running != restart_fingerprint(&config) => {
+                ulog_info!(
+                    "[config] Restarting channel {} with new config",
+                    r.channel_id
+                );
+                match manager
+                    .start_channel(app.clone(), sidecar_state.clone(), config)
+                    .await
+                {
+                    Ok(()) => event.channels_started.push(r),
+                    Err(e) => event
+                        .errors
+                        .push(format!("Restart {}: {}", r.channel_id, e)),
+                }
+            }
+            Some(_) => {
+                let patch = hot_patch(channel);
+                if previous.map(|(_, c)| hot_patch(c)) != Some(patch.clone()) {
+                    match manager
+                        .update_channel_config(&r.agent_id, &r.channel_id, &patch.to_string())
+                        .await
+                    {
+                        Ok(()) => event.channels_updated.push(r),
+                        Err(e) => event.errors.push(format!("Update {}: {}", r.channel_id, e)),
+                    }
+                }
+            }
+            None if desired && !was_desired => {
+                ulog_info!("[config] Starting channel {}", r.channel_id);
+                match manager
+                    .start_channel(app.clone(), sidecar_state.clone(), config)
+                    .await
+                {
+                    Ok(()) => event.channels_started.push(r),
+                    Err(e) => event.errors.push(format!("Start {}: {}", r.channel_id, e)),
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+async fn reconcile_proxy(app: &AppHandle, event: &mut ConfigChangedEvent) {
+    let sidecar_state: SidecarState = (*app.state::<SidecarState>()).clone();
+    if let Err(e) = crate::commands::propagate_proxy_to_sidecars(&sidecar_state).await {
+        event.errors.push(format!("Proxy propagation: {}", e));
+    }
+
+    // Feishu/DingTalk build their HTTP clients with the global proxy at startup.
+    // Telegram only uses its per-channel proxyUrl, so it is left alone.
+    let im_state: ImManagerState = (*app.state::<ImManagerState>()).clone();
+    let mut manager = im_state.lock().await;
+    for config in manager.running_configs() {
+        if !matches!(config.platform, ImPlatform::Feishu | ImPlatform::Dingtalk) {
+            continue;
+        }
+        let r = ChannelRef {
+            agent_id: config.agent_id.clone(),
+            channel_id: config.channel_id.clone(),
+        };
+        if event.channels_started.contains(&r) {
+            continue;
+        }
+        ulog_info!(
+            "[config] Restarting channel {} for proxy change",
+            r.channel_id
+        );
+        match manager
+            .start_channel(app.clone(), sidecar_state.clone(), config)
+            .await
+        {
+            Ok(()) => event.channels_started.push(r),
+            Err(e) => event
+                .errors
+                .push(format!("Restart {}: {}", r.channel_id, e)),
+        }
+    }
+}
+
+async fn reconcile_heartbeats(app: &AppHandle, new: &AppConfigSnapshot, agent_ids: &[String]) {
+    let heartbeat: HeartbeatManagerState = (*app.state::<HeartbeatManagerState>()).clone();
+    for agent_id in agent_ids {
+        match new.agents.iter().find(|a| &a.id == agent_id) {
+            Some(agent) => {
+                let config = agent.heartbeat.clone().filter(|_| agent.enabled);
+                heartbeat
+                    .sync_agent(
+                        agent_id,
+                        config,
+                        &agent.workspace_path,
+                        agent.memory_auto_update.clone(),
+                        app,
+                    )
+                    .await;
+            }
+            None => heartbeat.sync_agent(agent_id, None, "", None, app).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(json: serde_json::Value) -> AppConfigSnapshot {
+        AppConfigSnapshot::parse(&json.to_string()).unwrap()
+    }
+
+    fn agent(channel_enabled: bool, allowed: &[&str]) -> serde_json::Value {
+        serde_json::json!({
+            "id": "a1",
+            "name": "Agent",
+            "enabled": true,
+            "workspacePath": "/tmp/ws",
+            "channels": [{
+                "id": "c1",
+                "type": "telegram",
+                "enabled": channel_enabled,
+                "botToken": "123:abc",
+                "allowedUsers": allowed,
+            }],
+        })
+    }
+
+    #[test]
+    fn diff_detects_channel_and_proxy_changes() {
+        let old = snapshot(serde_json::json!({ "agents": [agent(true, &[])] }));
+        let same = snapshot(serde_json::json!({ "agents": [agent(true, &[])] }));
+        assert!(ConfigDiff::between(&old, &same).is_empty());
+
+        let new = snapshot(serde_json::json!({
+            "agents": [agent(true, &["42"])],
+            "proxySettings": { "enabled": true, "port": 7897 },
+        }));
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(diff.channels_changed.len(), 1);
+        assert!(diff.channels_added.is_empty() && diff.heartbeat_changed.is_empty());
+        assert!(diff.proxy_changed);
+
+        let removed = snapshot(serde_json::json!({ "agents": [] }));
+        let diff = ConfigDiff::between(&old, &removed);
+        assert_eq!(diff.channels_removed.len(), 1);
+        assert_eq!(diff.heartbeat_changed, vec!["a1".to_string()]);
+    }
+
+    #[test]
+    fn allowlist_change_does_not_need_restart() {
+        let old = snapshot(serde_json::json!({ "agents": [agent(true, &[])] }));
+        let new = snapshot(serde_json::json!({ "agents": [agent(true, &["42"])] }));
+        let fp = |s: &AppConfigSnapshot| {
+            let (a, c) = s
+                .find_channel(&ChannelRef {
+                    agent_id: "a1".into(),
+                    channel_id: "c1".into(),
+                })
+                .unwrap();
+            restart_fingerprint(&c.to_im_config(a))
+        };
+        assert_eq!(fp(&old), fp(&new));
+    }
+
+    #[test]
+    fn scheduler_limits_are_parsed_and_diffed() {
+        let old = snapshot(serde_json::json!({ "agents": [] }));
+        assert_eq!(old.im_scheduler, SchedulerLimits::default());
+        let new = snapshot(serde_json::json!({
+            "agents": [],
+            "imScheduler": { "maxConcurrent": 4, "perChannel": 2 },
+        }));
+        assert_eq!(new.im_scheduler.max_concurrent, 4);
+        assert_eq!(new.im_scheduler.per_channel, Some(2));
+        let diff = ConfigDiff::between(&old, &new);
+        assert!(diff.scheduler_changed && !diff.is_empty());
+    }
+
+    #[test]
+    fn metrics_endpoint_is_parsed() {
+        let s = snapshot(serde_json::json!({ "metricsEndpoint": { "enabled": true, "port": 9464 } }));
+        let m = s.metrics_endpoint.unwrap();
+        assert!(m.enabled);
+        assert_eq!(m.port, Some(9464));
+        let s = snapshot(serde_json::json!({ "metricsEndpoint": "on" }));
+        assert!(s.metrics_endpoint.is_none());
+    }
+
+    #[test]
+    fn bad_agent_entry_is_skipped() {
+        let s = snapshot(serde_json::json!({ "agents": [agent(true, &[]), { "id": "broken" }] }));
+        assert_eq!(s.agents.len(), 1);
+    }
+}
@@ -0,0 +1,229 @@
+// Tool activity — turns the Sidecar's tool_use / tool_result `activity` events into a
+// one-line status for the placeholder message ("🔧 Running Bash: npm test") and an
+// optional log of the tools used, sent after the reply.
+
+use serde_json::Value;
+
+use super::types::ToolActivityVerbosity;
+
+/// Longest target (command, path, query) in a summary status line
+const SUMMARY_TARGET_CHARS: usize = 40;
+/// Longest target in a detailed status line
+const DETAILED_TARGET_CHARS: usize = 120;
+/// Tools listed in the log before "… and N more"
+const MAX_LOG_ENTRIES: usize = 10;
+
+struct ToolCall {
+    id: String,
+    line: String,
+    failed: bool,
+}
+
+/// Tool calls of one turn
+pub struct ToolTracker {
+    verbosity: ToolActivityVerbosity,
+    calls: Vec<ToolCall>,
+}
+
+impl ToolTracker {
+    pub fn new(verbosity: ToolActivityVerbosity) -> Self {
+        Self {
+            verbosity,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Feed an `activity` event. Returns the new status line when a tool starts
+    /// (None for other activity, or when status lines are off).
+    pub fn on_activity(&mut self, event: &Value) -> Option<String> {
+        let tool = event.get("tool")?;
+        match event["kind"].as_str()? {
+            "tool_use" => {
+                let detailed = self.verbosity == ToolActivityVerbosity::Detailed;
+                let line = describe_tool(
+                    tool["name"].as_str().unwrap_or(""),
+                    &tool["input"],
+                    detailed,
+                );
+                self.calls.push(ToolCall {
+                    id: tool["id"].as_str().unwrap_or("").to_string(),
+                    line: line.clone(),
+                    failed: false,
+                });
+                match self.verbosity {
+                    ToolActivityVerbosity::Off => None,
+                    ToolActivityVerbosity::Summary => Some(line),
+                    ToolActivityVerbosity::Detailed => {
+                        Some(format!("{} (step {})", line, self.calls.len()))
+                    }
+                }
+            }
+            "tool_result" => {
+                if tool["isError"].as_bool().unwrap_or(false) {
+                    let id = tool["id"].as_str().unwrap_or("");
+                    if let Some(call) = self.calls.iter_mut().rev().find(|c| c.id == id) {
+                        call.failed = true;
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Short list of the tools used this turn, if any
+    pub fn log(&self) -> Option<String> {
+        if self.calls.is_empty() {
+            return None;
+        }
+        let mut lines = vec![format!("Tools used ({}):", self.calls.len())];
+        for call in self.calls.iter().take(MAX_LOG_ENTRIES) {
+            let failed = if call.failed { " ✗" } else { "" };
+            lines.push(format!("• {}{}", call.line, failed));
+        }
+        if self.calls.len() > MAX_LOG_ENTRIES {
+            lines.push(format!("… and {} more", self.calls.len() - MAX_LOG_ENTRIES));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// Status line for one tool call
+fn describe_tool(name: &str, input: &Value, detailed: bool) -> String {
+    let limit = if detailed {
+        DETAILED_TARGET_CHARS
+    } else {
+        SUMMARY_TARGET_CHARS
+    };
+    let field = |key: &str| input[key].as_str().unwrap_or("");
+    let target = |key: &str| shorten(field(key), limit);
+    let path = |key: &str| {
+        let p = field(key);
+        shorten(if detailed { p } else { short_path(p) }, limit)
+    };
+
+    match name {
+        "Bash" => with_target("🔧 Running Bash:", target("command")),
+        "Read" => with_target("📄 Reading", path("file_path")),
+        "Write" => with_target("✏️ Writing", path("file_path")),
+        "Edit" | "MultiEdit" => with_target("✏️ Editing", path("file_path")),
+        "NotebookEdit" => with_target("✏️ Editing", path("notebook_path")),
+        "Glob" => with_target("🔍 Finding files:", target("pattern")),
+        "Grep" => with_target("🔍 Searching:", target("pattern")),
+        "WebFetch" => with_target("🌐 Fetching", target("url")),
+        "WebSearch" => with_target("🌐 Searching the web:", target("query")),
+        "Task" | "Agent" => with_target("🤖 Delegating:", target("description")),
+        "TodoWrite" => "📝 Updating the todo list".to_string(),
+        _ => match name
+            .strip_prefix("mcp__")
+            .and_then(|rest| rest.split_once("__"))
+        {
+            Some((server, tool)) => format!("🧩 Using {}/{}", server, tool),
+            None if name.is_empty() => "🔧 Using a tool".to_string(),
+            None => format!("🔧 Using {}", name),
+        },
+    }
+}
+
+fn with_target(action: &str, target: String) -> String {
+    if target.is_empty() {
+        action.trim_end_matches(':').to_string()
+    } else {
+        format!("{} {}", action, target)
+    }
+}
+
+/// Last two components of a path ("src/app.ts")
+fn short_path(path: &str) -> &str {
+    let mut seps = path.rmatch_indices(['/', '\\']).map(|(i, _)| i);
+    match (seps.next(), seps.next()) {
+        (Some(_), Some(i)) => &path[i + 1..],
+        _ => path,
+    }
+}
+
+/// First line of `text`, cut to `limit` characters
+fn shorten(text: &str, limit: usize) -> String {
+    let line = text.lines().next().unwrap_or("").trim();
+    if line.chars().count() > limit {
+        let cut: String = line.chars().take(limit).collect();
+        format!("{}…", cut)
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool_use(id: &str, name: &str, input: Value) -> Value {
+        json!({
+            "type": "activity",
+            "kind": "tool_use",
+            "tool": { "id": id, "name": name, "input": input }
+        })
+    }
+
+    #[test]
+    fn status_lines_follow_verbosity() {
+        let mut summary = ToolTracker::new(ToolActivityVerbosity::Summary);
+        assert_eq!(
+            summary.on_activity(&tool_use(
+                "t1",
+                "Bash",
+                json!({ "command": "npm test\nnpm run lint" })
+            )),
+            Some("🔧 Running Bash: npm test".to_string())
+        );
+        assert_eq!(
+            summary.on_activity(&tool_use(
+                "t2",
+                "Read",
+                json!({ "file_path": "/home/me/proj/src/app.ts" })
+            )),
+            Some("📄 Reading src/app.ts".to_string())
+        );
+        assert_eq!(
+            summary.on_activity(&json!({ "type": "activity", "kind": "text" })),
+            None
+        );
+
+        let mut detailed = ToolTracker::new(ToolActivityVerbosity::Detailed);
+        assert_eq!(
+            detailed.on_activity(&tool_use(
+                "t1",
+                "Read",
+                json!({ "file_path": "/home/me/proj/src/app.ts" })
+            )),
+            Some("📄 Reading /home/me/proj/src/app.ts (step 1)".to_string())
+        );
+
+        let mut off = ToolTracker::new(ToolActivityVerbosity::Off);
+        assert_eq!(
+            off.on_activity(&tool_use("t1", "mcp__github__create_issue", json!({}))),
+            None
+        );
+        assert_eq!(
+            off.log().as_deref(),
+            Some("Tools used (1):\n• 🧩 Using github/create_issue")
+        );
+    }
+
+    #[test]
+    fn log_marks_failed_tools() {
+        let mut tracker = ToolTracker::new(ToolActivityVerbosity::Summary);
+        assert_eq!(tracker.log(), None);
+        tracker.on_activity(&tool_use("t1", "Grep", json!({ "pattern": "TODO" })));
+        tracker.on_activity(&json!({
+            "type": "activity",
+            "kind": "tool_result",
+            "tool": { "id": "t1", "name": "Grep", "isError": true }
+        }));
+        assert_eq!(
+            tracker.log().as_deref(),
+            Some("Tools used (1):\n• 🔍 Searching: TODO ✗")
+        );
+    }
+}
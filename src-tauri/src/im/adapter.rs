@@ -1,5 +1,7 @@
+use crate::im::rate_limit::RateLimiter;
 use crate::im::types::*;
 use async_trait::async_trait;
+use std::time::Duration;
 use tokio::sync::watch;
 
 #[async_trait]
@@ -27,6 +29,23 @@ pub trait ImAdapter: Send + Sync + 'static {
 
     /// Send typing indicator.
     async fn send_typing(&self, chat_id: &str) -> AdapterResult<()>;
+
+    /// How often `send_typing` must be repeated to keep the indicator visible during a
+    /// long turn (None = it doesn't expire, or the platform has no indicator).
+    fn typing_refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Upload a file to the chat as a document (with optional caption).
+    async fn send_file(
+        &self,
+        _chat_id: &str,
+        _filename: &str,
+        _data: Vec<u8>,
+        _caption: Option<&str>,
+    ) -> AdapterResult<()> {
+        Err("File upload is not supported on this platform".to_string())
+    }
 }
 
 #[async_trait]
@@ -49,47 +68,409 @@ pub trait ImStreamAdapter: ImAdapter {
     /// Delete a message.
     async fn delete_message(&self, chat_id: &str, message_id: &str) -> AdapterResult<()>;
 
+    /// Send one part of a message that already fits `max_message_length` (no splitting)
+    /// and return its ID when the platform reports one.
+    /// Failures are classified so the outbound queue can back off or give up.
+    async fn send_part(&self, chat_id: &str, text: &str) -> Result<Option<String>, SendError> {
+        self.send_message_returning_id(chat_id, text)
+            .await
+            .map_err(SendError::transient)
+    }
+
     /// Maximum message length for this platform.
     fn max_message_length(&self) -> usize;
 
+    /// How the platform counts `max_message_length`.
+    fn length_unit(&self) -> LengthUnit {
+        LengthUnit::Chars
+    }
+
     /// Whether to use draft-based streaming (Telegram-specific).
     fn use_draft_streaming(&self) -> bool {
         false
     }
 
+    /// Create or update the native streaming draft `draft_id` (Telegram sendMessageDraft).
+    /// `TelegramError::DraftPeerInvalid` means drafts aren't available for the chat —
+    /// stream by editing a sent message instead.
+    async fn send_draft_update(
+        &self,
+        _chat_id: &str,
+        _text: &str,
+        _draft_id: i64,
+    ) -> Result<(), TelegramError> {
+        Err(TelegramError::DraftPeerInvalid)
+    }
+
     /// Throttle interval for streaming edits.
     fn preferred_throttle_ms(&self) -> u64 {
         1000
     }
+
+    /// Throttle interval for draft updates.
+    fn draft_throttle_ms(&self) -> u64 {
+        self.preferred_throttle_ms()
+    }
+
+    /// Outbound rate limiter shared by all channels of this bot.
+    /// Intermediate streaming edits are skipped while it is saturated.
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        None
+    }
 }
 
-/// Split long text at natural break points (paragraph, line, sentence, word).
-pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    if text.len() <= max_len {
+// ===== Message Splitting =====
+
+/// How a platform counts message length against its limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthUnit {
+    /// UTF-8 bytes (limits on the request body)
+    Bytes,
+    /// Unicode characters
+    Chars,
+    /// UTF-16 code units (Telegram)
+    Utf16,
+}
+
+impl LengthUnit {
+    pub fn measure(self, text: &str) -> usize {
+        match self {
+            Self::Bytes => text.len(),
+            Self::Chars => text.chars().count(),
+            Self::Utf16 => text.encode_utf16().count(),
+        }
+    }
+
+    fn char_len(self, c: char) -> usize {
+        match self {
+            Self::Bytes => c.len_utf8(),
+            Self::Chars => 1,
+            Self::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
+/// Room kept free in every part for the "(2/3)" marker
+const PART_MARKER_RESERVE: usize = 12;
+/// Sentence ends a long line may be broken after
+const SENTENCE_BREAKS: [&str; 6] = [". ", "! ", "? ", "。", "！", "？"];
+
+/// Split long text into numbered parts that each fit the platform limit.
+/// Breaks between paragraphs, then lines, sentences and words — never inside a
+/// character. Fenced code blocks stay whole when they fit in a part; otherwise each
+/// piece is closed at the end of its part and reopened at the start of the next.
+pub fn split_message(text: &str, max_len: usize, unit: LengthUnit) -> Vec<String> {
+    if unit.measure(text) <= max_len {
         return vec![text.to_string()];
     }
+    let budget = max_len.saturating_sub(PART_MARKER_RESERVE).max(1);
 
     let mut parts = Vec::new();
-    let mut remaining = text;
+    let mut current = String::new();
+    let mut current_len = 0;
+    for block in markdown_blocks(text) {
+        let rendered = block.render();
+        let len = unit.measure(&rendered);
+        if !current.is_empty() && current_len + 2 + len <= budget {
+            current.push_str("\n\n");
+            current.push_str(&rendered);
+            current_len += 2 + len;
+            continue;
+        }
+        if !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+        if len <= budget {
+            current = rendered;
+            current_len = len;
+            continue;
+        }
+        // Oversized block: split on its own, the last piece may still take more blocks
+        let mut pieces = block.split(budget, unit);
+        if let Some(last) = pieces.pop() {
+            parts.extend(pieces);
+            current_len = unit.measure(&last);
+            current = last;
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
 
-    while !remaining.is_empty() {
-        if remaining.len() <= max_len {
-            parts.push(remaining.to_string());
-            break;
+    let total = parts.len();
+    if total <= 1 {
+        return parts;
+    }
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| format!("{}\n\n({}/{})", part, i + 1, total))
+        .collect()
+}
+
+/// Cut text for an in-progress preview (streaming draft) so it fits `max_len` with a
+/// trailing "...". An open code fence is closed so the preview still renders.
+pub fn truncate_message(text: &str, max_len: usize, unit: LengthUnit) -> String {
+    if unit.measure(text) <= max_len {
+        return text.to_string();
+    }
+    // "\n...\n```" plus slack for a longer fence marker
+    let budget = max_len.saturating_sub(10);
+    let mut head = &text[..fit_prefix(text, budget, unit)];
+    // Prefer ending on a whole line when one is close by
+    if let Some(nl) = head.rfind('\n').filter(|&i| i > head.len() / 2) {
+        head = &head[..nl];
+    }
+    match open_fence(head) {
+        Some(marker) => format!("{}\n...\n{}", head, marker),
+        None => format!("{}...", head),
+    }
+}
+
+/// A paragraph (or list / table) or a fenced code block
+enum Block<'a> {
+    Text(Vec<&'a str>),
+    /// `close` is None when the fence is never closed
+    Code {
+        open: &'a str,
+        lines: Vec<&'a str>,
+        close: Option<&'a str>,
+    },
+}
+
+impl Block<'_> {
+    fn render(&self) -> String {
+        match self {
+            Block::Text(lines) => lines.join("\n"),
+            Block::Code { open, lines, close } => std::iter::once(*open)
+                .chain(lines.iter().copied())
+                .chain(*close)
+                .collect::<Vec<_>>()
+                .join("\n"),
         }
+    }
 
-        let chunk = &remaining[..max_len];
-        let split_at = chunk.rfind("\n\n")
-            .or_else(|| chunk.rfind('\n'))
-            .or_else(|| chunk.rfind(". "))
-            .or_else(|| chunk.rfind(' '))
-            .unwrap_or(max_len);
+    /// Pieces of a block that does not fit in one part
+    fn split(&self, budget: usize, unit: LengthUnit) -> Vec<String> {
+        match self {
+            Block::Text(lines) => pack_lines(lines, budget, unit),
+            Block::Code { open, lines, close } => {
+                let marker = fence_marker(open).unwrap_or("```");
+                let closer = close.unwrap_or(marker);
+                let overhead = unit.measure(open) + unit.measure(closer) + 2;
+                // A fence that leaves no room for code is split as plain text
+                if budget < overhead * 2 {
+                    return pack_lines(&self.render().lines().collect::<Vec<_>>(), budget, unit);
+                }
+                let chunks = pack_lines(lines, budget - overhead, unit);
+                let last = chunks.len().saturating_sub(1);
+                chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, chunk)| match (i == last, close) {
+                        (true, None) => format!("{}\n{}", open, chunk),
+                        _ => format!("{}\n{}\n{}", open, chunk, closer),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
 
-        let split_at = if split_at == 0 { max_len } else { split_at };
+/// Group lines into paragraphs and fenced code blocks
+fn markdown_blocks(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = text.lines();
 
-        parts.push(remaining[..split_at].to_string());
-        remaining = remaining[split_at..].trim_start();
+    while let Some(line) = lines.next() {
+        if let Some(marker) = fence_marker(line) {
+            if !paragraph.is_empty() {
+                blocks.push(Block::Text(std::mem::take(&mut paragraph)));
+            }
+            let mut body = Vec::new();
+            let mut close = None;
+            for inner in lines.by_ref() {
+                if closes_fence(inner, marker) {
+                    close = Some(inner);
+                    break;
+                }
+                body.push(inner);
+            }
+            blocks.push(Block::Code {
+                open: line,
+                lines: body,
+                close,
+            });
+        } else if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                blocks.push(Block::Text(std::mem::take(&mut paragraph)));
+            }
+        } else {
+            paragraph.push(line);
+        }
     }
+    if !paragraph.is_empty() {
+        blocks.push(Block::Text(paragraph));
+    }
+    blocks
+}
 
-    parts
+/// The fence run ("```", "~~~~") if `line` opens a fenced code block
+fn fence_marker(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = trimmed.len() - trimmed.trim_start_matches(fence_char).len();
+    (run >= 3).then(|| &trimmed[..run])
+}
+
+fn closes_fence(line: &str, marker: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.len() >= marker.len() && trimmed.chars().all(|c| marker.starts_with(c))
+}
+
+/// Marker of the fence still open at the end of `text`, if any
+fn open_fence(text: &str) -> Option<&str> {
+    let mut open: Option<&str> = None;
+    for line in text.lines() {
+        match open {
+            Some(marker) if closes_fence(line, marker) => open = None,
+            Some(_) => {}
+            None => open = fence_marker(line),
+        }
+    }
+    open
+}
+
+/// Byte index of the longest prefix of `text` that fits in `budget` units
+fn fit_prefix(text: &str, budget: usize, unit: LengthUnit) -> usize {
+    let mut used = 0;
+    for (idx, c) in text.char_indices() {
+        used += unit.char_len(c);
+        if used > budget {
+            return idx;
+        }
+    }
+    text.len()
+}
+
+/// Join lines into chunks of at most `budget` units, breaking over-long lines
+fn pack_lines(lines: &[&str], budget: usize, unit: LengthUnit) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for line in lines {
+        for piece in split_long_line(line, budget, unit) {
+            let len = unit.measure(&piece);
+            if !current.is_empty() && current_len + 1 + len > budget {
+                chunks.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            if !current.is_empty() {
+                current.push('\n');
+                current_len += 1;
+            }
+            current.push_str(&piece);
+            current_len += len;
+        }
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Break one line at sentence ends, then spaces, then anywhere (on a char boundary)
+fn split_long_line(line: &str, budget: usize, unit: LengthUnit) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while unit.measure(rest) > budget {
+        let fit = fit_prefix(rest, budget, unit);
+        // Always make progress, even if a single character exceeds the budget
+        let fit = if fit == 0 {
+            rest.chars().next().map_or(rest.len(), char::len_utf8)
+        } else {
+            fit
+        };
+        let head = &rest[..fit];
+        let cut = SENTENCE_BREAKS
+            .iter()
+            .filter_map(|b| head.rfind(b).map(|i| i + b.len()))
+            .max()
+            .or_else(|| head.rfind(' '))
+            .filter(|&i| i > fit / 2)
+            .unwrap_or(fit);
+        pieces.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest.to_string());
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_untouched() {
+        assert_eq!(split_message("hi", 10, LengthUnit::Bytes), vec!["hi"]);
+    }
+
+    #[test]
+    fn cjk_and_emoji_split_on_char_boundaries() {
+        let text = "你好世界😀".repeat(40);
+        for unit in [LengthUnit::Bytes, LengthUnit::Chars, LengthUnit::Utf16] {
+            let parts = split_message(&text, 50, unit);
+            assert!(parts.len() > 1);
+            for part in &parts {
+                assert!(unit.measure(part) <= 50, "{:?}: {}", unit, part);
+            }
+            let rejoined: String = parts
+                .iter()
+                .map(|p| p.rsplit_once("\n\n(").unwrap().0)
+                .collect();
+            assert_eq!(rejoined, text);
+        }
+        // An emoji is two UTF-16 units but one char
+        assert_eq!(LengthUnit::Utf16.measure("😀"), 2);
+        assert_eq!(LengthUnit::Chars.measure("😀"), 1);
+    }
+
+    #[test]
+    fn parts_are_numbered_and_prefer_paragraphs() {
+        let text = format!("{}\n\n{}", "a".repeat(30), "b".repeat(30));
+        let parts = split_message(&text, 50, LengthUnit::Chars);
+        assert_eq!(
+            parts,
+            vec![
+                format!("{}\n\n(1/2)", "a".repeat(30)),
+                format!("{}\n\n(2/2)", "b".repeat(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_fences_are_closed_and_reopened() {
+        let code: Vec<String> = (0..20).map(|i| format!("let x{} = {};", i, i)).collect();
+        let text = format!("Intro\n\n```rust\n{}\n```\n\nOutro", code.join("\n"));
+        let parts = split_message(&text, 120, LengthUnit::Chars);
+        assert!(parts.len() > 2);
+        for part in &parts {
+            assert!(part.chars().count() <= 120);
+            assert_eq!(open_fence(part), None, "unbalanced fence in {:?}", part);
+        }
+        assert!(parts[1].starts_with("```rust\n"));
+        assert!(parts.last().unwrap().contains("Outro"));
+    }
+
+    #[test]
+    fn truncate_closes_open_fence() {
+        let text = format!("```\n{}", "line\n".repeat(50));
+        let preview = truncate_message(&text, 60, LengthUnit::Utf16);
+        assert!(preview.encode_utf16().count() <= 60);
+        assert!(preview.ends_with("\n...\n```"));
+        assert_eq!(truncate_message("short", 60, LengthUnit::Utf16), "short");
+    }
 }
@@ -0,0 +1,399 @@
+// Multi-agent routing — lets one channel front several agents.
+// A message picks an agent with "@name ..." or "/ask name ...", and the choice sticks
+// for that chat until changed. Each agent gets its own session key namespace
+// (`{sessionKey}#{name}`), so SessionRouter keeps contexts and Sidecars separate.
+// Sticky choice path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/agent_routes.json
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use super::types::{AgentConfigRust, AgentRoute, ImConfig};
+use crate::ulog_info;
+
+/// Separates the base session key from the route name
+const ROUTE_KEY_SEPARATOR: char = '#';
+
+/// Outcome of matching a message against the routing rules
+#[derive(Debug, PartialEq)]
+pub enum RouteSelection {
+    /// Run `text` (addressing prefix removed) on `route` (None = the channel's own agent)
+    Message { route: Option<String>, text: String },
+    /// The message only addressed an agent ("@coder") — switch the chat to it
+    Switched(String),
+}
+
+pub struct AgentRoutes {
+    routes: RwLock<Vec<AgentRoute>>,
+    /// Current agent per chat (chat_id → route name)
+    current: Mutex<HashMap<String, String>>,
+    persist_path: Option<PathBuf>,
+}
+
+impl AgentRoutes {
+    /// Load the sticky choices from `persist_path` (None = in-memory only)
+    pub fn load(routes: Vec<AgentRoute>, persist_path: Option<PathBuf>) -> Self {
+        let current = persist_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<HashMap<String, String>>(&s).ok())
+            .unwrap_or_default();
+        Self {
+            routes: RwLock::new(routes),
+            current: Mutex::new(current),
+            persist_path,
+        }
+    }
+
+    /// Replace the routing rules (hot reload). Chats whose agent was removed fall
+    /// back to the channel's own agent.
+    pub fn update_routes(&self, routes: Vec<AgentRoute>) {
+        let names: Vec<String> = routes.iter().map(|r| r.name.clone()).collect();
+        *self.routes.write().unwrap_or_else(|e| e.into_inner()) = routes;
+        let mut current = self.lock_current();
+        let before = current.len();
+        current.retain(|_, name| names.contains(name));
+        if current.len() != before {
+            self.persist(&current);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read_routes().is_empty()
+    }
+
+    /// Names of all configured agents (for `/agent` listings)
+    pub fn names(&self) -> Vec<String> {
+        self.read_routes().iter().map(|r| r.name.clone()).collect()
+    }
+
+    /// The chat's current agent (None = the channel's own agent)
+    pub fn current(&self, chat_id: &str) -> Option<String> {
+        self.lock_current().get(chat_id).cloned()
+    }
+
+    /// Switch the chat's current agent. Returns the canonical route name, or
+    /// Err if `name` matches no route. `None` switches back to the channel's agent.
+    pub fn set_current(&self, chat_id: &str, name: Option<&str>) -> Result<Option<String>, String> {
+        let canonical = match name {
+            Some(n) => Some(
+                self.find(n)
+                    .ok_or_else(|| format!("Unknown agent: {}", n))?,
+            ),
+            None => None,
+        };
+        let mut current = self.lock_current();
+        let changed = match &canonical {
+            Some(n) => current.insert(chat_id.to_string(), n.clone()).as_ref() != Some(n),
+            None => current.remove(chat_id).is_some(),
+        };
+        if changed {
+            ulog_info!(
+                "[im] Chat {} now routed to {}",
+                chat_id,
+                canonical.as_deref().unwrap_or("default agent")
+            );
+            self.persist(&current);
+        }
+        Ok(canonical)
+    }
+
+    /// Pick the agent for an incoming message. An explicit "@name" / "/ask name"
+    /// also becomes the chat's current agent; otherwise the current agent is used.
+    pub fn select(&self, chat_id: &str, text: &str) -> RouteSelection {
+        if self.is_empty() {
+            return RouteSelection::Message {
+                route: None,
+                text: text.to_string(),
+            };
+        }
+
+        if let Some((name, rest)) = self.match_address(text) {
+            let _ = self.set_current(chat_id, Some(&name));
+            if rest.is_empty() {
+                return RouteSelection::Switched(name);
+            }
+            return RouteSelection::Message {
+                route: Some(name),
+                text: rest,
+            };
+        }
+
+        RouteSelection::Message {
+            route: self.current(chat_id),
+            text: text.to_string(),
+        }
+    }
+
+    /// Channel config with the route's workspace / model / permission mode applied.
+    /// `session_key` selects the route via its `#name` suffix; keys without one (or
+    /// naming a removed route) get the channel config unchanged.
+    pub fn resolve_config(&self, base: &ImConfig, session_key: &str) -> ImConfig {
+        let mut config = base.clone();
+        let Some(name) = route_of(session_key) else {
+            return config;
+        };
+        let routes = self.read_routes();
+        let Some(route) = routes.iter().find(|r| r.name == name) else {
+            return config;
+        };
+        if let Some(ref v) = route.workspace_path {
+            config.workspace_path = v.clone();
+        }
+        if route.provider_id.is_some() || route.provider_env_json.is_some() {
+            config.provider_id = route.provider_id.clone();
+            config.provider_env_json = route.provider_env_json.clone();
+        }
+        if let Some(ref v) = route.model {
+            config.model = Some(v.clone());
+        }
+        if let Some(ref v) = route.permission_mode {
+            config.permission_mode = v.clone();
+        }
+        if let Some(ref v) = route.mcp_servers_json {
+            config.mcp_servers_json = Some(v.clone());
+        }
+        config
+    }
+
+    /// "@name rest", "rest @name more" or "/ask name rest" → (canonical name, rest)
+    fn match_address(&self, text: &str) -> Option<(String, String)> {
+        let trimmed = text.trim();
+        if let Some(after) = trimmed.strip_prefix("/ask ") {
+            let after = after.trim_start();
+            let (word, rest) = after.split_once(char::is_whitespace).unwrap_or((after, ""));
+            return self.find(word).map(|name| (name, rest.trim().to_string()));
+        }
+
+        let words: Vec<&str> = trimmed.split(' ').collect();
+        for (i, word) in words.iter().enumerate() {
+            let Some(handle) = word.strip_prefix('@') else {
+                continue;
+            };
+            let handle = handle.trim_end_matches([',', ':', '，', '：']);
+            if let Some(name) = self.find(handle) {
+                let mut rest = words.clone();
+                rest.remove(i);
+                return Some((name, rest.join(" ").trim().to_string()));
+            }
+        }
+        None
+    }
+
+    /// Canonical route name for `name` (matches names and aliases, case-insensitive)
+    fn find(&self, name: &str) -> Option<String> {
+        if name.is_empty() {
+            return None;
+        }
+        self.read_routes()
+            .iter()
+            .find(|r| {
+                r.name.eq_ignore_ascii_case(name)
+                    || r.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+            })
+            .map(|r| r.name.clone())
+    }
+
+    fn read_routes(&self) -> std::sync::RwLockReadGuard<'_, Vec<AgentRoute>> {
+        self.routes.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_current(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, current: &HashMap<String, String>) {
+        if let Some(path) = &self.persist_path {
+            save_current(path, current);
+        }
+    }
+}
+
+fn save_current(path: &Path, current: &HashMap<String, String>) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    if let Ok(s) = serde_json::to_string_pretty(current) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+/// Session key for `route` under the chat's base key (`None` = the base key itself)
+pub fn routed_session_key(base_key: &str, route: Option<&str>) -> String {
+    match route {
+        Some(name) => format!("{}{}{}", base_key, ROUTE_KEY_SEPARATOR, name),
+        None => base_key.to_string(),
+    }
+}
+
+/// Route name encoded in a session key, if any
+pub fn route_of(session_key: &str) -> Option<&str> {
+    session_key
+        .rsplit_once(ROUTE_KEY_SEPARATOR)
+        .map(|(_, name)| name)
+}
+
+/// Base session key without the route suffix
+pub fn base_session_key(session_key: &str) -> &str {
+    session_key
+        .rsplit_once(ROUTE_KEY_SEPARATOR)
+        .map_or(session_key, |(base, _)| base)
+}
+
+/// Fill unset route fields from the agent named by `agent_id`.
+pub fn resolve_agent_refs(routes: &mut [AgentRoute], agents: &[AgentConfigRust]) {
+    for route in routes.iter_mut() {
+        let Some(agent) = route
+            .agent_id
+            .as_ref()
+            .and_then(|id| agents.iter().find(|a| &a.id == id))
+        else {
+            continue;
+        };
+        if route.workspace_path.is_none() {
+            route.workspace_path = Some(agent.workspace_path.clone());
+        }
+        if route.provider_id.is_none() && route.provider_env_json.is_none() {
+            route.provider_id = agent.provider_id.clone();
+            route.provider_env_json = agent.provider_env_json.clone();
+        }
+        if route.model.is_none() {
+            route.model = agent.model.clone();
+        }
+        if route.permission_mode.is_none() {
+            route.permission_mode = Some(agent.permission_mode.clone());
+        }
+        if route.mcp_servers_json.is_none() {
+            route.mcp_servers_json = agent.mcp_servers_json.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes() -> AgentRoutes {
+        AgentRoutes::load(
+            vec![
+                AgentRoute {
+                    name: "coder".to_string(),
+                    aliases: vec!["dev".to_string()],
+                    model: Some("opus".to_string()),
+                    ..Default::default()
+                },
+                AgentRoute {
+                    name: "writer".to_string(),
+                    ..Default::default()
+                },
+            ],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_select_by_prefix_and_mention() {
+        let r = routes();
+        assert_eq!(
+            r.select("1", "@coder fix the build"),
+            RouteSelection::Message {
+                route: Some("coder".to_string()),
+                text: "fix the build".to_string()
+            }
+        );
+        // Sticky: follow-ups stay with the chosen agent
+        assert_eq!(
+            r.select("1", "and add a test"),
+            RouteSelection::Message {
+                route: Some("coder".to_string()),
+                text: "and add a test".to_string()
+            }
+        );
+        assert_eq!(
+            r.select("1", "/ask writer draft a post"),
+            RouteSelection::Message {
+                route: Some("writer".to_string()),
+                text: "draft a post".to_string()
+            }
+        );
+        assert_eq!(
+            r.select("2", "hey @DEV, look"),
+            RouteSelection::Message {
+                route: Some("coder".to_string()),
+                text: "hey look".to_string()
+            }
+        );
+        assert_eq!(
+            r.select("3", "@writer"),
+            RouteSelection::Switched("writer".to_string())
+        );
+        // Unknown handles are left alone
+        assert_eq!(
+            r.select("4", "@someone hi"),
+            RouteSelection::Message {
+                route: None,
+                text: "@someone hi".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_session_key_namespacing() {
+        let r = routes();
+        let key = routed_session_key("im:a:telegram:private:42", Some("coder"));
+        assert_eq!(key, "im:a:telegram:private:42#coder");
+        assert_eq!(route_of(&key), Some("coder"));
+        assert_eq!(base_session_key(&key), "im:a:telegram:private:42");
+        assert_eq!(route_of("im:a:telegram:private:42"), None);
+
+        let base = ImConfig {
+            agent_id: "a".to_string(),
+            channel_id: "c".to_string(),
+            platform: crate::im::types::ImPlatform::Telegram,
+            workspace_path: "/ws".to_string(),
+            bot_token: String::new(),
+            telegram_use_draft: None,
+            allowed_users: vec![],
+            provider_id: None,
+            model: Some("sonnet".to_string()),
+            provider_env_json: None,
+            permission_mode: "plan".to_string(),
+            mcp_enabled_servers: None,
+            mcp_servers_json: None,
+            proxy_url: None,
+            feishu_app_id: None,
+            feishu_app_secret: None,
+            dingtalk_client_id: None,
+            dingtalk_client_secret: None,
+            dingtalk_use_ai_card: None,
+            dingtalk_card_template_id: None,
+            local_port: None,
+            group_permissions: vec![],
+            group_activation: None,
+            agent_routes: vec![],
+            persona: Default::default(),
+            max_listen_restarts: None,
+            allow_proactive_send: None,
+            rerun_on_edit: None,
+            delete_reply_on_recall: None,
+            tool_activity: None,
+            tool_activity_log: None,
+            usage_footer: None,
+            review_before_send: None,
+            review_notice: None,
+            scheduler_weight: None,
+            workspace_allowlist: vec![],
+            admin_users: vec![],
+        };
+        assert_eq!(r.resolve_config(&base, &key).model.as_deref(), Some("opus"));
+        assert_eq!(
+            r.resolve_config(&base, "im:a:telegram:private:42")
+                .model
+                .as_deref(),
+            Some("sonnet")
+        );
+    }
+}
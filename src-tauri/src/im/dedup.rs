@@ -0,0 +1,115 @@
+// Inbound message dedup cache — shared by all platform adapters.
+// Remembers recently seen message/update IDs so redelivered events (WebSocket
+// reconnects, Telegram updates replayed after a crash) are processed only once.
+// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/dedup.json
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::Mutex;
+
+/// Entries older than this are forgotten
+const DEDUP_TTL_SECS: u64 = 72 * 60 * 60;
+/// Expired entries are pruned once the cache grows past this size
+const DEDUP_MAX_SIZE: usize = 5000;
+/// Minimum interval between background writes to disk
+const DEDUP_PERSIST_INTERVAL_MS: u64 = 500;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn load_dedup_cache(path: Option<&Path>) -> HashMap<String, u64> {
+    let path = match path {
+        Some(p) if p.exists() => p,
+        _ => return HashMap::new(),
+    };
+    match std::fs::read_to_string(path) {
+        Ok(content) => match serde_json::from_str::<HashMap<String, u64>>(&content) {
+            Ok(mut cache) => {
+                let now = now_secs();
+                cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
+                cache
+            }
+            Err(_) => HashMap::new(),
+        },
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn save_dedup_cache_to_disk(path: &Path, cache: &HashMap<String, u64>) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp.dedup");
+    if let Ok(s) = serde_json::to_string(cache) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+pub struct DedupCache {
+    entries: Mutex<HashMap<String, u64>>,
+    persist_path: Option<PathBuf>,
+    last_persist_ms: AtomicU64,
+}
+
+impl DedupCache {
+    /// Load the cache from `persist_path` (None = in-memory only)
+    pub fn load(persist_path: Option<PathBuf>) -> Self {
+        Self {
+            entries: Mutex::new(load_dedup_cache(persist_path.as_deref())),
+            persist_path,
+            last_persist_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns true if this is a NEW id (not a duplicate) and records it.
+    pub async fn check(&self, id: &str) -> bool {
+        let now = now_secs();
+        let mut cache = self.entries.lock().await;
+        if cache.len() > DEDUP_MAX_SIZE {
+            cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
+        }
+        if cache.contains_key(id) {
+            return false;
+        }
+        cache.insert(id.to_string(), now);
+        drop(cache);
+        self.maybe_persist().await;
+        true
+    }
+
+    /// Write to disk in the background, at most once per DEDUP_PERSIST_INTERVAL_MS
+    async fn maybe_persist(&self) {
+        let Some(path) = &self.persist_path else {
+            return;
+        };
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let last = self.last_persist_ms.load(Ordering::Relaxed);
+        if now_ms.saturating_sub(last) < DEDUP_PERSIST_INTERVAL_MS {
+            return;
+        }
+        self.last_persist_ms.store(now_ms, Ordering::Relaxed);
+        let snapshot = self.entries.lock().await.clone();
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || save_dedup_cache_to_disk(&path, &snapshot));
+    }
+
+    /// Write the full cache to disk now (listen loop shutdown)
+    pub async fn flush(&self) {
+        if let Some(path) = &self.persist_path {
+            let snapshot = self.entries.lock().await.clone();
+            save_dedup_cache_to_disk(path, &snapshot);
+        }
+    }
+}
@@ -2,12 +2,13 @@
 // Handles Stream mode WebSocket connection (JSON text frames),
 // message sending/editing (AI Card), OAuth2 token management,
 // and group discovery.
+// Stream-mode robots only receive new messages — DingTalk has no edit or recall
+// callbacks for them, so every inbound message is an ImMessageEvent::New.
 
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use reqwest::Client;
@@ -18,11 +19,14 @@ use tokio::time::sleep;
 use futures::SinkExt;
 use futures::StreamExt;
 
-use super::adapter::{ImAdapter, ImStreamAdapter};
+use super::adapter::{split_message, ImAdapter, ImStreamAdapter, LengthUnit};
+use super::dedup::DedupCache;
+use super::rate_limit::{shared_limiter, RateLimiter};
 use super::types::{
     AdapterResult, GroupPermission, GroupPermissionStatus,
-    ImConfig, ImMessage, ImPlatform, ImSourceType,
+    ImConfig, ImMessage, ImMessageEvent, ImPlatform, ImSourceType, SendError,
 };
+use crate::metrics;
 use crate::{proxy_config, ulog_error, ulog_info, ulog_warn};
 
 // ── Constants ─────────────────────────────────────────────────────────────────
@@ -37,12 +41,12 @@ const WS_MAX_BACKOFF_SECS: u64 = 60;
 const WS_READ_TIMEOUT_SECS: u64 = 120;
 const WS_PING_INTERVAL_SECS: u64 = 30;
 
-const DEDUP_TTL_SECS: u64 = 72 * 60 * 60;
-const DEDUP_MAX_SIZE: usize = 5000;
-const DEDUP_PERSIST_INTERVAL_MS: u64 = 500;
-
+/// Markdown content limit, counted in characters
 const MAX_MESSAGE_LENGTH: usize = 20000;
 
+/// Pause after a 429 that carries no Retry-After header
+const DEFAULT_RETRY_AFTER_SECS: u64 = 1;
+
 // ── AI Card tracking ──────────────────────────────────────────────────────────
 
 struct ActiveCardState {
@@ -50,20 +54,6 @@ struct ActiveCardState {
     last_content: String,
 }
 
-// ── Dedup cache persistence ───────────────────────────────────────────────────
-
-fn save_dedup_cache_to_disk(path: &std::path::Path, cache: &HashMap<String, u64>) {
-    if let Some(parent) = path.parent() {
-        let _ = std::fs::create_dir_all(parent);
-    }
-    let tmp = path.with_extension("json.tmp.dedup");
-    if let Ok(s) = serde_json::to_string(cache) {
-        if std::fs::write(&tmp, &s).is_ok() {
-            let _ = std::fs::rename(&tmp, path);
-        }
-    }
-}
-
 // ── Token cache ───────────────────────────────────────────────────────────────
 
 struct TokenCache {
@@ -93,9 +83,9 @@ pub struct DingtalkAdapter {
     known_groups: Arc<Mutex<HashSet<String>>>,
     /// Active AI Cards: chat_id → state
     active_cards: Arc<Mutex<HashMap<String, ActiveCardState>>>,
-    dedup_cache: Arc<Mutex<HashMap<String, u64>>>,
-    dedup_persist_path: Option<PathBuf>,
-    dedup_last_persist_ms: AtomicU64,
+    dedup: DedupCache,
+    /// Outbound budget shared with every channel using the same app
+    limiter: Arc<RateLimiter>,
 }
 
 impl DingtalkAdapter {
@@ -111,8 +101,6 @@ impl DingtalkAdapter {
         )
         .unwrap_or_else(|_| Client::new());
 
-        let dedup_cache = Self::load_dedup_cache(dedup_path.as_deref());
-
         // Pre-populate known groups from persisted permissions
         let known_groups: HashSet<String> = config
             .group_permissions
@@ -123,6 +111,7 @@ impl DingtalkAdapter {
         let client_id = config.dingtalk_client_id.clone().unwrap_or_default();
 
         Self {
+            limiter: shared_limiter(&ImPlatform::Dingtalk, &client_id),
             client_id: client_id.clone(),
             client_secret: config.dingtalk_client_secret.clone().unwrap_or_default(),
             use_ai_card: config.dingtalk_use_ai_card.unwrap_or(false),
@@ -141,69 +130,10 @@ impl DingtalkAdapter {
             group_permissions,
             known_groups: Arc::new(Mutex::new(known_groups)),
             active_cards: Arc::new(Mutex::new(HashMap::new())),
-            dedup_cache: Arc::new(Mutex::new(dedup_cache)),
-            dedup_persist_path: dedup_path,
-            dedup_last_persist_ms: AtomicU64::new(0),
-        }
-    }
-
-    fn load_dedup_cache(path: Option<&std::path::Path>) -> HashMap<String, u64> {
-        let path = match path {
-            Some(p) if p.exists() => p,
-            _ => return HashMap::new(),
-        };
-        match std::fs::read_to_string(path) {
-            Ok(content) => match serde_json::from_str::<HashMap<String, u64>>(&content) {
-                Ok(mut cache) => {
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs();
-                    cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
-                    cache
-                }
-                Err(_) => HashMap::new(),
-            },
-            Err(_) => HashMap::new(),
+            dedup: DedupCache::load(dedup_path),
         }
     }
 
-    async fn maybe_persist_dedup(&self) {
-        let Some(path) = &self.dedup_persist_path else {
-            return;
-        };
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-        let last = self.dedup_last_persist_ms.load(Ordering::Relaxed);
-        if now_ms.saturating_sub(last) < DEDUP_PERSIST_INTERVAL_MS {
-            return;
-        }
-        self.dedup_last_persist_ms.store(now_ms, Ordering::Relaxed);
-        let snapshot = self.dedup_cache.lock().await.clone();
-        let path = path.clone();
-        tokio::task::spawn_blocking(move || save_dedup_cache_to_disk(&path, &snapshot));
-    }
-
-    async fn dedup_check(&self, msg_id: &str) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let mut cache = self.dedup_cache.lock().await;
-        if cache.len() > DEDUP_MAX_SIZE {
-            cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
-        }
-        if cache.contains_key(msg_id) {
-            return false;
-        }
-        cache.insert(msg_id.to_string(), now);
-        drop(cache);
-        self.maybe_persist_dedup().await;
-        true
-    }
-
     // ── Token management ─────────────────────────────────────────────────────
 
     async fn get_token(&self) -> Result<String, String> {
@@ -277,10 +207,27 @@ impl DingtalkAdapter {
         method: &str,
         url: &str,
         body: Option<&Value>,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, SendError> {
+        let result = self.api_call_inner(method, url, body).await;
+        if let Err(e) = &result {
+            let kind = if e.retry_after.is_some() { "rate_limited" } else { "api_error" };
+            metrics::inc(
+                metrics::IM_PLATFORM_ERRORS,
+                &[("platform", "dingtalk".to_string()), ("kind", kind.to_string())],
+            );
+        }
+        result
+    }
+
+    async fn api_call_inner(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, SendError> {
         let mut retries = 0;
         loop {
-            let token = self.get_token().await?;
+            let token = self.get_token().await.map_err(SendError::transient)?;
             let mut req = match method {
                 "GET" => self.client.get(url),
                 "PUT" => self.client.put(url),
@@ -295,8 +242,13 @@ impl DingtalkAdapter {
             let resp = req
                 .send()
                 .await
-                .map_err(|e| format!("DingTalk API error: {}", e))?;
+                .map_err(|e| SendError::transient(format!("DingTalk API error: {}", e)))?;
             let status = resp.status();
+            let retry_after_header = resp
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok());
             let text = resp.text().await.unwrap_or_default();
 
             if status.as_u16() == 401 && retries == 0 {
@@ -306,8 +258,32 @@ impl DingtalkAdapter {
                 continue;
             }
 
+            if status.as_u16() == 429 {
+                let retry_after = retry_after_header
+                    .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+                    .max(1);
+                let limited_chat = rate_limited_chat(body, &text);
+                ulog_warn!(
+                    "[dingtalk] Rate limited{}, retry after {}s",
+                    limited_chat
+                        .as_deref()
+                        .map(|c| format!(" in chat {}", c))
+                        .unwrap_or_default(),
+                    retry_after
+                );
+                self.limiter
+                    .note_retry_after(limited_chat.as_deref(), retry_after);
+                return Err(SendError::rate_limited(
+                    format!("DingTalk API rate limited, retry after {}s", retry_after),
+                    retry_after,
+                ));
+            }
+
             if !status.is_success() {
-                return Err(format!("DingTalk API HTTP {}: {}", status, text));
+                return Err(SendError::from_status(
+                    status.as_u16(),
+                    format!("DingTalk API HTTP {}: {}", status, text),
+                ));
             }
 
             return Ok(serde_json::from_str(&text).unwrap_or_else(|_| json!({})));
@@ -331,7 +307,7 @@ impl DingtalkAdapter {
         &self,
         user_id: &str,
         text: &str,
-    ) -> Result<Option<String>, String> {
+    ) -> Result<Option<String>, SendError> {
         let url = format!("{}/v1.0/robot/oToMessages/batchSend", DINGTALK_API_BASE);
         let body = json!({
             "robotCode": self.robot_code,
@@ -350,7 +326,7 @@ impl DingtalkAdapter {
         &self,
         conversation_id: &str,
         text: &str,
-    ) -> Result<Option<String>, String> {
+    ) -> Result<Option<String>, SendError> {
         let url = format!("{}/v1.0/robot/groupMessages/send", DINGTALK_API_BASE);
         let body = json!({
             "robotCode": self.robot_code,
@@ -371,6 +347,20 @@ impl DingtalkAdapter {
         chat_id: &str,
         text: &str,
     ) -> Result<Option<String>, String> {
+        let mut last_id = None;
+        for chunk in split_message(text, MAX_MESSAGE_LENGTH, LengthUnit::Chars) {
+            last_id = self.send_single_text(chat_id, &chunk).await?;
+        }
+        Ok(last_id)
+    }
+
+    /// Send one message that fits MAX_MESSAGE_LENGTH
+    async fn send_single_text(
+        &self,
+        chat_id: &str,
+        text: &str,
+    ) -> Result<Option<String>, SendError> {
+        self.limiter.acquire(chat_id).await;
         if let Some(group_id) = chat_id.strip_prefix("group:") {
             self.send_group_message(group_id, text).await
         } else {
@@ -414,6 +404,7 @@ impl DingtalkAdapter {
             "guid": uuid::Uuid::new_v4().to_string(),
         });
 
+        self.limiter.acquire(chat_id).await;
         self.api_call("PUT", &url, Some(&body)).await?;
 
         if let Some(card) = self.active_cards.lock().await.get_mut(chat_id) {
@@ -471,6 +462,7 @@ impl DingtalkAdapter {
             body["imRobotOpenDeliverModel"] = m;
         }
 
+        self.limiter.acquire(chat_id).await;
         self.api_call("POST", &url, Some(&body)).await?;
 
         self.active_cards.lock().await.insert(
@@ -552,10 +544,7 @@ impl DingtalkAdapter {
         }
 
         // Flush dedup cache
-        if let Some(path) = &self.dedup_persist_path {
-            let snapshot = self.dedup_cache.lock().await.clone();
-            save_dedup_cache_to_disk(path, &snapshot);
-        }
+        self.dedup.flush().await;
 
         ulog_info!("[dingtalk] WS listen loop exited");
     }
@@ -758,7 +747,7 @@ impl DingtalkAdapter {
             return;
         }
 
-        if !self.dedup_check(msg_id).await {
+        if !self.dedup.check(msg_id).await {
             return;
         }
 
@@ -849,6 +838,7 @@ impl DingtalkAdapter {
             timestamp: chrono::Utc::now(),
             is_mention,
             reply_to_bot: false,
+            event: ImMessageEvent::New,
         };
 
         ulog_info!(
@@ -985,6 +975,10 @@ impl ImStreamAdapter for DingtalkAdapter {
         Ok(())
     }
 
+    async fn send_part(&self, chat_id: &str, text: &str) -> Result<Option<String>, SendError> {
+        self.send_single_text(chat_id, text).await
+    }
+
     fn max_message_length(&self) -> usize {
         MAX_MESSAGE_LENGTH
     }
@@ -992,6 +986,28 @@ impl ImStreamAdapter for DingtalkAdapter {
     fn preferred_throttle_ms(&self) -> u64 {
         1500
     }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        Some(&self.limiter)
+    }
+}
+
+/// Chat a rate-limited request was aimed at, when the limit applies to that chat only.
+/// App / API QPS limits (`...QpsLimit...` error codes) and requests without a target
+/// chat (card updates) pause the whole bot instead.
+fn rate_limited_chat(body: Option<&Value>, response: &str) -> Option<String> {
+    let code = serde_json::from_str::<Value>(response)
+        .ok()
+        .and_then(|v| v["code"].as_str().map(String::from))
+        .unwrap_or_default();
+    if code.contains("QpsLimit") {
+        return None;
+    }
+    let body = body?;
+    if let Some(group_id) = body["openConversationId"].as_str() {
+        return Some(format!("group:{}", group_id));
+    }
+    body["userIds"][0].as_str().map(String::from)
 }
 
 // ── Verify credentials (for Tauri command) ────────────────────────────────────
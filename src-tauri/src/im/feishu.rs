@@ -1,12 +1,13 @@
 // Feishu (Lark) Bot adapter
 // Handles WebSocket long connection using binary protobuf frames,
 // message sending (text format), edit/delete, and group discovery.
+// Edited (im.message.updated_v1) and recalled (im.message.recalled_v1) messages are
+// forwarded as edit / recall events.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use reqwest::Client;
@@ -16,11 +17,15 @@ use tokio::time::sleep;
 
 use prost::Message as ProstMessage;
 
-use super::adapter::{ImAdapter, ImStreamAdapter};
+use super::adapter::{split_message, ImAdapter, ImStreamAdapter, LengthUnit};
+use super::dedup::DedupCache;
+use super::rate_limit::{shared_limiter, RateLimiter};
+use super::util::{ext_to_mime, sanitize_filename, MultipartForm};
 use super::types::{
     AdapterResult, GroupPermission, GroupPermissionStatus,
-    ImConfig, ImMessage, ImPlatform, ImSourceType,
+    ImConfig, ImMessage, ImMessageEvent, ImPlatform, ImSourceType, SendError,
 };
+use crate::metrics;
 use crate::{proxy_config, ulog_error, ulog_info, ulog_warn};
 
 // ── Feishu WebSocket Protobuf Frame ──────────────────────────────────────────
@@ -62,10 +67,6 @@ const FRAME_METHOD_DATA: i32 = 1;
 
 // ── Constants ─────────────────────────────────────────────────────────────────
 
-const DEDUP_TTL_SECS: u64 = 72 * 60 * 60;
-const DEDUP_MAX_SIZE: usize = 5000;
-const DEDUP_PERSIST_INTERVAL_MS: u64 = 500;
-
 const FEISHU_API_BASE: &str = "https://open.feishu.cn/open-apis";
 const TOKEN_REFRESH_MARGIN_SECS: u64 = 600;
 const TOKEN_VALIDITY_SECS: u64 = 7200;
@@ -73,22 +74,9 @@ const WS_INITIAL_BACKOFF_SECS: u64 = 1;
 const WS_MAX_BACKOFF_SECS: u64 = 60;
 const WS_READ_TIMEOUT_SECS: u64 = 120;
 const WS_PING_INTERVAL_SECS: u64 = 30;
+/// Text content limit, counted in UTF-8 bytes
 const MAX_MESSAGE_LENGTH: usize = 30000;
 
-// ── Dedup cache persistence ───────────────────────────────────────────────────
-
-fn save_dedup_cache_to_disk(path: &std::path::Path, cache: &HashMap<String, u64>) {
-    if let Some(parent) = path.parent() {
-        let _ = std::fs::create_dir_all(parent);
-    }
-    let tmp = path.with_extension("json.tmp.dedup");
-    if let Ok(s) = serde_json::to_string(cache) {
-        if std::fs::write(&tmp, &s).is_ok() {
-            let _ = std::fs::rename(&tmp, path);
-        }
-    }
-}
-
 // ── Token cache ───────────────────────────────────────────────────────────────
 
 struct TokenCache {
@@ -114,9 +102,9 @@ pub struct FeishuAdapter {
     known_groups: Arc<Mutex<HashSet<String>>>,
     /// "mention" or "always"
     group_activation: String,
-    dedup_cache: Arc<Mutex<HashMap<String, u64>>>,
-    dedup_persist_path: Option<PathBuf>,
-    dedup_last_persist_ms: AtomicU64,
+    dedup: DedupCache,
+    /// Outbound budget shared with every channel using the same app
+    limiter: Arc<RateLimiter>,
 }
 
 impl FeishuAdapter {
@@ -132,8 +120,6 @@ impl FeishuAdapter {
         )
         .unwrap_or_else(|_| Client::new());
 
-        let dedup_cache = Self::load_dedup_cache(dedup_path.as_deref());
-
         // Pre-populate known groups from persisted permissions (sync read from config)
         let known_groups: HashSet<String> = config
             .group_permissions
@@ -141,8 +127,11 @@ impl FeishuAdapter {
             .map(|gp| gp.group_id.clone())
             .collect();
 
+        let app_id = config.feishu_app_id.clone().unwrap_or_default();
+
         Self {
-            app_id: config.feishu_app_id.clone().unwrap_or_default(),
+            limiter: shared_limiter(&ImPlatform::Feishu, &app_id),
+            app_id,
             app_secret: config.feishu_app_secret.clone().unwrap_or_default(),
             client,
             token_cache: Arc::new(RwLock::new(None)),
@@ -157,68 +146,8 @@ impl FeishuAdapter {
                 .group_activation
                 .clone()
                 .unwrap_or_else(|| "mention".to_string()),
-            dedup_cache: Arc::new(Mutex::new(dedup_cache)),
-            dedup_persist_path: dedup_path,
-            dedup_last_persist_ms: AtomicU64::new(0),
-        }
-    }
-
-    fn load_dedup_cache(path: Option<&std::path::Path>) -> HashMap<String, u64> {
-        let path = match path {
-            Some(p) if p.exists() => p,
-            _ => return HashMap::new(),
-        };
-        match std::fs::read_to_string(path) {
-            Ok(content) => match serde_json::from_str::<HashMap<String, u64>>(&content) {
-                Ok(mut cache) => {
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs();
-                    cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
-                    cache
-                }
-                Err(_) => HashMap::new(),
-            },
-            Err(_) => HashMap::new(),
-        }
-    }
-
-    async fn maybe_persist_dedup(&self) {
-        let Some(path) = &self.dedup_persist_path else {
-            return;
-        };
-        let now_ms = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as u64;
-        let last = self.dedup_last_persist_ms.load(Ordering::Relaxed);
-        if now_ms.saturating_sub(last) < DEDUP_PERSIST_INTERVAL_MS {
-            return;
-        }
-        self.dedup_last_persist_ms.store(now_ms, Ordering::Relaxed);
-        let snapshot = self.dedup_cache.lock().await.clone();
-        let path = path.clone();
-        tokio::task::spawn_blocking(move || save_dedup_cache_to_disk(&path, &snapshot));
-    }
-
-    /// Returns true if this is a NEW message (not a duplicate).
-    async fn dedup_check(&self, msg_id: &str) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        let mut cache = self.dedup_cache.lock().await;
-        if cache.len() > DEDUP_MAX_SIZE {
-            cache.retain(|_, ts| now.saturating_sub(*ts) < DEDUP_TTL_SECS);
-        }
-        if cache.contains_key(msg_id) {
-            return false;
+            dedup: DedupCache::load(dedup_path),
         }
-        cache.insert(msg_id.to_string(), now);
-        drop(cache);
-        self.maybe_persist_dedup().await;
-        true
     }
 
     // ── Token management ─────────────────────────────────────────────────────
@@ -294,10 +223,27 @@ impl FeishuAdapter {
         method: &str,
         url: &str,
         body: Option<&Value>,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, SendError> {
+        let result = self.api_call_inner(method, url, body).await;
+        if let Err(e) = &result {
+            let kind = if e.retry_after.is_some() { "rate_limited" } else { "api_error" };
+            metrics::inc(
+                metrics::IM_PLATFORM_ERRORS,
+                &[("platform", "feishu".to_string()), ("kind", kind.to_string())],
+            );
+        }
+        result
+    }
+
+    async fn api_call_inner(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<Value, SendError> {
         let mut retries = 0;
         loop {
-            let token = self.get_token().await?;
+            let token = self.get_token().await.map_err(SendError::transient)?;
             let mut req = match method {
                 "GET" => self.client.get(url),
                 "PUT" => self.client.put(url),
@@ -312,8 +258,14 @@ impl FeishuAdapter {
             let resp = req
                 .send()
                 .await
-                .map_err(|e| format!("Feishu API error: {}", e))?;
+                .map_err(|e| SendError::transient(format!("Feishu API error: {}", e)))?;
             let status = resp.status();
+            // Seconds until the rate-limit window resets (sent with 429 responses)
+            let reset_secs = resp
+                .headers()
+                .get("x-ogw-ratelimit-reset")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
             let text = resp.text().await.unwrap_or_default();
 
             if status.as_u16() == 401 && retries == 0 {
@@ -323,14 +275,29 @@ impl FeishuAdapter {
                 continue;
             }
 
-            let json: Value = serde_json::from_str(&text)
-                .map_err(|e| format!("API response parse error: {}", e))?;
+            let json: Value = serde_json::from_str(&text).map_err(|e| {
+                SendError::from_status(
+                    status.as_u16(),
+                    format!("API response parse error: {}", e),
+                )
+            })?;
 
             let code = json["code"].as_i64().unwrap_or(-1);
             if code == 0 {
                 return Ok(json);
             }
 
+            // 99991400: app-level frequency limit
+            if status.as_u16() == 429 || code == 99991400 {
+                let retry_after = reset_secs.unwrap_or(1).max(1);
+                ulog_warn!("[feishu] Rate limited, retry after {}s", retry_after);
+                self.limiter.note_retry_after(None, retry_after);
+                return Err(SendError::rate_limited(
+                    format!("Feishu API rate limited, retry after {}s", retry_after),
+                    retry_after,
+                ));
+            }
+
             if (code == 99991663 || code == 99991661) && retries == 0 {
                 ulog_warn!("[feishu] Token invalid (code {}), refreshing", code);
                 *self.token_cache.write().await = None;
@@ -338,10 +305,13 @@ impl FeishuAdapter {
                 continue;
             }
 
-            return Err(format!(
-                "Feishu API code {}: {}",
-                code,
-                json["msg"].as_str().unwrap_or("unknown")
+            return Err(SendError::from_status(
+                status.as_u16(),
+                format!(
+                    "Feishu API code {}: {}",
+                    code,
+                    json["msg"].as_str().unwrap_or("unknown")
+                ),
             ));
         }
     }
@@ -370,21 +340,21 @@ impl FeishuAdapter {
     ) -> Result<Option<String>, String> {
         // Split long messages
         if text.len() > MAX_MESSAGE_LENGTH {
-            let chunks = super::adapter::split_message(text, MAX_MESSAGE_LENGTH);
+            let chunks = split_message(text, MAX_MESSAGE_LENGTH, LengthUnit::Bytes);
             let mut last_id = None;
             for chunk in &chunks {
                 last_id = self.send_single_text(chat_id, chunk).await?;
             }
             return Ok(last_id);
         }
-        self.send_single_text(chat_id, text).await
+        Ok(self.send_single_text(chat_id, text).await?)
     }
 
     async fn send_single_text(
         &self,
         chat_id: &str,
         text: &str,
-    ) -> Result<Option<String>, String> {
+    ) -> Result<Option<String>, SendError> {
         let url = format!(
             "{}/im/v1/messages?receive_id_type=chat_id",
             FEISHU_API_BASE
@@ -396,6 +366,7 @@ impl FeishuAdapter {
             "msg_type": "text",
             "content": content,
         });
+        self.limiter.acquire(chat_id).await;
         let resp = self.api_call("POST", &url, Some(&body)).await?;
         Ok(resp["data"]["message_id"].as_str().map(String::from))
     }
@@ -410,6 +381,62 @@ impl FeishuAdapter {
         Ok(())
     }
 
+    /// Upload a file (im/v1/files) and post it to the chat as a file message.
+    async fn send_file_message(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+    ) -> Result<(), String> {
+        let token = self.get_token().await?;
+        let (content_type, body) = MultipartForm::new()
+            .text("file_type", "stream")
+            .text("file_name", &sanitize_filename(filename))
+            .file("file", filename, ext_to_mime(filename), &data)
+            .finish();
+        let resp = self
+            .client
+            .post(format!("{}/im/v1/files", FEISHU_API_BASE))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("Feishu file upload error: {}", e))?;
+        let json: Value = resp
+            .json()
+            .await
+            .map_err(|e| format!("API response parse error: {}", e))?;
+        let file_key = match json["data"]["file_key"].as_str() {
+            Some(key) if json["code"].as_i64() == Some(0) => key.to_string(),
+            _ => {
+                metrics::inc(
+                    metrics::IM_PLATFORM_ERRORS,
+                    &[("platform", "feishu".to_string()), ("kind", "api_error".to_string())],
+                );
+                return Err(format!(
+                    "Feishu file upload failed: {}",
+                    json["msg"].as_str().unwrap_or("unknown")
+                ));
+            }
+        };
+
+        let url = format!(
+            "{}/im/v1/messages?receive_id_type=chat_id",
+            FEISHU_API_BASE
+        );
+        let content =
+            serde_json::to_string(&json!({ "file_key": file_key })).unwrap_or_default();
+        let body = json!({
+            "receive_id": chat_id,
+            "msg_type": "file",
+            "content": content,
+        });
+        self.limiter.acquire(chat_id).await;
+        self.api_call("POST", &url, Some(&body)).await?;
+        Ok(())
+    }
+
     /// Delete a message.
     async fn delete_text_message(&self, message_id: &str) -> Result<(), String> {
         let url = format!("{}/im/v1/messages/{}", FEISHU_API_BASE, message_id);
@@ -456,12 +483,15 @@ impl FeishuAdapter {
 
     // ── Event parsing ─────────────────────────────────────────────────────────
 
-    /// Extract text and metadata from a Feishu im.message.receive_v1 event.
+    /// Extract text and metadata from a Feishu im.message.receive_v1 event
+    /// (or im.message.updated_v1, which carries the edited message).
     async fn parse_im_event(&self, event: &Value) -> Option<ImMessage> {
         let header = event.get("header")?;
-        if header["event_type"].as_str()? != "im.message.receive_v1" {
-            return None;
-        }
+        let kind = match header["event_type"].as_str()? {
+            "im.message.receive_v1" => ImMessageEvent::New,
+            "im.message.updated_v1" => ImMessageEvent::Edited,
+            _ => return None,
+        };
 
         let event_data = event.get("event")?;
         let message = event_data.get("message")?;
@@ -472,8 +502,12 @@ impl FeishuAdapter {
         let msg_type = message["message_type"].as_str()?;
         let chat_type = message["chat_type"].as_str().unwrap_or("p2p");
 
-        // Dedup
-        if !self.dedup_check(&message_id).await {
+        // Dedup (edits keep the message ID, so they are keyed by the event ID)
+        let dedup_key = match kind {
+            ImMessageEvent::New => message_id.clone(),
+            _ => format!("edit:{}", header["event_id"].as_str()?),
+        };
+        if !self.dedup.check(&dedup_key).await {
             return None;
         }
 
@@ -562,6 +596,7 @@ impl FeishuAdapter {
             timestamp: chrono::Utc::now(),
             is_mention,
             reply_to_bot: false,
+            event: kind,
         })
     }
 
@@ -813,10 +848,7 @@ impl FeishuAdapter {
         }
 
         // Flush dedup cache on shutdown
-        if let Some(path) = &self.dedup_persist_path {
-            let snapshot = self.dedup_cache.lock().await.clone();
-            save_dedup_cache_to_disk(path, &snapshot);
-        }
+        self.dedup.flush().await;
 
         ulog_info!("[feishu] WS listen loop exited");
     }
@@ -858,6 +890,20 @@ impl FeishuAdapter {
                 }
                 return;
             }
+            "im.message.recalled_v1" => {
+                if let Some(event_data) = event.get("event") {
+                    let chat_id = event_data["chat_id"].as_str().unwrap_or("");
+                    let message_id = event_data["message_id"].as_str().unwrap_or("");
+                    if !chat_id.is_empty() && !message_id.is_empty() {
+                        let recalled =
+                            ImMessage::recalled(ImPlatform::Feishu, chat_id, message_id);
+                        if let Err(e) = self.msg_tx.send(recalled).await {
+                            ulog_error!("[feishu] Failed to forward recall: {}", e);
+                        }
+                    }
+                }
+                return;
+            }
             _ => {}
         }
 
@@ -972,6 +1018,20 @@ impl ImAdapter for FeishuAdapter {
     async fn send_typing(&self, _chat_id: &str) -> AdapterResult<()> {
         Ok(())
     }
+
+    async fn send_file(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+        caption: Option<&str>,
+    ) -> AdapterResult<()> {
+        self.send_file_message(chat_id, filename, data).await?;
+        if let Some(caption) = caption {
+            self.send_text_message(chat_id, caption).await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -986,28 +1046,42 @@ impl ImStreamAdapter for FeishuAdapter {
 
     async fn edit_message(
         &self,
-        _chat_id: &str,
+        chat_id: &str,
         message_id: &str,
         text: &str,
     ) -> AdapterResult<()> {
+        self.limiter.acquire(chat_id).await;
         self.edit_text_message(message_id, text).await
     }
 
     async fn delete_message(
         &self,
-        _chat_id: &str,
+        chat_id: &str,
         message_id: &str,
     ) -> AdapterResult<()> {
+        self.limiter.acquire(chat_id).await;
         self.delete_text_message(message_id).await
     }
 
+    async fn send_part(&self, chat_id: &str, text: &str) -> Result<Option<String>, SendError> {
+        self.send_single_text(chat_id, text).await
+    }
+
     fn max_message_length(&self) -> usize {
         MAX_MESSAGE_LENGTH
     }
 
+    fn length_unit(&self) -> LengthUnit {
+        LengthUnit::Bytes
+    }
+
     fn preferred_throttle_ms(&self) -> u64 {
         1500
     }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        Some(&self.limiter)
+    }
 }
 
 // ── Verify credentials (for Tauri command) ────────────────────────────────────
@@ -7,12 +7,23 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{interval, Duration};
 
-use super::types::{ActiveSessionInfo, ImHealthState, ImStatus};
+use super::types::{
+    ActiveSessionInfo, ImHealthState, ImStatus, KnownChat, RestartRecord, TurnUsage,
+};
 use crate::{ulog_info, ulog_warn};
 
 /// Persist interval (seconds)
 const PERSIST_INTERVAL_SECS: u64 = 5;
 
+/// Restart records kept in the health state
+const MAX_RESTART_HISTORY: usize = 20;
+
+/// Known chats kept per channel (least recently active dropped first)
+const MAX_KNOWN_CHATS: usize = 200;
+
+/// Session keys with usage totals kept per channel (least recently active dropped first)
+const MAX_SESSION_USAGE: usize = 500;
+
 /// Managed health state with periodic persistence.
 /// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/state.json
 pub struct HealthManager {
@@ -65,6 +76,20 @@ impl HealthManager {
         self.state.lock().await.restart_count += 1;
     }
 
+    /// Record a supervised listen loop restart (bumps the restart count)
+    pub async fn record_restart(&self, record: RestartRecord) {
+        self.increment_restart_count().await;
+        let mut state = self.state.lock().await;
+        state.restart_history.push(record);
+        let excess = state.restart_history.len().saturating_sub(MAX_RESTART_HISTORY);
+        state.restart_history.drain(..excess);
+    }
+
+    /// Clear the error message (channel recovered)
+    pub async fn clear_error(&self) {
+        self.state.lock().await.error_message = None;
+    }
+
     /// Update uptime
     pub async fn set_uptime(&self, seconds: u64) {
         self.state.lock().await.uptime_seconds = seconds;
@@ -80,6 +105,52 @@ impl HealthManager {
         self.state.lock().await.active_sessions = sessions;
     }
 
+    /// Record (or refresh) a chat that messaged the channel
+    pub async fn record_chat(&self, chat: KnownChat) {
+        let mut state = self.state.lock().await;
+        let chats = &mut state.known_chats;
+        if let Some(existing) = chats.iter_mut().find(|c| c.chat_id == chat.chat_id) {
+            existing.chat_type = chat.chat_type;
+            existing.sender_id = chat.sender_id;
+            existing.last_active = chat.last_active;
+            if chat.display_name.is_some() {
+                existing.display_name = chat.display_name;
+            }
+            return;
+        }
+        chats.push(chat);
+        if chats.len() > MAX_KNOWN_CHATS {
+            if let Some(oldest) = chats
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.last_active.cmp(&b.1.last_active))
+                .map(|(i, _)| i)
+            {
+                chats.remove(oldest);
+            }
+        }
+    }
+
+    /// Add a finished turn's token usage to the channel and session key totals
+    pub async fn record_usage(&self, session_key: &str, usage: &TurnUsage, at: &str) {
+        let mut state = self.state.lock().await;
+        state.usage.add(usage, at);
+        let sessions = &mut state.session_usage;
+        sessions
+            .entry(session_key.to_string())
+            .or_default()
+            .add(usage, at);
+        if sessions.len() > MAX_SESSION_USAGE {
+            if let Some(oldest) = sessions
+                .iter()
+                .min_by(|a, b| a.1.last_turn_at.cmp(&b.1.last_turn_at))
+                .map(|(key, _)| key.clone())
+            {
+                sessions.remove(&oldest);
+            }
+        }
+    }
+
     /// Update buffered messages count
     pub async fn set_buffered_messages(&self, count: usize) {
         self.state.lock().await.buffered_messages = count;
@@ -166,6 +237,11 @@ fn soagents_dir() -> PathBuf {
         .join(".soagents")
 }
 
+/// ~/.soagents/agents/
+pub fn agents_data_dir() -> PathBuf {
+    soagents_dir().join("agents")
+}
+
 /// ~/.soagents/agents/{agentId}/channels/{channelId}/
 pub fn agent_channel_data_dir(agent_id: &str, channel_id: &str) -> PathBuf {
     debug_assert!(
@@ -184,8 +260,7 @@ pub fn agent_channel_data_dir(agent_id: &str, channel_id: &str) -> PathBuf {
         "[im-health] Invalid channel_id for path construction: {:?}",
         channel_id
     );
-    soagents_dir()
-        .join("agents")
+    agents_data_dir()
         .join(agent_id)
         .join("channels")
         .join(channel_id)
@@ -196,7 +271,17 @@ pub fn agent_channel_health_path(agent_id: &str, channel_id: &str) -> PathBuf {
     agent_channel_data_dir(agent_id, channel_id).join("state.json")
 }
 
-/// ~/.soagents/agents/{agentId}/channels/{channelId}/buffer.json
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/inbound.jsonl
+pub fn agent_channel_inbound_queue_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("inbound.jsonl")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/outbound.json
+pub fn agent_channel_outbound_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("outbound.json")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/buffer.json (legacy, imported into the inbound queue)
 pub fn agent_channel_buffer_path(agent_id: &str, channel_id: &str) -> PathBuf {
     agent_channel_data_dir(agent_id, channel_id).join("buffer.json")
 }
@@ -205,3 +290,48 @@ pub fn agent_channel_buffer_path(agent_id: &str, channel_id: &str) -> PathBuf {
 pub fn agent_channel_dedup_path(agent_id: &str, channel_id: &str) -> PathBuf {
     agent_channel_data_dir(agent_id, channel_id).join("dedup.json")
 }
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/telegram_offset.json
+pub fn agent_channel_telegram_offset_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("telegram_offset.json")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/messages.jsonl
+pub fn agent_channel_message_log_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("messages.jsonl")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/exports/
+pub fn agent_channel_exports_dir(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("exports")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/agent_routes.json
+pub fn agent_channel_agent_routes_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("agent_routes.json")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/usage.jsonl
+pub fn agent_channel_usage_log_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("usage.jsonl")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/chat_workspaces.json
+pub fn agent_channel_chat_workspaces_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("chat_workspaces.json")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/takeover.json
+pub fn agent_channel_takeover_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("takeover.json")
+}
+
+/// ~/.soagents/agents/{agentId}/channels/{channelId}/review_queue.json
+pub fn agent_channel_review_queue_path(agent_id: &str, channel_id: &str) -> PathBuf {
+    agent_channel_data_dir(agent_id, channel_id).join("review_queue.json")
+}
+
+/// ~/.soagents/im/identities.json (cross-platform identity links, shared by all agents)
+pub fn im_identities_path() -> PathBuf {
+    soagents_dir().join("im").join("identities.json")
+}
@@ -0,0 +1,565 @@
+// Cross-platform identity links — one person, several IM accounts.
+// Accounts are linked from the desktop (cmd_im_identity_link) or by the user with a
+// verification code: `/link` on one platform issues a code, `/link <code>` sent from
+// another account to the same agent within the TTL links the two. Codes are long random
+// strings, and a sender who keeps sending wrong codes is locked out for a while, so
+// codes can't be guessed.
+//
+// Private chats from linked accounts resolve to one session key per agent
+// (`im:{agentId}:person:{personId}`) and share its session id, so history and memory
+// follow the person across platforms. Each channel's SessionRouter still owns its own
+// reference to the shared Sidecar.
+// Persist path convention: ~/.soagents/im/identities.json
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::types::ImPlatform;
+use crate::ulog_info;
+
+/// Verification codes expire after this long
+const LINK_CODE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Characters of a verification code (no 0/O or 1/I look-alikes)
+const LINK_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+/// Length of a verification code (50 random bits)
+const LINK_CODE_LEN: usize = 10;
+/// Wrong codes a sender may send before being locked out
+const MAX_LINK_ATTEMPTS: u32 = 5;
+/// How long a sender stays locked out after too many wrong codes, and how long
+/// wrong codes count against a sender who isn't locked out
+const LINK_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// Marks a session key that belongs to a linked person rather than one chat
+const PERSON_KEY_MARKER: &str = ":person:";
+
+/// One IM account of a person
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedAccount {
+    pub platform: ImPlatform,
+    pub user_id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// RFC 3339 time the account was linked
+    pub linked_at: String,
+}
+
+/// A person and the accounts linked to them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImPerson {
+    pub id: String,
+    pub name: String,
+    pub accounts: Vec<LinkedAccount>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IdentityData {
+    #[serde(default)]
+    persons: Vec<ImPerson>,
+    /// Person session key → session id shared by all of the person's chats
+    #[serde(default)]
+    session_ids: HashMap<String, String>,
+}
+
+/// Code issued by `/link`, waiting to be confirmed from another account
+struct PendingLink {
+    /// Agent the code was issued by; only that agent accepts it
+    agent_id: String,
+    platform: ImPlatform,
+    user_id: String,
+    display_name: Option<String>,
+    /// Session id of the issuing chat, adopted as the shared conversation
+    session_seed: Option<String>,
+    issued: Instant,
+}
+
+/// Wrong codes sent by one account
+struct LinkAttempts {
+    failures: u32,
+    first_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl LinkAttempts {
+    /// When the entry stops counting: the end of the lockout, or `LINK_LOCKOUT`
+    /// after the first wrong code
+    fn expires(&self) -> Instant {
+        self.locked_until
+            .unwrap_or(self.first_failure + LINK_LOCKOUT)
+    }
+}
+
+pub struct IdentityStore {
+    data: Mutex<IdentityData>,
+    pending: Mutex<HashMap<String, PendingLink>>,
+    /// "{platform}:{userId}" → wrong codes sent
+    attempts: Mutex<HashMap<String, LinkAttempts>>,
+    persist_path: Option<PathBuf>,
+}
+
+impl IdentityStore {
+    /// Load links from `persist_path` (None = in-memory only)
+    pub fn load(persist_path: Option<PathBuf>) -> Self {
+        let data = persist_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<IdentityData>(&s).ok())
+            .unwrap_or_default();
+        Self {
+            data: Mutex::new(data),
+            pending: Mutex::new(HashMap::new()),
+            attempts: Mutex::new(HashMap::new()),
+            persist_path,
+        }
+    }
+
+    pub fn persons(&self) -> Vec<ImPerson> {
+        self.lock_data().persons.clone()
+    }
+
+    /// Person the account is linked to, if any
+    pub fn person_of(&self, platform: &ImPlatform, user_id: &str) -> Option<String> {
+        find_person(&self.lock_data().persons, platform, user_id).map(|p| p.id.clone())
+    }
+
+    /// Link an account to `person_id`, or to a new person called `name` when None.
+    /// An account belongs to one person; linking it elsewhere moves it.
+    pub fn link(
+        &self,
+        person_id: Option<&str>,
+        name: Option<&str>,
+        platform: ImPlatform,
+        user_id: &str,
+        display_name: Option<String>,
+    ) -> Result<ImPerson, String> {
+        let mut data = self.lock_data();
+        let target = match person_id {
+            Some(id) => {
+                if !data.persons.iter().any(|p| p.id == id) {
+                    return Err(format!("Unknown person: {}", id));
+                }
+                id.to_string()
+            }
+            None => {
+                let label = name
+                    .map(str::to_string)
+                    .or_else(|| display_name.clone())
+                    .unwrap_or_else(|| user_id.to_string());
+                new_person(&mut data, label)
+            }
+        };
+        let person = attach(&mut data, &target, platform, user_id, display_name);
+        self.persist(&data);
+        Ok(person)
+    }
+
+    /// Remove an account from its person. Returns false if it wasn't linked.
+    pub fn unlink(&self, platform: &ImPlatform, user_id: &str) -> bool {
+        let mut data = self.lock_data();
+        if !detach(&mut data, platform, user_id) {
+            return false;
+        }
+        ulog_info!("[im-identity] Unlinked {}:{}", platform, user_id);
+        self.persist(&data);
+        true
+    }
+
+    /// Issue a verification code for the account (`/link`), valid for `agent_id` only.
+    /// A previous code of the same account is replaced.
+    pub fn start_link(
+        &self,
+        agent_id: &str,
+        platform: ImPlatform,
+        user_id: &str,
+        display_name: Option<String>,
+        session_seed: Option<String>,
+    ) -> String {
+        let mut pending = self.lock_pending();
+        pending.retain(|_, p| {
+            p.issued.elapsed() < LINK_CODE_TTL && !(p.platform == platform && p.user_id == user_id)
+        });
+        let code = loop {
+            let code = new_link_code();
+            if !pending.contains_key(&code) {
+                break code;
+            }
+        };
+        pending.insert(
+            code.clone(),
+            PendingLink {
+                agent_id: agent_id.to_string(),
+                platform,
+                user_id: user_id.to_string(),
+                display_name,
+                session_seed,
+                issued: Instant::now(),
+            },
+        );
+        code
+    }
+
+    /// Confirm a code from another account (`/link <code>`) sent to `agent_id`, linking
+    /// both accounts to one person. The issuer's person wins when both are already linked.
+    /// Wrong codes count against the sender, who is locked out after `MAX_LINK_ATTEMPTS`.
+    pub fn complete_link(
+        &self,
+        agent_id: &str,
+        code: &str,
+        platform: ImPlatform,
+        user_id: &str,
+        display_name: Option<String>,
+    ) -> Result<ImPerson, String> {
+        let sender = format!("{}:{}", platform, user_id);
+        let mut attempts = self.lock_attempts();
+        let now = Instant::now();
+        prune_attempts(&mut attempts, now);
+        if attempts
+            .get(&sender)
+            .is_some_and(|a| a.locked_until.is_some())
+        {
+            return Err("Too many wrong codes, try again later".to_string());
+        }
+
+        let code = code.trim().to_ascii_uppercase();
+        let issuer = {
+            let mut pending = self.lock_pending();
+            pending.retain(|_, p| p.issued.elapsed() < LINK_CODE_TTL);
+            // Codes of other agents are as unknown here as wrong ones
+            match pending.get(&code).filter(|p| p.agent_id == agent_id) {
+                Some(p) if p.platform == platform && p.user_id == user_id => {
+                    return Err("Send the code from your other account".to_string());
+                }
+                Some(_) => pending.remove(&code).unwrap(),
+                None => {
+                    let entry = attempts.entry(sender).or_insert(LinkAttempts {
+                        failures: 0,
+                        first_failure: now,
+                        locked_until: None,
+                    });
+                    entry.failures += 1;
+                    if entry.failures >= MAX_LINK_ATTEMPTS {
+                        entry.locked_until = Some(now + LINK_LOCKOUT);
+                        ulog_info!(
+                            "[im-identity] {}:{} locked out of /link after {} wrong codes",
+                            platform,
+                            user_id,
+                            entry.failures
+                        );
+                    }
+                    return Err("Invalid or expired code".to_string());
+                }
+            }
+        };
+        attempts.remove(&sender);
+        drop(attempts);
+
+        let mut data = self.lock_data();
+        let target = find_person(&data.persons, &issuer.platform, &issuer.user_id)
+            .or_else(|| find_person(&data.persons, &platform, user_id))
+            .map(|p| p.id.clone());
+        let target = match target {
+            Some(id) => id,
+            None => {
+                let label = issuer
+                    .display_name
+                    .clone()
+                    .unwrap_or_else(|| issuer.user_id.clone());
+                new_person(&mut data, label)
+            }
+        };
+        attach(
+            &mut data,
+            &target,
+            issuer.platform,
+            &issuer.user_id,
+            issuer.display_name,
+        );
+        let person = attach(&mut data, &target, platform, user_id, display_name);
+
+        // First link: the conversation the code was issued from carries on
+        if let Some(session_id) = issuer.session_seed {
+            data.session_ids
+                .entry(person_session_key(&issuer.agent_id, &person.id))
+                .or_insert(session_id);
+        }
+        self.persist(&data);
+        Ok(person)
+    }
+
+    /// Session id shared by a person session key (exact key: each route suffix
+    /// has its own)
+    pub fn shared_session_id(&self, session_key: &str) -> Option<String> {
+        self.lock_data().session_ids.get(session_key).cloned()
+    }
+
+    /// Record the session id of a person session key (new conversation, `/new`)
+    pub fn set_shared_session_id(&self, session_key: &str, session_id: &str) {
+        let mut data = self.lock_data();
+        if data.session_ids.get(session_key).map(String::as_str) == Some(session_id) {
+            return;
+        }
+        data.session_ids
+            .insert(session_key.to_string(), session_id.to_string());
+        self.persist(&data);
+    }
+
+    fn lock_data(&self) -> std::sync::MutexGuard<'_, IdentityData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, HashMap<String, PendingLink>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_attempts(&self) -> std::sync::MutexGuard<'_, HashMap<String, LinkAttempts>> {
+        self.attempts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, data: &IdentityData) {
+        if let Some(path) = &self.persist_path {
+            save_data(path, data);
+        }
+    }
+}
+
+/// Forget wrong codes that no longer count, so the map doesn't grow with every sender
+fn prune_attempts(attempts: &mut HashMap<String, LinkAttempts>, now: Instant) {
+    attempts.retain(|_, a| a.expires() > now);
+}
+
+/// Random verification code of `LINK_CODE_LEN` characters from `LINK_CODE_ALPHABET`
+fn new_link_code() -> String {
+    let random = uuid::Uuid::new_v4().as_u128();
+    (0..LINK_CODE_LEN)
+        .map(|i| {
+            let idx = (random >> (i * 5)) as usize % LINK_CODE_ALPHABET.len();
+            LINK_CODE_ALPHABET[idx] as char
+        })
+        .collect()
+}
+
+fn find_person<'a>(
+    persons: &'a [ImPerson],
+    platform: &ImPlatform,
+    user_id: &str,
+) -> Option<&'a ImPerson> {
+    persons.iter().find(|p| {
+        p.accounts
+            .iter()
+            .any(|a| &a.platform == platform && a.user_id == user_id)
+    })
+}
+
+fn new_person(data: &mut IdentityData, name: String) -> String {
+    let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+    data.persons.push(ImPerson {
+        id: id.clone(),
+        name,
+        accounts: Vec::new(),
+    });
+    id
+}
+
+/// Move the account to `person_id` (which must exist) and return the updated person
+fn attach(
+    data: &mut IdentityData,
+    person_id: &str,
+    platform: ImPlatform,
+    user_id: &str,
+    display_name: Option<String>,
+) -> ImPerson {
+    let already = find_person(&data.persons, &platform, user_id).is_some_and(|p| p.id == person_id);
+    if !already {
+        detach(data, &platform, user_id);
+        ulog_info!("[im-identity] Linked {}:{} to person {}", platform, user_id, person_id);
+    }
+    let person = data
+        .persons
+        .iter_mut()
+        .find(|p| p.id == person_id)
+        .expect("attach target exists");
+    if !already {
+        person.accounts.push(LinkedAccount {
+            platform,
+            user_id: user_id.to_string(),
+            display_name,
+            linked_at: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+    person.clone()
+}
+
+/// Remove the account from its person; persons left without accounts are dropped
+fn detach(data: &mut IdentityData, platform: &ImPlatform, user_id: &str) -> bool {
+    let mut removed = false;
+    let mut emptied: Vec<String> = Vec::new();
+    for person in data.persons.iter_mut() {
+        let before = person.accounts.len();
+        person
+            .accounts
+            .retain(|a| !(&a.platform == platform && a.user_id == user_id));
+        if person.accounts.len() != before {
+            removed = true;
+            if person.accounts.is_empty() {
+                emptied.push(person.id.clone());
+            }
+        }
+    }
+    data.persons.retain(|p| !emptied.contains(&p.id));
+    data.session_ids.retain(|key, _| {
+        !emptied
+            .iter()
+            .any(|id| person_of_session_key(key) == Some(id.as_str()))
+    });
+    removed
+}
+
+fn save_data(path: &Path, data: &IdentityData) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    if let Ok(s) = serde_json::to_string_pretty(data) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+/// Session key shared by all private chats of a linked person
+pub fn person_session_key(agent_id: &str, person_id: &str) -> String {
+    format!("im:{}{}{}", agent_id, PERSON_KEY_MARKER, person_id)
+}
+
+/// Whether a session key (route suffix allowed) belongs to a linked person
+pub fn is_person_key(session_key: &str) -> bool {
+    session_key.contains(PERSON_KEY_MARKER)
+}
+
+/// Person id of a person session key (route suffix ignored)
+fn person_of_session_key(session_key: &str) -> Option<&str> {
+    let (_, rest) = session_key.split_once(PERSON_KEY_MARKER)?;
+    Some(rest.split('#').next().unwrap_or(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_links_accounts_and_seeds_session() {
+        let store = IdentityStore::load(None);
+        let code = store.start_link(
+            "a",
+            ImPlatform::Telegram,
+            "111",
+            Some("Alice".to_string()),
+            Some("sess-1".to_string()),
+        );
+        assert_eq!(code.len(), LINK_CODE_LEN);
+        assert!(store
+            .complete_link("a", &code, ImPlatform::Telegram, "111", None)
+            .is_err());
+        // Scoped to the issuing agent
+        assert!(store
+            .complete_link("b", &code, ImPlatform::Feishu, "ou_abc", None)
+            .is_err());
+
+        // Codes are case-insensitive
+        let typed = code.to_lowercase();
+        let person = store
+            .complete_link("a", &typed, ImPlatform::Feishu, "ou_abc", None)
+            .unwrap();
+        assert_eq!(person.name, "Alice");
+        assert_eq!(person.accounts.len(), 2);
+        assert_eq!(
+            store.person_of(&ImPlatform::Telegram, "111"),
+            store.person_of(&ImPlatform::Feishu, "ou_abc")
+        );
+        let key = person_session_key("a", &person.id);
+        assert!(is_person_key(&key));
+        assert_eq!(store.shared_session_id(&key).as_deref(), Some("sess-1"));
+
+        // Codes are single use
+        assert!(store
+            .complete_link("a", &code, ImPlatform::Dingtalk, "d1", None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_wrong_codes_lock_out_sender() {
+        let store = IdentityStore::load(None);
+        let code = store.start_link("a", ImPlatform::Telegram, "111", None, None);
+        for _ in 0..MAX_LINK_ATTEMPTS {
+            assert!(store
+                .complete_link("a", "WRONGCODE2", ImPlatform::Feishu, "ou_x", None)
+                .is_err());
+        }
+        // Even the right code is refused while locked out
+        let locked = store.complete_link("a", &code, ImPlatform::Feishu, "ou_x", None);
+        assert!(locked.unwrap_err().contains("Too many"));
+        // Other senders are unaffected
+        assert!(store
+            .complete_link("a", &code, ImPlatform::Dingtalk, "d1", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_wrong_codes_expire() {
+        let now = Instant::now();
+        let mut attempts = HashMap::new();
+        attempts.insert(
+            "telegram:1".to_string(),
+            LinkAttempts {
+                failures: 2,
+                first_failure: now,
+                locked_until: None,
+            },
+        );
+        attempts.insert(
+            "telegram:2".to_string(),
+            LinkAttempts {
+                failures: MAX_LINK_ATTEMPTS,
+                first_failure: now,
+                locked_until: Some(now + LINK_LOCKOUT * 2),
+            },
+        );
+        // Wrong codes of a sender who isn't locked out stop counting after LINK_LOCKOUT
+        prune_attempts(&mut attempts, now + LINK_LOCKOUT);
+        assert_eq!(attempts.len(), 1);
+        assert!(attempts.contains_key("telegram:2"));
+        // A lockout is dropped once it ends
+        prune_attempts(&mut attempts, now + LINK_LOCKOUT * 2);
+        assert!(attempts.is_empty());
+    }
+
+    #[test]
+    fn test_unlink_drops_empty_person() {
+        let store = IdentityStore::load(None);
+        let person = store
+            .link(None, Some("Bob"), ImPlatform::Telegram, "222", None)
+            .unwrap();
+        store
+            .link(Some(&person.id), None, ImPlatform::Feishu, "ou_b", None)
+            .unwrap();
+        store.set_shared_session_id(&person_session_key("a", &person.id), "s");
+
+        assert!(store.unlink(&ImPlatform::Telegram, "222"));
+        assert!(!store.unlink(&ImPlatform::Telegram, "222"));
+        assert_eq!(store.persons().len(), 1);
+        assert!(store.unlink(&ImPlatform::Feishu, "ou_b"));
+        assert!(store.persons().is_empty());
+        assert_eq!(
+            store.shared_session_id(&person_session_key("a", &person.id)),
+            None
+        );
+        assert!(store
+            .link(Some(&person.id), None, ImPlatform::Feishu, "ou_b", None)
+            .is_err());
+    }
+}
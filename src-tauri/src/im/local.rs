@@ -0,0 +1,639 @@
+// Local loopback adapter
+// Exposes a channel as a WebSocket endpoint on 127.0.0.1 so the desktop, a script or an
+// integration test can act as "a chat" without a real IM platform. Messages go through the
+// same processing loop, streaming edits, queueing and command pipeline as Telegram/Feishu.
+//
+// Handshake: clients authenticate with the channel's `botToken` as
+// `Authorization: Bearer <token>`. Handshakes carrying an `Origin` header come from a
+// browser and are refused, so web pages can't reach the channel. A client holding the
+// token is trusted like the platform itself (it picks `senderId` freely).
+//
+// Protocol (JSON text frames):
+//   client → bot  {"type":"message","chatId":"c1","text":"hi","senderId":"u1",
+//                  "senderName":"Alice","group":false,"mention":true,"messageId":"m1"}
+//                 (everything except text is optional; chatId defaults to senderId)
+//                 {"type":"edit", ...same fields, "messageId" required}
+//                 {"type":"recall","chatId","messageId","group"}
+//   bot → client  {"type":"message"|"edit","chatId","messageId","text"}
+//                 {"type":"delete","chatId","messageId"}
+//                 {"type":"typing","chatId"}
+//                 {"type":"reaction","chatId","messageId","state":"received"|"processing"|"clear"}
+//                 {"type":"file","chatId","filename","size","caption"} + one binary frame
+// Outbound frames are broadcast to every connected client; with no client connected a
+// send fails, so final replies wait in the outbound queue like on a real platform.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::adapter::{split_message, ImAdapter, ImStreamAdapter, LengthUnit};
+use super::dedup::DedupCache;
+use super::types::{
+    AdapterResult, GroupPermission, GroupPermissionStatus, ImConfig, ImMessage, ImMessageEvent,
+    ImPlatform, ImSourceType,
+};
+use crate::{ulog_info, ulog_warn};
+
+// ── Constants ──
+
+/// Same limit as Telegram, so long replies exercise splitting
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+// ── Wire format ──
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InboundFrame {
+    #[serde(rename = "type", default = "default_frame_type")]
+    frame_type: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    chat_id: Option<String>,
+    #[serde(default)]
+    message_id: Option<String>,
+    #[serde(default)]
+    sender_id: Option<String>,
+    #[serde(default)]
+    sender_name: Option<String>,
+    #[serde(default)]
+    group: bool,
+    #[serde(default)]
+    mention: Option<bool>,
+    #[serde(default)]
+    reply_to_bot: bool,
+}
+
+fn default_frame_type() -> String {
+    "message".to_string()
+}
+
+// ── Adapter ──
+
+pub struct LocalAdapter {
+    /// Requested port (0 = any free port)
+    port: u16,
+    /// Bound by verify_connection, taken by the first listen_loop run
+    listener: Mutex<Option<TcpListener>>,
+    local_addr: RwLock<Option<SocketAddr>>,
+    shared: Arc<LocalShared>,
+}
+
+/// State shared with the per-connection tasks
+struct LocalShared {
+    /// Bearer token clients must present (the channel's `botToken`)
+    token: String,
+    msg_tx: mpsc::Sender<ImMessage>,
+    allowed_users: Arc<RwLock<Vec<String>>>,
+    /// "mention" or "always"
+    group_activation: String,
+    group_permissions: Arc<RwLock<Vec<GroupPermission>>>,
+    known_groups: Mutex<HashSet<String>>,
+    /// Connected clients: connection id → outbound frame sender
+    clients: Mutex<HashMap<u64, mpsc::UnboundedSender<Message>>>,
+    next_client_id: AtomicU64,
+    next_message_id: AtomicU64,
+    dedup: DedupCache,
+}
+
+impl LocalAdapter {
+    pub fn new(
+        config: &ImConfig,
+        msg_tx: mpsc::Sender<ImMessage>,
+        allowed_users: Arc<RwLock<Vec<String>>>,
+        group_permissions: Arc<RwLock<Vec<GroupPermission>>>,
+        dedup_path: Option<PathBuf>,
+    ) -> Self {
+        let known_groups = config
+            .group_permissions
+            .iter()
+            .map(|gp| gp.group_id.clone())
+            .collect();
+        let shared = LocalShared {
+            token: config.bot_token.trim().to_string(),
+            msg_tx,
+            allowed_users,
+            group_activation: config
+                .group_activation
+                .clone()
+                .unwrap_or_else(|| "mention".to_string()),
+            group_permissions,
+            known_groups: Mutex::new(known_groups),
+            clients: Mutex::new(HashMap::new()),
+            next_client_id: AtomicU64::new(1),
+            next_message_id: AtomicU64::new(1),
+            dedup: DedupCache::load(dedup_path),
+        };
+        Self {
+            port: config.local_port.unwrap_or(0),
+            listener: Mutex::new(None),
+            local_addr: RwLock::new(None),
+            shared: Arc::new(shared),
+        }
+    }
+
+    async fn bind(&self) -> AdapterResult<SocketAddr> {
+        if self.shared.token.is_empty() {
+            return Err("Local channel needs a botToken for clients to authenticate".to_string());
+        }
+        // Reuse the port picked on first bind so restarts keep the same address
+        let port = match *self.local_addr.read().await {
+            Some(addr) => addr.port(),
+            None => self.port,
+        };
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| format!("Failed to bind 127.0.0.1:{}: {}", port, e))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to read local address: {}", e))?;
+        *self.listener.lock().await = Some(listener);
+        *self.local_addr.write().await = Some(addr);
+        Ok(addr)
+    }
+}
+
+impl LocalShared {
+    fn new_message_id(&self) -> String {
+        format!(
+            "local-{}",
+            self.next_message_id.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Send a frame to every connected client. Fails when nobody is listening.
+    async fn broadcast(&self, frames: Vec<Message>) -> AdapterResult<()> {
+        let mut clients = self.clients.lock().await;
+        clients.retain(|_, tx| frames.iter().all(|f| tx.send(f.clone()).is_ok()));
+        if clients.is_empty() {
+            return Err("No local client connected".to_string());
+        }
+        Ok(())
+    }
+
+    async fn broadcast_json(&self, frame: Value) -> AdapterResult<()> {
+        self.broadcast(vec![Message::Text(frame.to_string())]).await
+    }
+
+    async fn serve_connection(&self, stream: TcpStream, peer: SocketAddr) {
+        let check = |request: &Request, response: Response| {
+            check_handshake(request, &self.token)
+                .map(|()| response)
+                .map_err(|(status, reason)| {
+                    let mut error = ErrorResponse::new(Some(reason.to_string()));
+                    *error.status_mut() = status;
+                    error
+                })
+        };
+        let ws = match tokio_tungstenite::accept_hdr_async(stream, check).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                ulog_warn!("[local] WebSocket handshake with {} failed: {}", peer, e);
+                return;
+            }
+        };
+        let (mut sink, mut stream) = ws.split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+        let client_id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        self.clients.lock().await.insert(client_id, tx);
+        ulog_info!("[local] Client {} connected from {}", client_id, peer);
+
+        loop {
+            tokio::select! {
+                outbound = rx.recv() => match outbound {
+                    Some(frame) => {
+                        if sink.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                inbound = stream.next() => match inbound {
+                    Some(Ok(Message::Text(text))) => self.handle_text_frame(&text).await,
+                    Some(Ok(Message::Ping(data))) => {
+                        let _ = sink.send(Message::Pong(data)).await;
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        self.clients.lock().await.remove(&client_id);
+        ulog_info!("[local] Client {} disconnected", client_id);
+    }
+
+    async fn handle_text_frame(&self, text: &str) {
+        let frame: InboundFrame = match serde_json::from_str(text) {
+            Ok(f) => f,
+            Err(e) => {
+                ulog_warn!("[local] Ignoring malformed frame: {}", e);
+                return;
+            }
+        };
+        let kind = match frame.frame_type.as_str() {
+            "message" => ImMessageEvent::New,
+            "edit" => ImMessageEvent::Edited,
+            "recall" => ImMessageEvent::Recalled,
+            _ => return,
+        };
+        if kind != ImMessageEvent::Recalled && frame.text.trim().is_empty() {
+            return;
+        }
+
+        // Edits and recalls refer to an earlier message, so they need its ID
+        let message_id = match (kind, frame.message_id) {
+            (_, Some(id)) => id,
+            (ImMessageEvent::New, None) => self.new_message_id(),
+            (_, None) => {
+                ulog_warn!("[local] Ignoring {} frame without messageId", frame.frame_type);
+                return;
+            }
+        };
+        if kind == ImMessageEvent::New && !self.dedup.check(&message_id).await {
+            return;
+        }
+
+        let sender_id = frame.sender_id.unwrap_or_else(|| "local-user".to_string());
+        let (source_type, chat_id, is_mention) = if frame.group {
+            let raw = frame.chat_id.unwrap_or_else(|| "local".to_string());
+            let chat_id = format!("group:{}", raw.trim_start_matches("group:"));
+            if self.known_groups.lock().await.insert(chat_id.clone()) {
+                self.register_new_group(&chat_id).await;
+            }
+            (ImSourceType::Group, chat_id, frame.mention.unwrap_or(false))
+        } else {
+            let chat_id = frame.chat_id.unwrap_or_else(|| sender_id.clone());
+            (ImSourceType::Private, chat_id, true)
+        };
+
+        if source_type == ImSourceType::Group
+            && kind != ImMessageEvent::Recalled
+            && self.group_activation != "always"
+            && !is_mention
+            && !frame.reply_to_bot
+        {
+            return;
+        }
+
+        if source_type == ImSourceType::Private {
+            let allowed = self.allowed_users.read().await;
+            if !allowed.is_empty() && !allowed.iter().any(|u| u == &sender_id) {
+                ulog_info!("[local] Message from {} blocked by allowlist", sender_id);
+                return;
+            }
+        }
+
+        let msg = if kind == ImMessageEvent::Recalled {
+            ImMessage::recalled(ImPlatform::Local, &chat_id, &message_id)
+        } else {
+            ImMessage {
+                chat_id,
+                message_id,
+                text: frame.text,
+                sender_id,
+                sender_name: frame.sender_name,
+                source_type,
+                platform: ImPlatform::Local,
+                timestamp: chrono::Utc::now(),
+                is_mention,
+                reply_to_bot: frame.reply_to_bot,
+                event: kind,
+            }
+        };
+        if let Err(e) = self.msg_tx.send(msg).await {
+            ulog_warn!("[local] Failed to forward message: {}", e);
+        }
+    }
+
+    async fn register_new_group(&self, chat_id: &str) {
+        ulog_info!("[local] New group discovered: {}", chat_id);
+        let mut perms = self.group_permissions.write().await;
+        if !perms.iter().any(|p| p.group_id == chat_id) {
+            perms.push(GroupPermission {
+                group_id: chat_id.to_string(),
+                group_name: chat_id.to_string(),
+                platform: ImPlatform::Local,
+                status: GroupPermissionStatus::Pending,
+                discovered_at: chrono::Utc::now().to_rfc3339(),
+            });
+        }
+    }
+
+    async fn send_reaction(&self, chat_id: &str, message_id: &str, state: &str) {
+        let _ = self
+            .broadcast_json(json!({
+                "type": "reaction",
+                "chatId": chat_id,
+                "messageId": message_id,
+                "state": state,
+            }))
+            .await;
+    }
+}
+
+/// Refuse browser handshakes (they carry `Origin`) and require the channel token
+fn check_handshake(request: &Request, token: &str) -> Result<(), (StatusCode, &'static str)> {
+    let headers = request.headers();
+    if headers.contains_key("origin") {
+        return Err((StatusCode::FORBIDDEN, "Browser connections are not allowed"));
+    }
+    let presented = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if presented != Some(token) {
+        return Err((StatusCode::UNAUTHORIZED, "Missing or wrong channel token"));
+    }
+    Ok(())
+}
+
+// ── Trait implementations ──
+
+#[async_trait]
+impl ImAdapter for LocalAdapter {
+    async fn verify_connection(&self) -> AdapterResult<String> {
+        let addr = self.bind().await?;
+        ulog_info!("[local] Listening on ws://{}", addr);
+        Ok(format!("local:{}", addr.port()))
+    }
+
+    async fn register_commands(&self) -> AdapterResult<()> {
+        Ok(())
+    }
+
+    async fn listen_loop(&self, mut shutdown_rx: watch::Receiver<bool>) -> AdapterResult<()> {
+        let listener = match self.listener.lock().await.take() {
+            Some(l) => l,
+            None => {
+                // Restarted by the supervisor — bind the same port again
+                self.bind().await?;
+                self.listener
+                    .lock()
+                    .await
+                    .take()
+                    .ok_or_else(|| "Listener missing after bind".to_string())?
+            }
+        };
+
+        // Connection tasks are aborted when the loop exits
+        let mut connections = JoinSet::new();
+        let result = loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        let shared = Arc::clone(&self.shared);
+                        connections.spawn(async move {
+                            shared.serve_connection(stream, peer).await
+                        });
+                    }
+                    Err(e) => break Err(format!("Accept failed: {}", e)),
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        break Ok(());
+                    }
+                }
+            }
+        };
+
+        connections.shutdown().await;
+        self.shared.clients.lock().await.clear();
+        self.shared.dedup.flush().await;
+        ulog_info!("[local] Listen loop exited");
+        result
+    }
+
+    async fn send_message(&self, chat_id: &str, text: &str) -> AdapterResult<()> {
+        for chunk in split_message(text, MAX_MESSAGE_LENGTH, LengthUnit::Chars) {
+            self.send_message_returning_id(chat_id, &chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn ack_received(&self, chat_id: &str, message_id: &str) -> AdapterResult<()> {
+        self.shared
+            .send_reaction(chat_id, message_id, "received")
+            .await;
+        Ok(())
+    }
+
+    async fn ack_processing(&self, chat_id: &str, message_id: &str) -> AdapterResult<()> {
+        self.shared
+            .send_reaction(chat_id, message_id, "processing")
+            .await;
+        Ok(())
+    }
+
+    async fn ack_clear(&self, chat_id: &str, message_id: &str) -> AdapterResult<()> {
+        self.shared
+            .send_reaction(chat_id, message_id, "clear")
+            .await;
+        Ok(())
+    }
+
+    async fn send_typing(&self, chat_id: &str) -> AdapterResult<()> {
+        let _ = self
+            .shared
+            .broadcast_json(json!({ "type": "typing", "chatId": chat_id }))
+            .await;
+        Ok(())
+    }
+
+    /// Mirrors Telegram so test clients see the same indicator cadence
+    fn typing_refresh_interval(&self) -> Option<std::time::Duration> {
+        Some(std::time::Duration::from_secs(4))
+    }
+
+    async fn send_file(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+        caption: Option<&str>,
+    ) -> AdapterResult<()> {
+        let header = json!({
+            "type": "file",
+            "chatId": chat_id,
+            "filename": filename,
+            "size": data.len(),
+            "caption": caption,
+        });
+        self.shared
+            .broadcast(vec![
+                Message::Text(header.to_string()),
+                Message::Binary(data),
+            ])
+            .await
+    }
+}
+
+#[async_trait]
+impl ImStreamAdapter for LocalAdapter {
+    async fn send_message_returning_id(
+        &self,
+        chat_id: &str,
+        text: &str,
+    ) -> AdapterResult<Option<String>> {
+        let message_id = self.shared.new_message_id();
+        self.shared
+            .broadcast_json(json!({
+                "type": "message",
+                "chatId": chat_id,
+                "messageId": message_id,
+                "text": text,
+            }))
+            .await?;
+        Ok(Some(message_id))
+    }
+
+    async fn edit_message(&self, chat_id: &str, message_id: &str, text: &str) -> AdapterResult<()> {
+        self.shared
+            .broadcast_json(json!({
+                "type": "edit",
+                "chatId": chat_id,
+                "messageId": message_id,
+                "text": text,
+            }))
+            .await
+    }
+
+    async fn delete_message(&self, chat_id: &str, message_id: &str) -> AdapterResult<()> {
+        self.shared
+            .broadcast_json(json!({
+                "type": "delete",
+                "chatId": chat_id,
+                "messageId": message_id,
+            }))
+            .await
+    }
+
+    fn max_message_length(&self) -> usize {
+        MAX_MESSAGE_LENGTH
+    }
+
+    fn preferred_throttle_ms(&self) -> u64 {
+        300
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    fn test_config() -> ImConfig {
+        serde_json::from_value(json!({
+            "agentId": "a1",
+            "channelId": "c1",
+            "platform": "local",
+            "workspacePath": "/tmp",
+            "botToken": "secret",
+            "allowedUsers": ["u1"],
+            "permissionMode": "plan",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trip_over_websocket() {
+        let (msg_tx, mut msg_rx) = mpsc::channel(8);
+        let config = test_config();
+        let adapter = Arc::new(LocalAdapter::new(
+            &config,
+            msg_tx,
+            Arc::new(RwLock::new(config.allowed_users.clone())),
+            Arc::new(RwLock::new(Vec::new())),
+            None,
+        ));
+        let name = adapter.verify_connection().await.unwrap();
+        let port = name.strip_prefix("local:").unwrap();
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let listen = {
+            let adapter = Arc::clone(&adapter);
+            tokio::spawn(async move { adapter.listen_loop(shutdown_rx).await })
+        };
+
+        let url = format!("ws://127.0.0.1:{}", port);
+        let request = |token: &str, origin: Option<&str>| {
+            let mut request = url.as_str().into_client_request().unwrap();
+            let headers = request.headers_mut();
+            headers.insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            if let Some(origin) = origin {
+                headers.insert("origin", origin.parse().unwrap());
+            }
+            request
+        };
+
+        // Wrong token and browser handshakes are refused
+        let wrong_token = request("guess", None);
+        assert!(tokio_tungstenite::connect_async(wrong_token).await.is_err());
+        let from_page = request("secret", Some("https://example.com"));
+        assert!(tokio_tungstenite::connect_async(from_page).await.is_err());
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(request("secret", None))
+            .await
+            .unwrap();
+
+        // Blocked by the allowlist, then accepted
+        for sender in ["u2", "u1"] {
+            let frame = json!({ "text": format!("hi from {}", sender), "senderId": sender });
+            ws.send(Message::Text(frame.to_string())).await.unwrap();
+        }
+        let msg = msg_rx.recv().await.unwrap();
+        assert_eq!(msg.sender_id, "u1");
+        assert_eq!(msg.chat_id, "u1");
+        assert_eq!(msg.platform, ImPlatform::Local);
+
+        // Edits and recalls point at the original message
+        for frame in [
+            json!({ "type": "edit", "text": "hi again", "senderId": "u1", "messageId": "m1" }),
+            json!({ "type": "recall", "chatId": "u1", "senderId": "u1", "messageId": "m1" }),
+        ] {
+            ws.send(Message::Text(frame.to_string())).await.unwrap();
+        }
+        let edited = msg_rx.recv().await.unwrap();
+        assert_eq!(edited.event, ImMessageEvent::Edited);
+        assert_eq!((edited.message_id.as_str(), edited.text.as_str()), ("m1", "hi again"));
+        let recalled = msg_rx.recv().await.unwrap();
+        assert_eq!(recalled.event, ImMessageEvent::Recalled);
+        assert_eq!(recalled.chat_id, "u1");
+
+        let id = adapter
+            .send_message_returning_id("u1", "hello")
+            .await
+            .unwrap()
+            .unwrap();
+        adapter.edit_message("u1", &id, "hello!").await.unwrap();
+        for expected in ["message", "edit"] {
+            let Some(Ok(Message::Text(text))) = ws.next().await else {
+                panic!("expected a text frame");
+            };
+            let frame: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(frame["type"], expected);
+            assert_eq!(frame["messageId"], id.as_str());
+        }
+
+        shutdown_tx.send(true).unwrap();
+        listen.await.unwrap().unwrap();
+        assert!(adapter.send_message("u1", "late").await.is_err());
+    }
+}
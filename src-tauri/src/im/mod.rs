@@ -1,43 +1,134 @@
 // IM Bot integration module
 // Manages IM channel lifecycle, routing messages to AI Sidecars.
 
+pub mod activity;
 pub mod adapter;
-pub mod buffer;
+pub mod agent_routes;
 pub mod feishu;
 pub mod dingtalk;
+pub mod dedup;
 pub mod health;
+pub mod identity;
+pub mod inbound;
+pub mod local;
+pub mod outbound;
+pub mod proactive;
+pub mod rate_limit;
+pub mod review;
 pub mod router;
+pub mod scheduler;
+pub mod supervisor;
+pub mod takeover;
 pub mod telegram;
+pub mod transcript;
+pub mod turns;
 pub mod types;
+pub mod usage;
 mod util;
+pub mod workspaces;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use futures_util::StreamExt;
 use serde_json::json;
 use tauri::AppHandle;
-use tokio::sync::{mpsc, watch, Mutex, RwLock, Semaphore};
+use tokio::sync::{mpsc, watch, Mutex, Notify, RwLock};
 use tokio::task::JoinHandle;
 
+use crate::metrics;
 use crate::sidecar::ManagedSidecarState;
 use crate::{ulog_error, ulog_info, ulog_warn};
 
-use adapter::{ImAdapter, ImStreamAdapter};
-use buffer::MessageBuffer;
+use activity::ToolTracker;
+use adapter::ImStreamAdapter;
+use agent_routes::{AgentRoutes, RouteSelection};
 use health::HealthManager;
-use router::{create_sidecar_stream_client, SessionRouter, GLOBAL_CONCURRENCY};
+use identity::{ImPerson, IdentityStore};
+use inbound::{DropReason, InboundQueue};
+use outbound::{Delivery, Outbox};
+use review::{ReviewItem, ReviewQueue};
+use router::{create_sidecar_stream_client, SessionRouter};
+use scheduler::{Admission, SchedulerLimits, SchedulerPermit, TurnRequest, TurnScheduler};
+use supervisor::SupervisorPolicy;
+use takeover::{TakeoverMessage, TakeoverRegistry, TakeoverSession};
 use telegram::TelegramAdapter;
+use turns::{EditOutcome, RecallOutcome, TurnRegistry, TurnTicket};
 use feishu::FeishuAdapter;
 use dingtalk::DingtalkAdapter;
+use local::LocalAdapter;
 use types::{
-    GroupPermission, GroupPermissionStatus, ImBotStatusResponse, ImConfig, ImMessage,
-    ImPlatform, ImStatus, RouteError,
+    BufferedMessage, ChannelKnownChats, ChannelPersona, GroupPermission, GroupPermissionStatus,
+    ImBotStatusResponse, ImConfig, ImMessage, ImMessageEvent, ImPlatform, ImStatus, KnownChat,
+    RouteError, TelegramError, TurnUsage,
 };
 
 // ===== Channel Instance =====
 
+/// Handle for sending into a running channel without holding the manager lock
+/// (scheduled task results, heartbeat notices, ...)
+#[derive(Clone)]
+pub struct ChannelOutlet {
+    adapter: Arc<dyn ImStreamAdapter>,
+    outbox: Arc<Outbox>,
+    /// Set on review_before_send channels
+    review: Option<ReviewGate>,
+}
+
+/// Review queue of a review_before_send channel, as seen by its outlet
+#[derive(Clone)]
+struct ReviewGate {
+    queue: Arc<ReviewQueue>,
+    app: AppHandle,
+    agent_id: String,
+    channel_id: String,
+}
+
+impl ChannelOutlet {
+    /// Send text through the outbox (split by the adapter, retried on failure).
+    /// Review-gated channels hold it for approval instead.
+    pub async fn send_text(&self, chat_id: &str, text: &str) -> Delivery {
+        if let Some(gate) = &self.review {
+            let item = gate.queue.hold(chat_id, text);
+            emit_review_queued(&gate.app, &gate.agent_id, &gate.channel_id, &item);
+            return Delivery::Held { review_id: item.id };
+        }
+        self.outbox.deliver(chat_id, text).await
+    }
+
+    /// Whether text sent here waits for approval (review_before_send)
+    pub fn is_reviewed(&self) -> bool {
+        self.review.is_some()
+    }
+
+    /// The same outlet sending directly, for the operator who does the reviewing
+    pub fn without_review(self) -> Self {
+        Self {
+            review: None,
+            ..self
+        }
+    }
+
+    /// Upload a file; Err when the platform doesn't support uploads, the channel
+    /// reviews messages before sending (files can't be held), or the send fails
+    pub async fn send_file(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+        caption: Option<&str>,
+    ) -> Result<(), String> {
+        if self.is_reviewed() {
+            return Err(
+                "This channel reviews messages before sending; files can't be held for review"
+                    .to_string(),
+            );
+        }
+        self.adapter.send_file(chat_id, filename, data, caption).await
+    }
+}
+
 /// A running IM channel instance (one per Agent+Channel pair)
 pub struct ChannelInstance {
     pub agent_id: String,
@@ -45,10 +136,17 @@ pub struct ChannelInstance {
     pub shutdown_tx: watch::Sender<bool>,
     pub health: Arc<HealthManager>,
     pub router: Arc<Mutex<SessionRouter>>,
-    pub buffer: Arc<Mutex<MessageBuffer>>,
+    /// Needed to release Sidecars outside the processing loop (workspace switches)
+    pub sidecar_manager: ManagedSidecarState,
+    pub queue: Arc<Mutex<InboundQueue>>,
+    /// Persistent delivery queue for final replies that failed to send
+    pub outbox: Arc<Outbox>,
+    pub adapter: Arc<dyn ImStreamAdapter>,
     pub started_at: Instant,
     pub listen_handle: JoinHandle<()>,
     pub processing_handle: JoinHandle<()>,
+    pub drain_handle: JoinHandle<()>,
+    pub outbox_handle: JoinHandle<()>,
     pub idle_handle: JoinHandle<()>,
     pub health_handle: JoinHandle<()>,
     pub config: ImConfig,
@@ -56,13 +154,46 @@ pub struct ChannelInstance {
     pub allowed_users: Arc<RwLock<Vec<String>>>,
     /// Runtime group permissions (shared with adapter for live updates)
     pub group_permissions: Arc<RwLock<Vec<GroupPermission>>>,
+    /// Multi-agent routing rules (shared with the processing loop for live updates)
+    pub agent_routes: Arc<AgentRoutes>,
+    /// Channel persona (shared with the processing loop for live updates)
+    pub persona: Arc<std::sync::RwLock<ChannelPersona>>,
+    /// Sessions an operator has taken over from the agent
+    pub takeover: Arc<TakeoverRegistry>,
+    /// Final replies waiting for approval (review_before_send)
+    pub review: Arc<ReviewQueue>,
+    /// For review events raised by outlet sends
+    pub app: AppHandle,
+}
+
+impl ChannelInstance {
+    fn outlet(&self) -> ChannelOutlet {
+        let review = self
+            .config
+            .review_before_send
+            .unwrap_or(false)
+            .then(|| ReviewGate {
+                queue: Arc::clone(&self.review),
+                app: self.app.clone(),
+                agent_id: self.agent_id.clone(),
+                channel_id: self.channel_id.clone(),
+            });
+        ChannelOutlet {
+            adapter: Arc::clone(&self.adapter),
+            outbox: Arc::clone(&self.outbox),
+            review,
+        }
+    }
 }
 
 // ===== IM Manager =====
 
 pub struct ImManager {
     channels: HashMap<String, ChannelInstance>,
-    concurrency_semaphore: Arc<Semaphore>,
+    /// Admits AI turns across all channels (fair share, per-channel / per-user caps)
+    scheduler: Arc<TurnScheduler>,
+    /// Cross-platform identity links, shared by every channel's router
+    identities: Arc<IdentityStore>,
 }
 
 fn channel_key(agent_id: &str, channel_id: &str) -> String {
@@ -75,7 +206,8 @@ impl ImManager {
     pub fn new() -> Self {
         Self {
             channels: HashMap::new(),
-            concurrency_semaphore: Arc::new(Semaphore::new(GLOBAL_CONCURRENCY)),
+            scheduler: TurnScheduler::new(crate::config_service::current().im_scheduler.clone()),
+            identities: Arc::new(IdentityStore::load(Some(health::im_identities_path()))),
         }
     }
 
@@ -83,7 +215,7 @@ impl ImManager {
         &mut self,
         app: AppHandle,
         sidecar_manager: ManagedSidecarState,
-        config: ImConfig,
+        mut config: ImConfig,
     ) -> Result<(), String> {
         let key = channel_key(&config.agent_id, &config.channel_id);
 
@@ -100,18 +232,58 @@ impl ImManager {
             config.workspace_path
         );
 
+        if config.agent_routes.iter().any(|r| r.agent_id.is_some()) {
+            agent_routes::resolve_agent_refs(
+                &mut config.agent_routes,
+                &crate::config_service::current().agents,
+            );
+        }
+        let persona = Arc::new(std::sync::RwLock::new(config.persona.clone()));
+        let agent_routes = Arc::new(AgentRoutes::load(
+            config.agent_routes.clone(),
+            Some(health::agent_channel_agent_routes_path(
+                &config.agent_id,
+                &config.channel_id,
+            )),
+        ));
+
         let health_path =
             health::agent_channel_health_path(&config.agent_id, &config.channel_id);
         let health = Arc::new(HealthManager::new(health_path));
         health.set_status(ImStatus::Connecting).await;
 
-        let buffer_path =
+        let queue_path =
+            health::agent_channel_inbound_queue_path(&config.agent_id, &config.channel_id);
+        let legacy_buffer_path =
             health::agent_channel_buffer_path(&config.agent_id, &config.channel_id);
-        let buffer = Arc::new(Mutex::new(MessageBuffer::load_from_disk(&buffer_path)));
+        let queue = Arc::new(Mutex::new(InboundQueue::open(
+            &queue_path,
+            Some(&legacy_buffer_path),
+        )));
+        let metric_labels = channel_metric_labels(&config);
+        let queue_depth = queue.lock().await.len();
+        health.set_buffered_messages(queue_depth).await;
+        metrics::set_gauge(metrics::IM_INBOUND_QUEUE_DEPTH, &metric_labels, queue_depth as f64);
 
         let default_workspace = std::path::PathBuf::from(&config.workspace_path);
-        let mut router_inner =
-            SessionRouter::new(default_workspace, config.agent_id.clone());
+        let mut router_inner = SessionRouter::new(
+            default_workspace,
+            config.agent_id.clone(),
+            config.channel_id.clone(),
+            Some(health::agent_channel_chat_workspaces_path(
+                &config.agent_id,
+                &config.channel_id,
+            )),
+            Some(Arc::clone(&self.identities)),
+        );
+
+        let takeover = Arc::new(TakeoverRegistry::load(Some(
+            health::agent_channel_takeover_path(&config.agent_id, &config.channel_id),
+        )));
+
+        let review = Arc::new(ReviewQueue::load(Some(
+            health::agent_channel_review_queue_path(&config.agent_id, &config.channel_id),
+        )));
 
         let prev_sessions = health.get_state().await.active_sessions;
         router_inner.restore_sessions(&prev_sessions);
@@ -126,13 +298,25 @@ impl ImManager {
 
         let (msg_tx, msg_rx) = mpsc::channel::<ImMessage>(256);
 
-        // Dedup path for Feishu/DingTalk
-        let dedup_path = dirs::home_dir().map(|h| {
-            h.join(".soagents")
-                .join("im")
-                .join(&config.agent_id)
-                .join(format!("{}.dedup.json", config.channel_id))
-        });
+        // Dedup cache path (all platforms). Older builds kept Feishu/DingTalk caches
+        // under ~/.soagents/im/{agentId}/{channelId}.dedup.json — move it over once.
+        let dedup_path =
+            health::agent_channel_dedup_path(&config.agent_id, &config.channel_id);
+        if !dedup_path.exists() {
+            if let Some(legacy) = dirs::home_dir().map(|h| {
+                h.join(".soagents")
+                    .join("im")
+                    .join(&config.agent_id)
+                    .join(format!("{}.dedup.json", config.channel_id))
+            }) {
+                if legacy.exists() {
+                    if let Some(parent) = dedup_path.parent() {
+                        let _ = std::fs::create_dir_all(parent);
+                    }
+                    let _ = std::fs::rename(&legacy, &dedup_path);
+                }
+            }
+        }
 
         // Create platform adapter
         let adapter: Arc<dyn ImStreamAdapter> = match config.platform {
@@ -140,20 +324,32 @@ impl ImManager {
                 &config,
                 msg_tx.clone(),
                 Arc::clone(&allowed_users),
+                Some(dedup_path),
+                Some(health::agent_channel_telegram_offset_path(
+                    &config.agent_id,
+                    &config.channel_id,
+                )),
             )),
             ImPlatform::Feishu => Arc::new(FeishuAdapter::new(
                 &config,
                 msg_tx.clone(),
                 Arc::clone(&allowed_users),
                 Arc::clone(&group_permissions),
-                dedup_path,
+                Some(dedup_path),
             )),
             ImPlatform::Dingtalk => Arc::new(DingtalkAdapter::new(
                 &config,
                 msg_tx.clone(),
                 Arc::clone(&allowed_users),
                 Arc::clone(&group_permissions),
-                dedup_path,
+                Some(dedup_path),
+            )),
+            ImPlatform::Local => Arc::new(LocalAdapter::new(
+                &config,
+                msg_tx.clone(),
+                Arc::clone(&allowed_users),
+                Arc::clone(&group_permissions),
+                Some(dedup_path),
             )),
         };
 
@@ -184,25 +380,51 @@ impl ImManager {
 
         let health_handle = Arc::clone(&health).start_persist_loop(shutdown_rx.clone());
 
-        let adapter_for_listen = Arc::clone(&adapter);
-        let listen_shutdown_rx = shutdown_rx.clone();
-        let listen_handle = tokio::spawn(async move {
-            let _ = adapter_for_listen.listen_loop(listen_shutdown_rx).await;
-        });
+        let outbox = Arc::new(Outbox::new(
+            adapter.clone(),
+            health::agent_channel_outbound_path(&config.agent_id, &config.channel_id),
+            metric_labels.clone(),
+        ));
+        let outbox_handle = Arc::clone(&outbox).spawn_retry_loop(shutdown_rx.clone());
 
-        let processing_handle = spawn_message_processing_loop(
-            msg_rx,
-            shutdown_rx.clone(),
-            Arc::clone(&router),
-            Arc::clone(&buffer),
+        let listen_handle = supervisor::spawn_listen_supervisor(
+            Arc::clone(&adapter),
             Arc::clone(&health),
-            adapter.clone(),
-            app.clone(),
-            sidecar_manager.clone(),
-            Arc::clone(&self.concurrency_semaphore),
-            config.clone(),
+            shutdown_rx.clone(),
+            SupervisorPolicy::with_max_restarts(config.max_listen_restarts),
+            metric_labels.clone(),
         );
 
+        let turn_ctx = TurnContext {
+            router: Arc::clone(&router),
+            queue: Arc::clone(&queue),
+            health: Arc::clone(&health),
+            adapter: adapter.clone(),
+            outbox: Arc::clone(&outbox),
+            app: app.clone(),
+            sidecar_manager: sidecar_manager.clone(),
+            scheduler: Arc::clone(&self.scheduler),
+            stream_client: create_sidecar_stream_client(),
+            config: config.clone(),
+            agent_routes: Arc::clone(&agent_routes),
+            persona: Arc::clone(&persona),
+            drain_notify: Arc::new(Notify::new()),
+            turns: Arc::new(TurnRegistry::new()),
+            identities: Arc::clone(&self.identities),
+            takeover: Arc::clone(&takeover),
+            review: Arc::clone(&review),
+            metric_labels,
+        };
+        // Replay whatever survived the last run as soon as the channel is up
+        if !queue.lock().await.is_empty() {
+            turn_ctx.drain_notify.notify_one();
+        }
+
+        let processing_handle =
+            spawn_message_processing_loop(msg_rx, shutdown_rx.clone(), turn_ctx.clone());
+
+        let drain_handle = spawn_queue_drain_loop(shutdown_rx.clone(), turn_ctx);
+
         let idle_handle = spawn_idle_collection_loop(
             shutdown_rx.clone(),
             Arc::clone(&router),
@@ -217,15 +439,25 @@ impl ImManager {
                 shutdown_tx,
                 health,
                 router,
-                buffer,
+                sidecar_manager,
+                queue,
+                outbox,
+                adapter,
                 started_at: Instant::now(),
                 listen_handle,
                 processing_handle,
+                drain_handle,
+                outbox_handle,
                 idle_handle,
                 health_handle,
                 config,
                 allowed_users,
                 group_permissions,
+                agent_routes,
+                persona,
+                takeover,
+                review,
+                app,
             },
         );
 
@@ -258,10 +490,13 @@ impl ImManager {
 
         instance.idle_handle.abort();
         let _ = tokio::time::timeout(Duration::from_secs(2), instance.idle_handle).await;
+        let _ = tokio::time::timeout(Duration::from_secs(2), instance.drain_handle).await;
+        let _ = tokio::time::timeout(Duration::from_secs(2), instance.outbox_handle).await;
         let _ = tokio::time::timeout(Duration::from_secs(2), instance.health_handle).await;
 
-        if let Err(e) = instance.buffer.lock().await.save_to_disk() {
-            ulog_warn!("[im] Failed to persist buffer on shutdown: {}", e);
+        // Queue is durable already; just leave a compact log behind
+        if let Err(e) = instance.queue.lock().await.compact() {
+            ulog_warn!("[im] Failed to compact inbound queue on shutdown: {}", e);
         }
 
         instance
@@ -275,6 +510,79 @@ impl ImManager {
         Ok(())
     }
 
+    pub fn outlet(&self, agent_id: &str, channel_id: &str) -> Option<ChannelOutlet> {
+        self.channels
+            .get(&channel_key(agent_id, channel_id))
+            .map(ChannelInstance::outlet)
+    }
+
+    /// Outlet for a proactive send to `chat_id`, after the channel policy and allowlist checks
+    pub async fn proactive_outlet(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+        chat_id: &str,
+    ) -> Result<ChannelOutlet, String> {
+        let key = channel_key(agent_id, channel_id);
+        let instance = self
+            .channels
+            .get(&key)
+            .ok_or_else(|| format!("Channel {} is not running", key))?;
+        let known = instance.health.get_state().await.known_chats;
+        proactive::check_target(
+            chat_id,
+            instance.config.allow_proactive_send.unwrap_or(true),
+            &known,
+            &instance.allowed_users.read().await,
+            &instance.group_permissions.read().await,
+        )?;
+        Ok(instance.outlet())
+    }
+
+    /// Chats that have messaged running channels (most recent first),
+    /// optionally narrowed to one agent and/or channel.
+    pub async fn known_chats(
+        &self,
+        agent_id: Option<&str>,
+        channel_id: Option<&str>,
+    ) -> Vec<ChannelKnownChats> {
+        let mut result = Vec::new();
+        for instance in self.channels.values() {
+            if agent_id.is_some_and(|a| a != instance.agent_id)
+                || channel_id.is_some_and(|c| c != instance.channel_id)
+            {
+                continue;
+            }
+            let mut chats = instance.health.get_state().await.known_chats;
+            let groups = instance.group_permissions.read().await;
+            for chat in chats.iter_mut().filter(|c| c.display_name.is_none()) {
+                chat.display_name = groups
+                    .iter()
+                    .find(|g| g.group_id == chat.chat_id)
+                    .map(|g| g.group_name.clone());
+            }
+            chats.sort_by(|a, b| b.last_active.cmp(&a.last_active));
+            result.push(ChannelKnownChats {
+                agent_id: instance.agent_id.clone(),
+                channel_id: instance.channel_id.clone(),
+                platform: instance.config.platform.clone(),
+                chats,
+            });
+        }
+        result
+    }
+
+    /// Config a running channel was started with (hot-patched fields included)
+    pub fn running_config(&self, agent_id: &str, channel_id: &str) -> Option<&ImConfig> {
+        self.channels
+            .get(&channel_key(agent_id, channel_id))
+            .map(|instance| &instance.config)
+    }
+
+    pub fn running_configs(&self) -> Vec<ImConfig> {
+        self.channels.values().map(|instance| instance.config.clone()).collect()
+    }
+
     pub async fn stop_all(&mut self) {
         let keys: Vec<String> = self.channels.keys().cloned().collect();
         for key in keys {
@@ -283,6 +591,8 @@ impl ImManager {
                 let _ = instance.shutdown_tx.send(true);
                 instance.listen_handle.abort();
                 instance.processing_handle.abort();
+                instance.drain_handle.abort();
+                instance.outbox_handle.abort();
                 instance.idle_handle.abort();
                 instance.health_handle.abort();
             }
@@ -302,7 +612,8 @@ impl ImManager {
 
         let health_state = instance.health.get_state().await;
         let active_sessions = instance.router.lock().await.get_active_sessions();
-        let buffered = instance.buffer.lock().await.len();
+        let buffered = instance.queue.lock().await.len();
+        let pending_deliveries = instance.outbox.pending_count().await;
         let uptime = instance.started_at.elapsed().as_secs();
         let group_perms = instance.group_permissions.read().await.clone();
 
@@ -313,8 +624,11 @@ impl ImManager {
             active_sessions,
             error_message: health_state.error_message,
             restart_count: health_state.restart_count,
+            restart_history: health_state.restart_history,
             buffered_messages: buffered,
+            pending_deliveries,
             group_permissions: group_perms,
+            usage: health_state.usage,
         })
     }
 
@@ -323,7 +637,8 @@ impl ImManager {
         for (key, instance) in &self.channels {
             let health_state = instance.health.get_state().await;
             let active_sessions = instance.router.lock().await.get_active_sessions();
-            let buffered = instance.buffer.lock().await.len();
+            let buffered = instance.queue.lock().await.len();
+            let pending_deliveries = instance.outbox.pending_count().await;
             let uptime = instance.started_at.elapsed().as_secs();
             let group_perms = instance.group_permissions.read().await.clone();
 
@@ -336,8 +651,11 @@ impl ImManager {
                     active_sessions,
                     error_message: health_state.error_message,
                     restart_count: health_state.restart_count,
+                    restart_history: health_state.restart_history,
                     buffered_messages: buffered,
+                    pending_deliveries,
                     group_permissions: group_perms,
+                    usage: health_state.usage,
                 },
             );
         }
@@ -369,6 +687,33 @@ impl ImManager {
             }
         }
 
+        if let Some(allow) = patch.get("allowProactiveSend") {
+            instance.config.allow_proactive_send = allow.as_bool();
+        }
+
+        if let Some(overrides) = patch.get("overrides") {
+            let overrides: types::ChannelOverrides = serde_json::from_value(overrides.clone())
+                .map_err(|e| format!("Invalid overrides: {}", e))?;
+            let persona = ChannelPersona::from_overrides(Some(&overrides));
+            if persona != instance.config.persona {
+                ulog_info!("[im] Channel {} persona updated", key);
+                *instance.persona.write().unwrap_or_else(|e| e.into_inner()) = persona.clone();
+                instance.config.persona = persona;
+            }
+        }
+
+        if let Some(routes) = patch.get("agentRoutes") {
+            let mut routes: Vec<types::AgentRoute> = serde_json::from_value(routes.clone())
+                .map_err(|e| format!("Invalid agentRoutes: {}", e))?;
+            if routes.iter().any(|r| r.agent_id.is_some()) {
+                let agents = &crate::config_service::current().agents;
+                agent_routes::resolve_agent_refs(&mut routes, agents);
+            }
+            ulog_info!("[im] Channel {} now routes to {} agent(s)", key, routes.len());
+            instance.agent_routes.update_routes(routes.clone());
+            instance.config.agent_routes = routes;
+        }
+
         Ok(())
     }
 
@@ -389,6 +734,219 @@ impl ImManager {
         Ok(())
     }
 
+    /// Point a session at a workspace from the agent's allowlist (None = default workspace)
+    pub async fn set_chat_workspace(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+        session_key: &str,
+        workspace: Option<&str>,
+    ) -> Result<(), String> {
+        let key = channel_key(agent_id, channel_id);
+        let instance = self
+            .channels
+            .get(&key)
+            .ok_or_else(|| format!("Channel {} not found", key))?;
+        let workspace = match workspace {
+            Some(path) => Some(workspaces::resolve_choice(
+                &instance.config.workspace_allowlist,
+                path,
+            )?),
+            None => None,
+        };
+        instance
+            .router
+            .lock()
+            .await
+            .set_chat_workspace(session_key, workspace, &instance.sidecar_manager);
+        Ok(())
+    }
+
+    // ── Identity links ───────────────────────────────────────────────────────
+
+    pub fn identities(&self) -> Vec<ImPerson> {
+        self.identities.persons()
+    }
+
+    /// Link a platform account to a person (None = a new person called `name`)
+    pub fn link_identity(
+        &self,
+        person_id: Option<&str>,
+        name: Option<&str>,
+        platform: ImPlatform,
+        user_id: &str,
+    ) -> Result<ImPerson, String> {
+        self.identities.link(person_id, name, platform, user_id, None)
+    }
+
+    pub fn unlink_identity(&self, platform: &ImPlatform, user_id: &str) -> Result<(), String> {
+        if self.identities.unlink(platform, user_id) {
+            Ok(())
+        } else {
+            Err(format!("{}:{} is not linked", platform, user_id))
+        }
+    }
+
+    // ── Human takeover ───────────────────────────────────────────────────────
+
+    fn running_instance(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+    ) -> Result<&ChannelInstance, String> {
+        let key = channel_key(agent_id, channel_id);
+        self.channels
+            .get(&key)
+            .ok_or_else(|| format!("Channel {} not found", key))
+    }
+
+    /// Pause the agent for `session_key`; its messages go to the desktop until handed back
+    pub fn start_takeover(
+        &self,
+        app: &AppHandle,
+        agent_id: &str,
+        channel_id: &str,
+        session_key: &str,
+        chat_id: &str,
+    ) -> Result<(), String> {
+        let instance = self.running_instance(agent_id, channel_id)?;
+        if !instance.takeover.start(session_key, chat_id) {
+            return Err(format!("Session {} is already taken over", session_key));
+        }
+        emit_takeover_changed(app, agent_id, channel_id, session_key, true);
+        Ok(())
+    }
+
+    /// Send an operator reply as the bot
+    pub async fn send_takeover_message(
+        &self,
+        app: &AppHandle,
+        agent_id: &str,
+        channel_id: &str,
+        session_key: &str,
+        text: &str,
+    ) -> Result<TakeoverMessage, String> {
+        let instance = self.running_instance(agent_id, channel_id)?;
+        let chat_id = instance
+            .takeover
+            .chat_id(session_key)
+            .ok_or_else(|| format!("Session {} is not taken over", session_key))?;
+        instance
+            .adapter
+            .send_message(&chat_id, text)
+            .await
+            .map_err(|e| format!("Failed to send: {}", e))?;
+        let (_, message) = instance.takeover.record_operator(session_key, text)?;
+        emit_takeover_message(app, agent_id, channel_id, session_key, &chat_id, &message);
+        Ok(message)
+    }
+
+    /// Resume the agent; it sees the operator exchange with the next message
+    pub fn hand_back_takeover(
+        &self,
+        app: &AppHandle,
+        agent_id: &str,
+        channel_id: &str,
+        session_key: &str,
+    ) -> Result<(), String> {
+        let instance = self.running_instance(agent_id, channel_id)?;
+        instance.takeover.hand_back(session_key)?;
+        emit_takeover_changed(app, agent_id, channel_id, session_key, false);
+        Ok(())
+    }
+
+    pub fn takeovers(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+    ) -> Result<Vec<TakeoverSession>, String> {
+        Ok(self.running_instance(agent_id, channel_id)?.takeover.list())
+    }
+
+    // ── Review queue ─────────────────────────────────────────────────────────
+
+    pub fn review_list(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+    ) -> Result<Vec<ReviewItem>, String> {
+        Ok(self.running_instance(agent_id, channel_id)?.review.list())
+    }
+
+    /// Send a queued reply, as drafted or with `text` replacing it. A reply the platform
+    /// rejects for good stays in the queue and the error is returned.
+    pub async fn review_send(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+        id: &str,
+        text: Option<&str>,
+    ) -> Result<(), String> {
+        if text.is_some_and(|t| t.trim().is_empty()) {
+            return Err("Reply text is empty".to_string());
+        }
+        let instance = self.running_instance(agent_id, channel_id)?;
+        // Taken while sending so a second approval can't post it twice
+        let item = instance.review.take(id)?;
+        let text = text.unwrap_or(&item.draft);
+        let delivery = instance.outbox.deliver(&item.chat_id, text).await;
+        if let Some(e) = delivery.failure() {
+            ulog_warn!("[im-review] Reply {} could not be sent: {}", id, e);
+            instance.review.restore(item);
+            return Err(format!("Send failed: {}", e));
+        }
+        ulog_info!(
+            "[im-review] Reply {} approved{} for {}",
+            id,
+            if text == item.draft { "" } else { " with edits" },
+            item.session_key
+        );
+        Ok(())
+    }
+
+    /// Drop a queued reply without sending it
+    pub fn review_reject(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+        id: &str,
+    ) -> Result<(), String> {
+        let item = self.running_instance(agent_id, channel_id)?.review.take(id)?;
+        ulog_info!("[im-review] Reply {} rejected for {}", id, item.session_key);
+        Ok(())
+    }
+
+    /// Apply `imScheduler` limits from config.json
+    pub fn set_scheduler_limits(&self, limits: SchedulerLimits) {
+        ulog_info!("[im] Scheduler limits updated: {:?}", limits);
+        self.scheduler.set_limits(limits);
+    }
+
+    /// Export a session's transcript. Works for stopped channels too, using the
+    /// session list persisted in the channel's health state.
+    pub async fn export_transcript(
+        &self,
+        agent_id: &str,
+        channel_id: &str,
+        session_key: &str,
+        format: transcript::TranscriptFormat,
+    ) -> Result<transcript::ExportedTranscript, String> {
+        let key = channel_key(agent_id, channel_id);
+        let session_id = match self.channels.get(&key) {
+            Some(instance) => instance.router.lock().await.get_session_id(session_key),
+            None => HealthManager::new(health::agent_channel_health_path(agent_id, channel_id))
+                .get_state()
+                .await
+                .active_sessions
+                .into_iter()
+                .find(|s| s.session_key == session_key)
+                .map(|s| s.session_id),
+        }
+        .ok_or_else(|| format!("No session found for {}", session_key))?;
+
+        transcript::export_transcript(agent_id, channel_id, session_key, &session_id, format)
+    }
+
     // ── Group permission management ──────────────────────────────────────────
 
     pub async fn approve_group(
@@ -462,6 +1020,8 @@ pub fn signal_all_shutdown(im_state: &ImManagerState) {
             let _ = instance.shutdown_tx.send(true);
             instance.listen_handle.abort();
             instance.processing_handle.abort();
+            instance.drain_handle.abort();
+            instance.outbox_handle.abort();
             instance.idle_handle.abort();
             instance.health_handle.abort();
         }
@@ -470,24 +1030,115 @@ pub fn signal_all_shutdown(im_state: &ImManagerState) {
 
 // ===== Message Processing Loop =====
 
-fn spawn_message_processing_loop(
-    mut msg_rx: mpsc::Receiver<ImMessage>,
-    mut shutdown_rx: watch::Receiver<bool>,
+/// Shared handles needed to run one turn. Cloned into every per-message task
+/// and into the inbound queue drain worker.
+#[derive(Clone)]
+struct TurnContext {
     router: Arc<Mutex<SessionRouter>>,
-    buffer: Arc<Mutex<MessageBuffer>>,
+    queue: Arc<Mutex<InboundQueue>>,
     health: Arc<HealthManager>,
     adapter: Arc<dyn ImStreamAdapter>,
+    outbox: Arc<Outbox>,
     app: AppHandle,
     sidecar_manager: ManagedSidecarState,
-    semaphore: Arc<Semaphore>,
+    scheduler: Arc<TurnScheduler>,
+    stream_client: reqwest::Client,
     config: ImConfig,
-) -> JoinHandle<()> {
-    let stream_client = create_sidecar_stream_client();
-    let provider_env: Option<serde_json::Value> = config
-        .provider_env_json
-        .as_ref()
-        .and_then(|json_str| serde_json::from_str(json_str).ok());
+    /// Per-message agent selection (routing rules + sticky agent per chat)
+    agent_routes: Arc<AgentRoutes>,
+    /// Channel persona (hot-reloaded by cmd_update_agent_channel_config)
+    persona: Arc<std::sync::RwLock<ChannelPersona>>,
+    /// Wakes the drain worker when a message was queued
+    drain_notify: Arc<Notify>,
+    /// In-flight / answered turns, for edit and recall events
+    turns: Arc<TurnRegistry>,
+    /// Cross-platform identity links (/link command)
+    identities: Arc<IdentityStore>,
+    /// Sessions an operator has taken over — their messages skip the agent
+    takeover: Arc<TakeoverRegistry>,
+    /// Replies held for approval (review_before_send)
+    review: Arc<ReviewQueue>,
+    /// agent / channel / platform labels for this channel's metrics
+    metric_labels: metrics::Labels,
+}
+
+impl TurnContext {
+    fn record_error(&self, kind: &str) {
+        let mut labels = self.metric_labels.clone();
+        labels.push(("kind", kind.to_string()));
+        metrics::inc(metrics::IM_ERRORS, &labels);
+    }
+
+    /// Scheduler identity of a turn for `msg`
+    fn turn_request(&self, msg: &ImMessage) -> TurnRequest {
+        TurnRequest {
+            channel: channel_key(&self.config.agent_id, &self.config.channel_id),
+            user: msg.sender_id.clone(),
+            weight: self.config.scheduler_weight.unwrap_or(1),
+            private: msg.source_type == types::ImSourceType::Private,
+            admin: self.config.admin_users.iter().any(|a| a == &msg.sender_id),
+        }
+    }
 
+    /// Wait for a turn slot. With `announce`, a message that has to wait is told its
+    /// place in line.
+    async fn acquire_turn_slot(&self, msg: &ImMessage, announce: bool) -> SchedulerPermit {
+        let wait_started = Instant::now();
+        let permit = match self.scheduler.request(self.turn_request(msg)) {
+            Admission::Ready(permit) => permit,
+            Admission::Queued(turn) => {
+                let position = turn.position();
+                if announce && position > 0 {
+                    self.outbox
+                        .deliver(
+                            &msg.chat_id,
+                            &format!(
+                                "The agent is busy right now. You're #{} in line.",
+                                position
+                            ),
+                        )
+                        .await;
+                }
+                turn.wait().await
+            }
+        };
+        metrics::observe(
+            metrics::IM_CONCURRENCY_WAIT_SECONDS,
+            &self.metric_labels,
+            wait_started.elapsed().as_secs_f64(),
+        );
+        permit
+    }
+
+    async fn update_queue_depth(&self) {
+        let depth = self.queue.lock().await.len();
+        self.health.set_buffered_messages(depth).await;
+        metrics::set_gauge(metrics::IM_INBOUND_QUEUE_DEPTH, &self.metric_labels, depth as f64);
+    }
+}
+
+/// Labels identifying one channel in metrics
+fn channel_metric_labels(config: &ImConfig) -> metrics::Labels {
+    vec![
+        ("agent", config.agent_id.clone()),
+        ("channel", config.channel_id.clone()),
+        ("platform", config.platform.to_string()),
+    ]
+}
+
+/// How a turn ended
+enum TurnOutcome {
+    /// Response streamed, or a non-retryable error was already reported to the user
+    Done,
+    /// Sidecar unreachable — the message should wait in the inbound queue
+    Deferred(String),
+}
+
+fn spawn_message_processing_loop(
+    mut msg_rx: mpsc::Receiver<ImMessage>,
+    mut shutdown_rx: watch::Receiver<bool>,
+    ctx: TurnContext,
+) -> JoinHandle<()> {
     tokio::spawn(async move {
         ulog_info!("[im] Message processing loop started");
 
@@ -503,19 +1154,50 @@ fn spawn_message_processing_loop(
                 }
             };
 
-            let session_key = {
-                let r = router.lock().await;
+            // Edits and recalls act on an earlier message; an edit may re-run it
+            let msg = match msg.event {
+                ImMessageEvent::New => msg,
+                ImMessageEvent::Edited => match handle_edited_message(&ctx, msg).await {
+                    Some(rerun) => rerun,
+                    None => continue,
+                },
+                ImMessageEvent::Recalled => {
+                    handle_recalled_message(&ctx, &msg).await;
+                    continue;
+                }
+            };
+
+            metrics::inc(metrics::IM_MESSAGES_RECEIVED, &ctx.metric_labels);
+            ctx.health.record_chat(KnownChat::from_message(&msg)).await;
+
+            let base_key = {
+                let r = ctx.router.lock().await;
                 r.session_key(&msg)
             };
+            // Commands act on the chat's current agent
+            let mut session_key = agent_routes::routed_session_key(
+                &base_key,
+                ctx.agent_routes.current(&msg.chat_id).as_deref(),
+            );
+
+            // Taken over by an operator: forward to the desktop instead of the agent
+            if let Some(key) = [&session_key, &base_key]
+                .into_iter()
+                .find(|key| ctx.takeover.is_active(key))
+            {
+                forward_to_operator(&ctx, key, &msg).await;
+                continue;
+            }
 
             let chat_id = msg.chat_id.clone();
             let message_id = msg.message_id.clone();
             let text = msg.text.trim().to_string();
+            let adapter = &ctx.adapter;
 
             // Bot command dispatch
             if text == "/new" {
                 let _ = adapter.ack_processing(&chat_id, &message_id).await;
-                let result = router.lock().await.reset_session(&session_key).await;
+                let result = ctx.router.lock().await.reset_session(&session_key).await;
                 let _ = adapter.ack_clear(&chat_id, &message_id).await;
                 match result {
                     Some(new_id) => {
@@ -523,11 +1205,11 @@ fn spawn_message_processing_loop(
                             "New conversation started ({})",
                             &new_id[..8.min(new_id.len())]
                         );
-                        let _ = adapter.send_message(&chat_id, &reply).await;
+                        ctx.outbox.deliver(&chat_id, &reply).await;
                     }
                     None => {
-                        let _ = adapter
-                            .send_message(&chat_id, "Failed to reset session")
+                        ctx.outbox
+                            .deliver(&chat_id, "Failed to reset session")
                             .await;
                     }
                 }
@@ -535,12 +1217,17 @@ fn spawn_message_processing_loop(
             }
 
             if text == "/start" {
-                let _ = adapter
-                    .send_message(
+                ctx.outbox
+                    .deliver(
                         &chat_id,
                         "Hello! I'm a SoAgents Bot.\n\n\
                          Commands:\n\
                          /new - Start a new conversation\n\
+                         /export [md|html|jsonl] - Export this conversation\n\
+                         /agent [name] - Show or switch the agent you're talking to\n\
+                         /workspace [name] - Show or switch this chat's project directory\n\
+                         /link [code] - Share this conversation with your account on another app\n\
+                         /unlink - Stop sharing conversations with your other accounts\n\
                          /start - Show this message\n\n\
                          Send a message to start chatting.",
                     )
@@ -548,257 +1235,985 @@ fn spawn_message_processing_loop(
                 continue;
             }
 
-            ulog_info!(
-                "[im] Routing message from {} to Sidecar (session_key={}, {} chars)",
-                msg.sender_name.as_deref().unwrap_or("?"),
-                session_key,
-                text.len(),
-            );
-
-            let task_router = Arc::clone(&router);
-            let task_adapter = Arc::clone(&adapter);
-            let task_app = app.clone();
-            let task_manager = Arc::clone(&sidecar_manager);
-            let task_buffer = Arc::clone(&buffer);
-            let task_health = Arc::clone(&health);
-            let task_sem = Arc::clone(&semaphore);
-            let task_stream_client = stream_client.clone();
-            let task_config = config.clone();
-            let task_provider_env = provider_env.clone();
+            if let Some(arg) = text.strip_prefix("/agent") {
+                if arg.is_empty() || arg.starts_with(' ') {
+                    handle_agent_command(&ctx, &chat_id, arg.trim()).await;
+                    continue;
+                }
+            }
 
-            tokio::spawn(async move {
-                let _permit = match task_sem.acquire_owned().await {
-                    Ok(p) => p,
-                    Err(_) => return,
-                };
+            if let Some(arg) = text.strip_prefix("/export") {
+                if arg.is_empty() || arg.starts_with(' ') {
+                    handle_export_command(&ctx, &chat_id, &session_key, arg).await;
+                    continue;
+                }
+            }
 
-                let _ = task_adapter.ack_processing(&chat_id, &message_id).await;
-                let _ = task_adapter.send_typing(&chat_id).await;
+            if let Some(arg) = text.strip_prefix("/workspace") {
+                if arg.is_empty() || arg.starts_with(' ') {
+                    handle_workspace_command(&ctx, &msg, &session_key, arg.trim()).await;
+                    continue;
+                }
+            }
 
-                let (port, is_new_sidecar) = match task_router
-                    .lock()
-                    .await
-                    .ensure_sidecar(
-                        &session_key,
-                        &task_app,
-                        &task_manager,
-                        &task_config,
-                    )
-                    .await
-                {
-                    Ok(result) => result,
-                    Err(e) => {
-                        let _ = task_adapter.ack_clear(&chat_id, &message_id).await;
-                        let err_msg = format!("Failed to start Sidecar: {}", e);
-                        ulog_error!("[im] {}", err_msg);
-                        if matches!(e, RouteError::Unavailable(_)) {
-                            task_buffer.lock().await.push(&msg);
-                        }
-                        let _ = task_adapter
-                            .send_message(&chat_id, &format!("Error: {}", err_msg))
-                            .await;
-                        return;
-                    }
+            if let Some(arg) = text.strip_prefix("/link") {
+                if arg.is_empty() || arg.starts_with(' ') {
+                    handle_link_command(&ctx, &msg, &base_key, arg.trim()).await;
+                    continue;
+                }
+            }
+
+            if text == "/unlink" {
+                let reply = if ctx.identities.unlink(&msg.platform, &msg.sender_id) {
+                    "This account is no longer linked. New messages start a separate conversation."
+                } else {
+                    "This account isn't linked to any other account."
                 };
+                ctx.outbox.deliver(&chat_id, reply).await;
+                continue;
+            }
 
-                if is_new_sidecar {
-                    let router_guard = task_router.lock().await;
-                    router_guard
-                        .sync_ai_config(
-                            port,
-                            task_config.model.as_deref(),
-                            task_config.mcp_servers_json.as_deref(),
-                            task_provider_env.as_ref(),
-                        )
-                        .await;
-                    router_guard
-                        .sync_permission_mode(port, &task_config.permission_mode)
+            let mut msg = msg;
+            match ctx.agent_routes.select(&chat_id, &text) {
+                RouteSelection::Switched(name) => {
+                    ctx.outbox
+                        .deliver(&chat_id, &format!("Now talking to {}.", name))
                         .await;
+                    continue;
+                }
+                RouteSelection::Message { route, text: routed_text } => {
+                    session_key = agent_routes::routed_session_key(&base_key, route.as_deref());
+                    msg.text = routed_text;
                 }
+            }
+            let text = msg.text.trim().to_string();
 
-                let source = format!(
-                    "{}_{}",
-                    msg.platform,
-                    match msg.source_type {
-                        types::ImSourceType::Private => "private",
-                        types::ImSourceType::Group => "group",
+            transcript::record_inbound(
+                &health::agent_channel_message_log_path(
+                    &ctx.config.agent_id,
+                    &ctx.config.channel_id,
+                ),
+                &session_key,
+                &msg,
+            );
+
+            ulog_info!(
+                "[im] Routing message from {} to Sidecar (session_key={}, {} chars)",
+                msg.sender_name.as_deref().unwrap_or("?"),
+                session_key,
+                text.len(),
+            );
+
+            let task_ctx = ctx.clone();
+            let mut ticket = ctx
+                .turns
+                .register(&chat_id, &message_id, &session_key, &msg.text);
+
+            tokio::spawn(async move {
+                let mut msg = msg;
+                // Keep per-session order: while earlier messages of this session are
+                // still queued, this one waits behind them.
+                if task_ctx.queue.lock().await.has_session(&session_key) {
+                    let Some(text) = task_ctx.turns.release(&ticket) else {
+                        return;
+                    };
+                    msg.text = text;
+                    if enqueue_message(&task_ctx, &msg, &session_key).await {
+                        task_ctx
+                            .outbox
+                            .deliver(
+                                &msg.chat_id,
+                                "Your earlier messages are still waiting for the agent. \
+                                 This one is queued behind them.",
+                            )
+                            .await;
                     }
-                );
+                    return;
+                }
 
-                // Replay buffered messages for this session
-                if is_new_sidecar {
-                    let mut replay_count = 0u32;
-                    loop {
-                        let buffered =
-                            task_buffer.lock().await.pop_for_session(&session_key);
-                        let bm = match buffered {
-                            Some(bm) => bm,
-                            None => break,
-                        };
-                        replay_count += 1;
-                        let replay_body = json!({
-                            "message": bm.text,
-                            "agentDir": task_config.workspace_path,
-                            "permissionMode": task_config.permission_mode,
-                            "sessionId": task_router.lock().await.get_session_id(&session_key),
-                            "metadata": {
-                                "source": source,
-                                "sourceId": bm.sender_id,
-                                "senderName": bm.sender_name,
-                            },
-                        });
-                        let replay_url =
-                            format!("http://127.0.0.1:{}/api/im/chat", port);
-                        match task_stream_client
-                            .post(&replay_url)
-                            .json(&replay_body)
-                            .send()
-                            .await
-                        {
-                            Ok(resp) if resp.status().is_success() => {
-                                if let Err(e) = consume_sse_stream(
-                                    resp,
-                                    task_adapter.as_ref(),
-                                    &bm.chat_id,
-                                )
-                                .await
-                                {
-                                    ulog_warn!(
-                                        "[im] Buffer replay stream error: {}",
-                                        e
-                                    );
-                                }
-                            }
-                            Ok(resp) => {
-                                ulog_warn!(
-                                    "[im] Buffer replay HTTP {}",
-                                    resp.status()
-                                );
-                            }
-                            Err(e) => {
-                                ulog_warn!(
-                                    "[im] Buffer replay request failed: {}",
-                                    e
-                                );
-                                break;
-                            }
-                        }
+                let _permit = task_ctx.acquire_turn_slot(&msg, true).await;
+
+                // Recalled while waiting for a slot
+                match task_ctx.turns.begin(&ticket) {
+                    Some(text) => msg.text = text,
+                    None => {
+                        ulog_info!("[im] Message {} was recalled, skipping", msg.message_id);
+                        return;
                     }
-                    if replay_count > 0 {
-                        ulog_info!(
-                            "[im] Replayed {} buffered message(s) for {}",
-                            replay_count,
-                            session_key
-                        );
-                        if let Err(e) = task_buffer.lock().await.save_to_disk() {
-                            ulog_warn!(
-                                "[im] Failed to persist buffer after replay: {}",
-                                e
-                            );
-                        }
+                }
+
+                if let TurnOutcome::Deferred(reason) =
+                    run_turn(&task_ctx, &msg, &session_key, None, &mut ticket).await
+                {
+                    ulog_warn!(
+                        "[im] Sidecar unavailable for {}, queueing message: {}",
+                        session_key,
+                        reason
+                    );
+                    let Some(text) = task_ctx.turns.release(&ticket) else {
+                        return;
+                    };
+                    msg.text = text;
+                    if enqueue_message(&task_ctx, &msg, &session_key).await {
+                        task_ctx
+                            .outbox
+                            .deliver(
+                                &msg.chat_id,
+                                &format!(
+                                    "The agent is unavailable right now ({}). \
+                                     Your message is queued and will be processed automatically.",
+                                    reason
+                                ),
+                            )
+                            .await;
                     }
+                } else {
+                    task_ctx.turns.finish(&ticket);
                 }
+            });
+        }
 
-                let peer_session_id =
-                    task_router.lock().await.get_session_id(&session_key);
-
-                let mut body = json!({
-                    "message": text,
-                    "agentDir": task_config.workspace_path,
-                    "permissionMode": task_config.permission_mode,
-                    "sessionId": peer_session_id,
-                    "metadata": {
-                        "source": source,
-                        "sourceId": msg.sender_id,
-                        "senderName": msg.sender_name,
-                    },
-                });
-                if let Some(ref model) = task_config.model {
-                    body["model"] = json!(model);
+        ulog_info!("[im] Message processing loop exited");
+    })
+}
+
+/// Apply an edit to the message it targets. A waiting turn or queued message picks up
+/// the new text; a turn that already started is re-run (returned as a new message)
+/// when the channel opts in, otherwise the edit is ignored.
+async fn handle_edited_message(ctx: &TurnContext, mut msg: ImMessage) -> Option<ImMessage> {
+    let chat_id = msg.chat_id.clone();
+    let message_id = msg.message_id.clone();
+    match ctx.turns.apply_edit(&chat_id, &message_id, &msg.text) {
+        EditOutcome::Replaced => {
+            ulog_info!("[im] Message {} edited before processing, using new text", message_id);
+            None
+        }
+        EditOutcome::Started { session_key, running } => {
+            if !ctx.config.rerun_on_edit.unwrap_or(false) {
+                ulog_info!("[im] Message {} edited after it was answered, ignoring", message_id);
+                return None;
+            }
+            if running {
+                ctx.turns.cancel(&chat_id, &message_id);
+                stop_sidecar_run(ctx, &session_key).await;
+            }
+            ulog_info!("[im] Message {} edited, re-running its turn", message_id);
+            msg.event = ImMessageEvent::New;
+            Some(msg)
+        }
+        EditOutcome::Unknown => {
+            let edited = ctx
+                .queue
+                .lock()
+                .await
+                .edit_message(&chat_id, &message_id, &msg.text);
+            if edited {
+                ulog_info!("[im] Queued message {} edited", message_id);
+            }
+            None
+        }
+    }
+}
+
+/// Drop a recalled message: queued or waiting turns are cancelled, a streaming turn is
+/// stopped, and an answered one optionally has the bot's reply deleted.
+async fn handle_recalled_message(ctx: &TurnContext, msg: &ImMessage) {
+    let chat_id = msg.chat_id.as_str();
+    let message_id = msg.message_id.as_str();
+
+    let removed = ctx.queue.lock().await.remove_message(chat_id, message_id);
+    if removed.is_some() {
+        ulog_info!("[im] Recalled message {} removed from the queue", message_id);
+        ctx.update_queue_depth().await;
+    }
+    if ctx.review.discard_for_message(chat_id, message_id) > 0 {
+        ulog_info!("[im] Reply to recalled message {} dropped from review", message_id);
+    }
+
+    match ctx.turns.apply_recall(chat_id, message_id) {
+        RecallOutcome::CancelledWaiting => {
+            ulog_info!("[im] Message {} recalled before processing", message_id);
+        }
+        RecallOutcome::CancelledRunning { session_key } => {
+            ulog_info!("[im] Message {} recalled while streaming, stopping", message_id);
+            stop_sidecar_run(ctx, &session_key).await;
+        }
+        RecallOutcome::Answered { reply_ids } => {
+            if !ctx.config.delete_reply_on_recall.unwrap_or(false) {
+                return;
+            }
+            for reply_id in &reply_ids {
+                if let Err(e) = ctx.adapter.delete_message(chat_id, reply_id).await {
+                    ulog_warn!("[im] Failed to delete reply {}: {}", reply_id, e);
                 }
-                if let Some(ref penv) = task_provider_env {
-                    body["providerEnv"] = penv.clone();
+            }
+        }
+        RecallOutcome::Unknown => {}
+    }
+}
+
+/// Abort whatever the session's Sidecar is generating (cancelled turns)
+async fn stop_sidecar_run(ctx: &TurnContext, session_key: &str) {
+    let (port, client) = {
+        let router = ctx.router.lock().await;
+        let port = router
+            .get_peer_session(session_key)
+            .map(|ps| ps.sidecar_port)
+            .unwrap_or(0);
+        (port, router.http_client().clone())
+    };
+    if port == 0 {
+        return;
+    }
+    let url = format!("http://127.0.0.1:{}/chat/stop", port);
+    if let Err(e) = client.post(&url).json(&json!({})).send().await {
+        ulog_warn!("[im] Failed to stop Sidecar run for {}: {}", session_key, e);
+    }
+}
+
+/// `/agent` lists the channel's agents, `/agent name` switches this chat to one,
+/// `/agent default` switches back to the channel's own agent.
+async fn handle_agent_command(ctx: &TurnContext, chat_id: &str, arg: &str) {
+    let names = ctx.agent_routes.names();
+    if names.is_empty() {
+        ctx.outbox
+            .deliver(chat_id, "This bot has no other agents configured.")
+            .await;
+        return;
+    }
+    if arg.is_empty() {
+        let current = ctx.agent_routes.current(chat_id);
+        let reply = format!(
+            "Current agent: {}\nAvailable: default, {}\n\n\
+             Switch with /agent <name>, or address one with @name or /ask <name> ...",
+            current.as_deref().unwrap_or("default"),
+            names.join(", ")
+        );
+        ctx.outbox.deliver(chat_id, &reply).await;
+        return;
+    }
+    let target = (!arg.eq_ignore_ascii_case("default")).then_some(arg);
+    let reply = match ctx.agent_routes.set_current(chat_id, target) {
+        Ok(Some(name)) => format!("Now talking to {}.", name),
+        Ok(None) => "Now talking to the default agent.".to_string(),
+        Err(e) => format!("{}. Available: default, {}", e, names.join(", ")),
+    };
+    ctx.outbox.deliver(chat_id, &reply).await;
+}
+
+/// `/workspace` lists the agent's allowed workspaces, `/workspace name|number` moves
+/// this chat to one, `/workspace default` moves it back. Switching is limited to the
+/// channel's admins (any allowed user when none are configured).
+async fn handle_workspace_command(
+    ctx: &TurnContext,
+    msg: &ImMessage,
+    session_key: &str,
+    arg: &str,
+) {
+    let chat_id = msg.chat_id.as_str();
+    let allowlist = &ctx.config.workspace_allowlist;
+    if allowlist.is_empty() {
+        ctx.outbox
+            .deliver(chat_id, "This agent has no other workspaces configured.")
+            .await;
+        return;
+    }
+    let current = ctx.router.lock().await.chat_workspace(session_key, allowlist);
+
+    if arg.is_empty() {
+        let reply = format!(
+            "Current workspace: {}\n\nAvailable:\n{}\n\n\
+             Switch with /workspace <name or number>, or /workspace default.",
+            current.as_deref().unwrap_or(&ctx.config.workspace_path),
+            workspaces::format_list(allowlist, current.as_deref())
+        );
+        ctx.outbox.deliver(chat_id, &reply).await;
+        return;
+    }
+
+    let admins = &ctx.config.admin_users;
+    if !admins.is_empty() && !admins.iter().any(|a| a == &msg.sender_id) {
+        ctx.outbox
+            .deliver(chat_id, "Only channel admins can change the workspace.")
+            .await;
+        return;
+    }
+
+    let target = if arg.eq_ignore_ascii_case("default") {
+        None
+    } else {
+        match workspaces::resolve_choice(allowlist, arg) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                ctx.outbox
+                    .deliver(chat_id, &format!("{}. Send /workspace to see the list.", e))
+                    .await;
+                return;
+            }
+        }
+    };
+    let changed = ctx.router.lock().await.set_chat_workspace(
+        session_key,
+        target,
+        &ctx.sidecar_manager,
+    );
+    let reply = match (target, changed) {
+        (_, false) => "This chat already uses that workspace.".to_string(),
+        (Some(path), true) => format!(
+            "Switched to {} ({}). A new conversation was started.",
+            workspaces::display_name(path),
+            path
+        ),
+        (None, true) => format!(
+            "Switched back to the default workspace ({}). A new conversation was started.",
+            ctx.config.workspace_path
+        ),
+    };
+    ctx.outbox.deliver(chat_id, &reply).await;
+}
+
+/// Record a message of a taken-over session and stream it to the desktop
+async fn forward_to_operator(ctx: &TurnContext, session_key: &str, msg: &ImMessage) {
+    transcript::record_inbound(
+        &health::agent_channel_message_log_path(&ctx.config.agent_id, &ctx.config.channel_id),
+        session_key,
+        msg,
+    );
+    let Some(message) = ctx.takeover.record_inbound(session_key, msg) else {
+        return;
+    };
+    let _ = ctx.adapter.ack_received(&msg.chat_id, &msg.message_id).await;
+    ulog_info!(
+        "[im-takeover] Forwarding message from {} to the operator (session_key={})",
+        msg.sender_name.as_deref().unwrap_or("?"),
+        session_key,
+    );
+    emit_takeover_message(
+        &ctx.app,
+        &ctx.config.agent_id,
+        &ctx.config.channel_id,
+        session_key,
+        &msg.chat_id,
+        &message,
+    );
+}
+
+fn emit_takeover_message(
+    app: &AppHandle,
+    agent_id: &str,
+    channel_id: &str,
+    session_key: &str,
+    chat_id: &str,
+    message: &TakeoverMessage,
+) {
+    use tauri::Emitter;
+    let event = takeover::TakeoverEvent {
+        agent_id,
+        channel_id,
+        session_key,
+        chat_id,
+        message,
+    };
+    let _ = app.emit(takeover::TAKEOVER_MESSAGE_EVENT, &event);
+}
+
+fn emit_takeover_changed(
+    app: &AppHandle,
+    agent_id: &str,
+    channel_id: &str,
+    session_key: &str,
+    active: bool,
+) {
+    use tauri::Emitter;
+    let _ = app.emit(
+        takeover::TAKEOVER_CHANGED_EVENT,
+        json!({
+            "agentId": agent_id,
+            "channelId": channel_id,
+            "sessionKey": session_key,
+            "active": active,
+        }),
+    );
+}
+
+/// `/link` issues a verification code for the sender's account; `/link <code>` sent from
+/// another account (any platform) to the same agent links the two, so their private chats
+/// share one session. The conversation `/link` was issued from carries on as the shared one.
+async fn handle_link_command(ctx: &TurnContext, msg: &ImMessage, base_key: &str, arg: &str) {
+    let chat_id = msg.chat_id.as_str();
+    if msg.source_type != types::ImSourceType::Private {
+        ctx.outbox
+            .deliver(chat_id, "Use /link in a private chat with the bot.")
+            .await;
+        return;
+    }
+
+    if arg.is_empty() {
+        let session_seed = ctx
+            .router
+            .lock()
+            .await
+            .get_session_id(base_key);
+        let code = ctx.identities.start_link(
+            &ctx.config.agent_id,
+            msg.platform.clone(),
+            &msg.sender_id,
+            msg.sender_name.clone(),
+            session_seed,
+        );
+        ctx.outbox
+            .deliver(
+                chat_id,
+                &format!(
+                    "Your link code is {}. Within 10 minutes, send \"/link {}\" to this \
+                     assistant from your other account to share conversations between them.",
+                    code, code
+                ),
+            )
+            .await;
+        return;
+    }
+
+    let reply = match ctx.identities.complete_link(
+        &ctx.config.agent_id,
+        arg,
+        msg.platform.clone(),
+        &msg.sender_id,
+        msg.sender_name.clone(),
+    ) {
+        Ok(person) => {
+            let accounts = person
+                .accounts
+                .iter()
+                .map(|a| a.platform.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "Linked. Your accounts ({}) now share one conversation.",
+                accounts
+            )
+        }
+        Err(e) => format!("Link failed: {}.", e),
+    };
+    ctx.outbox.deliver(chat_id, &reply).await;
+}
+
+/// `/export [format]` — write the chat's transcript and upload it back to the chat.
+/// Platforms without file upload get the saved path instead.
+async fn handle_export_command(ctx: &TurnContext, chat_id: &str, session_key: &str, arg: &str) {
+    let Some(format) = transcript::TranscriptFormat::from_arg(arg) else {
+        ctx.outbox
+            .deliver(chat_id, "Usage: /export [md|html|jsonl]")
+            .await;
+        return;
+    };
+    let Some(session_id) = ctx.router.lock().await.get_session_id(session_key) else {
+        ctx.outbox
+            .deliver(chat_id, "Nothing to export yet — this chat has no conversation.")
+            .await;
+        return;
+    };
+
+    let exported = match transcript::export_transcript(
+        &ctx.config.agent_id,
+        &ctx.config.channel_id,
+        session_key,
+        &session_id,
+        format,
+    ) {
+        Ok(exported) => exported,
+        Err(e) => {
+            ulog_error!("[im] Transcript export failed for {}: {}", session_key, e);
+            ctx.outbox
+                .deliver(chat_id, &format!("Export failed: {}", e))
+                .await;
+            return;
+        }
+    };
+    ulog_info!(
+        "[im] Exported transcript for {} ({} messages) to {}",
+        session_key,
+        exported.message_count,
+        exported.path
+    );
+
+    let caption = format!("Conversation transcript ({} messages)", exported.message_count);
+    let upload = match std::fs::read(&exported.path) {
+        Ok(data) => {
+            ctx.adapter
+                .send_file(chat_id, &exported.file_name, data, Some(&caption))
+                .await
+        }
+        Err(e) => Err(format!("Failed to read transcript: {}", e)),
+    };
+    match upload {
+        Ok(()) => ctx.outbox.record_sent(),
+        Err(e) => {
+            ulog_warn!("[im] Transcript upload failed: {}", e);
+            ctx.outbox
+                .deliver(
+                    chat_id,
+                    &format!(
+                        "{} saved on the desktop at {} (upload failed: {})",
+                        caption, exported.path, e
+                    ),
+                )
+                .await;
+        }
+    }
+}
+
+/// Run one message through its Sidecar and stream the reply back to the chat.
+/// `delayed` is set when the message comes out of the inbound queue.
+/// A turn cancelled through `ticket` (recall, re-run edit) ends quietly.
+async fn run_turn(
+    ctx: &TurnContext,
+    msg: &ImMessage,
+    session_key: &str,
+    delayed: Option<&BufferedMessage>,
+    ticket: &mut TurnTicket,
+) -> TurnOutcome {
+    let adapter = ctx.adapter.as_ref();
+    let chat_id = msg.chat_id.as_str();
+    let message_id = msg.message_id.as_str();
+    let text = msg.text.trim();
+    let turn_started = Instant::now();
+
+    let _ = adapter.ack_processing(chat_id, message_id).await;
+    let _ = adapter.send_typing(chat_id).await;
+    // Dropped on every early return; handed to the stream consumer otherwise
+    let typing = TypingKeepAlive::start(&ctx.adapter, chat_id);
+
+    // Workspace / model / permission mode of the agent this session is routed to,
+    // then the workspace this chat picked with /workspace
+    let mut config = ctx.agent_routes.resolve_config(&ctx.config, session_key);
+    if let Some(workspace) = ctx
+        .router
+        .lock()
+        .await
+        .chat_workspace(session_key, &ctx.config.workspace_allowlist)
+    {
+        config.workspace_path = workspace;
+    }
+    let provider_env: Option<serde_json::Value> = config
+        .provider_env_json
+        .as_ref()
+        .and_then(|json_str| serde_json::from_str(json_str).ok());
+    let system_prompt = ctx
+        .persona
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .resolve(&ctx.config.workspace_path);
+
+    let (port, is_new_sidecar) = match ctx
+        .router
+        .lock()
+        .await
+        .ensure_sidecar(session_key, &ctx.app, &ctx.sidecar_manager, &config)
+        .await
+    {
+        Ok(result) => result,
+        Err(RouteError::Unavailable(e)) => {
+            ctx.record_error("unavailable");
+            let _ = adapter.ack_clear(chat_id, message_id).await;
+            return TurnOutcome::Deferred(e);
+        }
+        Err(e) => {
+            ctx.record_error(e.kind());
+            let _ = adapter.ack_clear(chat_id, message_id).await;
+            let err_msg = format!("Failed to start Sidecar: {}", e);
+            ulog_error!("[im] {}", err_msg);
+            ctx.outbox
+                .deliver(chat_id, &format!("Error: {}", err_msg))
+                .await;
+            return TurnOutcome::Done;
+        }
+    };
+
+    if is_new_sidecar {
+        let router_guard = ctx.router.lock().await;
+        router_guard
+            .sync_ai_config(
+                port,
+                config.model.as_deref(),
+                config.mcp_servers_json.as_deref(),
+                provider_env.as_ref(),
+                system_prompt.as_deref(),
+            )
+            .await;
+        router_guard
+            .sync_permission_mode(port, &config.permission_mode)
+            .await;
+    }
+
+    if let Some(entry) = delayed {
+        let sent_at = entry
+            .received_at()
+            .map(|t| t.with_timezone(&chrono::Local).format("%H:%M").to_string())
+            .unwrap_or_else(|| "earlier".to_string());
+        ctx.outbox
+            .deliver(
+                chat_id,
+                &format!(
+                    "Processing your delayed message from {}: \"{}\"",
+                    sent_at,
+                    message_preview(&entry.text)
+                ),
+            )
+            .await;
+    }
+
+    let source = format!(
+        "{}_{}",
+        msg.platform,
+        match msg.source_type {
+            types::ImSourceType::Private => "private",
+            types::ImSourceType::Group => "group",
+        }
+    );
+
+    let peer_session_id = ctx.router.lock().await.get_session_id(session_key);
+
+    // What an operator said while the session was taken over reaches the agent
+    // together with the next message
+    let takeover_context = ctx.takeover.pending_context(session_key);
+    let message = match &takeover_context {
+        Some(context) => takeover::with_context(context, text),
+        None => text.to_string(),
+    };
+
+    let mut body = json!({
+        "message": message,
+        "agentDir": config.workspace_path,
+        "permissionMode": config.permission_mode,
+        "sessionId": peer_session_id,
+        "systemPrompt": system_prompt,
+        "metadata": {
+            "source": source,
+            "sourceId": msg.sender_id,
+            "senderName": msg.sender_name,
+        },
+    });
+    if let Some(ref model) = config.model {
+        body["model"] = json!(model);
+    }
+    if let Some(ref penv) = provider_env {
+        body["providerEnv"] = penv.clone();
+    }
+
+    if ticket.is_cancelled() {
+        let _ = adapter.ack_clear(chat_id, message_id).await;
+        return TurnOutcome::Done;
+    }
+
+    let url = format!("http://127.0.0.1:{}/api/im/chat", port);
+    ulog_info!("[im-stream] POST {} (SSE)", url);
+
+    let response = match ctx.stream_client.post(&url).json(&body).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            ulog_error!("[im] SSE request failed: {}", e);
+            ctx.record_error("connection");
+            let _ = adapter.ack_clear(chat_id, message_id).await;
+            return TurnOutcome::Deferred(format!("Connection error: {}", e));
+        }
+    };
+
+    if !response.status().is_success() {
+        let error = RouteError::Response(
+            response.status().as_u16(),
+            response.text().await.unwrap_or_default(),
+        );
+        ulog_error!("[im] Sidecar returned {}", error);
+        ctx.record_error(error.kind());
+        let _ = adapter.ack_clear(chat_id, message_id).await;
+        if let RouteError::Response(status, error_text) = &error {
+            ctx.outbox
+                .deliver(
+                    chat_id,
+                    &format!("Sidecar error ({}): {}", status, error_text),
+                )
+                .await;
+        }
+        return TurnOutcome::Done;
+    }
+    if takeover_context.is_some() {
+        ctx.takeover.clear_context(session_key);
+    }
+
+    let mut tools = ToolTracker::new(ctx.config.tool_activity.unwrap_or_default());
+    let reviewed = ctx.config.review_before_send.unwrap_or(false);
+    let streamed = if reviewed {
+        let streamed =
+            collect_reply_for_review(response, ctx, msg, session_key, turn_started, ticket).await;
+        drop(typing);
+        streamed
+    } else {
+        let options = StreamOptions {
+            // Native drafts only exist in private chats
+            use_drafts: adapter.use_draft_streaming()
+                && msg.source_type == types::ImSourceType::Private,
+            typing,
+        };
+        consume_sse_stream(response, ctx, chat_id, turn_started, ticket, &mut tools, options)
+            .await
+    };
+    match streamed {
+        Ok(outcome) => {
+            if outcome.cancelled {
+                ulog_info!("[im] Turn cancelled for {}", session_key);
+            } else {
+                ulog_info!("[im] Stream complete for {}", session_key);
+                if ctx.config.tool_activity_log.unwrap_or(false) && !reviewed {
+                    if let Some(log) = tools.log() {
+                        ctx.outbox.deliver(chat_id, &log).await;
+                    }
                 }
+            }
+            if let Some(usage) = outcome.usage {
+                record_turn_usage(ctx, msg, session_key, usage).await;
+            }
+            // Native drafts are virtual; deleting them on recall would be a no-op
+            let reply_ids = outcome
+                .reply_ids
+                .into_iter()
+                .filter(|id| parse_draft_id(id).is_none())
+                .collect();
+            ctx.turns.record_replies(ticket, reply_ids);
+        }
+        // Stopped Sidecar runs may end the stream with an error
+        Err(_) if ticket.is_cancelled() => {
+            ulog_info!("[im] Turn cancelled for {}", session_key);
+        }
+        Err(e) => {
+            ulog_error!("[im] Stream error for {}: {}", session_key, e);
+            ctx.record_error("stream");
+            ctx.outbox.deliver(chat_id, &format!("Error: {}", e)).await;
+        }
+    }
+    metrics::observe(
+        metrics::IM_TURN_SECONDS,
+        &ctx.metric_labels,
+        turn_started.elapsed().as_secs_f64(),
+    );
+
+    let _ = adapter.ack_clear(chat_id, message_id).await;
+
+    ctx.router.lock().await.record_response(session_key);
+    ctx.health
+        .set_last_message_at(chrono::Utc::now().to_rfc3339())
+        .await;
+    ctx.health
+        .set_active_sessions(ctx.router.lock().await.get_active_sessions())
+        .await;
+    TurnOutcome::Done
+}
 
-                let url = format!("http://127.0.0.1:{}/api/im/chat", port);
-                ulog_info!("[im-stream] POST {} (SSE)", url);
+/// Count a finished turn in the channel's health state and usage log
+async fn record_turn_usage(
+    ctx: &TurnContext,
+    msg: &ImMessage,
+    session_key: &str,
+    usage: TurnUsage,
+) {
+    let at = chrono::Utc::now().to_rfc3339();
+    ctx.health.record_usage(session_key, &usage, &at).await;
+    let record = usage::UsageRecord::new(msg, session_key, usage, &at);
+    let path = health::agent_channel_usage_log_path(&ctx.config.agent_id, &ctx.config.channel_id);
+    let _ = tokio::task::spawn_blocking(move || usage::record_turn(&path, &record)).await;
+}
 
-                let response = match task_stream_client
-                    .post(&url)
-                    .json(&body)
-                    .send()
-                    .await
-                {
-                    Ok(resp) => resp,
-                    Err(e) => {
-                        ulog_error!("[im] SSE request failed: {}", e);
-                        let _ = task_adapter.ack_clear(&chat_id, &message_id).await;
-                        task_buffer.lock().await.push(&msg);
-                        let _ = task_adapter
-                            .send_message(&chat_id, &format!("Connection error: {}", e))
-                            .await;
-                        return;
+// ===== Inbound Queue =====
+
+/// First retry delay for the drain worker; doubles while Sidecars stay unavailable
+const DRAIN_RETRY_BASE_SECS: u64 = 5;
+/// Upper bound for the drain worker retry delay
+const DRAIN_RETRY_MAX_SECS: u64 = 300;
+
+/// Durably queue a message for the drain worker. Returns false (after telling
+/// the user) if the queue could not be written.
+async fn enqueue_message(ctx: &TurnContext, msg: &ImMessage, session_key: &str) -> bool {
+    let pushed = ctx.queue.lock().await.push(msg, session_key);
+    match pushed {
+        Ok(evicted) => {
+            for entry in &evicted {
+                notify_dropped(&ctx.outbox, entry, DropReason::Overflow).await;
+            }
+            ctx.update_queue_depth().await;
+            ctx.drain_notify.notify_one();
+            true
+        }
+        Err(e) => {
+            ulog_error!("[im-queue] Failed to queue message: {}", e);
+            ctx.outbox
+                .deliver(
+                    &msg.chat_id,
+                    "Error: The agent is unavailable and your message could not be queued. \
+                     Please try again later.",
+                )
+                .await;
+            false
+        }
+    }
+}
+
+async fn notify_dropped(outbox: &Outbox, entry: &BufferedMessage, reason: DropReason) {
+    ulog_warn!(
+        "[im-queue] Dropping queued message from chat {} ({:?})",
+        entry.chat_id,
+        reason
+    );
+    outbox
+        .deliver(
+            &entry.chat_id,
+            &format!(
+                "Your message \"{}\" was not processed because {}. Please send it again.",
+                message_preview(&entry.text),
+                reason.describe()
+            ),
+        )
+        .await;
+}
+
+/// Short single-line preview of a message for notices
+fn message_preview(text: &str) -> String {
+    const PREVIEW_CHARS: usize = 40;
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() > PREVIEW_CHARS {
+        let cut: String = flat.chars().take(PREVIEW_CHARS).collect();
+        format!("{}...", cut)
+    } else {
+        flat
+    }
+}
+
+/// Background worker that replays queued messages once their Sidecar is
+/// reachable again, oldest first, backing off while it stays unavailable.
+fn spawn_queue_drain_loop(
+    mut shutdown_rx: watch::Receiver<bool>,
+    ctx: TurnContext,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let base_delay = Duration::from_secs(DRAIN_RETRY_BASE_SECS);
+        let mut delay = base_delay;
+
+        loop {
+            tokio::select! {
+                _ = ctx.drain_notify.notified() => {}
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        break;
                     }
+                    continue;
+                }
+            }
+
+            let expired = ctx.queue.lock().await.expire(chrono::Utc::now());
+            for entry in &expired {
+                notify_dropped(&ctx.outbox, entry, DropReason::Expired).await;
+            }
+
+            // Sessions whose Sidecar couldn't take a turn this pass; their messages wait
+            // for the next pass while other sessions keep draining
+            let mut deferred_sessions: HashSet<String> = HashSet::new();
+            while !*shutdown_rx.borrow() {
+                let Some(next) = ctx.queue.lock().await.front_excluding(&deferred_sessions)
+                else {
+                    break;
                 };
+                let _permit = ctx
+                    .acquire_turn_slot(&next.to_im_message(ctx.config.platform.clone()), false)
+                    .await;
 
-                if !response.status().is_success() {
-                    let status = response.status().as_u16();
-                    let error_text = response.text().await.unwrap_or_default();
-                    ulog_error!("[im] Sidecar returned {}: {}", status, error_text);
-                    let _ = task_adapter.ack_clear(&chat_id, &message_id).await;
-                    let _ = task_adapter
-                        .send_message(
-                            &chat_id,
-                            &format!("Sidecar error ({}): {}", status, error_text),
-                        )
-                        .await;
-                    return;
-                }
+                // Read after the wait so edits / recalls made meanwhile are honoured
+                let entry = match ctx.queue.lock().await.front_excluding(&deferred_sessions) {
+                    Some(e) => e,
+                    None => break,
+                };
 
-                let stream_result =
-                    consume_sse_stream(response, task_adapter.as_ref(), &chat_id)
-                        .await;
+                let msg = entry.to_im_message(ctx.config.platform.clone());
+                // Keeps the agent the message was routed to when it was queued
+                let session_key = entry.session_key.clone();
+
+                let mut ticket =
+                    ctx.turns
+                        .register(&msg.chat_id, &msg.message_id, &session_key, &msg.text);
+                ctx.turns.begin(&ticket);
 
-                match stream_result {
-                    Ok(_) => {
-                        ulog_info!("[im] Stream complete for {}", session_key);
+                match run_turn(&ctx, &msg, &session_key, Some(&entry), &mut ticket).await {
+                    TurnOutcome::Done => {
+                        ctx.turns.finish(&ticket);
+                        ctx.queue.lock().await.remove(&entry.id);
+                        ulog_info!("[im-queue] Delivered queued message for {}", session_key);
                     }
-                    Err(e) => {
-                        ulog_error!("[im] Stream error for {}: {}", session_key, e);
-                        let _ = task_adapter
-                            .send_message(&chat_id, &format!("Error: {}", e))
+                    TurnOutcome::Deferred(reason) => {
+                        ctx.turns.release(&ticket);
+                        ulog_warn!(
+                            "[im-queue] Sidecar still unavailable for {}: {}",
+                            session_key,
+                            reason
+                        );
+                        let dropped = ctx.queue.lock().await.record_attempt(&entry.id);
+                        if let Some(dropped) = dropped {
+                            notify_dropped(
+                                &ctx.outbox,
+                                &dropped,
+                                DropReason::RetriesExhausted,
+                            )
                             .await;
+                        }
+                        deferred_sessions.insert(session_key);
                     }
                 }
+            }
 
-                let _ = task_adapter.ack_clear(&chat_id, &message_id).await;
+            ctx.update_queue_depth().await;
 
-                task_router.lock().await.record_response(&session_key);
-                task_health
-                    .set_last_message_at(chrono::Utc::now().to_rfc3339())
-                    .await;
-                task_health
-                    .set_active_sessions(
-                        task_router.lock().await.get_active_sessions(),
-                    )
-                    .await;
-                task_health
-                    .set_buffered_messages(task_buffer.lock().await.len())
-                    .await;
-            });
+            delay = if !deferred_sessions.is_empty() {
+                (delay * 2).min(Duration::from_secs(DRAIN_RETRY_MAX_SECS))
+            } else {
+                base_delay
+            };
         }
-
-        ulog_info!("[im] Message processing loop exited");
     })
 }
 
 // ===== SSE Stream Consumption =====
 
+/// How to show a reply while it streams
+struct StreamOptions {
+    /// Stream through native drafts (Telegram private chats)
+    use_drafts: bool,
+    /// Stopped once something visible is sent
+    typing: Option<TypingKeepAlive>,
+}
+
+/// How a streamed reply ended
+struct StreamOutcome {
+    /// Bot messages known to hold the reply (edited-in-place drafts / placeholders)
+    reply_ids: Vec<String>,
+    /// Stopped early by a recall or re-run edit
+    cancelled: bool,
+    /// Token usage reported by the Sidecar's `complete` event
+    usage: Option<TurnUsage>,
+}
+
 async fn consume_sse_stream(
     response: reqwest::Response,
-    adapter: &dyn ImStreamAdapter,
+    ctx: &TurnContext,
     chat_id: &str,
-) -> Result<(), String> {
+    turn_started: Instant,
+    ticket: &mut TurnTicket,
+    tools: &mut ToolTracker,
+    StreamOptions {
+        mut use_drafts,
+        mut typing,
+    }: StreamOptions,
+) -> Result<StreamOutcome, String> {
+    let adapter = ctx.adapter.as_ref();
+    let outbox = ctx.outbox.as_ref();
+    let metric_labels = &ctx.metric_labels;
     let mut byte_stream = response.bytes_stream();
     let mut sse_buffer = String::new();
 
@@ -808,8 +2223,28 @@ async fn consume_sse_stream(
     let mut any_text_sent = false;
     let mut placeholder_id: Option<String> = None;
     let mut first_content_sent = false;
-
-    while let Some(chunk_result) = byte_stream.next().await {
+    let mut first_token_seen = false;
+    let mut reply_ids: Vec<String> = Vec::new();
+    // Last finalized block: (message edited in place, text), for the usage footer
+    let mut last_block: Option<(Option<String>, String)> = None;
+
+    loop {
+        let chunk_result = tokio::select! {
+            chunk = byte_stream.next() => match chunk {
+                Some(c) => c,
+                None => break,
+            },
+            _ = ticket.cancelled() => {
+                for id in draft_id.iter().chain(placeholder_id.iter()) {
+                    let _ = adapter.delete_message(chat_id, id).await;
+                }
+                return Ok(StreamOutcome {
+                    reply_ids,
+                    cancelled: true,
+                    usage: None,
+                });
+            }
+        };
         let chunk = chunk_result.map_err(|e| format!("SSE stream error: {}", e))?;
         sse_buffer.push_str(&String::from_utf8_lossy(&chunk));
 
@@ -831,66 +2266,136 @@ async fn consume_sse_stream(
                 Err(_) => continue,
             };
 
-            match json_val["type"].as_str().unwrap_or("") {
+            let event_type = json_val["type"].as_str().unwrap_or("");
+            // The reply is about to be delivered — no typing refresh after it
+            if matches!(event_type, "block-end" | "complete" | "error") {
+                typing.take();
+            }
+
+            match event_type {
                 "partial" => {
                     if let Some(text) = json_val["text"].as_str() {
                         block_text = text.to_string();
 
+                        if !first_token_seen && !block_text.trim().is_empty() {
+                            first_token_seen = true;
+                            metrics::observe(
+                                metrics::IM_FIRST_TOKEN_SECONDS,
+                                metric_labels,
+                                turn_started.elapsed().as_secs_f64(),
+                            );
+                        }
+
                         if draft_id.is_none()
                             && !block_text.trim().is_empty()
                             && has_sentence_boundary(&block_text)
+                            && !rate_saturated(adapter, chat_id)
                         {
-                            if let Some(pid) = placeholder_id.take() {
-                                draft_id = Some(pid);
-                                let display = format_draft_text(
-                                    &block_text,
-                                    adapter.max_message_length(),
-                                );
-                                let _ = adapter
-                                    .edit_message(
-                                        chat_id,
-                                        draft_id.as_ref().unwrap(),
-                                        &display,
-                                    )
-                                    .await;
+                            if use_drafts {
+                                let display = format_draft_text(&block_text, adapter);
+                                draft_id =
+                                    start_draft(adapter, chat_id, &display, &mut use_drafts).await;
                                 last_edit = Instant::now();
-                            } else {
-                                let display = format_draft_text(
-                                    &block_text,
-                                    adapter.max_message_length(),
-                                );
-                                match adapter
-                                    .send_message_returning_id(chat_id, &display)
-                                    .await
-                                {
-                                    Ok(Some(id)) => {
-                                        draft_id = Some(id);
-                                        last_edit = Instant::now();
+                            }
+                            if !use_drafts {
+                                if let Some(pid) = placeholder_id.take() {
+                                    draft_id = Some(pid);
+                                    let display = format_draft_text(
+                                        &block_text,
+                                        adapter,
+                                    );
+                                    let _ = adapter
+                                        .edit_message(
+                                            chat_id,
+                                            draft_id.as_ref().unwrap(),
+                                            &display,
+                                        )
+                                        .await;
+                                    last_edit = Instant::now();
+                                } else {
+                                    let display = format_draft_text(
+                                        &block_text,
+                                        adapter,
+                                    );
+                                    match adapter
+                                        .send_message_returning_id(chat_id, &display)
+                                        .await
+                                    {
+                                        Ok(Some(id)) => {
+                                            draft_id = Some(id);
+                                            last_edit = Instant::now();
+                                        }
+                                        _ => {}
                                     }
-                                    _ => {}
                                 }
                             }
                             first_content_sent = true;
                         }
 
-                        if let Some(ref did) = draft_id {
-                            let throttle = Duration::from_millis(
-                                adapter.preferred_throttle_ms(),
-                            );
-                            if last_edit.elapsed() >= throttle {
+                        if let Some(did) = draft_id.clone() {
+                            let draft_number = parse_draft_id(&did);
+                            let throttle = Duration::from_millis(if draft_number.is_some() {
+                                adapter.draft_throttle_ms()
+                            } else {
+                                adapter.preferred_throttle_ms()
+                            });
+                            if last_edit.elapsed() >= throttle
+                                && !rate_saturated(adapter, chat_id)
+                            {
                                 last_edit = Instant::now();
                                 let display = format_draft_text(
                                     &block_text,
-                                    adapter.max_message_length(),
+                                    adapter,
                                 );
-                                let _ =
-                                    adapter.edit_message(chat_id, did, &display).await;
+                                match draft_number {
+                                    Some(number) => {
+                                        if let Err(TelegramError::DraftPeerInvalid) = adapter
+                                            .send_draft_update(chat_id, &display, number)
+                                            .await
+                                        {
+                                            // Continue the block as a sent-then-edited message
+                                            ulog_warn!(
+                                                "[im-stream] Drafts unavailable for {}, \
+                                                 falling back to edits",
+                                                chat_id
+                                            );
+                                            use_drafts = false;
+                                            draft_id = adapter
+                                                .send_message_returning_id(chat_id, &display)
+                                                .await
+                                                .ok()
+                                                .flatten();
+                                        }
+                                    }
+                                    None => {
+                                        let _ =
+                                            adapter.edit_message(chat_id, &did, &display).await;
+                                    }
+                                }
                             }
                         }
                     }
                 }
                 "activity" => {
-                    if !first_content_sent {
+                    if let Some(status) = tools.on_activity(&json_val) {
+                        // Tool progress goes to the status message while no text streams
+                        if draft_id.is_none() && !rate_saturated(adapter, chat_id) {
+                            if let Some(ref pid) = placeholder_id {
+                                let throttle =
+                                    Duration::from_millis(adapter.preferred_throttle_ms());
+                                if last_edit.elapsed() >= throttle {
+                                    last_edit = Instant::now();
+                                    let _ = adapter.edit_message(chat_id, pid, &status).await;
+                                }
+                            } else if let Ok(Some(id)) =
+                                adapter.send_message_returning_id(chat_id, &status).await
+                            {
+                                placeholder_id = Some(id);
+                                last_edit = Instant::now();
+                            }
+                            first_content_sent = true;
+                        }
+                    } else if !first_content_sent && !rate_saturated(adapter, chat_id) {
                         match adapter
                             .send_message_returning_id(chat_id, "Generating...")
                             .await
@@ -914,22 +2419,27 @@ async fn consume_sse_stream(
                             let _ = adapter.delete_message(chat_id, did).await;
                         }
                     } else {
-                        finalize_block(adapter, chat_id, draft_id.clone(), &final_text)
-                            .await;
+                        let ids =
+                            finalize_block(adapter, outbox, chat_id, draft_id.clone(), &final_text)
+                                .await;
+                        reply_ids.extend(ids.iter().cloned());
+                        last_block = Some((sole_message(&ids), final_text));
                         any_text_sent = true;
                     }
                     block_text.clear();
                     draft_id = None;
                 }
                 "complete" => {
+                    let usage = json_val
+                        .get("turnUsage")
+                        .filter(|u| u.is_object())
+                        .and_then(|u| serde_json::from_value::<TurnUsage>(u.clone()).ok());
                     if !block_text.trim().is_empty() {
-                        finalize_block(
-                            adapter,
-                            chat_id,
-                            draft_id.clone(),
-                            &block_text,
-                        )
-                        .await;
+                        let ids =
+                            finalize_block(adapter, outbox, chat_id, draft_id.clone(), &block_text)
+                                .await;
+                        reply_ids.extend(ids.iter().cloned());
+                        last_block = Some((sole_message(&ids), std::mem::take(&mut block_text)));
                         any_text_sent = true;
                     } else if let Some(ref did) = draft_id {
                         let _ = adapter.delete_message(chat_id, did).await;
@@ -943,14 +2453,30 @@ async fn consume_sse_stream(
                                 .is_err()
                             {
                                 let _ = adapter.delete_message(chat_id, pid).await;
-                                let _ =
-                                    adapter.send_message(chat_id, "(No response)").await;
+                                let sent = outbox.deliver(chat_id, "(No response)").await;
+                                reply_ids.extend(sent.into_message_ids());
+                            } else {
+                                reply_ids.push(pid.clone());
                             }
                         } else {
-                            let _ = adapter.send_message(chat_id, "(No response)").await;
+                            let sent = outbox.deliver(chat_id, "(No response)").await;
+                            reply_ids.extend(sent.into_message_ids());
+                        }
+                    } else if let Some(ref pid) = placeholder_id {
+                        // Status message of tools that ran after the last text block
+                        let _ = adapter.delete_message(chat_id, pid).await;
+                    }
+                    if let Some(ref usage) = usage {
+                        if any_text_sent && ctx.config.usage_footer.unwrap_or(false) {
+                            append_usage_footer(adapter, outbox, chat_id, last_block.take(), usage)
+                                .await;
                         }
                     }
-                    return Ok(());
+                    return Ok(StreamOutcome {
+                        reply_ids,
+                        cancelled: false,
+                        usage,
+                    });
                 }
                 "error" => {
                     let error =
@@ -965,11 +2491,18 @@ async fn consume_sse_stream(
                 }
                 _ => {}
             }
+            // A draft or status message is visible now; it replaces the indicator
+            if first_content_sent {
+                typing.take();
+            }
         }
     }
+    typing.take();
 
     if !block_text.trim().is_empty() {
-        finalize_block(adapter, chat_id, draft_id.clone(), &block_text).await;
+        reply_ids.extend(
+            finalize_block(adapter, outbox, chat_id, draft_id.clone(), &block_text).await,
+        );
         any_text_sent = true;
     } else if let Some(ref did) = draft_id {
         let _ = adapter.delete_message(chat_id, did).await;
@@ -983,14 +2516,239 @@ async fn consume_sse_stream(
                 .is_err()
             {
                 let _ = adapter.delete_message(chat_id, pid).await;
-                let _ = adapter.send_message(chat_id, "(No response)").await;
+                let sent = outbox.deliver(chat_id, "(No response)").await;
+                reply_ids.extend(sent.into_message_ids());
+            } else {
+                reply_ids.push(pid.clone());
             }
         } else {
-            let _ = adapter.send_message(chat_id, "(No response)").await;
+            let sent = outbox.deliver(chat_id, "(No response)").await;
+            reply_ids.extend(sent.into_message_ids());
+        }
+    } else if let Some(ref pid) = placeholder_id {
+        let _ = adapter.delete_message(chat_id, pid).await;
+    }
+
+    Ok(StreamOutcome {
+        reply_ids,
+        cancelled: false,
+        usage: None,
+    })
+}
+
+/// Review mode: read the whole reply without showing drafts in the chat, then hold it
+/// in the review queue for the desktop to approve.
+async fn collect_reply_for_review(
+    response: reqwest::Response,
+    ctx: &TurnContext,
+    msg: &ImMessage,
+    session_key: &str,
+    turn_started: Instant,
+    ticket: &mut TurnTicket,
+) -> Result<StreamOutcome, String> {
+    let mut byte_stream = response.bytes_stream();
+    let mut sse_buffer = String::new();
+    let mut block_text = String::new();
+    let mut blocks: Vec<String> = Vec::new();
+    let mut first_token_seen = false;
+    let mut usage: Option<TurnUsage> = None;
+
+    'stream: loop {
+        let chunk_result = tokio::select! {
+            chunk = byte_stream.next() => match chunk {
+                Some(c) => c,
+                None => break,
+            },
+            _ = ticket.cancelled() => {
+                return Ok(StreamOutcome {
+                    reply_ids: Vec::new(),
+                    cancelled: true,
+                    usage: None,
+                });
+            }
+        };
+        let chunk = chunk_result.map_err(|e| format!("SSE stream error: {}", e))?;
+        sse_buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(pos) = sse_buffer.find("\n\n") {
+            let event_str: String = sse_buffer.drain(..pos).collect();
+            sse_buffer.drain(..2);
+            if event_str.starts_with(':') {
+                continue;
+            }
+            let data = extract_sse_data(&event_str);
+            let Ok(json_val) = serde_json::from_str::<serde_json::Value>(&data) else {
+                continue;
+            };
+
+            match json_val["type"].as_str().unwrap_or("") {
+                "partial" => {
+                    if let Some(text) = json_val["text"].as_str() {
+                        block_text = text.to_string();
+                        if !first_token_seen && !block_text.trim().is_empty() {
+                            first_token_seen = true;
+                            metrics::observe(
+                                metrics::IM_FIRST_TOKEN_SECONDS,
+                                &ctx.metric_labels,
+                                turn_started.elapsed().as_secs_f64(),
+                            );
+                        }
+                    }
+                }
+                "block-end" => {
+                    let final_text = json_val["text"]
+                        .as_str()
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| std::mem::take(&mut block_text));
+                    if !final_text.trim().is_empty() {
+                        blocks.push(final_text);
+                    }
+                    block_text.clear();
+                }
+                "complete" => {
+                    usage = json_val
+                        .get("turnUsage")
+                        .filter(|u| u.is_object())
+                        .and_then(|u| serde_json::from_value::<TurnUsage>(u.clone()).ok());
+                    break 'stream;
+                }
+                "error" => {
+                    return Err(json_val["error"]
+                        .as_str()
+                        .unwrap_or("Unknown error")
+                        .to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+    if !block_text.trim().is_empty() {
+        blocks.push(block_text);
+    }
+
+    let draft = if blocks.is_empty() {
+        "(No response)".to_string()
+    } else {
+        blocks.join("\n\n")
+    };
+    let item = ctx.review.push(
+        session_key,
+        &msg.chat_id,
+        &msg.message_id,
+        msg.sender_name.clone(),
+        &msg.text,
+        &draft,
+    );
+    emit_review_queued(&ctx.app, &ctx.config.agent_id, &ctx.config.channel_id, &item);
+    if ctx.config.review_notice.unwrap_or(false) {
+        ctx.outbox
+            .deliver(
+                &msg.chat_id,
+                "Your answer is being reviewed and will be sent shortly.",
+            )
+            .await;
+    }
+
+    Ok(StreamOutcome {
+        reply_ids: Vec::new(),
+        cancelled: false,
+        usage,
+    })
+}
+
+/// Tell the desktop a message is waiting for review
+fn emit_review_queued(app: &AppHandle, agent_id: &str, channel_id: &str, item: &ReviewItem) {
+    use tauri::Emitter;
+    let event = review::ReviewEvent {
+        agent_id,
+        channel_id,
+        item,
+    };
+    let _ = app.emit(review::REVIEW_QUEUED_EVENT, &event);
+}
+
+/// Repeats the typing indicator of an in-flight turn on the adapter's cadence. Stops
+/// when dropped: at the first visible content, on completion, or on any early return.
+struct TypingKeepAlive {
+    handle: JoinHandle<()>,
+}
+
+impl TypingKeepAlive {
+    /// None when the adapter's indicator doesn't need refreshing
+    fn start(adapter: &Arc<dyn ImStreamAdapter>, chat_id: &str) -> Option<Self> {
+        let interval = adapter.typing_refresh_interval()?;
+        let adapter = Arc::clone(adapter);
+        let chat_id = chat_id.to_string();
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if rate_saturated(adapter.as_ref(), &chat_id) {
+                    continue;
+                }
+                if let Err(e) = adapter.send_typing(&chat_id).await {
+                    ulog_warn!("[im] Typing refresh failed for {}: {}", chat_id, e);
+                    break;
+                }
+            }
+        });
+        Some(Self { handle })
+    }
+}
+
+impl Drop for TypingKeepAlive {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Reply IDs of native drafts are virtual: `draft:{draft_id}`
+const DRAFT_ID_PREFIX: &str = "draft:";
+
+fn parse_draft_id(id: &str) -> Option<i64> {
+    id.strip_prefix(DRAFT_ID_PREFIX)?.parse().ok()
+}
+
+/// Non-zero and increasing, also across restarts, so a new block never updates the
+/// draft of an earlier one
+fn next_draft_id() -> i64 {
+    use std::sync::atomic::{AtomicI64, Ordering};
+    static LAST: AtomicI64 = AtomicI64::new(0);
+    let now = chrono::Utc::now().timestamp_millis().max(1);
+    let prev = LAST
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |prev| {
+            Some(now.max(prev + 1))
+        })
+        .unwrap_or_default();
+    now.max(prev + 1)
+}
+
+/// Open a native draft for the block. On `DraftPeerInvalid` the rest of the turn
+/// streams by editing a sent message (`use_drafts` is cleared).
+async fn start_draft(
+    adapter: &dyn ImStreamAdapter,
+    chat_id: &str,
+    text: &str,
+    use_drafts: &mut bool,
+) -> Option<String> {
+    let number = next_draft_id();
+    match adapter.send_draft_update(chat_id, text, number).await {
+        Ok(()) => Some(format!("{}{}", DRAFT_ID_PREFIX, number)),
+        Err(TelegramError::DraftPeerInvalid) => {
+            ulog_warn!("[im-stream] Drafts unavailable for {}, falling back to edits", chat_id);
+            *use_drafts = false;
+            None
+        }
+        Err(e) => {
+            ulog_warn!("[im-stream] Draft update failed: {}", e);
+            None
         }
     }
+}
 
-    Ok(())
+/// Intermediate updates (drafts, placeholders, streaming edits) are dropped while the
+/// bot's outbound budget is saturated, so final messages are not delayed behind them.
+fn rate_saturated(adapter: &dyn ImStreamAdapter, chat_id: &str) -> bool {
+    matches!(adapter.rate_limiter(), Some(limiter) if limiter.is_saturated(chat_id))
 }
 
 fn extract_sse_data(event_str: &str) -> String {
@@ -1006,45 +2764,74 @@ fn extract_sse_data(event_str: &str) -> String {
         .join("\n")
 }
 
+/// Turn the streamed draft into the final message. Returns the IDs of the messages that
+/// show it: the draft's when it was finalized in place, otherwise the ones the outbox
+/// sent. Native drafts are virtual and never returned, so recalls delete what the user
+/// actually sees.
 async fn finalize_block(
     adapter: &dyn ImStreamAdapter,
+    outbox: &Outbox,
     chat_id: &str,
     draft_id: Option<String>,
     text: &str,
-) {
+) -> Vec<String> {
     if text.is_empty() {
-        return;
+        return Vec::new();
     }
 
     let is_draft = draft_id
-        .as_ref()
-        .map_or(false, |id| id.starts_with("draft:"));
+        .as_deref()
+        .map_or(false, |id| parse_draft_id(id).is_some());
     if is_draft {
         if let Some(ref did) = draft_id {
             let _ = adapter.delete_message(chat_id, did).await;
         }
-        let _ = adapter.send_message(chat_id, text).await;
-    } else if let Some(ref mid) = draft_id {
-        if let Err(e) = adapter.edit_message(chat_id, mid, text).await {
-            ulog_warn!("[im-stream] finalize edit failed: {}, sending new", e);
-            let _ = adapter.send_message(chat_id, text).await;
+    } else if let Some(mid) = draft_id {
+        match adapter.edit_message(chat_id, &mid, text).await {
+            Ok(()) => {
+                outbox.record_sent();
+                return vec![mid];
+            }
+            Err(e) => {
+                ulog_warn!("[im-stream] finalize edit failed: {}, sending new", e);
+            }
         }
-    } else {
-        let _ = adapter.send_message(chat_id, text).await;
+    }
+    outbox.deliver(chat_id, text).await.into_message_ids()
+}
+
+/// The block's message when it fits in one (the usage footer can be edited into it)
+fn sole_message(ids: &[String]) -> Option<String> {
+    match ids {
+        [id] => Some(id.clone()),
+        _ => None,
     }
 }
 
-fn format_draft_text(text: &str, max_len: usize) -> String {
-    let limit = max_len.saturating_sub(10);
-    if text.len() > limit {
-        let mut truncate_at = limit.min(text.len());
-        while !text.is_char_boundary(truncate_at) && truncate_at > 0 {
-            truncate_at -= 1;
+/// Add the usage footer to the reply's last message when it was edited in place and
+/// still fits, otherwise send it as a message of its own.
+async fn append_usage_footer(
+    adapter: &dyn ImStreamAdapter,
+    outbox: &Outbox,
+    chat_id: &str,
+    last_block: Option<(Option<String>, String)>,
+    usage: &TurnUsage,
+) {
+    let footer = usage::format_footer(usage);
+    if let Some((Some(mid), text)) = last_block {
+        let combined = format!("{}\n\n{}", text, footer);
+        if adapter.length_unit().measure(&combined) <= adapter.max_message_length()
+            && adapter.edit_message(chat_id, &mid, &combined).await.is_ok()
+        {
+            return;
         }
-        format!("{}...", &text[..truncate_at])
-    } else {
-        text.to_string()
     }
+    outbox.deliver(chat_id, &footer).await;
+}
+
+/// Streaming preview of a block, cut to the platform's limit (in its length unit)
+fn format_draft_text(text: &str, adapter: &dyn ImStreamAdapter) -> String {
+    adapter::truncate_message(text, adapter.max_message_length(), adapter.length_unit())
 }
 
 fn has_sentence_boundary(text: &str) -> bool {
@@ -1166,6 +2953,217 @@ pub async fn cmd_im_reset_session(
         .await
 }
 
+/// Point an IM session at one of the agent's allowed workspaces (None = default)
+#[tauri::command]
+pub async fn cmd_im_set_chat_workspace(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    session_key: String,
+    workspace: Option<String>,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager
+        .set_chat_workspace(&agent_id, &channel_id, &session_key, workspace.as_deref())
+        .await
+}
+
+/// Persons with linked IM accounts (private chats of one person share a session)
+#[tauri::command]
+pub async fn cmd_im_list_identities(
+    im_state: tauri::State<'_, ImManagerState>,
+) -> Result<Vec<ImPerson>, String> {
+    let manager = im_state.lock().await;
+    Ok(manager.identities())
+}
+
+/// Link a platform account to `person_id`, or to a new person called `name`
+#[tauri::command]
+pub async fn cmd_im_link_identity(
+    im_state: tauri::State<'_, ImManagerState>,
+    person_id: Option<String>,
+    name: Option<String>,
+    platform: ImPlatform,
+    user_id: String,
+) -> Result<ImPerson, String> {
+    let manager = im_state.lock().await;
+    manager.link_identity(person_id.as_deref(), name.as_deref(), platform, &user_id)
+}
+
+#[tauri::command]
+pub async fn cmd_im_unlink_identity(
+    im_state: tauri::State<'_, ImManagerState>,
+    platform: ImPlatform,
+    user_id: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager.unlink_identity(&platform, &user_id)
+}
+
+#[tauri::command]
+pub async fn cmd_im_export_transcript(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    session_key: String,
+    format: transcript::TranscriptFormat,
+) -> Result<transcript::ExportedTranscript, String> {
+    let manager = im_state.lock().await;
+    manager
+        .export_transcript(&agent_id, &channel_id, &session_key, format)
+        .await
+}
+
+/// Send a message to a known chat without an inbound message to reply to
+#[tauri::command]
+pub async fn cmd_im_send_message(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    chat_id: String,
+    text: String,
+    attachments: Option<Vec<String>>,
+) -> Result<(), String> {
+    let request = proactive::SendMessageRequest {
+        agent_id,
+        channel_id,
+        chat_id,
+        text,
+        attachments: attachments.unwrap_or_default(),
+    };
+    proactive::send(im_state.inner(), &request, None)
+        .await
+        .map(|_status| ())
+}
+
+/// Take over an IM session: the agent pauses and the chat's messages stream to the
+/// desktop as `im:takeover-message` events
+#[tauri::command]
+pub async fn cmd_im_takeover_start(
+    app: AppHandle,
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    session_key: String,
+    chat_id: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager.start_takeover(&app, &agent_id, &channel_id, &session_key, &chat_id)
+}
+
+/// Reply as the bot in a taken-over session
+#[tauri::command]
+pub async fn cmd_im_takeover_send(
+    app: AppHandle,
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    session_key: String,
+    text: String,
+) -> Result<TakeoverMessage, String> {
+    let manager = im_state.lock().await;
+    manager
+        .send_takeover_message(&app, &agent_id, &channel_id, &session_key, &text)
+        .await
+}
+
+/// Hand a taken-over session back to the agent
+#[tauri::command]
+pub async fn cmd_im_takeover_hand_back(
+    app: AppHandle,
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    session_key: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager.hand_back_takeover(&app, &agent_id, &channel_id, &session_key)
+}
+
+#[tauri::command]
+pub async fn cmd_im_takeover_list(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+) -> Result<Vec<TakeoverSession>, String> {
+    let manager = im_state.lock().await;
+    manager.takeovers(&agent_id, &channel_id)
+}
+
+/// Replies of review-before-send channels waiting for approval
+#[tauri::command]
+pub async fn cmd_im_review_list(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+) -> Result<Vec<ReviewItem>, String> {
+    let manager = im_state.lock().await;
+    manager.review_list(&agent_id, &channel_id)
+}
+
+#[tauri::command]
+pub async fn cmd_im_review_approve(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    id: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager.review_send(&agent_id, &channel_id, &id, None).await
+}
+
+#[tauri::command]
+pub async fn cmd_im_review_edit_and_send(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    id: String,
+    text: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager
+        .review_send(&agent_id, &channel_id, &id, Some(&text))
+        .await
+}
+
+#[tauri::command]
+pub async fn cmd_im_review_reject(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: String,
+    channel_id: String,
+    id: String,
+) -> Result<(), String> {
+    let manager = im_state.lock().await;
+    manager.review_reject(&agent_id, &channel_id, &id)
+}
+
+/// Daily token usage per channel and per user over the last `days` days (default 30)
+#[tauri::command]
+pub async fn cmd_im_usage_report(
+    agent_id: Option<String>,
+    channel_id: Option<String>,
+    days: Option<u32>,
+) -> Result<usage::UsageReport, String> {
+    tokio::task::spawn_blocking(move || {
+        usage::build_report(agent_id.as_deref(), channel_id.as_deref(), days)
+    })
+    .await
+    .map_err(|e| format!("Usage report failed: {}", e))
+}
+
+/// Chats that have messaged running channels (targets for cmd_im_send_message)
+#[tauri::command]
+pub async fn cmd_im_list_known_chats(
+    im_state: tauri::State<'_, ImManagerState>,
+    agent_id: Option<String>,
+    channel_id: Option<String>,
+) -> Result<Vec<ChannelKnownChats>, String> {
+    let manager = im_state.lock().await;
+    Ok(manager
+        .known_chats(agent_id.as_deref(), channel_id.as_deref())
+        .await)
+}
+
 #[tauri::command]
 pub async fn cmd_im_verify_token(
     platform: String,
@@ -1263,35 +3261,13 @@ pub async fn cmd_im_remove_group(
 
 // ===== Auto-Start on App Boot =====
 
-fn read_agent_configs_from_disk() -> Vec<types::AgentConfigRust> {
-    let home = match dirs::home_dir() {
-        Some(h) => h,
-        None => return Vec::new(),
-    };
-    let config_path = home.join(".soagents").join("config.json");
-    let content = match std::fs::read_to_string(&config_path) {
-        Ok(c) => c,
-        Err(_) => return Vec::new(),
-    };
-    let json: serde_json::Value = match serde_json::from_str(&content) {
-        Ok(v) => v,
-        Err(_) => return Vec::new(),
-    };
-    match serde_json::from_value::<Vec<types::AgentConfigRust>>(json["agents"].clone()) {
-        Ok(agents) => agents,
-        Err(e) => {
-            log::warn!("[im] Failed to parse agents from config: {}", e);
-            Vec::new()
-        }
-    }
-}
-
 pub fn schedule_agent_auto_start(app_handle: tauri::AppHandle) {
     use tauri::Manager;
     tauri::async_runtime::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_secs(4)).await;
 
-        let agents = read_agent_configs_from_disk();
+        let snapshot = crate::config_service::current();
+        let agents = &snapshot.agents;
         if agents.is_empty() {
             return;
         }
@@ -1300,7 +3276,7 @@ pub fn schedule_agent_auto_start(app_handle: tauri::AppHandle) {
         let sidecar_state: ManagedSidecarState =
             (*app_handle.state::<crate::commands::SidecarState>()).clone();
 
-        for agent in &agents {
+        for agent in agents {
             if !agent.enabled {
                 continue;
             }
@@ -1309,33 +3285,7 @@ pub fn schedule_agent_auto_start(app_handle: tauri::AppHandle) {
                     continue;
                 }
 
-                // Check platform credentials
-                let has_credentials = match channel.channel_type {
-                    ImPlatform::Telegram => {
-                        channel.bot_token.as_ref().map_or(false, |t| !t.is_empty())
-                    }
-                    ImPlatform::Feishu => {
-                        channel
-                            .feishu_app_id
-                            .as_ref()
-                            .map_or(false, |t| !t.is_empty())
-                            && channel
-                                .feishu_app_secret
-                                .as_ref()
-                                .map_or(false, |t| !t.is_empty())
-                    }
-                    ImPlatform::Dingtalk => {
-                        channel
-                            .dingtalk_client_id
-                            .as_ref()
-                            .map_or(false, |t| !t.is_empty())
-                            && channel
-                                .dingtalk_client_secret
-                                .as_ref()
-                                .map_or(false, |t| !t.is_empty())
-                    }
-                };
-                if !has_credentials {
+                if !channel.has_credentials() {
                     continue;
                 }
 
@@ -0,0 +1,579 @@
+// Outbound delivery queue — final replies that could not be sent yet.
+// Keeps per-chat ordering: once a chat has a pending delivery, later final messages
+// for that chat queue behind it instead of overtaking it. Long replies are split into
+// platform-sized parts up front; each part is queued and delivered on its own, so a
+// retry resumes at the part that failed instead of re-posting the earlier ones.
+// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/outbound.json
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use super::adapter::{split_message, ImStreamAdapter};
+use super::types::SendError;
+use crate::metrics;
+use crate::{ulog_info, ulog_warn};
+
+/// First retry delay; doubles per failed attempt
+const RETRY_BASE_SECS: u64 = 2;
+/// Upper bound for the retry delay
+const RETRY_MAX_SECS: u64 = 300;
+/// Failed attempts before a delivery is abandoned
+const MAX_DELIVERY_ATTEMPTS: u32 = 10;
+/// Max pending deliveries per channel before the oldest are dropped
+const MAX_PENDING: usize = 200;
+
+/// One message part waiting to be delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingDelivery {
+    pub id: String,
+    pub chat_id: String,
+    pub text: String,
+    /// Last part of its reply (the reply counts as sent once this one is delivered)
+    #[serde(default = "default_last_part")]
+    pub last_part: bool,
+    pub attempts: u32,
+    /// Unix millis before which no retry is attempted
+    pub next_attempt_at: i64,
+    pub created_at: String,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+fn default_last_part() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OutboundData {
+    deliveries: VecDeque<PendingDelivery>,
+}
+
+/// Retry delay after `attempts` failures, or the platform's retry-after when given
+fn retry_delay(attempts: u32, error: &SendError) -> Duration {
+    if let Some(secs) = error.retry_after {
+        return Duration::from_secs(secs.max(1));
+    }
+    let exp = RETRY_BASE_SECS.saturating_mul(1u64 << attempts.saturating_sub(1).min(16));
+    Duration::from_secs(exp.min(RETRY_MAX_SECS))
+}
+
+pub struct OutboundQueue {
+    deliveries: VecDeque<PendingDelivery>,
+    persist_path: PathBuf,
+}
+
+impl OutboundQueue {
+    /// Load pending deliveries from disk (missing file = empty queue)
+    pub fn load(persist_path: PathBuf) -> Self {
+        let deliveries = match std::fs::read_to_string(&persist_path) {
+            Ok(content) => match serde_json::from_str::<OutboundData>(&content) {
+                Ok(data) => data.deliveries,
+                Err(e) => {
+                    ulog_warn!("[im-outbound] Failed to parse outbound queue: {}", e);
+                    VecDeque::new()
+                }
+            },
+            Err(_) => VecDeque::new(),
+        };
+        if !deliveries.is_empty() {
+            ulog_info!(
+                "[im-outbound] Loaded {} pending deliveries from disk",
+                deliveries.len()
+            );
+        }
+        Self {
+            deliveries,
+            persist_path,
+        }
+    }
+
+    /// Persist atomically (write tmp, then rename)
+    fn save(&self) {
+        let data = OutboundData {
+            deliveries: self.deliveries.clone(),
+        };
+        let result = serde_json::to_string_pretty(&data)
+            .map_err(|e| format!("Serialize error: {}", e))
+            .and_then(|json| {
+                if let Some(parent) = self.persist_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create outbound dir: {}", e))?;
+                }
+                let tmp = self.persist_path.with_extension("json.tmp");
+                std::fs::write(&tmp, json)
+                    .map_err(|e| format!("Failed to write outbound queue: {}", e))?;
+                std::fs::rename(&tmp, &self.persist_path)
+                    .map_err(|e| format!("Failed to replace outbound queue: {}", e))
+            });
+        if let Err(e) = result {
+            ulog_warn!("[im-outbound] {}", e);
+        }
+    }
+
+    pub fn has_pending(&self, chat_id: &str) -> bool {
+        self.deliveries.iter().any(|d| d.chat_id == chat_id)
+    }
+
+    /// Queue the parts of a reply, in order. `error` is the failure of the first part
+    /// (None when they were queued only to keep ordering behind earlier deliveries).
+    pub fn push(&mut self, chat_id: &str, parts: &[String], error: Option<&SendError>) {
+        let now = chrono::Utc::now();
+        for (i, part) in parts.iter().enumerate() {
+            let failed = error.filter(|_| i == 0);
+            let (attempts, next_attempt_at) = match failed {
+                Some(e) => (
+                    1,
+                    now.timestamp_millis() + retry_delay(1, e).as_millis() as i64,
+                ),
+                None => (0, now.timestamp_millis()),
+            };
+            self.deliveries.push_back(PendingDelivery {
+                id: uuid::Uuid::new_v4().to_string(),
+                chat_id: chat_id.to_string(),
+                text: part.clone(),
+                last_part: i + 1 == parts.len(),
+                attempts,
+                next_attempt_at,
+                created_at: now.to_rfc3339(),
+                last_error: failed.map(|e| e.to_string()),
+            });
+        }
+        while self.deliveries.len() > MAX_PENDING {
+            if let Some(old) = self.deliveries.pop_front() {
+                ulog_warn!(
+                    "[im-outbound] Queue full, dropping oldest delivery for chat {}",
+                    old.chat_id
+                );
+            }
+        }
+        self.save();
+    }
+
+    /// Head delivery of each chat whose retry time has come (per-chat FIFO)
+    pub fn due(&self, now_ms: i64) -> Vec<PendingDelivery> {
+        let mut seen: Vec<&str> = Vec::new();
+        let mut due = Vec::new();
+        for d in &self.deliveries {
+            if seen.contains(&d.chat_id.as_str()) {
+                continue;
+            }
+            seen.push(&d.chat_id);
+            if d.next_attempt_at <= now_ms {
+                due.push(d.clone());
+            }
+        }
+        due
+    }
+
+    /// Milliseconds until the earliest chat head becomes due
+    pub fn next_due_in_ms(&self, now_ms: i64) -> Option<i64> {
+        let mut seen: Vec<&str> = Vec::new();
+        let mut earliest: Option<i64> = None;
+        for d in &self.deliveries {
+            if seen.contains(&d.chat_id.as_str()) {
+                continue;
+            }
+            seen.push(&d.chat_id);
+            let wait = (d.next_attempt_at - now_ms).max(0);
+            earliest = Some(earliest.map_or(wait, |e| e.min(wait)));
+        }
+        earliest
+    }
+
+    pub fn mark_delivered(&mut self, id: &str) {
+        self.deliveries.retain(|d| d.id != id);
+        self.save();
+    }
+
+    /// Record a failed attempt. Returns the delivery if it was abandoned.
+    pub fn mark_failed(&mut self, id: &str, error: &SendError) -> Option<PendingDelivery> {
+        let idx = self.deliveries.iter().position(|d| d.id == id)?;
+        let abandon = {
+            let d = &mut self.deliveries[idx];
+            d.attempts += 1;
+            d.last_error = Some(error.to_string());
+            d.next_attempt_at = chrono::Utc::now().timestamp_millis()
+                + retry_delay(d.attempts, error).as_millis() as i64;
+            d.attempts >= MAX_DELIVERY_ATTEMPTS || error.permanent
+        };
+        let abandoned = if abandon {
+            self.deliveries.remove(idx)
+        } else {
+            None
+        };
+        self.save();
+        abandoned
+    }
+
+    pub fn len(&self) -> usize {
+        self.deliveries.len()
+    }
+}
+
+/// What became of a message handed to `Outbox::deliver` (or a channel outlet).
+/// `message_ids` are the platform IDs of the parts sent directly (parts delivered
+/// later by the retry worker aren't tracked).
+#[derive(Debug)]
+pub enum Delivery {
+    /// Every part reached the platform
+    Sent { message_ids: Vec<String> },
+    /// Some parts wait in the retry queue
+    Queued { message_ids: Vec<String> },
+    /// The platform rejected a part for good; the unsent parts were dropped.
+    /// With `sent > 0` the chat got a truncated reply.
+    Dropped {
+        error: SendError,
+        sent: usize,
+        total: usize,
+        message_ids: Vec<String>,
+    },
+    /// Held in the channel's review queue (review_before_send); goes out once approved
+    Held { review_id: String },
+}
+
+impl Delivery {
+    /// IDs of the messages that reached the chat
+    pub fn into_message_ids(self) -> Vec<String> {
+        match self {
+            Delivery::Sent { message_ids }
+            | Delivery::Queued { message_ids }
+            | Delivery::Dropped { message_ids, .. } => message_ids,
+            Delivery::Held { .. } => Vec::new(),
+        }
+    }
+
+    /// Why the message didn't (fully) arrive, when it was dropped
+    pub fn failure(&self) -> Option<String> {
+        match self {
+            Delivery::Dropped { error, sent: 0, .. } => Some(error.to_string()),
+            Delivery::Dropped {
+                error, sent, total, ..
+            } => Some(format!(
+                "{} (only {} of {} parts were delivered)",
+                error, sent, total
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Per-channel delivery front: sends final messages directly when possible,
+/// otherwise hands them to the persistent queue and its retry worker.
+pub struct Outbox {
+    queue: Mutex<OutboundQueue>,
+    /// Per-chat send locks, so concurrent deliveries to a chat don't interleave parts
+    chat_locks: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    adapter: Arc<dyn ImStreamAdapter>,
+    notify: Notify,
+    metric_labels: metrics::Labels,
+}
+
+impl Outbox {
+    pub fn new(
+        adapter: Arc<dyn ImStreamAdapter>,
+        persist_path: PathBuf,
+        metric_labels: metrics::Labels,
+    ) -> Self {
+        let queue = OutboundQueue::load(persist_path);
+        metrics::set_gauge(metrics::IM_OUTBOUND_PENDING, &metric_labels, queue.len() as f64);
+        Self {
+            queue: Mutex::new(queue),
+            chat_locks: std::sync::Mutex::new(HashMap::new()),
+            adapter,
+            notify: Notify::new(),
+            metric_labels,
+        }
+    }
+
+    fn record_pending(&self, queue: &OutboundQueue) {
+        metrics::set_gauge(
+            metrics::IM_OUTBOUND_PENDING,
+            &self.metric_labels,
+            queue.len() as f64,
+        );
+    }
+
+    /// Send lock of one chat; locks nobody holds are pruned on the way
+    fn chat_lock(&self, chat_id: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.chat_locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        Arc::clone(locks.entry(chat_id.to_string()).or_default())
+    }
+
+    /// Deliver a final message. Goes straight to the adapter unless the chat already
+    /// has pending deliveries (ordering) — failed parts are queued for retry.
+    /// Deliveries to the same chat run one at a time, so their parts never interleave.
+    pub async fn deliver(&self, chat_id: &str, text: &str) -> Delivery {
+        if text.is_empty() {
+            return Delivery::Sent {
+                message_ids: Vec::new(),
+            };
+        }
+        let chat_lock = self.chat_lock(chat_id);
+        let _sending = chat_lock.lock().await;
+        let parts = split_message(
+            text,
+            self.adapter.max_message_length(),
+            self.adapter.length_unit(),
+        );
+
+        {
+            let mut queue = self.queue.lock().await;
+            if queue.has_pending(chat_id) {
+                queue.push(chat_id, &parts, None);
+                self.record_pending(&queue);
+                drop(queue);
+                self.notify.notify_one();
+                return Delivery::Queued {
+                    message_ids: Vec::new(),
+                };
+            }
+        }
+
+        let mut message_ids = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            let e = match self.adapter.send_part(chat_id, part).await {
+                Ok(id) => {
+                    message_ids.extend(id);
+                    continue;
+                }
+                Err(e) => e,
+            };
+            let unsent = &parts[i..];
+            if e.permanent {
+                ulog_warn!(
+                    "[im-outbound] Send to chat {} failed permanently, dropped {} of {} part(s): {}",
+                    chat_id,
+                    unsent.len(),
+                    parts.len(),
+                    e
+                );
+                return Delivery::Dropped {
+                    error: e,
+                    sent: i,
+                    total: parts.len(),
+                    message_ids,
+                };
+            }
+            ulog_warn!(
+                "[im-outbound] Send to chat {} failed, queued {} of {} part(s) for retry: {}",
+                chat_id,
+                unsent.len(),
+                parts.len(),
+                e
+            );
+            let mut queue = self.queue.lock().await;
+            queue.push(chat_id, unsent, Some(&e));
+            self.record_pending(&queue);
+            drop(queue);
+            self.notify.notify_one();
+            return Delivery::Queued { message_ids };
+        }
+        metrics::inc(metrics::IM_MESSAGES_SENT, &self.metric_labels);
+        Delivery::Sent { message_ids }
+    }
+
+    /// Count a final reply delivered outside the queue (streamed message edited in place)
+    pub fn record_sent(&self) {
+        metrics::inc(metrics::IM_MESSAGES_SENT, &self.metric_labels);
+    }
+
+    /// Number of deliveries still waiting
+    pub async fn pending_count(&self) -> usize {
+        self.queue.lock().await.len()
+    }
+
+    /// Background retry worker. Sleeps until the next delivery is due (or new work
+    /// is queued) and retries each chat's head delivery in order.
+    pub fn spawn_retry_loop(
+        self: Arc<Self>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait_ms = self
+                    .queue
+                    .lock()
+                    .await
+                    .next_due_in_ms(chrono::Utc::now().timestamp_millis())
+                    .unwrap_or(60_000);
+
+                tokio::select! {
+                    _ = self.notify.notified() => {}
+                    _ = tokio::time::sleep(Duration::from_millis(wait_ms as u64)) => {}
+                    _ = shutdown_rx.changed() => {
+                        if *shutdown_rx.borrow() {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+
+                let due = self
+                    .queue
+                    .lock()
+                    .await
+                    .due(chrono::Utc::now().timestamp_millis());
+
+                for delivery in due {
+                    match self
+                        .adapter
+                        .send_part(&delivery.chat_id, &delivery.text)
+                        .await
+                    {
+                        Ok(_) => {
+                            ulog_info!(
+                                "[im-outbound] Delivered queued reply to chat {} (attempt {})",
+                                delivery.chat_id,
+                                delivery.attempts + 1
+                            );
+                            if delivery.last_part {
+                                metrics::inc(metrics::IM_MESSAGES_SENT, &self.metric_labels);
+                            }
+                            let mut queue = self.queue.lock().await;
+                            queue.mark_delivered(&delivery.id);
+                            self.record_pending(&queue);
+                        }
+                        Err(e) => {
+                            let abandoned = {
+                                let mut queue = self.queue.lock().await;
+                                let abandoned = queue.mark_failed(&delivery.id, &e);
+                                self.record_pending(&queue);
+                                abandoned
+                            };
+                            if let Some(d) = abandoned {
+                                ulog_warn!(
+                                    "[im-outbound] Giving up on reply to chat {} after {} attempts: {}",
+                                    d.chat_id,
+                                    d.attempts,
+                                    e
+                                );
+                            } else {
+                                ulog_warn!(
+                                    "[im-outbound] Retry to chat {} failed: {}",
+                                    delivery.chat_id,
+                                    e
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_queue(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "soagents-outbound-test-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        dir.join("outbound.json")
+    }
+
+    fn parts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_due_returns_chat_heads_in_order() {
+        let path = temp_queue("fifo");
+        let mut q = OutboundQueue::load(path.clone());
+        q.push("c1", &parts(&["a1", "a2"]), None);
+        q.push("c2", &parts(&["b1"]), Some(&SendError::rate_limited("slow down", 60)));
+        q.push("c1", &parts(&["a3"]), None);
+
+        let now = chrono::Utc::now().timestamp_millis();
+        // c2's head waits for its retry-after; c1 only offers its first part
+        let due = q.due(now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].text, "a1");
+        assert!(!due[0].last_part);
+
+        q.mark_delivered(&due[0].id);
+        let due = q.due(now);
+        assert_eq!(due[0].text, "a2");
+        assert!(due[0].last_part);
+        assert_eq!(q.next_due_in_ms(now), Some(0));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_and_honours_retry_after() {
+        let err = SendError::transient("timeout");
+        assert_eq!(retry_delay(1, &err), Duration::from_secs(2));
+        assert_eq!(retry_delay(2, &err), Duration::from_secs(4));
+        assert_eq!(retry_delay(4, &err), Duration::from_secs(16));
+        assert_eq!(retry_delay(30, &err), Duration::from_secs(RETRY_MAX_SECS));
+        let limited = SendError::rate_limited("429", 42);
+        assert_eq!(retry_delay(5, &limited), Duration::from_secs(42));
+    }
+
+    #[test]
+    fn test_mark_failed_abandons() {
+        let path = temp_queue("abandon");
+        let mut q = OutboundQueue::load(path.clone());
+        q.push("c1", &parts(&["x"]), Some(&SendError::transient("timeout")));
+        let id = q.due(i64::MAX)[0].id.clone();
+        for _ in 1..MAX_DELIVERY_ATTEMPTS - 1 {
+            assert!(q.mark_failed(&id, &SendError::transient("timeout")).is_none());
+        }
+        let abandoned = q.mark_failed(&id, &SendError::transient("timeout")).unwrap();
+        assert_eq!(abandoned.attempts, MAX_DELIVERY_ATTEMPTS);
+        assert_eq!(q.len(), 0);
+
+        // Permanent errors are not retried
+        q.push("c1", &parts(&["y"]), None);
+        let id = q.due(i64::MAX)[0].id.clone();
+        assert!(q.mark_failed(&id, &SendError::permanent("kicked")).is_some());
+        assert_eq!(q.len(), 0);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_survives_reload() {
+        let path = temp_queue("reload");
+        {
+            let mut q = OutboundQueue::load(path.clone());
+            q.push("c1", &parts(&["p1", "p2"]), Some(&SendError::transient("timeout")));
+            let head = q.due(i64::MAX)[0].id.clone();
+            q.mark_delivered(&head);
+        }
+        let q = OutboundQueue::load(path.clone());
+        assert_eq!(q.len(), 1);
+        let due = q.due(i64::MAX);
+        assert_eq!(due[0].text, "p2");
+        assert!(due[0].last_part);
+        assert!(q.has_pending("c1"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_partial_drop_reports_truncation() {
+        let dropped = |sent| Delivery::Dropped {
+            error: SendError::permanent("chat not found"),
+            sent,
+            total: 3,
+            message_ids: Vec::new(),
+        };
+        assert_eq!(dropped(0).failure().as_deref(), Some("chat not found"));
+        assert!(dropped(2).failure().unwrap().contains("only 2 of 3 parts"));
+        let queued = Delivery::Queued {
+            message_ids: vec!["m1".to_string()],
+        };
+        assert!(queued.failure().is_none());
+        assert_eq!(queued.into_message_ids(), vec!["m1".to_string()]);
+    }
+}
@@ -0,0 +1,531 @@
+// Proactive sends — messages that don't answer an inbound message.
+// Used by the desktop (cmd_im_send_message), scheduled tasks and agents. Agents reach
+// it through a token-protected HTTP endpoint on 127.0.0.1, whose URL and token are
+// injected into workspace Sidecars' environment (SOAGENTS_IM_API_URL / SOAGENTS_IM_API_TOKEN).
+// Each workspace gets its own token, so a Sidecar can only send through agents that own
+// its workspace (`workspacePath` or `workspaceAllowlist`), and only attach files from
+// those workspaces.
+//
+// A target chat must pass the channel policy (`allowProactiveSend`, default on) and the
+// same checks inbound messages get: approved groups only, and private chats only for
+// users on the allowlist. Chats the bot has never heard from are rejected unless the chat
+// ID itself is allowlisted (Telegram private chat ID == user ID).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::outbound::Delivery;
+use super::types::{GroupPermission, GroupPermissionStatus, ImConfig, ImSourceType, KnownChat};
+use super::ImManagerState;
+use crate::{ulog_info, ulog_warn};
+
+/// Largest attachment read from disk for a proactive send
+const MAX_ATTACHMENT_BYTES: u64 = 50 * 1024 * 1024;
+/// Largest request body accepted by the endpoint
+const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+/// Proactive send request (Tauri command args / endpoint JSON body)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageRequest {
+    pub agent_id: String,
+    pub channel_id: String,
+    pub chat_id: String,
+    #[serde(default)]
+    pub text: String,
+    /// Absolute paths of files to upload after the text (inside the agent's
+    /// workspaces when an agent sends)
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+/// What became of the text of a proactive send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SendStatus {
+    Sent,
+    /// Waits in the retry queue
+    Queued,
+    /// Waits for approval on a review_before_send channel
+    HeldForReview,
+}
+
+// ── Policy ──
+
+/// Whether `chat_id` may receive a proactive message on this channel
+pub fn check_target(
+    chat_id: &str,
+    policy_allows: bool,
+    known: &[KnownChat],
+    allowed_users: &[String],
+    groups: &[GroupPermission],
+) -> Result<(), String> {
+    if !policy_allows {
+        return Err("Proactive sending is disabled for this channel".to_string());
+    }
+
+    if let Some(group) = groups.iter().find(|g| g.group_id == chat_id) {
+        return match group.status {
+            GroupPermissionStatus::Approved => Ok(()),
+            GroupPermissionStatus::Pending => {
+                Err(format!("Group {} is not approved", group.group_name))
+            }
+        };
+    }
+
+    let user_allowed = |id: &str, name: Option<&str>| {
+        allowed_users.is_empty()
+            || allowed_users
+                .iter()
+                .any(|a| a == id || name.is_some_and(|n| a.eq_ignore_ascii_case(n)))
+    };
+
+    match known.iter().find(|c| c.chat_id == chat_id) {
+        Some(chat) if chat.chat_type == ImSourceType::Group => {
+            Err(format!("Group {} is not approved", chat_id))
+        }
+        Some(chat) => {
+            let sender = chat.sender_id.as_deref().unwrap_or(chat_id);
+            if user_allowed(sender, chat.display_name.as_deref()) {
+                Ok(())
+            } else {
+                Err(format!("Chat {} is not on the allowlist", chat_id))
+            }
+        }
+        None if allowed_users.iter().any(|a| a == chat_id) => Ok(()),
+        None => Err(format!(
+            "Unknown chat {} — it has to message the bot first",
+            chat_id
+        )),
+    }
+}
+
+/// Workspaces an agent may work in: `workspacePath` plus `workspaceAllowlist`,
+/// canonicalized (entries that don't resolve are skipped)
+pub fn workspace_roots(config: &ImConfig) -> Vec<PathBuf> {
+    std::iter::once(&config.workspace_path)
+        .chain(&config.workspace_allowlist)
+        .filter_map(|p| std::fs::canonicalize(p).ok())
+        .collect()
+}
+
+// ── Send ──
+
+/// Send text and attachments to a chat after the policy checks.
+/// `caller` is the workspace of the Sidecar asking (None for the desktop and
+/// in-process callers); the agent has to own it, and attachments have to sit
+/// inside the agent's workspaces.
+/// On review_before_send channels an agent's text is held for approval and its
+/// attachments are refused (the desktop operator sends directly).
+/// The manager lock is only held to resolve the outlet.
+pub async fn send(
+    im_state: &ImManagerState,
+    req: &SendMessageRequest,
+    caller: Option<&Path>,
+) -> Result<SendStatus, String> {
+    if req.text.trim().is_empty() && req.attachments.is_empty() {
+        return Err("Nothing to send (text and attachments are empty)".to_string());
+    }
+    let (outlet, roots) = {
+        let manager = im_state.lock().await;
+        let mut outlet = manager
+            .proactive_outlet(&req.agent_id, &req.channel_id, &req.chat_id)
+            .await?;
+        if caller.is_none() {
+            outlet = outlet.without_review();
+        }
+        let roots = manager
+            .running_config(&req.agent_id, &req.channel_id)
+            .map(workspace_roots)
+            .unwrap_or_default();
+        (outlet, roots)
+    };
+    if let Some(caller) = caller {
+        if !roots.iter().any(|r| r == caller) {
+            return Err(format!(
+                "Agent {} does not own workspace {}",
+                req.agent_id,
+                caller.display()
+            ));
+        }
+    }
+
+    if outlet.is_reviewed() && !req.attachments.is_empty() {
+        return Err(
+            "This channel reviews messages before sending; attachments can't be held for review"
+                .to_string(),
+        );
+    }
+
+    // Read attachments up front so a bad path doesn't leave a half-sent message
+    let allowed = caller.map(|_| roots.as_slice());
+    let mut files = Vec::with_capacity(req.attachments.len());
+    for path in &req.attachments {
+        files.push(read_attachment(Path::new(path), allowed)?);
+    }
+
+    let mut status = SendStatus::Sent;
+    if !req.text.trim().is_empty() {
+        match outlet.send_text(&req.chat_id, &req.text).await {
+            Delivery::Sent { .. } => {}
+            Delivery::Queued { .. } => status = SendStatus::Queued,
+            Delivery::Held { .. } => status = SendStatus::HeldForReview,
+            dropped @ Delivery::Dropped { .. } => {
+                return Err(format!(
+                    "Send failed: {}",
+                    dropped.failure().unwrap_or_default()
+                ))
+            }
+        }
+    }
+    let mut failures = Vec::new();
+    for (filename, data) in files {
+        if let Err(e) = outlet.send_file(&req.chat_id, &filename, data, None).await {
+            failures.push(format!("{}: {}", filename, e));
+        }
+    }
+
+    ulog_info!(
+        "[im] Proactive send to {}:{} chat {} ({} chars, {} attachment(s){})",
+        req.agent_id,
+        req.channel_id,
+        req.chat_id,
+        req.text.chars().count(),
+        req.attachments.len(),
+        match status {
+            SendStatus::Sent => "",
+            SendStatus::Queued => ", text queued",
+            SendStatus::HeldForReview => ", text held for review",
+        }
+    );
+    if failures.is_empty() {
+        Ok(status)
+    } else {
+        Err(format!("Attachment upload failed: {}", failures.join("; ")))
+    }
+}
+
+/// Read an attachment; with `roots` set, its canonical path must be inside one of them
+fn read_attachment(path: &Path, roots: Option<&[PathBuf]>) -> Result<(String, Vec<u8>), String> {
+    if !path.is_absolute() {
+        return Err(format!(
+            "Attachment path must be absolute: {}",
+            path.display()
+        ));
+    }
+    let path = &std::fs::canonicalize(path)
+        .map_err(|e| format!("Attachment {}: {}", path.display(), e))?;
+    if let Some(roots) = roots {
+        check_inside(path, roots)?;
+    }
+    let meta =
+        std::fs::metadata(path).map_err(|e| format!("Attachment {}: {}", path.display(), e))?;
+    if !meta.is_file() {
+        return Err(format!("Attachment {} is not a file", path.display()));
+    }
+    if meta.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "Attachment {} is too large ({} MB max)",
+            path.display(),
+            MAX_ATTACHMENT_BYTES / 1024 / 1024
+        ));
+    }
+    let data = std::fs::read(path).map_err(|e| format!("Attachment {}: {}", path.display(), e))?;
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "attachment".to_string());
+    Ok((filename, data))
+}
+
+/// Err unless canonical `path` is inside one of the canonical `roots`
+fn check_inside(path: &Path, roots: &[PathBuf]) -> Result<(), String> {
+    if roots.iter().any(|root| path.starts_with(root)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Attachment {} is outside the agent's workspaces",
+            path.display()
+        ))
+    }
+}
+
+// ── Localhost endpoint ──
+
+struct Endpoint {
+    url: String,
+    /// Canonical workspace → token handed to that workspace's Sidecars
+    tokens: Mutex<HashMap<PathBuf, String>>,
+}
+
+static ENDPOINT: OnceLock<Endpoint> = OnceLock::new();
+
+/// `(url, token)` for env injection into a Sidecar running in `workspace`
+/// (None until the endpoint is bound). The token only works for that workspace.
+pub fn endpoint_env(workspace: &Path) -> Option<(String, String)> {
+    let endpoint = ENDPOINT.get()?;
+    let workspace = std::fs::canonicalize(workspace).ok()?;
+    let mut tokens = endpoint.tokens.lock().ok()?;
+    let token = tokens
+        .entry(workspace)
+        .or_insert_with(|| uuid::Uuid::new_v4().simple().to_string());
+    Some((endpoint.url.clone(), token.clone()))
+}
+
+/// Workspace the bearer token in `authorization` was issued to
+fn caller_workspace(endpoint: &Endpoint, authorization: Option<&str>) -> Option<PathBuf> {
+    let token = authorization?.strip_prefix("Bearer ")?;
+    endpoint
+        .tokens
+        .lock()
+        .ok()?
+        .iter()
+        .find(|(_, t)| t.as_str() == token)
+        .map(|(workspace, _)| workspace.clone())
+}
+
+/// Bind the send endpoint on an ephemeral 127.0.0.1 port and serve it.
+/// Binding happens synchronously so Sidecars spawned afterwards get the env vars.
+pub fn spawn_send_endpoint(im_state: ImManagerState) {
+    let listener = match std::net::TcpListener::bind(("127.0.0.1", 0)) {
+        Ok(l) => l,
+        Err(e) => {
+            ulog_warn!("[im] Failed to bind proactive send endpoint: {}", e);
+            return;
+        }
+    };
+    let (Ok(addr), Ok(())) = (listener.local_addr(), listener.set_nonblocking(true)) else {
+        ulog_warn!("[im] Failed to configure proactive send endpoint");
+        return;
+    };
+    let _ = ENDPOINT.set(Endpoint {
+        url: format!("http://127.0.0.1:{}", addr.port()),
+        tokens: Mutex::new(HashMap::new()),
+    });
+    ulog_info!("[im] Proactive send endpoint listening on {}", addr);
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match tokio::net::TcpListener::from_std(listener) {
+            Ok(l) => l,
+            Err(e) => {
+                ulog_warn!("[im] Proactive send endpoint unusable: {}", e);
+                return;
+            }
+        };
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(serve_connection(stream, im_state.clone()));
+        }
+    });
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<HttpRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_REQUEST_BYTES {
+            return None;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut parts = lines.next()?.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut content_length = 0usize;
+    let mut authorization = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().ok()?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+    if content_length > MAX_REQUEST_BYTES {
+        return None;
+    }
+
+    let mut body = buf[head_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    Some(HttpRequest {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+async fn serve_connection(mut stream: TcpStream, im_state: ImManagerState) {
+    let request =
+        match tokio::time::timeout(Duration::from_secs(10), read_request(&mut stream)).await {
+            Ok(Some(r)) => r,
+            _ => return,
+        };
+    let (status, body) = handle_request(&request, &im_state).await;
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn handle_request(
+    request: &HttpRequest,
+    im_state: &ImManagerState,
+) -> (&'static str, serde_json::Value) {
+    let Some(endpoint) = ENDPOINT.get() else {
+        return (
+            "503 Service Unavailable",
+            serde_json::json!({ "error": "not ready" }),
+        );
+    };
+    let Some(caller) = caller_workspace(endpoint, request.authorization.as_deref()) else {
+        return (
+            "401 Unauthorized",
+            serde_json::json!({ "error": "unauthorized" }),
+        );
+    };
+
+    let (path, query) = request
+        .path
+        .split_once('?')
+        .unwrap_or((request.path.as_str(), ""));
+    match (request.method.as_str(), path) {
+        ("POST", "/im/send") => {
+            let req: SendMessageRequest = match serde_json::from_slice(&request.body) {
+                Ok(r) => r,
+                Err(e) => {
+                    return (
+                        "400 Bad Request",
+                        serde_json::json!({ "error": format!("Invalid request: {}", e) }),
+                    )
+                }
+            };
+            match send(im_state, &req, Some(&caller)).await {
+                Ok(status) => (
+                    "200 OK",
+                    serde_json::json!({ "ok": true, "status": status }),
+                ),
+                Err(e) => (
+                    "422 Unprocessable Entity",
+                    serde_json::json!({ "ok": false, "error": e }),
+                ),
+            }
+        }
+        ("GET", "/im/chats") => {
+            let param = |key: &str| {
+                query.split('&').find_map(|pair| {
+                    pair.split_once('=')
+                        .filter(|(k, v)| *k == key && !v.is_empty())
+                        .map(|(_, v)| v.to_string())
+                })
+            };
+            let (agent_id, channel_id) = (param("agentId"), param("channelId"));
+            let manager = im_state.lock().await;
+            let mut chats = manager
+                .known_chats(agent_id.as_deref(), channel_id.as_deref())
+                .await;
+            // Only channels of agents that own the caller's workspace
+            chats.retain(|c| {
+                manager
+                    .running_config(&c.agent_id, &c.channel_id)
+                    .is_some_and(|config| workspace_roots(config).contains(&caller))
+            });
+            ("200 OK", serde_json::to_value(chats).unwrap_or_default())
+        }
+        _ => ("404 Not Found", serde_json::json!({ "error": "not found" })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::im::types::ImPlatform;
+
+    fn known(chat_id: &str, chat_type: ImSourceType, sender: &str) -> KnownChat {
+        KnownChat {
+            chat_id: chat_id.to_string(),
+            chat_type,
+            sender_id: Some(sender.to_string()),
+            display_name: None,
+            last_active: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn target_checks_follow_policy_and_allowlist() {
+        let chats = vec![
+            known("100", ImSourceType::Private, "100"),
+            known("200", ImSourceType::Private, "200"),
+            known("-5", ImSourceType::Group, "100"),
+        ];
+        let groups = vec![GroupPermission {
+            group_id: "-9".to_string(),
+            group_name: "Team".to_string(),
+            platform: ImPlatform::Telegram,
+            status: GroupPermissionStatus::Approved,
+            discovered_at: String::new(),
+        }];
+        let allow = vec!["100".to_string(), "300".to_string()];
+
+        assert!(check_target("100", true, &chats, &allow, &groups).is_ok());
+        assert!(check_target("100", false, &chats, &allow, &groups).is_err());
+        assert!(check_target("200", true, &chats, &allow, &groups).is_err());
+        assert!(check_target("200", true, &chats, &[], &groups).is_ok());
+        // Allowlisted but never seen (Telegram private chat ID == user ID)
+        assert!(check_target("300", true, &chats, &allow, &groups).is_ok());
+        assert!(check_target("400", true, &chats, &[], &groups).is_err());
+        // Groups need an approval, whoever talked there
+        assert!(check_target("-9", true, &chats, &allow, &groups).is_ok());
+        assert!(check_target("-5", true, &chats, &allow, &groups).is_err());
+    }
+
+    #[test]
+    fn attachments_must_stay_inside_workspaces() {
+        let roots = vec![PathBuf::from("/ws/app"), PathBuf::from("/ws/docs")];
+        assert!(check_inside(Path::new("/ws/app/out/report.pdf"), &roots).is_ok());
+        assert!(check_inside(Path::new("/ws/docs/a.txt"), &roots).is_ok());
+        // Prefix of a name is not containment
+        assert!(check_inside(Path::new("/ws/app-secrets/key"), &roots).is_err());
+        assert!(check_inside(Path::new("/home/me/.ssh/id_rsa"), &roots).is_err());
+    }
+}
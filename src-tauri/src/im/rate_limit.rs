@@ -0,0 +1,386 @@
+// Outbound rate limiter — token buckets shared by every channel of the same bot.
+// Each bot gets one global bucket plus one bucket per chat. Final messages wait for a
+// token; intermediate streaming edits are skipped while the limiter is saturated.
+// Retry-after responses from the platform pause the affected chat (or the whole bot).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
+
+use super::types::ImPlatform;
+
+/// Per-chat buckets idle (and full) for this long are discarded
+const CHAT_BUCKET_IDLE_SECS: u64 = 600;
+
+/// Budget for one bot: global and per-chat refill rates plus burst sizes
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    pub global_per_sec: f64,
+    pub global_burst: f64,
+    pub chat_per_sec: f64,
+    pub chat_burst: f64,
+}
+
+impl RateLimits {
+    pub fn for_platform(platform: &ImPlatform) -> Self {
+        match platform {
+            // Bot API: ~30 msg/s overall, ~1 msg/s in a single chat
+            ImPlatform::Telegram => Self {
+                global_per_sec: 30.0,
+                global_burst: 30.0,
+                chat_per_sec: 1.0,
+                chat_burst: 3.0,
+            },
+            // Open platform: 50 QPS per app, 5 QPS per user / group
+            ImPlatform::Feishu => Self {
+                global_per_sec: 50.0,
+                global_burst: 50.0,
+                chat_per_sec: 5.0,
+                chat_burst: 5.0,
+            },
+            // Robot / card APIs: 20 QPS per app
+            ImPlatform::Dingtalk => Self {
+                global_per_sec: 20.0,
+                global_burst: 20.0,
+                chat_per_sec: 2.0,
+                chat_burst: 4.0,
+            },
+            // Loopback: effectively unlimited
+            ImPlatform::Local => Self {
+                global_per_sec: 1000.0,
+                global_burst: 1000.0,
+                chat_per_sec: 100.0,
+                chat_burst: 100.0,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill_per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until one token is available (zero if available now)
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ChatState {
+    bucket: TokenBucket,
+    paused_until: Option<Instant>,
+    /// Final messages for this chat currently waiting for a token
+    waiting: usize,
+}
+
+impl ChatState {
+    fn new(limits: &RateLimits, now: Instant) -> Self {
+        Self {
+            bucket: TokenBucket::new(limits.chat_burst, limits.chat_per_sec, now),
+            paused_until: None,
+            waiting: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    global: TokenBucket,
+    global_paused_until: Option<Instant>,
+    chats: HashMap<String, ChatState>,
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    state: Mutex<LimiterState>,
+}
+
+/// Marks a chat as having a waiting final message; cleared even if `acquire` is cancelled
+struct WaitingGuard<'a> {
+    limiter: &'a RateLimiter,
+    chat_id: &'a str,
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.lock();
+        if let Some(chat) = state.chats.get_mut(self.chat_id) {
+            chat.waiting = chat.waiting.saturating_sub(1);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(LimiterState {
+                global: TokenBucket::new(
+                    limits.global_burst,
+                    limits.global_per_sec,
+                    Instant::now(),
+                ),
+                global_paused_until: None,
+                chats: HashMap::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take a token for `chat_id` if both budgets allow it now; otherwise return
+    /// how long to wait before trying again.
+    fn try_take(&self, chat_id: &str, now: Instant) -> Option<Duration> {
+        let mut state = self.lock();
+        let limits = self.limits;
+
+        let idle = Duration::from_secs(CHAT_BUCKET_IDLE_SECS);
+        state.chats.retain(|_, c| {
+            c.waiting > 0
+                || now.saturating_duration_since(c.bucket.last_refill) < idle
+                || c.paused_until.is_some_and(|t| t > now)
+        });
+
+        if let Some(until) = state.global_paused_until {
+            if until > now {
+                return Some(until - now);
+            }
+            state.global_paused_until = None;
+        }
+
+        state.global.refill(now);
+        let chat = state
+            .chats
+            .entry(chat_id.to_string())
+            .or_insert_with(|| ChatState::new(&limits, now));
+        if let Some(until) = chat.paused_until {
+            if until > now {
+                return Some(until - now);
+            }
+            chat.paused_until = None;
+        }
+        chat.bucket.refill(now);
+
+        let wait = chat.bucket.wait_time().max(state.global.wait_time());
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+
+        if let Some(chat) = state.chats.get_mut(chat_id) {
+            chat.bucket.tokens -= 1.0;
+        }
+        state.global.tokens -= 1.0;
+        None
+    }
+
+    /// Wait until a message to `chat_id` may be sent, then consume its token.
+    /// Used for every outbound API call that creates, edits or deletes a message.
+    pub async fn acquire(&self, chat_id: &str) {
+        let Some(mut wait) = self.try_take(chat_id, Instant::now()) else {
+            return;
+        };
+        // Out of budget: flag the chat so its intermediate edits yield to this send
+        self.lock()
+            .chats
+            .entry(chat_id.to_string())
+            .or_insert_with(|| ChatState::new(&self.limits, Instant::now()))
+            .waiting += 1;
+        let _guard = WaitingGuard {
+            limiter: self,
+            chat_id,
+        };
+        loop {
+            tokio::time::sleep(wait).await;
+            match self.try_take(chat_id, Instant::now()) {
+                Some(next) => wait = next,
+                None => break,
+            }
+        }
+    }
+
+    /// Take a token from the bot-wide budget only; otherwise return how long to wait
+    fn try_take_global(&self, now: Instant) -> Option<Duration> {
+        let mut state = self.lock();
+        if let Some(until) = state.global_paused_until {
+            if until > now {
+                return Some(until - now);
+            }
+            state.global_paused_until = None;
+        }
+        state.global.refill(now);
+        let wait = state.global.wait_time();
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+        state.global.tokens -= 1.0;
+        None
+    }
+
+    /// Wait for the bot-wide budget only. For calls the platform doesn't count against
+    /// the chat's message rate (Telegram draft updates).
+    pub async fn acquire_global(&self) {
+        while let Some(wait) = self.try_take_global(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Whether an intermediate (droppable) update for `chat_id` should be skipped:
+    /// no token available right now, a pause is active, or final messages are waiting.
+    pub fn is_saturated(&self, chat_id: &str) -> bool {
+        let now = Instant::now();
+        let mut state = self.lock();
+        if state.global_paused_until.is_some_and(|t| t > now) {
+            return true;
+        }
+        state.global.refill(now);
+        if state.global.tokens < 1.0 {
+            return true;
+        }
+        match state.chats.get_mut(chat_id) {
+            Some(chat) => {
+                if chat.waiting > 0 || chat.paused_until.is_some_and(|t| t > now) {
+                    return true;
+                }
+                chat.bucket.refill(now);
+                chat.bucket.tokens < 1.0
+            }
+            None => false,
+        }
+    }
+
+    /// The platform answered with retry-after: pause `chat_id` (or the whole bot
+    /// when the limit was not chat-specific) and drain the affected bucket.
+    pub fn note_retry_after(&self, chat_id: Option<&str>, secs: u64) {
+        let now = Instant::now();
+        let until = now + Duration::from_secs(secs.max(1));
+        let mut state = self.lock();
+        match chat_id {
+            Some(id) => {
+                let limits = self.limits;
+                let chat = state
+                    .chats
+                    .entry(id.to_string())
+                    .or_insert_with(|| ChatState::new(&limits, now));
+                chat.paused_until = Some(chat.paused_until.map_or(until, |t| t.max(until)));
+                chat.bucket.tokens = 0.0;
+                chat.bucket.last_refill = until;
+            }
+            None => {
+                state.global_paused_until =
+                    Some(state.global_paused_until.map_or(until, |t| t.max(until)));
+                state.global.tokens = 0.0;
+                state.global.last_refill = until;
+            }
+        }
+    }
+}
+
+/// Limiters by bot identity. Weak so a bot's buckets go away with its last channel.
+fn registry() -> &'static Mutex<HashMap<String, Weak<RateLimiter>>> {
+    static LIMITERS: OnceLock<Mutex<HashMap<String, Weak<RateLimiter>>>> = OnceLock::new();
+    LIMITERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Limiter shared by every channel running the same bot (`bot_key` identifies the
+/// bot credentials, e.g. the Telegram token or Feishu app ID).
+pub fn shared_limiter(platform: &ImPlatform, bot_key: &str) -> Arc<RateLimiter> {
+    let key = format!("{}:{}", platform, bot_key);
+    let mut map = registry().lock().unwrap_or_else(|e| e.into_inner());
+    map.retain(|_, weak| weak.strong_count() > 0);
+    if let Some(existing) = map.get(&key).and_then(Weak::upgrade) {
+        return existing;
+    }
+    let limiter = Arc::new(RateLimiter::new(RateLimits::for_platform(platform)));
+    map.insert(key, Arc::downgrade(&limiter));
+    limiter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_limits() -> RateLimits {
+        RateLimits {
+            global_per_sec: 10.0,
+            global_burst: 3.0,
+            chat_per_sec: 1.0,
+            chat_burst: 2.0,
+        }
+    }
+
+    #[test]
+    fn test_per_chat_budget() {
+        let limiter = RateLimiter::new(test_limits());
+        let now = Instant::now();
+        assert!(limiter.try_take("a", now).is_none());
+        assert!(limiter.try_take("a", now).is_none());
+        // Chat burst exhausted, another chat still has budget
+        assert!(limiter.try_take("a", now).is_some());
+        assert!(limiter.try_take("b", now).is_none());
+        // Global burst (3) exhausted
+        assert!(limiter.try_take("c", now).is_some());
+        // Refill after a second
+        assert!(limiter
+            .try_take("a", now + Duration::from_secs(1))
+            .is_none());
+    }
+
+    #[test]
+    fn test_global_only_spares_chat_budget() {
+        let limiter = RateLimiter::new(test_limits());
+        let now = Instant::now();
+        assert!(limiter.try_take_global(now).is_none());
+        assert!(limiter.try_take_global(now).is_none());
+        // The chat bucket is untouched, the global one has a single token left
+        assert!(limiter.try_take("a", now).is_none());
+        assert!(limiter.try_take_global(now).is_some());
+    }
+
+    #[test]
+    fn test_retry_after_pauses_chat() {
+        let limiter = RateLimiter::new(test_limits());
+        limiter.note_retry_after(Some("a"), 5);
+        assert!(limiter.is_saturated("a"));
+        assert!(!limiter.is_saturated("b"));
+        let wait = limiter.try_take("a", Instant::now()).unwrap();
+        assert!(wait > Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_shared_between_channels() {
+        let a = shared_limiter(&ImPlatform::Telegram, "test-token-shared");
+        let b = shared_limiter(&ImPlatform::Telegram, "test-token-shared");
+        let c = shared_limiter(&ImPlatform::Telegram, "test-token-other");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+}
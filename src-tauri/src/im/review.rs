@@ -0,0 +1,205 @@
+// Outbound review queue — final replies held for approval before they are posted.
+// Channels with `review_before_send` stream nothing to the chat: each finished reply is
+// queued here, shown on the desktop (`im:review-queued` events, cmd_im_review_list) and
+// only delivered once an operator approves (optionally edited) or dropped on reject.
+// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/review_queue.json
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ulog_info;
+
+/// Tauri event sent when a reply enters the review queue
+pub const REVIEW_QUEUED_EVENT: &str = "im:review-queued";
+
+/// A reply waiting for approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewItem {
+    pub id: String,
+    pub session_key: String,
+    pub chat_id: String,
+    /// The user message the reply answers (empty for agent-initiated messages)
+    pub message_id: String,
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    pub question: String,
+    pub draft: String,
+    /// RFC 3339
+    pub created_at: String,
+}
+
+/// Payload of REVIEW_QUEUED_EVENT
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewEvent<'a> {
+    pub agent_id: &'a str,
+    pub channel_id: &'a str,
+    #[serde(flatten)]
+    pub item: &'a ReviewItem,
+}
+
+pub struct ReviewQueue {
+    items: Mutex<Vec<ReviewItem>>,
+    persist_path: Option<PathBuf>,
+}
+
+impl ReviewQueue {
+    /// Load pending reviews from `persist_path` (None = in-memory only)
+    pub fn load(persist_path: Option<PathBuf>) -> Self {
+        let items = persist_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<Vec<ReviewItem>>(&s).ok())
+            .unwrap_or_default();
+        Self {
+            items: Mutex::new(items),
+            persist_path,
+        }
+    }
+
+    /// Queue a reply; returns the stored item
+    pub fn push(
+        &self,
+        session_key: &str,
+        chat_id: &str,
+        message_id: &str,
+        sender_name: Option<String>,
+        question: &str,
+        draft: &str,
+    ) -> ReviewItem {
+        let item = ReviewItem {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_key: session_key.to_string(),
+            chat_id: chat_id.to_string(),
+            message_id: message_id.to_string(),
+            sender_name,
+            question: question.to_string(),
+            draft: draft.to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let mut items = self.lock();
+        items.push(item.clone());
+        ulog_info!(
+            "[im-review] Reply for {} queued for review ({} pending)",
+            session_key,
+            items.len()
+        );
+        self.persist(&items);
+        item
+    }
+
+    /// Queue a message the agent starts itself (proactive send, scheduled task result,
+    /// heartbeat notice), so review-gated channels can't be bypassed
+    pub fn hold(&self, chat_id: &str, text: &str) -> ReviewItem {
+        let session_key = format!("proactive:{}", chat_id);
+        self.push(&session_key, chat_id, "", None, "", text)
+    }
+
+    /// Pending replies, oldest first
+    pub fn list(&self) -> Vec<ReviewItem> {
+        self.lock().clone()
+    }
+
+    /// Remove and return an item (approve / edit / reject)
+    pub fn take(&self, id: &str) -> Result<ReviewItem, String> {
+        let mut items = self.lock();
+        let pos = items
+            .iter()
+            .position(|item| item.id == id)
+            .ok_or_else(|| format!("Review item {} not found", id))?;
+        let item = items.remove(pos);
+        self.persist(&items);
+        Ok(item)
+    }
+
+    /// Put back an item whose send failed, at its place in the queue (oldest first)
+    pub fn restore(&self, item: ReviewItem) {
+        let mut items = self.lock();
+        let pos = items
+            .iter()
+            .position(|other| other.created_at > item.created_at)
+            .unwrap_or(items.len());
+        items.insert(pos, item);
+        self.persist(&items);
+    }
+
+    /// Drop replies to a recalled message. Returns how many were dropped.
+    pub fn discard_for_message(&self, chat_id: &str, message_id: &str) -> usize {
+        let mut items = self.lock();
+        let before = items.len();
+        items.retain(|item| !(item.chat_id == chat_id && item.message_id == message_id));
+        let dropped = before - items.len();
+        if dropped > 0 {
+            self.persist(&items);
+        }
+        dropped
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ReviewItem>> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, items: &[ReviewItem]) {
+        if let Some(path) = &self.persist_path {
+            save_items(path, items);
+        }
+    }
+}
+
+fn save_items(path: &Path, items: &[ReviewItem]) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    if let Ok(s) = serde_json::to_string_pretty(items) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_survives_reload() {
+        let dir = std::env::temp_dir().join(format!("im-review-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("review_queue.json");
+
+        let queue = ReviewQueue::load(Some(path.clone()));
+        let first = queue.push("k", "42", "m1", None, "refund?", "Sure, refunded.");
+        queue.push("k", "42", "m2", Some("Ann".into()), "thanks", "You're welcome!");
+        assert!(queue.take("missing").is_err());
+        let taken = queue.take(&first.id).unwrap();
+        assert_eq!(taken.draft, "Sure, refunded.");
+        // A failed send puts the reply back at the head
+        queue.restore(taken);
+        assert_eq!(queue.list()[0].id, first.id);
+        queue.take(&first.id).unwrap();
+
+        let reloaded = ReviewQueue::load(Some(path));
+        let items = reloaded.list();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].message_id, "m2");
+
+        assert_eq!(reloaded.discard_for_message("42", "m2"), 1);
+        assert!(reloaded.list().is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_held_message_waits_for_approval() {
+        let queue = ReviewQueue::load(None);
+        queue.push("k", "42", "m1", None, "hi", "Hello!");
+        let held = queue.hold("42", "Your report is ready.");
+        assert_eq!(held.message_id, "");
+        // Not tied to a user message, so recalls in the chat don't drop it
+        assert_eq!(queue.discard_for_message("42", "m1"), 1);
+        assert_eq!(queue.list().len(), 1);
+        assert_eq!(queue.take(&held.id).unwrap().draft, "Your report is ready.");
+    }
+}
@@ -1,17 +1,21 @@
 // Session Router — maps IM peers to independent Sidecar processes.
 //
 // Each IM conversation (platform + chat_id) gets its own Bun Sidecar process,
-// identified by a session_key like `im:{agentId}:{platform}:private:{chatId}`.
+// identified by a session_key like `im:{agentId}:{platform}:private:{chatId}`
+// (suffixed with `#{route}` when the channel routes messages to several agents).
+// Private chats of linked identities share `im:{agentId}:person:{personId}` across
+// channels, along with its session id (see identity.rs).
 //
 // The router handles:
 //   - peer -> Sidecar mapping (ensure_sidecar)
 //   - HTTP health checks for existing Sidecars
 //   - Session reset (/new command)
+//   - Per-chat workspace selection (/workspace command)
 //   - Idle session collection (30-min timeout)
 //   - AI config sync to new Sidecars
 //
 // Concurrency model:
-//   Global semaphore + per-peer locks live OUTSIDE the router (in the processing loop).
+//   The turn scheduler + per-peer locks live OUTSIDE the router (in the processing loop).
 //   The router lock is only held briefly for data operations.
 //   SSE streaming to Sidecars happens WITHOUT the router lock, enabling per-peer parallelism.
 
@@ -29,10 +33,11 @@ use crate::sidecar::{
 };
 use crate::{local_http, ulog_info, ulog_warn};
 
-use super::types::{ActiveSessionInfo, ImConfig, ImMessage, PeerSession, RouteError};
-
-/// Max concurrent AI requests across all peers
-pub const GLOBAL_CONCURRENCY: usize = 8;
+use super::identity::{self, IdentityStore};
+use super::types::{
+    ActiveSessionInfo, ImConfig, ImMessage, ImSourceType, PeerSession, RouteError,
+};
+use super::workspaces::ChatWorkspaces;
 
 /// Idle session timeout (30 minutes)
 const IDLE_TIMEOUT_SECS: u64 = 1800;
@@ -65,37 +70,81 @@ pub struct SessionRouter {
     http_client: Client,
     /// Agent ID — used in session key format: `im:{agentId}:{platform}:private:{chatId}`
     agent_id: String,
+    /// Channel ID — tells this channel's Sidecar ownership apart on shared person sessions
+    channel_id: String,
+    /// Workspaces chosen per session key with `/workspace` (persisted)
+    chat_workspaces: ChatWorkspaces,
+    /// Cross-platform identity links (None = every chat has its own session)
+    identities: Option<Arc<IdentityStore>>,
 }
 
 impl SessionRouter {
     /// Create a new SessionRouter for an Agent channel.
-    pub fn new(default_workspace: PathBuf, agent_id: String) -> Self {
+    /// `chat_workspaces_path` persists per-chat workspace choices (None = in-memory only).
+    pub fn new(
+        default_workspace: PathBuf,
+        agent_id: String,
+        channel_id: String,
+        chat_workspaces_path: Option<PathBuf>,
+        identities: Option<Arc<IdentityStore>>,
+    ) -> Self {
         Self {
             peer_sessions: HashMap::new(),
             default_workspace,
             http_client: create_sidecar_http_client(),
             agent_id,
+            channel_id,
+            chat_workspaces: ChatWorkspaces::load(chat_workspaces_path),
+            identities,
         }
     }
 
     // ── Session Key ────────────────────────────────────────────────
 
     /// Generate session key from IM message.
-    /// Format: `im:{agentId}:{platform}:private:{chatId}` (Phase 1: private chat only)
+    /// Format: `im:{agentId}:{platform}:private:{chatId}` (Phase 1: private chat only),
+    /// or `im:{agentId}:person:{personId}` for private chats of a linked identity.
     pub fn session_key(&self, msg: &ImMessage) -> String {
+        if msg.source_type == ImSourceType::Private {
+            if let Some(person_id) = self
+                .identities
+                .as_ref()
+                .and_then(|ids| ids.person_of(&msg.platform, &msg.sender_id))
+            {
+                return identity::person_session_key(&self.agent_id, &person_id);
+            }
+        }
         format!(
             "im:{}:{}:private:{}",
             self.agent_id, msg.platform, msg.chat_id
         )
     }
 
+    /// Session id shared by all chats of a linked person (person session keys only)
+    fn shared_session_id(&self, session_key: &str) -> Option<String> {
+        if !identity::is_person_key(session_key) {
+            return None;
+        }
+        self.identities.as_ref()?.shared_session_id(session_key)
+    }
+
+    fn record_shared_session_id(&self, session_key: &str, session_id: &str) {
+        if !identity::is_person_key(session_key) {
+            return;
+        }
+        if let Some(ids) = &self.identities {
+            ids.set_shared_session_id(session_key, session_id);
+        }
+    }
+
     // ── Ensure Sidecar ─────────────────────────────────────────────
 
     /// Ensure a Sidecar is running for the given session key.
     /// Returns `(port, is_new_sidecar)` — `is_new_sidecar` is true when a new Sidecar was created
     /// (caller should sync AI config like model/MCP after creation).
     ///
-    /// Uses `SidecarOwner::Agent(session_key)` as the owner, so idle collection can release it.
+    /// Uses `SidecarOwner::Agent(session_key)` as the owner (tagged with the channel for
+    /// person sessions, whose Sidecar is shared), so idle collection can release it.
     ///
     /// NOTE: `start_sidecar()` is a blocking function (health check loop). This method
     /// wraps it in `tokio::task::spawn_blocking` so it's safe to call from async context.
@@ -104,8 +153,40 @@ impl SessionRouter {
         session_key: &str,
         app_handle: &AppHandle,
         sidecar_manager: &ManagedSidecarState,
-        _config: &ImConfig,
+        config: &ImConfig,
     ) -> Result<(u16, bool), RouteError> {
+        // The (route- and chat-resolved) config decides where the Sidecar runs
+        let workspace = PathBuf::from(&config.workspace_path);
+
+        // A Sidecar running in another workspace (chat switched with /workspace) is replaced
+        let moved = self
+            .peer_sessions
+            .get(session_key)
+            .is_some_and(|ps| ps.sidecar_port > 0 && ps.workspace_path != workspace);
+        if moved {
+            ulog_info!(
+                "[im-router] Workspace of {} changed to {}, restarting its Sidecar",
+                session_key,
+                workspace.display()
+            );
+            self.release_peer_sidecar(session_key, sidecar_manager);
+        }
+
+        // A linked person's conversation was reset (or started) on another channel — follow it
+        if let Some(shared) = self.shared_session_id(session_key) {
+            let stale = self
+                .peer_sessions
+                .get(session_key)
+                .is_some_and(|ps| ps.session_id != shared);
+            if stale {
+                self.release_peer_sidecar(session_key, sidecar_manager);
+                if let Some(ps) = self.peer_sessions.get_mut(session_key) {
+                    ps.session_id = shared;
+                    ps.message_count = 0;
+                }
+            }
+        }
+
         // Phase 1: Check existing peer session with healthy Sidecar
         if let Some(ps) = self.peer_sessions.get(session_key) {
             if ps.sidecar_port > 0 {
@@ -125,13 +206,9 @@ impl SessionRouter {
             .peer_sessions
             .get(session_key)
             .map(|ps| ps.session_id.clone())
+            .or_else(|| self.shared_session_id(session_key))
             .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-
-        let workspace = self
-            .peer_sessions
-            .get(session_key)
-            .map(|ps| ps.workspace_path.clone())
-            .unwrap_or_else(|| self.default_workspace.clone());
+        self.record_shared_session_id(session_key, &session_id);
 
         let prev_count = self
             .peer_sessions
@@ -148,7 +225,7 @@ impl SessionRouter {
         // start_sidecar is blocking (TCP health check loop), so use spawn_blocking
         let sidecar_id = session_id.clone();
         let agent_dir = Some(workspace.clone());
-        let owner = SidecarOwner::Agent(session_key.to_string());
+        let owner = sidecar_owner(session_key, &self.channel_id);
         let mgr = Arc::clone(sidecar_manager);
         let bun = bun_path.clone();
         let script = script_path.clone();
@@ -158,7 +235,7 @@ impl SessionRouter {
         })
         .await
         .map_err(|e| RouteError::Setup(format!("spawn_blocking failed: {}", e)))?
-        .map_err(|e| RouteError::Setup(format!("Failed to start Sidecar: {}", e)))?;
+        .map_err(|e| RouteError::Unavailable(format!("Failed to start Sidecar: {}", e)))?;
 
         // Phase 3: Record in peer_sessions
         self.peer_sessions.insert(
@@ -231,9 +308,10 @@ impl SessionRouter {
     /// Handle /new command — reset session for a peer.
     /// Generates a new session_id but keeps the Sidecar alive (reuse port).
     /// If the Sidecar has a `/api/im/session/new` endpoint, calls it to reset server-side state.
-    /// Returns the new session_id.
+    /// Returns the new session_id. A linked person's other chats pick it up on their next message.
     pub async fn reset_session(&mut self, session_key: &str) -> Option<String> {
         let new_session_id = uuid::Uuid::new_v4().to_string();
+        self.record_shared_session_id(session_key, &new_session_id);
 
         if let Some(ps) = self.peer_sessions.get_mut(session_key) {
             // Try to notify Sidecar about the reset
@@ -260,6 +338,57 @@ impl SessionRouter {
         }
     }
 
+    // ── Per-chat Workspace (/workspace command) ────────────────────
+
+    /// Workspace chosen for the session, if it is still in `allowlist`
+    /// (None = run in the agent's / route's own workspace).
+    pub fn chat_workspace(&self, session_key: &str, allowlist: &[String]) -> Option<String> {
+        self.chat_workspaces
+            .get_allowed(session_key, allowlist)
+            .map(str::to_string)
+    }
+
+    /// Point a session at another workspace (None = back to the default).
+    /// A changed workspace releases the session's Sidecar and starts a new conversation,
+    /// since the old one belongs to the previous project; the next message starts a
+    /// Sidecar in the new directory. Returns false if nothing changed.
+    pub fn set_chat_workspace(
+        &mut self,
+        session_key: &str,
+        workspace: Option<&str>,
+        sidecar_manager: &ManagedSidecarState,
+    ) -> bool {
+        if !self.chat_workspaces.set(session_key, workspace) {
+            return false;
+        }
+        self.release_peer_sidecar(session_key, sidecar_manager);
+        let session_id = uuid::Uuid::new_v4().to_string();
+        self.record_shared_session_id(session_key, &session_id);
+        if let Some(ps) = self.peer_sessions.get_mut(session_key) {
+            ps.session_id = session_id;
+            ps.message_count = 0;
+            ps.workspace_path = workspace
+                .map(PathBuf::from)
+                .unwrap_or_else(|| self.default_workspace.clone());
+            ps.last_active = Instant::now();
+        }
+        true
+    }
+
+    /// Release the session's running Sidecar (if any), keeping the PeerSession entry
+    fn release_peer_sidecar(&mut self, session_key: &str, sidecar_manager: &ManagedSidecarState) {
+        if let Some(ps) = self.peer_sessions.get_mut(session_key) {
+            if ps.sidecar_port == 0 {
+                return;
+            }
+            let owner = sidecar_owner(session_key, &self.channel_id);
+            if let Ok(mut mgr) = sidecar_manager.lock() {
+                let _ = mgr.release_sidecar(&ps.session_id, &owner);
+            }
+            ps.sidecar_port = 0;
+        }
+    }
+
     // ── Idle Session Collection ────────────────────────────────────
 
     /// Collect idle sessions that haven't been active for IDLE_TIMEOUT_SECS.
@@ -294,7 +423,7 @@ impl SessionRouter {
                 );
 
                 // Release the Sidecar via SidecarManager
-                let owner = SidecarOwner::Agent(key.clone());
+                let owner = sidecar_owner(&key, &self.channel_id);
                 if let Ok(mut mgr) = sidecar_manager.lock() {
                     let _ = mgr.release_sidecar(&ps.session_id, &owner);
                 }
@@ -334,7 +463,7 @@ impl SessionRouter {
 
     // ── AI Config Sync ─────────────────────────────────────────────
 
-    /// Sync AI config (model + MCP + provider) to a newly created Sidecar.
+    /// Sync AI config (model + MCP + provider + channel persona) to a newly created Sidecar.
     /// Called after ensure_sidecar returns is_new=true.
     pub async fn sync_ai_config(
         &self,
@@ -342,6 +471,7 @@ impl SessionRouter {
         model: Option<&str>,
         mcp_servers_json: Option<&str>,
         provider_env: Option<&serde_json::Value>,
+        system_prompt: Option<&str>,
     ) {
         // 1. Provider env (sync BEFORE model so pre-warm uses the correct provider)
         if let Some(penv) = provider_env {
@@ -377,7 +507,30 @@ impl SessionRouter {
             }
         }
 
-        // 3. MCP servers
+        // 3. Channel persona (before MCP, whose pre-warm builds the system prompt)
+        if let Some(prompt) = system_prompt {
+            let url = format!("http://127.0.0.1:{}/api/im/system-prompt", port);
+            match self
+                .http_client
+                .post(&url)
+                .json(&json!({ "systemPrompt": prompt }))
+                .send()
+                .await
+            {
+                Ok(_) => ulog_info!(
+                    "[im-router] Synced channel persona ({} chars) to port {}",
+                    prompt.chars().count(),
+                    port
+                ),
+                Err(e) => ulog_warn!(
+                    "[im-router] Failed to sync channel persona to port {}: {}",
+                    port,
+                    e
+                ),
+            }
+        }
+
+        // 4. MCP servers
         if let Some(mcp_json) = mcp_servers_json {
             if let Ok(servers) = serde_json::from_str::<Vec<serde_json::Value>>(mcp_json) {
                 let url = format!("http://127.0.0.1:{}/api/mcp/set", port);
@@ -464,7 +617,7 @@ impl SessionRouter {
         let keys: Vec<String> = self.peer_sessions.keys().cloned().collect();
         for key in keys {
             if let Some(ps) = self.peer_sessions.remove(&key) {
-                let owner = SidecarOwner::Agent(key);
+                let owner = sidecar_owner(&key, &self.channel_id);
                 if let Ok(mut mgr) = sidecar_manager.lock() {
                     let _ = mgr.release_sidecar(&ps.session_id, &owner);
                 }
@@ -483,7 +636,11 @@ impl SessionRouter {
                     session_key: s.session_key.clone(),
                     session_id: s.session_id.clone(),
                     sidecar_port: 0, // Sidecar not running; ensure_sidecar will start it
-                    workspace_path: self.default_workspace.clone(),
+                    workspace_path: self
+                        .chat_workspaces
+                        .get(&s.session_key)
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| self.default_workspace.clone()),
                     message_count: s.message_count,
                     last_active: Instant::now(),
                 },
@@ -505,3 +662,13 @@ impl SessionRouter {
         }
     }
 }
+
+/// Sidecar owner for a session. Person sessions share one Sidecar across channels, so
+/// each channel holds its own reference and releasing one leaves the others running.
+fn sidecar_owner(session_key: &str, channel_id: &str) -> SidecarOwner {
+    if identity::is_person_key(session_key) {
+        SidecarOwner::Agent(format!("{}@{}", session_key, channel_id))
+    } else {
+        SidecarOwner::Agent(session_key.to_string())
+    }
+}
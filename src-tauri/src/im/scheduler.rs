@@ -0,0 +1,440 @@
+// Turn scheduler — admits AI turns across all IM channels.
+//
+// Replaces the single global semaphore, where one busy group channel could take every
+// slot. A turn runs when the global, per-channel and per-user caps allow it; among the
+// waiting turns, priority ones (private chats, admins) go first, then channels are
+// served by weighted fair queueing (start-time fair queueing on per-channel virtual
+// time), FIFO within a channel. Waiters learn their estimated place in line.
+//
+// Limits come from `imScheduler` in config.json (hot-reloaded); channel weights from
+// each channel's `schedulerWeight`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Max concurrent AI turns across all channels (default)
+pub const DEFAULT_MAX_CONCURRENT: usize = 8;
+/// Max concurrent turns of one user (default)
+const DEFAULT_PER_USER: usize = 2;
+
+/// Scheduler limits (`imScheduler` in config.json)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SchedulerLimits {
+    /// Concurrent turns across all channels
+    pub max_concurrent: usize,
+    /// Concurrent turns of one channel (None = only the global cap)
+    pub per_channel: Option<usize>,
+    /// Concurrent turns of one user in a channel
+    pub per_user: usize,
+    /// Private chats go ahead of group chats
+    pub prioritize_private: bool,
+    /// Channel admins (`adminUsers`) go ahead of everyone else
+    pub prioritize_admins: bool,
+}
+
+impl Default for SchedulerLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            per_channel: None,
+            per_user: DEFAULT_PER_USER,
+            prioritize_private: true,
+            prioritize_admins: true,
+        }
+    }
+}
+
+/// Who a turn belongs to
+#[derive(Debug, Clone)]
+pub struct TurnRequest {
+    /// `{agentId}:{channelId}`
+    pub channel: String,
+    pub user: String,
+    /// Share of the slots relative to other busy channels (min 1)
+    pub weight: u32,
+    pub private: bool,
+    pub admin: bool,
+}
+
+struct Waiter {
+    id: u64,
+    request: TurnRequest,
+    priority: bool,
+    grant: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct State {
+    limits: SchedulerLimits,
+    running: usize,
+    per_channel: HashMap<String, usize>,
+    per_user: HashMap<(String, String), usize>,
+    waiting: Vec<Waiter>,
+    /// Virtual time at which each channel's next turn starts
+    finish: HashMap<String, f64>,
+    /// Start tag of the most recently admitted turn
+    clock: f64,
+    next_id: u64,
+}
+
+impl State {
+    fn has_room(&self, request: &TurnRequest) -> bool {
+        let limits = &self.limits;
+        self.running < limits.max_concurrent.max(1)
+            && limits.per_channel.map_or(true, |cap| {
+                self.per_channel.get(&request.channel).copied().unwrap_or(0) < cap.max(1)
+            })
+            && self
+                .per_user
+                .get(&(request.channel.clone(), request.user.clone()))
+                .copied()
+                .unwrap_or(0)
+                < limits.per_user.max(1)
+    }
+
+    fn start_tag(&self, channel: &str) -> f64 {
+        self.finish.get(channel).copied().unwrap_or(0.0).max(self.clock)
+    }
+
+    fn admit(&mut self, request: &TurnRequest) {
+        let start = self.start_tag(&request.channel);
+        self.finish.insert(
+            request.channel.clone(),
+            start + 1.0 / f64::from(request.weight.max(1)),
+        );
+        self.clock = start;
+        self.running += 1;
+        *self.per_channel.entry(request.channel.clone()).or_default() += 1;
+        *self
+            .per_user
+            .entry((request.channel.clone(), request.user.clone()))
+            .or_default() += 1;
+    }
+
+    fn release(&mut self, request: &TurnRequest) {
+        self.running = self.running.saturating_sub(1);
+        if let Some(n) = self.per_channel.get_mut(&request.channel) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                self.per_channel.remove(&request.channel);
+            }
+        }
+        let user_key = (request.channel.clone(), request.user.clone());
+        if let Some(n) = self.per_user.get_mut(&user_key) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                self.per_user.remove(&user_key);
+            }
+        }
+    }
+
+    fn is_priority(&self, request: &TurnRequest) -> bool {
+        (self.limits.prioritize_private && request.private)
+            || (self.limits.prioritize_admins && request.admin)
+    }
+
+    /// Next waiter to admit among those the caps allow
+    fn pick(&self) -> Option<usize> {
+        self.waiting
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| self.has_room(&w.request))
+            .min_by(|(_, a), (_, b)| {
+                b.priority
+                    .cmp(&a.priority)
+                    .then(
+                        self.start_tag(&a.request.channel)
+                            .total_cmp(&self.start_tag(&b.request.channel)),
+                    )
+                    .then(a.id.cmp(&b.id))
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// Admit waiting turns while there is room
+    fn dispatch(&mut self) {
+        while let Some(index) = self.pick() {
+            let waiter = self.waiting.remove(index);
+            self.admit(&waiter.request);
+            if waiter.grant.send(()).is_err() {
+                // Waiter went away between its last check and now
+                self.release(&waiter.request);
+            }
+        }
+    }
+
+    /// 1-based place of waiter `id` if turns were admitted one at a time from now on
+    fn position(&self, id: u64) -> usize {
+        let mut finish = self.finish.clone();
+        let mut clock = self.clock;
+        let mut pending: Vec<&Waiter> = self.waiting.iter().collect();
+        let mut position = 0;
+        while !pending.is_empty() {
+            position += 1;
+            let tag = |w: &Waiter| {
+                finish
+                    .get(&w.request.channel)
+                    .copied()
+                    .unwrap_or(0.0)
+                    .max(clock)
+            };
+            let (index, next) = pending
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    b.priority
+                        .cmp(&a.priority)
+                        .then(tag(a).total_cmp(&tag(b)))
+                        .then(a.id.cmp(&b.id))
+                })
+                .map(|(i, w)| (i, *w))
+                .unwrap_or((0, pending[0]));
+            if next.id == id {
+                return position;
+            }
+            let start = tag(next);
+            finish.insert(
+                next.request.channel.clone(),
+                start + 1.0 / f64::from(next.request.weight.max(1)),
+            );
+            clock = start;
+            pending.remove(index);
+        }
+        position
+    }
+}
+
+pub struct TurnScheduler {
+    state: Mutex<State>,
+}
+
+/// Result of asking for a slot
+pub enum Admission {
+    Ready(SchedulerPermit),
+    /// Wait with `QueuedTurn::wait`; `position()` is the place in line
+    Queued(QueuedTurn),
+}
+
+impl TurnScheduler {
+    pub fn new(limits: SchedulerLimits) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                limits,
+                ..State::default()
+            }),
+        })
+    }
+
+    /// Apply new limits; raised caps admit waiting turns right away
+    pub fn set_limits(&self, limits: SchedulerLimits) {
+        let mut state = self.lock();
+        state.limits = limits;
+        state.dispatch();
+    }
+
+    /// Take a slot for `request`, or a place in line when none is free
+    pub fn request(self: &Arc<Self>, request: TurnRequest) -> Admission {
+        let mut state = self.lock();
+        let priority = state.is_priority(&request);
+        let id = state.next_id;
+        state.next_id += 1;
+        let (grant, mut rx) = oneshot::channel();
+        state.waiting.push(Waiter {
+            id,
+            request: request.clone(),
+            priority,
+            grant,
+        });
+        // Every waiter the caps allowed is already running, so this admits at most
+        // the new turn
+        state.dispatch();
+        if rx.try_recv().is_ok() {
+            return Admission::Ready(SchedulerPermit {
+                scheduler: Arc::clone(self),
+                request,
+            });
+        }
+        Admission::Queued(QueuedTurn {
+            scheduler: Arc::clone(self),
+            id,
+            request,
+            rx: Some(rx),
+        })
+    }
+
+    /// Turns running / waiting right now
+    pub fn load(&self) -> (usize, usize) {
+        let state = self.lock();
+        (state.running, state.waiting.len())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A turn waiting for a slot. Dropping it leaves the line.
+pub struct QueuedTurn {
+    scheduler: Arc<TurnScheduler>,
+    id: u64,
+    request: TurnRequest,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl QueuedTurn {
+    /// Estimated place in line (1 = next); 0 once admitted
+    pub fn position(&self) -> usize {
+        let state = self.scheduler.lock();
+        if state.waiting.iter().any(|w| w.id == self.id) {
+            state.position(self.id)
+        } else {
+            0
+        }
+    }
+
+    pub async fn wait(mut self) -> SchedulerPermit {
+        if let Some(rx) = self.rx.as_mut() {
+            // The sender lives in the waiter list, which only drops it after sending
+            let _ = rx.await;
+        }
+        self.rx = None;
+        SchedulerPermit {
+            scheduler: Arc::clone(&self.scheduler),
+            request: self.request.clone(),
+        }
+    }
+}
+
+impl Drop for QueuedTurn {
+    fn drop(&mut self) {
+        let Some(mut rx) = self.rx.take() else {
+            return;
+        };
+        let mut state = self.scheduler.lock();
+        let before = state.waiting.len();
+        state.waiting.retain(|w| w.id != self.id);
+        if state.waiting.len() == before && rx.try_recv().is_ok() {
+            // Admitted, but nobody will run the turn
+            state.release(&self.request);
+            state.dispatch();
+        }
+    }
+}
+
+/// A running turn's slot, released on drop
+pub struct SchedulerPermit {
+    scheduler: Arc<TurnScheduler>,
+    request: TurnRequest,
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.lock();
+        state.release(&self.request);
+        state.dispatch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(channel: &str, user: &str, weight: u32, private: bool) -> TurnRequest {
+        TurnRequest {
+            channel: channel.to_string(),
+            user: user.to_string(),
+            weight,
+            private,
+            admin: false,
+        }
+    }
+
+    fn limits(max_concurrent: usize) -> SchedulerLimits {
+        SchedulerLimits {
+            max_concurrent,
+            per_user: 10,
+            ..SchedulerLimits::default()
+        }
+    }
+
+    fn ready(admission: Admission) -> SchedulerPermit {
+        match admission {
+            Admission::Ready(permit) => permit,
+            Admission::Queued(_) => panic!("expected a free slot"),
+        }
+    }
+
+    fn queued(admission: Admission) -> QueuedTurn {
+        match admission {
+            Admission::Queued(turn) => turn,
+            Admission::Ready(_) => panic!("expected to wait"),
+        }
+    }
+
+    #[test]
+    fn test_busy_channel_does_not_starve_others() {
+        let s = TurnScheduler::new(limits(1));
+        let running = ready(s.request(turn("busy", "u1", 1, false)));
+        let busy: Vec<QueuedTurn> = (2..5)
+            .map(|i| queued(s.request(turn("busy", &format!("u{}", i), 1, false))))
+            .collect();
+        let quiet = queued(s.request(turn("quiet", "v1", 1, false)));
+        // The quiet channel only waits behind the first busy turn, not all of them
+        assert_eq!(busy[0].position(), 2);
+        assert_eq!(quiet.position(), 1);
+
+        drop(running);
+        assert_eq!(quiet.position(), 0);
+        assert_eq!(busy[0].position(), 1);
+    }
+
+    #[test]
+    fn test_caps_and_priority() {
+        let s = TurnScheduler::new(SchedulerLimits {
+            max_concurrent: 4,
+            per_channel: Some(2),
+            per_user: 1,
+            ..SchedulerLimits::default()
+        });
+        let _a = ready(s.request(turn("c", "u1", 1, false)));
+        // Per-user cap
+        let second = queued(s.request(turn("c", "u1", 1, false)));
+        let _b = ready(s.request(turn("c", "u2", 1, false)));
+        // Per-channel cap
+        let group = queued(s.request(turn("c", "u3", 1, false)));
+        let private = queued(s.request(turn("c", "u4", 1, true)));
+        assert_eq!(private.position(), 1);
+        assert_eq!(s.load(), (2, 3));
+
+        // Leaving the line frees the place
+        drop(second);
+        assert_eq!(s.load(), (2, 2));
+        assert_eq!(group.position(), 2);
+
+        s.set_limits(SchedulerLimits {
+            max_concurrent: 4,
+            per_channel: Some(4),
+            per_user: 1,
+            ..SchedulerLimits::default()
+        });
+        assert_eq!(s.load(), (4, 0));
+    }
+
+    #[test]
+    fn test_weights_share_slots() {
+        let s = TurnScheduler::new(limits(1));
+        let running = ready(s.request(turn("x", "u0", 1, false)));
+        let heavy: Vec<QueuedTurn> = (0..4)
+            .map(|i| queued(s.request(turn("heavy", &format!("h{}", i), 3, false))))
+            .collect();
+        let light = queued(s.request(turn("light", "l0", 1, false)));
+        // Three heavy turns per light one: light comes after the first heavy turns
+        assert!(light.position() > 1 && light.position() < 5);
+        assert_eq!(heavy[0].position(), 1);
+        drop(running);
+    }
+}
@@ -0,0 +1,180 @@
+// Listen loop supervisor — keeps a channel's inbound side alive.
+// If the adapter's listen_loop returns (Ok or Err) or panics while the channel is
+// still running, the supervisor records the exit in HealthManager, waits with
+// exponential backoff and starts it again. After too many consecutive restarts the
+// channel is put into the Error state instead of flapping forever.
+// A run that stays up for `stable_run` resets the consecutive counter; a restarted
+// channel stays Connecting (with the last error) until its loop has lasted that long.
+
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures_util::FutureExt;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+
+use super::adapter::ImStreamAdapter;
+use super::health::HealthManager;
+use super::types::{ImStatus, RestartRecord};
+use crate::metrics;
+use crate::{ulog_error, ulog_info, ulog_warn};
+
+/// Consecutive restarts before giving up (overridable per channel)
+pub const DEFAULT_MAX_LISTEN_RESTARTS: u32 = 10;
+
+// ── Policy ──
+
+#[derive(Debug, Clone)]
+pub struct SupervisorPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_restarts: u32,
+    /// A run lasting at least this long resets the consecutive restart counter
+    pub stable_run: Duration,
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            max_restarts: DEFAULT_MAX_LISTEN_RESTARTS,
+            stable_run: Duration::from_secs(300),
+        }
+    }
+}
+
+impl SupervisorPolicy {
+    pub fn with_max_restarts(max_restarts: Option<u32>) -> Self {
+        Self {
+            max_restarts: max_restarts.unwrap_or(DEFAULT_MAX_LISTEN_RESTARTS),
+            ..Self::default()
+        }
+    }
+
+    /// Delay before the `attempt`-th consecutive restart (0-based)
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.min(31)).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |d| d.min(self.max_backoff))
+    }
+}
+
+// ── Supervisor task ──
+
+/// Run the adapter's listen loop under supervision until shutdown.
+/// The listen future runs inside the returned task, so aborting it stops listening.
+pub fn spawn_listen_supervisor(
+    adapter: Arc<dyn ImStreamAdapter>,
+    health: Arc<HealthManager>,
+    mut shutdown_rx: watch::Receiver<bool>,
+    policy: SupervisorPolicy,
+    metric_labels: metrics::Labels,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut consecutive: u32 = 0;
+        let mut restarted = false;
+        loop {
+            let started = Instant::now();
+            let listen = AssertUnwindSafe(adapter.listen_loop(shutdown_rx.clone())).catch_unwind();
+            tokio::pin!(listen);
+            let outcome = if restarted {
+                tokio::select! {
+                    outcome = &mut listen => outcome,
+                    _ = tokio::time::sleep(policy.stable_run) => {
+                        ulog_info!("[im] Listen loop stable again after restart");
+                        health.set_status(ImStatus::Online).await;
+                        health.clear_error().await;
+                        let _ = health.persist().await;
+                        listen.await
+                    }
+                }
+            } else {
+                listen.await
+            };
+            if *shutdown_rx.borrow() {
+                break;
+            }
+
+            let reason = match outcome {
+                Ok(Ok(())) => "Listen loop exited unexpectedly".to_string(),
+                Ok(Err(e)) => format!("Listen loop failed: {}", e),
+                Err(panic) => format!("Listen loop panicked: {}", panic_message(&*panic)),
+            };
+            if started.elapsed() >= policy.stable_run {
+                consecutive = 0;
+            }
+
+            if consecutive >= policy.max_restarts {
+                let err_msg = format!(
+                    "{} — giving up after {} consecutive restarts",
+                    reason, consecutive
+                );
+                ulog_error!("[im] {}", err_msg);
+                health.set_status(ImStatus::Error).await;
+                health.set_error(err_msg).await;
+                let _ = health.persist().await;
+                break;
+            }
+
+            let delay = policy.backoff(consecutive);
+            consecutive += 1;
+            ulog_warn!(
+                "[im] {}; restarting in {}s (attempt {}/{})",
+                reason,
+                delay.as_secs(),
+                consecutive,
+                policy.max_restarts
+            );
+            metrics::inc(metrics::IM_LISTEN_RESTARTS, &metric_labels);
+            health.set_status(ImStatus::Connecting).await;
+            health.set_error(reason.clone()).await;
+            health
+                .record_restart(RestartRecord {
+                    at: chrono::Utc::now().to_rfc3339(),
+                    reason,
+                    delay_secs: delay.as_secs(),
+                })
+                .await;
+            let _ = health.persist().await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown_rx.changed() => {}
+            }
+            if *shutdown_rx.borrow() {
+                break;
+            }
+
+            ulog_info!("[im] Restarting listen loop (attempt {})", consecutive);
+            restarted = true;
+        }
+    })
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let policy = SupervisorPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(8));
+        assert_eq!(policy.backoff(9), Duration::from_secs(300));
+        assert_eq!(policy.backoff(40), Duration::from_secs(300));
+    }
+}
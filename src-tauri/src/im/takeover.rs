@@ -0,0 +1,330 @@
+// Human takeover — an operator answers a chat in place of the agent.
+// While a session key is taken over, its inbound messages skip the AI and are streamed
+// to the desktop (`im:takeover-message` events); the operator replies as the bot through
+// the adapter. Handing back queues the human exchange as context for the agent's next
+// turn, so it knows what was said meanwhile.
+// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/takeover.json
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::types::ImMessage;
+use crate::ulog_info;
+
+/// Tauri event carrying each message of a taken-over chat
+pub const TAKEOVER_MESSAGE_EVENT: &str = "im:takeover-message";
+/// Tauri event sent when a takeover starts or is handed back
+pub const TAKEOVER_CHANGED_EVENT: &str = "im:takeover-changed";
+
+/// Messages kept per takeover (oldest dropped first)
+const MAX_EXCHANGE_MESSAGES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TakeoverRole {
+    /// The IM user
+    User,
+    /// The human operator replying as the bot
+    Operator,
+}
+
+/// One message exchanged while a chat is taken over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoverMessage {
+    pub role: TakeoverRole,
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    pub text: String,
+    /// RFC 3339
+    pub at: String,
+}
+
+/// A taken-over session (cmd_im_takeover_list)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoverSession {
+    pub session_key: String,
+    pub chat_id: String,
+    /// RFC 3339
+    pub started_at: String,
+    pub messages: Vec<TakeoverMessage>,
+}
+
+/// Payload of TAKEOVER_MESSAGE_EVENT
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TakeoverEvent<'a> {
+    pub agent_id: &'a str,
+    pub channel_id: &'a str,
+    pub session_key: &'a str,
+    pub chat_id: &'a str,
+    #[serde(flatten)]
+    pub message: &'a TakeoverMessage,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeoverData {
+    #[serde(default)]
+    active: HashMap<String, TakeoverSession>,
+    /// Handed-back exchanges waiting to be prepended to the session's next turn
+    #[serde(default)]
+    pending_context: HashMap<String, String>,
+}
+
+pub struct TakeoverRegistry {
+    data: Mutex<TakeoverData>,
+    persist_path: Option<PathBuf>,
+}
+
+impl TakeoverRegistry {
+    /// Load takeovers from `persist_path` (None = in-memory only)
+    pub fn load(persist_path: Option<PathBuf>) -> Self {
+        let data = persist_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<TakeoverData>(&s).ok())
+            .unwrap_or_default();
+        Self {
+            data: Mutex::new(data),
+            persist_path,
+        }
+    }
+
+    /// Pause the agent for `session_key`. Returns false if it was already taken over.
+    pub fn start(&self, session_key: &str, chat_id: &str) -> bool {
+        let mut data = self.lock();
+        if data.active.contains_key(session_key) {
+            return false;
+        }
+        data.active.insert(
+            session_key.to_string(),
+            TakeoverSession {
+                session_key: session_key.to_string(),
+                chat_id: chat_id.to_string(),
+                started_at: chrono::Utc::now().to_rfc3339(),
+                messages: Vec::new(),
+            },
+        );
+        ulog_info!("[im-takeover] Operator took over {}", session_key);
+        self.persist(&data);
+        true
+    }
+
+    pub fn is_active(&self, session_key: &str) -> bool {
+        self.lock().active.contains_key(session_key)
+    }
+
+    /// Chat a taken-over session replies to
+    pub fn chat_id(&self, session_key: &str) -> Option<String> {
+        self.lock().active.get(session_key).map(|s| s.chat_id.clone())
+    }
+
+    pub fn list(&self) -> Vec<TakeoverSession> {
+        let mut sessions: Vec<TakeoverSession> = self.lock().active.values().cloned().collect();
+        sessions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        sessions
+    }
+
+    /// Record a user message of a taken-over session. None if it isn't taken over.
+    pub fn record_inbound(&self, session_key: &str, msg: &ImMessage) -> Option<TakeoverMessage> {
+        self.record(
+            session_key,
+            TakeoverMessage {
+                role: TakeoverRole::User,
+                sender_name: msg.sender_name.clone(),
+                text: msg.text.clone(),
+                at: msg.timestamp.to_rfc3339(),
+            },
+        )
+        .map(|(_, message)| message)
+    }
+
+    /// Record an operator reply. Returns the chat to send it to, or Err if the session
+    /// isn't taken over.
+    pub fn record_operator(
+        &self,
+        session_key: &str,
+        text: &str,
+    ) -> Result<(String, TakeoverMessage), String> {
+        self.record(
+            session_key,
+            TakeoverMessage {
+                role: TakeoverRole::Operator,
+                sender_name: None,
+                text: text.to_string(),
+                at: chrono::Utc::now().to_rfc3339(),
+            },
+        )
+        .ok_or_else(|| format!("Session {} is not taken over", session_key))
+    }
+
+    /// Resume the agent. The exchange (if any) becomes context for its next turn.
+    /// Returns the number of messages handed over.
+    pub fn hand_back(&self, session_key: &str) -> Result<usize, String> {
+        let mut data = self.lock();
+        let session = data
+            .active
+            .remove(session_key)
+            .ok_or_else(|| format!("Session {} is not taken over", session_key))?;
+        let count = session.messages.len();
+        if let Some(context) = format_context(&session.messages) {
+            let entry = data
+                .pending_context
+                .entry(session_key.to_string())
+                .or_default();
+            if !entry.is_empty() {
+                entry.push_str("\n\n");
+            }
+            entry.push_str(&context);
+        }
+        ulog_info!(
+            "[im-takeover] {} handed back to the agent ({} message(s))",
+            session_key,
+            count
+        );
+        self.persist(&data);
+        Ok(count)
+    }
+
+    /// Context waiting for the session's next turn
+    pub fn pending_context(&self, session_key: &str) -> Option<String> {
+        self.lock().pending_context.get(session_key).cloned()
+    }
+
+    /// Drop the pending context once a turn carrying it reached the agent
+    pub fn clear_context(&self, session_key: &str) {
+        let mut data = self.lock();
+        if data.pending_context.remove(session_key).is_some() {
+            self.persist(&data);
+        }
+    }
+
+    fn record(
+        &self,
+        session_key: &str,
+        message: TakeoverMessage,
+    ) -> Option<(String, TakeoverMessage)> {
+        let mut data = self.lock();
+        let session = data.active.get_mut(session_key)?;
+        session.messages.push(message.clone());
+        let excess = session.messages.len().saturating_sub(MAX_EXCHANGE_MESSAGES);
+        session.messages.drain(..excess);
+        let chat_id = session.chat_id.clone();
+        self.persist(&data);
+        Some((chat_id, message))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TakeoverData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn persist(&self, data: &TakeoverData) {
+        if let Some(path) = &self.persist_path {
+            save_data(path, data);
+        }
+    }
+}
+
+fn save_data(path: &Path, data: &TakeoverData) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    if let Ok(s) = serde_json::to_string_pretty(data) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+/// Exchange as a context note for the agent (None when nothing was said)
+fn format_context(messages: &[TakeoverMessage]) -> Option<String> {
+    if messages.is_empty() {
+        return None;
+    }
+    let lines = messages
+        .iter()
+        .map(|m| match m.role {
+            TakeoverRole::User => format!(
+                "User{}: {}",
+                m.sender_name
+                    .as_deref()
+                    .map(|n| format!(" ({})", n))
+                    .unwrap_or_default(),
+                m.text
+            ),
+            TakeoverRole::Operator => format!("Operator (as you): {}", m.text),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(format!(
+        "[While you were paused, a human operator answered this chat on your behalf:]\n{}\n\
+         [End of the operator conversation. Continue from here.]",
+        lines
+    ))
+}
+
+/// Prepend pending takeover context to a user message
+pub fn with_context(context: &str, text: &str) -> String {
+    format!("{}\n\n{}", context, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::im::types::{ImMessageEvent, ImPlatform, ImSourceType};
+
+    fn message(text: &str) -> ImMessage {
+        ImMessage {
+            chat_id: "42".to_string(),
+            message_id: "1".to_string(),
+            text: text.to_string(),
+            sender_id: "u1".to_string(),
+            sender_name: Some("Ann".to_string()),
+            source_type: ImSourceType::Private,
+            platform: ImPlatform::Telegram,
+            timestamp: chrono::Utc::now(),
+            is_mention: false,
+            reply_to_bot: false,
+            event: ImMessageEvent::New,
+        }
+    }
+
+    #[test]
+    fn test_exchange_becomes_context_on_hand_back() {
+        let reg = TakeoverRegistry::load(None);
+        let key = "im:a:telegram:private:42";
+        assert!(reg.record_inbound(key, &message("ignored")).is_none());
+        assert!(reg.record_operator(key, "hi").is_err());
+
+        assert!(reg.start(key, "42"));
+        assert!(!reg.start(key, "42"));
+        assert!(reg.is_active(key));
+        reg.record_inbound(key, &message("the export is broken"));
+        let (chat_id, _) = reg.record_operator(key, "fixed, try again").unwrap();
+        assert_eq!(chat_id, "42");
+
+        assert_eq!(reg.hand_back(key), Ok(2));
+        assert!(!reg.is_active(key));
+        let context = reg.pending_context(key).unwrap();
+        assert!(context.contains("User (Ann): the export is broken"));
+        assert!(context.contains("Operator (as you): fixed, try again"));
+
+        reg.clear_context(key);
+        assert_eq!(reg.pending_context(key), None);
+        assert!(reg.hand_back(key).is_err());
+    }
+
+    #[test]
+    fn test_silent_takeover_leaves_no_context() {
+        let reg = TakeoverRegistry::load(None);
+        reg.start("k", "42");
+        assert_eq!(reg.hand_back("k"), Ok(0));
+        assert_eq!(reg.pending_context("k"), None);
+    }
+}
@@ -1,8 +1,12 @@
 // Telegram Bot API adapter
-// Handles long-polling, message sending (split + markdown fallback), ACK reactions,
-// MessageCoalescer (fragment merging + debounce), and rate limit handling.
+// Handles long-polling (with persisted update offset + dedup), message sending
+// (split + markdown fallback), ACK reactions, MessageCoalescer (fragment merging +
+// debounce), and rate limit handling.
+// Edits arrive as `edited_message` updates; the Bot API does not tell bots about
+// deleted messages, so recalls are not supported on Telegram.
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -12,25 +16,36 @@ use serde_json::{json, Value};
 use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::time::{sleep, Instant};
 
-use super::adapter::{split_message, ImAdapter, ImStreamAdapter};
-use super::types::{AdapterResult, ImConfig, ImMessage, ImPlatform, ImSourceType, TelegramError};
+use super::adapter::{split_message, ImAdapter, ImStreamAdapter, LengthUnit};
+use super::dedup::DedupCache;
+use super::rate_limit::{shared_limiter, RateLimiter};
+use super::types::{
+    AdapterResult, ImConfig, ImMessage, ImMessageEvent, ImPlatform, ImSourceType, SendError,
+    TelegramError,
+};
+use super::util::{ext_to_mime, MultipartForm};
+use crate::metrics;
 use crate::{ulog_info, ulog_warn, ulog_error};
 
 // ===== Constants =====
 
 /// Telegram Bot API base URL
 const TELEGRAM_API_BASE: &str = "https://api.telegram.org/bot";
-/// Maximum message length for Telegram
+/// Maximum message length for Telegram (UTF-16 code units)
 const MAX_MESSAGE_LENGTH: usize = 4096;
 
 /// Telegram long-poll timeout (seconds)
 const LONG_POLL_TIMEOUT: u64 = 30;
 /// Max retries for transient errors before giving up
 const MAX_TRANSIENT_RETRIES: u32 = 3;
+/// 429 retry_after up to this is waited out inline; longer waits return `RateLimited`
+const MAX_INLINE_RETRY_AFTER_SECS: u64 = 5;
 /// Initial backoff for reconnect (seconds)
 const INITIAL_BACKOFF_SECS: u64 = 1;
 /// Max backoff for reconnect (seconds)
 const MAX_BACKOFF_SECS: u64 = 30;
+/// Chat actions expire after ~5s; refresh a little earlier
+const TYPING_REFRESH_SECS: u64 = 4;
 
 // MessageCoalescer constants
 const DEFAULT_DEBOUNCE_MS: u64 = 500;
@@ -205,10 +220,46 @@ impl MessageCoalescer {
             timestamp: chrono::Utc::now(),
             is_mention: batch.is_mention,
             reply_to_bot: batch.reply_to_bot,
+            event: ImMessageEvent::New,
         })
     }
 }
 
+// ===== Update offset persistence =====
+
+/// Numeric bot ID (token prefix before ':'). Stored with the offset because update
+/// IDs are per bot — an offset saved for another token must not be reused.
+fn bot_id_from_token(token: &str) -> &str {
+    token.split(':').next().unwrap_or("")
+}
+
+/// Last confirmed offset for this bot, or 0 when none is stored
+fn load_update_offset(path: Option<&Path>, bot_id: &str) -> i64 {
+    let Some(path) = path else {
+        return 0;
+    };
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return 0;
+    };
+    match serde_json::from_str::<Value>(&content) {
+        Ok(v) if v["botId"].as_str() == Some(bot_id) => v["offset"].as_i64().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn save_update_offset(path: &Path, bot_id: &str, offset: i64) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    let data = json!({ "botId": bot_id, "offset": offset });
+    if std::fs::write(&tmp, data.to_string()).is_ok() {
+        if let Err(e) = std::fs::rename(&tmp, path) {
+            ulog_warn!("[telegram] Failed to persist update offset: {}", e);
+        }
+    }
+}
+
 // ===== TelegramAdapter =====
 
 /// Telegram Bot API adapter
@@ -227,6 +278,12 @@ pub struct TelegramAdapter {
     /// Whether this adapter instance has fallen back to standard mode due to draft errors.
     /// AtomicBool avoids try_lock fragility and contention issues across concurrent streams.
     draft_fallback: Arc<std::sync::atomic::AtomicBool>,
+    /// Outbound budget shared with every channel using the same bot token
+    limiter: Arc<RateLimiter>,
+    /// Seen update IDs — guards against redelivery after a crash or restart
+    dedup: DedupCache,
+    /// Where the last confirmed update offset is stored (None = start from 0)
+    offset_path: Option<PathBuf>,
 }
 
 impl TelegramAdapter {
@@ -234,6 +291,8 @@ impl TelegramAdapter {
         config: &ImConfig,
         message_tx: mpsc::Sender<ImMessage>,
         allowed_users: Arc<RwLock<Vec<String>>>,
+        dedup_path: Option<PathBuf>,
+        offset_path: Option<PathBuf>,
     ) -> Self {
         let client = build_telegram_client(config.proxy_url.as_deref())
             .unwrap_or_else(|e| {
@@ -254,6 +313,9 @@ impl TelegramAdapter {
             bot_user_id: Arc::new(Mutex::new(None)),
             use_message_draft: config.telegram_use_draft.unwrap_or(true),
             draft_fallback: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            limiter: shared_limiter(&ImPlatform::Telegram, &config.bot_token),
+            dedup: DedupCache::load(dedup_path),
+            offset_path,
         }
     }
 
@@ -268,13 +330,62 @@ impl TelegramAdapter {
         format!("{}{}/{}", TELEGRAM_API_BASE, self.bot_token, method)
     }
 
-    /// Generic API call with rate limit and error handling.
+    /// Chat whose send budget a call consumes (None = not a message send/edit/delete).
+    /// Draft updates don't post messages, so they only count against the bot-wide budget.
+    fn rate_limited_chat(method: &str, body: &Value) -> Option<String> {
+        let counts = (method.starts_with("send")
+            && method != "sendChatAction"
+            && method != "sendMessageDraft")
+            || method.starts_with("edit")
+            || method == "deleteMessage";
+        if !counts {
+            return None;
+        }
+        Self::body_chat(body)
+    }
+
+    fn body_chat(body: &Value) -> Option<String> {
+        match &body["chat_id"] {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Generic API call with rate limit and error handling (failures are counted in metrics).
+    async fn api_call(&self, method: &str, body: &Value) -> Result<Value, TelegramError> {
+        let result = self.api_call_inner(method, body).await;
+        if let Err(e) = &result {
+            if !matches!(e, TelegramError::MessageNotModified) {
+                metrics::inc(
+                    metrics::IM_PLATFORM_ERRORS,
+                    &[("platform", "telegram".to_string()), ("kind", e.kind().to_string())],
+                );
+            }
+        }
+        result
+    }
+
     /// Retries up to MAX_TRANSIENT_RETRIES on transient errors.
     /// Respects retry_after on 429 rate limits.
-    async fn api_call(&self, method: &str, body: &Value) -> Result<Value, TelegramError> {
+    async fn api_call_inner(&self, method: &str, body: &Value) -> Result<Value, TelegramError> {
         let mut retries = 0;
+        let limited_chat = Self::rate_limited_chat(method, body);
+        let is_draft = method == "sendMessageDraft";
+        // Chat to pause when Telegram answers 429 (drafts included)
+        let paused_chat = if is_draft {
+            Self::body_chat(body)
+        } else {
+            limited_chat.clone()
+        };
 
         loop {
+            if let Some(chat_id) = &limited_chat {
+                self.limiter.acquire(chat_id).await;
+            } else if is_draft {
+                self.limiter.acquire_global().await;
+            }
+
             let resp = self
                 .client
                 .post(&self.api_url(method))
@@ -303,6 +414,13 @@ impl TelegramAdapter {
                     method,
                     retry_after
                 );
+                self.limiter
+                    .note_retry_after(paused_chat.as_deref(), retry_after);
+                // Long waits are handed back to the caller (outbound queue backs off)
+                // instead of blocking this request.
+                if retry_after > MAX_INLINE_RETRY_AFTER_SECS {
+                    return Err(TelegramError::RateLimited(retry_after));
+                }
                 sleep(Duration::from_secs(retry_after)).await;
                 continue;
             }
@@ -328,6 +446,9 @@ impl TelegramAdapter {
                 400 if description.contains("MESSAGE_TOO_LONG") => {
                     return Err(TelegramError::MessageTooLong);
                 }
+                400 if description.contains("chat not found") => {
+                    return Err(TelegramError::ChatNotFound);
+                }
                 400 if description.contains("TEXTDRAFT_PEER_INVALID") => {
                     return Err(TelegramError::DraftPeerInvalid);
                 }
@@ -386,20 +507,77 @@ impl TelegramAdapter {
     pub async fn set_my_commands(&self) -> Result<(), TelegramError> {
         let commands = json!({
             "commands": [
-                { "command": "new", "description": "Start a new conversation" }
+                { "command": "new", "description": "Start a new conversation" },
+                { "command": "export", "description": "Export this conversation as a file" }
             ]
         });
         self.api_call("setMyCommands", &commands).await?;
         Ok(())
     }
 
+    /// Upload a file with sendDocument (multipart; not retried — callers fall back to text)
+    pub async fn send_document(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+        caption: Option<&str>,
+    ) -> Result<(), TelegramError> {
+        self.limiter.acquire(chat_id).await;
+
+        let mut form = MultipartForm::new().text("chat_id", chat_id);
+        if let Some(caption) = caption {
+            form = form.text("caption", caption);
+        }
+        let (content_type, body) = form
+            .file("document", filename, ext_to_mime(filename), &data)
+            .finish();
+
+        let resp = self
+            .client
+            .post(self.api_url("sendDocument"))
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    TelegramError::NetworkTimeout
+                } else {
+                    TelegramError::Other(format!("HTTP error: {}", e))
+                }
+            })?;
+        let status = resp.status();
+        let json: Value = resp
+            .json()
+            .await
+            .map_err(|e| TelegramError::Other(format!("JSON parse error: {}", e)))?;
+        if json["ok"].as_bool() == Some(true) {
+            return Ok(());
+        }
+
+        let description = json["description"].as_str().unwrap_or("unknown error");
+        let err = if status.as_u16() == 429 {
+            let retry_after = json["parameters"]["retry_after"].as_u64().unwrap_or(5);
+            self.limiter.note_retry_after(Some(chat_id), retry_after);
+            TelegramError::RateLimited(retry_after)
+        } else {
+            TelegramError::Other(format!("sendDocument failed: {}", description))
+        };
+        metrics::inc(
+            metrics::IM_PLATFORM_ERRORS,
+            &[("platform", "telegram".to_string()), ("kind", err.kind().to_string())],
+        );
+        Err(err)
+    }
+
     /// Get updates via long-polling
     async fn get_updates(&self, offset: i64) -> Result<Vec<Value>, TelegramError> {
         let body = json!({
             "offset": offset,
             "limit": 100,
             "timeout": LONG_POLL_TIMEOUT,
-            "allowed_updates": ["message"]
+            "allowed_updates": ["message", "edited_message"]
         });
         let result = self.api_call("getUpdates", &body).await?;
         Ok(result.as_array().cloned().unwrap_or_default())
@@ -411,20 +589,28 @@ impl TelegramAdapter {
         chat_id: &str,
         text: &str,
     ) -> Result<Option<i64>, TelegramError> {
-        let chunks = split_message(text, MAX_MESSAGE_LENGTH);
-        let total = chunks.len();
+        let chunks = split_message(text, MAX_MESSAGE_LENGTH, LengthUnit::Utf16);
         let mut last_message_id = None;
 
         for (i, chunk) in chunks.iter().enumerate() {
-            let decorated = if total == 1 {
-                chunk.clone()
-            } else if i < total - 1 {
-                format!("{}\n\n_(continues...)_", chunk)
-            } else {
-                format!("_(continued)_\n\n{}", chunk)
-            };
-
-            last_message_id = Some(self.send_single_message(chat_id, &decorated).await?);
+            let mut waits = 0;
+            loop {
+                match self.send_single_message(chat_id, chunk).await {
+                    Ok(id) => {
+                        last_message_id = Some(id);
+                        break;
+                    }
+                    // Earlier parts are already posted: wait out long limits here rather
+                    // than fail with a partial send the caller would repeat in full
+                    Err(TelegramError::RateLimited(secs))
+                        if i > 0 && waits < MAX_TRANSIENT_RETRIES =>
+                    {
+                        waits += 1;
+                        sleep(Duration::from_secs(secs)).await;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
         }
 
         Ok(last_message_id)
@@ -525,60 +711,6 @@ impl TelegramAdapter {
         Ok(())
     }
 
-    /// Use sendMessageDraft to send/update a typing draft.
-    /// On DraftPeerInvalid, sets draft_fallback = true for this adapter instance.
-    async fn send_draft_update(
-        &self,
-        chat_id: &str,
-        text: &str,
-        draft_id: i64,
-    ) -> Result<(), TelegramError> {
-        use std::sync::atomic::Ordering;
-        if self.draft_fallback.load(Ordering::Relaxed) {
-            return Err(TelegramError::DraftPeerInvalid);
-        }
-        match self
-            .api_call(
-                "sendMessageDraft",
-                &json!({
-                    "chat_id": chat_id,
-                    "text": text,
-                    "draft_id": draft_id,
-                    "parse_mode": "Markdown"
-                }),
-            )
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(TelegramError::DraftPeerInvalid) => {
-                self.draft_fallback.store(true, Ordering::Relaxed);
-                Err(TelegramError::DraftPeerInvalid)
-            }
-            Err(TelegramError::MarkdownParseError) => {
-                // Retry without Markdown parse_mode
-                match self
-                    .api_call(
-                        "sendMessageDraft",
-                        &json!({
-                            "chat_id": chat_id,
-                            "text": text,
-                            "draft_id": draft_id
-                        }),
-                    )
-                    .await
-                {
-                    Ok(_) => Ok(()),
-                    Err(TelegramError::DraftPeerInvalid) => {
-                        self.draft_fallback.store(true, Ordering::Relaxed);
-                        Err(TelegramError::DraftPeerInvalid)
-                    }
-                    Err(e) => Err(e),
-                }
-            }
-            Err(e) => Err(e),
-        }
-    }
-
     /// Set reaction emoji on a message (ACK)
     pub async fn set_reaction(
         &self,
@@ -642,10 +774,11 @@ impl TelegramAdapter {
         &self,
         mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
     ) {
-        let mut offset: i64 = 0;
+        let bot_id = bot_id_from_token(&self.bot_token);
+        let mut offset = load_update_offset(self.offset_path.as_deref(), bot_id);
         let mut backoff_secs = INITIAL_BACKOFF_SECS;
 
-        ulog_info!("[telegram] Starting long-poll loop");
+        ulog_info!("[telegram] Starting long-poll loop (offset {})", offset);
 
         loop {
             // Check shutdown signal
@@ -666,14 +799,29 @@ impl TelegramAdapter {
             match result {
                 Ok(updates) => {
                     backoff_secs = INITIAL_BACKOFF_SECS; // Reset backoff on success
+                    let batch_start_offset = offset;
 
                     for update in updates {
                         // Update offset to acknowledge this update
                         if let Some(update_id) = update["update_id"].as_i64() {
                             offset = update_id + 1;
+                            // Already handled before a restart (offset not yet persisted)
+                            if !self.dedup.check(&update_id.to_string()).await {
+                                continue;
+                            }
                         }
 
                         if let Some(msg) = self.process_update(&update).await {
+                            // Edits replace a queued / answered message — skip fragment
+                            // merging and the ACK reaction
+                            if msg.event == ImMessageEvent::Edited {
+                                if self.message_tx.send(msg).await.is_err() {
+                                    ulog_error!("[telegram] Message channel closed");
+                                    return;
+                                }
+                                continue;
+                            }
+
                             // Push through coalescer — returns messages ready to send
                             let ready_msgs = {
                                 let mut coalescer = self.coalescer.lock().await;
@@ -700,6 +848,12 @@ impl TelegramAdapter {
                         }
                     }
 
+                    if offset != batch_start_offset {
+                        if let Some(path) = &self.offset_path {
+                            save_update_offset(path, bot_id, offset);
+                        }
+                    }
+
                     // Flush any debounce-expired fragment batches
                     let expired_msgs = {
                         let mut coalescer = self.coalescer.lock().await;
@@ -721,15 +875,20 @@ impl TelegramAdapter {
                     break;
                 }
                 Err(e) => {
+                    // Honor the server's retry_after instead of our own backoff
+                    let wait_secs = match e {
+                        TelegramError::RateLimited(secs) => secs,
+                        _ => backoff_secs,
+                    };
                     ulog_warn!(
                         "[telegram] Long-poll error: {}, retrying in {}s",
                         e,
-                        backoff_secs
+                        wait_secs
                     );
 
                     // Check shutdown during backoff
                     tokio::select! {
-                        _ = sleep(Duration::from_secs(backoff_secs)) => {}
+                        _ = sleep(Duration::from_secs(wait_secs)) => {}
                         _ = shutdown_rx.changed() => {
                             if *shutdown_rx.borrow() {
                                 ulog_info!("[telegram] Shutdown during backoff");
@@ -744,13 +903,18 @@ impl TelegramAdapter {
             }
         }
 
+        self.dedup.flush().await;
         ulog_info!("[telegram] Listen loop exited");
     }
 
     /// Process a single Telegram update into an ImMessage.
     /// Phase 1: handles text messages only (no media, no group detection).
+    /// `edited_message` updates become `ImMessageEvent::Edited`.
     async fn process_update(&self, update: &Value) -> Option<ImMessage> {
-        let message = update.get("message")?;
+        let (message, event) = match update.get("message") {
+            Some(message) => (message, ImMessageEvent::New),
+            None => (update.get("edited_message")?, ImMessageEvent::Edited),
+        };
         let chat = &message["chat"];
         let from = &message["from"];
 
@@ -808,6 +972,7 @@ impl TelegramAdapter {
             timestamp: chrono::Utc::now(),
             is_mention,
             reply_to_bot,
+            event,
         })
     }
 
@@ -872,6 +1037,18 @@ impl ImAdapter for TelegramAdapter {
             .map_err(|e| e.to_string())
     }
 
+    async fn send_file(
+        &self,
+        chat_id: &str,
+        filename: &str,
+        data: Vec<u8>,
+        caption: Option<&str>,
+    ) -> AdapterResult<()> {
+        self.send_document(chat_id, filename, data, caption)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
     async fn ack_received(&self, chat_id: &str, message_id: &str) -> AdapterResult<()> {
         if let Ok(mid) = message_id.parse::<i64>() {
             self.ack_received_impl(chat_id, mid).await;
@@ -897,6 +1074,10 @@ impl ImAdapter for TelegramAdapter {
         self.send_typing_impl(chat_id).await;
         Ok(())
     }
+
+    fn typing_refresh_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(TYPING_REFRESH_SECS))
+    }
 }
 
 // ===== ImStreamAdapter trait implementation =====
@@ -908,39 +1089,10 @@ impl ImStreamAdapter for TelegramAdapter {
         chat_id: &str,
         text: &str,
     ) -> AdapterResult<Option<String>> {
-        use std::sync::atomic::Ordering;
-        if self.use_message_draft && !self.draft_fallback.load(Ordering::Relaxed) {
-            // Draft mode: use sendMessageDraft, return virtual "draft:{id}" ID.
-            // The draft_id is encoded into the virtual ID string so each stream
-            // is self-contained — no shared mutable state across concurrent chats.
-            let draft_id = {
-                use std::time::{SystemTime, UNIX_EPOCH};
-                let t = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default();
-                (t.as_millis() as i64).max(1)
-            };
-            match self.send_draft_update(chat_id, text, draft_id).await {
-                Ok(()) => Ok(Some(format!("draft:{}", draft_id))),
-                Err(TelegramError::DraftPeerInvalid) => {
-                    // Fallback to standard mode
-                    ulog_warn!(
-                        "[telegram] sendMessageDraft not supported, falling back to standard mode"
-                    );
-                    self.send_message_impl(chat_id, text)
-                        .await
-                        .map(|opt| opt.map(|id| id.to_string()))
-                        .map_err(|e| e.to_string())
-                }
-                Err(e) => Err(e.to_string()),
-            }
-        } else {
-            // Standard mode
-            self.send_message_impl(chat_id, text)
-                .await
-                .map(|opt_id| opt_id.map(|id| id.to_string()))
-                .map_err(|e| e.to_string())
-        }
+        self.send_message_impl(chat_id, text)
+            .await
+            .map(|opt_id| opt_id.map(|id| id.to_string()))
+            .map_err(|e| e.to_string())
     }
 
     async fn edit_message(
@@ -949,18 +1101,6 @@ impl ImStreamAdapter for TelegramAdapter {
         message_id: &str,
         text: &str,
     ) -> AdapterResult<()> {
-        // Parse draft_id directly from the virtual "draft:xxx" ID string.
-        // This keeps draft routing stream-local — no shared state across concurrent chats.
-        if let Some(id_str) = message_id.strip_prefix("draft:") {
-            let draft_id = id_str
-                .parse::<i64>()
-                .map_err(|e| format!("Invalid draft ID: {}", e))?;
-            return self
-                .send_draft_update(chat_id, text, draft_id)
-                .await
-                .map_err(|e| e.to_string());
-        }
-        // Standard mode
         let mid = message_id
             .parse::<i64>()
             .map_err(|e| format!("Invalid message_id: {}", e))?;
@@ -983,10 +1123,21 @@ impl ImStreamAdapter for TelegramAdapter {
             .map_err(|e| e.to_string())
     }
 
+    async fn send_part(&self, chat_id: &str, text: &str) -> Result<Option<String>, SendError> {
+        self.send_single_message(chat_id, text)
+            .await
+            .map(|id| Some(id.to_string()))
+            .map_err(SendError::from)
+    }
+
     fn max_message_length(&self) -> usize {
         MAX_MESSAGE_LENGTH
     }
 
+    fn length_unit(&self) -> LengthUnit {
+        LengthUnit::Utf16
+    }
+
     fn use_draft_streaming(&self) -> bool {
         self.use_message_draft
             && !self
@@ -994,13 +1145,71 @@ impl ImStreamAdapter for TelegramAdapter {
                 .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    fn preferred_throttle_ms(&self) -> u64 {
-        if self.use_draft_streaming() {
-            300
-        } else {
-            1000
+    /// Use sendMessageDraft to send/update a typing draft.
+    /// On DraftPeerInvalid, sets draft_fallback = true for this adapter instance.
+    async fn send_draft_update(
+        &self,
+        chat_id: &str,
+        text: &str,
+        draft_id: i64,
+    ) -> Result<(), TelegramError> {
+        use std::sync::atomic::Ordering;
+        if self.draft_fallback.load(Ordering::Relaxed) {
+            return Err(TelegramError::DraftPeerInvalid);
+        }
+        match self
+            .api_call(
+                "sendMessageDraft",
+                &json!({
+                    "chat_id": chat_id,
+                    "text": text,
+                    "draft_id": draft_id,
+                    "parse_mode": "Markdown"
+                }),
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(TelegramError::DraftPeerInvalid) => {
+                self.draft_fallback.store(true, Ordering::Relaxed);
+                Err(TelegramError::DraftPeerInvalid)
+            }
+            Err(TelegramError::MarkdownParseError) => {
+                // Retry without Markdown parse_mode
+                match self
+                    .api_call(
+                        "sendMessageDraft",
+                        &json!({
+                            "chat_id": chat_id,
+                            "text": text,
+                            "draft_id": draft_id
+                        }),
+                    )
+                    .await
+                {
+                    Ok(_) => Ok(()),
+                    Err(TelegramError::DraftPeerInvalid) => {
+                        self.draft_fallback.store(true, Ordering::Relaxed);
+                        Err(TelegramError::DraftPeerInvalid)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
         }
     }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        Some(&self.limiter)
+    }
+
+    fn preferred_throttle_ms(&self) -> u64 {
+        1000
+    }
+
+    fn draft_throttle_ms(&self) -> u64 {
+        300
+    }
 }
 
 // ===== Tests =====
@@ -1021,6 +1230,7 @@ mod tests {
             timestamp: chrono::Utc::now(),
             is_mention: false,
             reply_to_bot: false,
+            event: ImMessageEvent::New,
         }
     }
 
@@ -1093,4 +1303,18 @@ mod tests {
         // The important thing is it doesn't panic
         let _ = client;
     }
+
+    #[test]
+    fn test_update_offset_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("soagents-tg-offset-{}", uuid::Uuid::new_v4()))
+            .join("telegram_offset.json");
+        assert_eq!(load_update_offset(Some(&path), "123"), 0);
+        save_update_offset(&path, "123", 42);
+        assert_eq!(load_update_offset(Some(&path), "123"), 42);
+        // Offset of another bot is ignored
+        assert_eq!(load_update_offset(Some(&path), "456"), 0);
+        assert_eq!(bot_id_from_token("123:ABC-def"), "123");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
 }
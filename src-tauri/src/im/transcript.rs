@@ -0,0 +1,559 @@
+// IM transcript export — joins the Sidecar's session log
+// (~/.soagents/sessions/{sessionId}.jsonl) with the channel's inbound message log
+// (sender names, platform message IDs) and renders Markdown, HTML or JSONL.
+// Message log path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/messages.jsonl
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::agent_routes::base_session_key;
+use super::health;
+use super::types::{ImMessage, ImSourceType};
+use crate::ulog_warn;
+
+/// The message log is compacted once it grows past this size
+const MESSAGE_LOG_MAX_BYTES: u64 = 4 * 1024 * 1024;
+/// Entries kept when the message log is compacted
+const MESSAGE_LOG_KEEP_ENTRIES: usize = 5000;
+
+/// Output format of an exported transcript
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TranscriptFormat {
+    #[serde(rename = "md", alias = "markdown")]
+    Markdown,
+    #[serde(rename = "html")]
+    Html,
+    #[serde(rename = "jsonl")]
+    Jsonl,
+}
+
+impl TranscriptFormat {
+    /// Parse a `/export` argument (defaults to Markdown)
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.trim().to_ascii_lowercase().as_str() {
+            "" | "md" | "markdown" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            "jsonl" | "json" => Some(Self::Jsonl),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// One inbound IM message, as recorded when it was received
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageLogEntry {
+    pub session_key: String,
+    pub message_id: String,
+    pub sender_id: String,
+    pub sender_name: Option<String>,
+    pub source_type: ImSourceType,
+    pub received_at: String,
+    pub text: String,
+}
+
+/// Result of an export (returned to the frontend)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedTranscript {
+    pub path: String,
+    pub file_name: String,
+    pub session_id: String,
+    pub message_count: usize,
+}
+
+/// Message line from the session JSONL (only fields we need)
+#[derive(Debug, Deserialize)]
+struct SessionLine {
+    role: String,
+    #[serde(default)]
+    content: serde_json::Value,
+    #[serde(default)]
+    timestamp: Option<String>,
+}
+
+/// Session metadata from sessions.json (only fields we need)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionMeta {
+    id: String,
+    #[serde(default)]
+    title: Option<String>,
+}
+
+/// A transcript entry after joining session messages with the message log
+struct TranscriptEntry {
+    role: String,
+    sender_id: Option<String>,
+    sender_name: Option<String>,
+    message_id: Option<String>,
+    timestamp: Option<String>,
+    content: String,
+}
+
+/// Platform, chat and session details shown in the transcript header
+struct TranscriptHeader {
+    title: String,
+    platform: String,
+    chat_kind: String,
+    chat_id: String,
+    session_id: String,
+    exported_at: String,
+    participants: Vec<String>,
+}
+
+// ── Message log ──────────────────────────────────────────────────────────────
+
+/// Append an inbound message to the channel's message log.
+pub fn record_inbound(path: &Path, session_key: &str, msg: &ImMessage) {
+    let entry = MessageLogEntry {
+        session_key: session_key.to_string(),
+        message_id: msg.message_id.clone(),
+        sender_id: msg.sender_id.clone(),
+        sender_name: msg.sender_name.clone(),
+        source_type: msg.source_type.clone(),
+        received_at: msg.timestamp.to_rfc3339(),
+        text: msg.text.trim().to_string(),
+    };
+    let Ok(line) = serde_json::to_string(&entry) else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let appended = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| writeln!(f, "{}", line));
+    if let Err(e) = appended {
+        ulog_warn!("[im] Failed to append message log: {}", e);
+        return;
+    }
+    if std::fs::metadata(path).is_ok_and(|m| m.len() > MESSAGE_LOG_MAX_BYTES) {
+        compact_message_log(path);
+    }
+}
+
+/// Keep only the newest MESSAGE_LOG_KEEP_ENTRIES lines (atomic rewrite)
+fn compact_message_log(path: &Path) {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return;
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.len().saturating_sub(MESSAGE_LOG_KEEP_ENTRIES);
+    let mut kept = lines[start..].join("\n");
+    kept.push('\n');
+    let tmp = path.with_extension("jsonl.tmp");
+    if std::fs::write(&tmp, kept).is_ok() {
+        let _ = std::fs::rename(&tmp, path);
+    }
+}
+
+fn read_message_log(path: &Path, session_key: &str) -> Vec<MessageLogEntry> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<MessageLogEntry>(line).ok())
+        .filter(|e| e.session_key == session_key)
+        .collect()
+}
+
+// ── Session store ────────────────────────────────────────────────────────────
+
+fn soagents_dir() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".soagents")
+}
+
+fn read_session_lines(session_id: &str) -> Result<Vec<SessionLine>, String> {
+    if session_id.is_empty()
+        || session_id.contains('/')
+        || session_id.contains('\\')
+        || session_id.contains("..")
+    {
+        return Err(format!("Invalid session id: {}", session_id));
+    }
+    let path = soagents_dir()
+        .join("sessions")
+        .join(format!("{}.jsonl", session_id));
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read session {}: {}", session_id, e))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<SessionLine>(line).ok())
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .collect())
+}
+
+/// Text of the last assistant message in a session (scheduled task results)
+pub fn last_assistant_text(session_id: &str) -> Option<String> {
+    read_session_lines(session_id)
+        .ok()?
+        .into_iter()
+        .rev()
+        .find(|line| line.role == "assistant")
+        .and_then(|line| match line.content {
+            serde_json::Value::String(s) => Some(s),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        })
+        .filter(|text| !text.trim().is_empty())
+}
+
+fn read_session_title(session_id: &str) -> Option<String> {
+    let content = std::fs::read_to_string(soagents_dir().join("sessions.json")).ok()?;
+    let sessions: Vec<SessionMeta> = serde_json::from_str(&content).ok()?;
+    sessions
+        .into_iter()
+        .find(|s| s.id == session_id)
+        .and_then(|s| s.title)
+        .filter(|t| !t.trim().is_empty())
+}
+
+/// Attach sender details from the message log to the session's user messages.
+/// Log entries are consumed in order, matching on the message text, so messages
+/// the log doesn't know about (desktop input, system injections) stay anonymous.
+fn join_entries(lines: Vec<SessionLine>, log: &[MessageLogEntry]) -> Vec<TranscriptEntry> {
+    let mut cursor = 0;
+    lines
+        .into_iter()
+        .map(|line| {
+            let content = match line.content {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            let mut entry = TranscriptEntry {
+                role: line.role,
+                sender_id: None,
+                sender_name: None,
+                message_id: None,
+                timestamp: line.timestamp,
+                content,
+            };
+            if entry.role == "user" {
+                let wanted = entry.content.trim();
+                if let Some(offset) = log[cursor..].iter().position(|e| e.text == wanted) {
+                    let matched = &log[cursor + offset];
+                    entry.sender_id = Some(matched.sender_id.clone());
+                    entry.sender_name = matched.sender_name.clone();
+                    entry.message_id = Some(matched.message_id.clone());
+                    entry.timestamp = Some(matched.received_at.clone());
+                    cursor += offset + 1;
+                }
+            }
+            entry
+        })
+        .collect()
+}
+
+// ── Rendering ────────────────────────────────────────────────────────────────
+
+fn speaker(entry: &TranscriptEntry) -> String {
+    if entry.role == "assistant" {
+        return "Assistant".to_string();
+    }
+    entry
+        .sender_name
+        .clone()
+        .or_else(|| entry.sender_id.clone())
+        .unwrap_or_else(|| "User".to_string())
+}
+
+fn display_time(timestamp: Option<&str>) -> String {
+    timestamp
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+fn render_markdown(header: &TranscriptHeader, entries: &[TranscriptEntry]) -> String {
+    let mut out = format!("# {}\n\n", header.title);
+    out.push_str(&format!("- Platform: {}\n", header.platform));
+    out.push_str(&format!(
+        "- Chat: {} ({})\n",
+        header.chat_id, header.chat_kind
+    ));
+    out.push_str(&format!("- Session: {}\n", header.session_id));
+    if !header.participants.is_empty() {
+        out.push_str(&format!(
+            "- Participants: {}\n",
+            header.participants.join(", ")
+        ));
+    }
+    out.push_str(&format!("- Exported: {}\n\n---\n", header.exported_at));
+    for entry in entries {
+        let time = display_time(entry.timestamp.as_deref());
+        if time.is_empty() {
+            out.push_str(&format!("\n**{}**\n\n", speaker(entry)));
+        } else {
+            out.push_str(&format!("\n**{}** · {}\n\n", speaker(entry), time));
+        }
+        out.push_str(entry.content.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_html(header: &TranscriptHeader, entries: &[TranscriptEntry]) -> String {
+    let title = escape_html(&header.title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n\
+         body {{ font-family: -apple-system, sans-serif; max-width: 760px; margin: 2em auto; \
+         color: #222; }}\n\
+         .meta {{ color: #666; font-size: 0.9em; }}\n\
+         .msg {{ margin: 1.2em 0; }}\n\
+         .who {{ font-weight: 600; }}\n\
+         .time {{ color: #999; font-size: 0.85em; margin-left: 0.5em; }}\n\
+         .assistant .who {{ color: #3b6fd6; }}\n\
+         .body {{ white-space: pre-wrap; margin-top: 0.3em; }}\n\
+         </style>\n</head>\n<body>\n<h1>{}</h1>\n<ul class=\"meta\">\n",
+        title, title
+    );
+    out.push_str(&format!(
+        "<li>Platform: {}</li>\n",
+        escape_html(&header.platform)
+    ));
+    out.push_str(&format!(
+        "<li>Chat: {} ({})</li>\n",
+        escape_html(&header.chat_id),
+        escape_html(&header.chat_kind)
+    ));
+    out.push_str(&format!(
+        "<li>Session: {}</li>\n",
+        escape_html(&header.session_id)
+    ));
+    if !header.participants.is_empty() {
+        out.push_str(&format!(
+            "<li>Participants: {}</li>\n",
+            escape_html(&header.participants.join(", "))
+        ));
+    }
+    out.push_str(&format!(
+        "<li>Exported: {}</li>\n</ul>\n<hr>\n",
+        escape_html(&header.exported_at)
+    ));
+    for entry in entries {
+        out.push_str(&format!(
+            "<div class=\"msg {}\">\n<span class=\"who\">{}</span>\
+             <span class=\"time\">{}</span>\n<div class=\"body\">{}</div>\n</div>\n",
+            escape_html(&entry.role),
+            escape_html(&speaker(entry)),
+            escape_html(&display_time(entry.timestamp.as_deref())),
+            escape_html(entry.content.trim_end())
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_jsonl(header: &TranscriptHeader, entries: &[TranscriptEntry]) -> String {
+    let mut out = json!({
+        "type": "meta",
+        "title": header.title,
+        "platform": header.platform,
+        "chatType": header.chat_kind,
+        "chatId": header.chat_id,
+        "sessionId": header.session_id,
+        "participants": header.participants,
+        "exportedAt": header.exported_at,
+    })
+    .to_string();
+    out.push('\n');
+    for entry in entries {
+        out.push_str(
+            &json!({
+                "type": "message",
+                "role": entry.role,
+                "senderId": entry.sender_id,
+                "senderName": entry.sender_name,
+                "messageId": entry.message_id,
+                "timestamp": entry.timestamp,
+                "content": entry.content,
+            })
+            .to_string(),
+        );
+        out.push('\n');
+    }
+    out
+}
+
+// ── Export ───────────────────────────────────────────────────────────────────
+
+/// Split a session key (`im:{agentId}:{platform}:{kind}:{chatId}`) into
+/// `(platform, kind, chat_id)`. Linked identities (`im:{agentId}:person:{personId}`)
+/// give `("person", "private", person_id)`.
+fn parse_session_key(session_key: &str) -> Option<(String, String, String)> {
+    let mut parts = session_key.splitn(5, ':');
+    if parts.next()? != "im" {
+        return None;
+    }
+    let _agent = parts.next()?;
+    let platform = parts.next()?.to_string();
+    if platform == "person" {
+        let person_id = parts.collect::<Vec<_>>().join(":");
+        return (!person_id.is_empty()).then(|| (platform, "private".to_string(), person_id));
+    }
+    let kind = parts.next()?.to_string();
+    let chat_id = parts.next()?.to_string();
+    Some((platform, kind, chat_id))
+}
+
+/// Build the transcript for `session_key` / `session_id` and write it under the
+/// channel's exports directory.
+pub fn export_transcript(
+    agent_id: &str,
+    channel_id: &str,
+    session_key: &str,
+    session_id: &str,
+    format: TranscriptFormat,
+) -> Result<ExportedTranscript, String> {
+    let (platform, chat_kind, chat_id) = parse_session_key(base_session_key(session_key))
+        .ok_or_else(|| format!("Invalid session key: {}", session_key))?;
+    let lines = read_session_lines(session_id)?;
+    let log = read_message_log(
+        &health::agent_channel_message_log_path(agent_id, channel_id),
+        session_key,
+    );
+    let entries = join_entries(lines, &log);
+
+    let mut participants: Vec<String> = Vec::new();
+    for entry in entries.iter().filter(|e| e.role == "user") {
+        let name = speaker(entry);
+        if !participants.contains(&name) {
+            participants.push(name);
+        }
+    }
+    let header = TranscriptHeader {
+        title: read_session_title(session_id).unwrap_or_else(|| "Conversation".to_string()),
+        platform,
+        chat_kind,
+        chat_id,
+        session_id: session_id.to_string(),
+        exported_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        participants,
+    };
+
+    let rendered = match format {
+        TranscriptFormat::Markdown => render_markdown(&header, &entries),
+        TranscriptFormat::Html => render_html(&header, &entries),
+        TranscriptFormat::Jsonl => render_jsonl(&header, &entries),
+    };
+
+    let dir = health::agent_channel_exports_dir(agent_id, channel_id);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create exports dir: {}", e))?;
+    let file_name = format!(
+        "transcript-{}-{}.{}",
+        &session_id[..8.min(session_id.len())],
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    );
+    let path = dir.join(&file_name);
+    std::fs::write(&path, rendered).map_err(|e| format!("Failed to write transcript: {}", e))?;
+
+    Ok(ExportedTranscript {
+        path: path.to_string_lossy().to_string(),
+        file_name,
+        session_id: session_id.to_string(),
+        message_count: entries.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_entry(text: &str, name: &str) -> MessageLogEntry {
+        MessageLogEntry {
+            session_key: "im:a:telegram:private:1".to_string(),
+            message_id: format!("m-{}", text),
+            sender_id: "42".to_string(),
+            sender_name: Some(name.to_string()),
+            source_type: ImSourceType::Private,
+            received_at: "2026-01-02T03:04:05+00:00".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn line(role: &str, content: &str) -> SessionLine {
+        SessionLine {
+            role: role.to_string(),
+            content: serde_json::Value::String(content.to_string()),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_join_attaches_senders_in_order() {
+        let log = vec![log_entry("hi", "Alice"), log_entry("again", "Bob")];
+        let lines = vec![
+            line("user", "hi"),
+            line("assistant", "hello"),
+            line("user", "typed on desktop"),
+            line("user", "again"),
+        ];
+        let entries = join_entries(lines, &log);
+        assert_eq!(speaker(&entries[0]), "Alice");
+        assert_eq!(speaker(&entries[1]), "Assistant");
+        assert_eq!(speaker(&entries[2]), "User");
+        assert_eq!(speaker(&entries[3]), "Bob");
+        assert_eq!(entries[3].message_id.as_deref(), Some("m-again"));
+    }
+
+    #[test]
+    fn test_parse_session_key_and_escape() {
+        assert_eq!(
+            parse_session_key("im:agent:feishu:private:oc_1:x"),
+            Some((
+                "feishu".to_string(),
+                "private".to_string(),
+                "oc_1:x".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_session_key("im:agent:person:3f2a"),
+            Some((
+                "person".to_string(),
+                "private".to_string(),
+                "3f2a".to_string()
+            ))
+        );
+        assert_eq!(parse_session_key("desktop:1"), None);
+        assert_eq!(escape_html("<a & 'b'>"), "&lt;a &amp; &#39;b&#39;&gt;");
+    }
+}
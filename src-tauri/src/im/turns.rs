@@ -0,0 +1,331 @@
+// In-flight turn registry — tracks what happened to each accepted message so that
+// platform edit / recall events can be applied to it:
+//   Waiting  → spawned, waiting for a concurrency slot (an edit replaces its text,
+//              a recall cancels it before it reaches the Sidecar)
+//   Running  → streaming a reply (a recall or a re-run edit cancels the stream)
+//   Answered → done; the bot messages holding the reply are remembered so a recall
+//              can optionally delete them
+// Messages deferred to the inbound queue leave the registry; the queue handles them.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+/// Answered turns are forgotten after this long
+const ANSWERED_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Upper bound on remembered turns per channel
+const MAX_TURNS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Waiting,
+    Running,
+    Answered,
+}
+
+struct TurnEntry {
+    /// Distinguishes a re-run from the turn it replaced (same chat / message ID)
+    generation: u64,
+    session_key: String,
+    phase: Phase,
+    /// Latest text (edits while waiting replace it)
+    text: String,
+    cancel_tx: watch::Sender<bool>,
+    /// Bot messages carrying the reply (only those whose IDs are known)
+    reply_ids: Vec<String>,
+    updated: Instant,
+}
+
+/// Held by the task running a turn
+pub struct TurnTicket {
+    key: String,
+    generation: u64,
+    cancel_rx: watch::Receiver<bool>,
+}
+
+impl TurnTicket {
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel_rx.borrow()
+    }
+
+    /// Resolves once the turn is cancelled (never, if the registry dropped it)
+    pub async fn cancelled(&mut self) {
+        loop {
+            if *self.cancel_rx.borrow_and_update() {
+                return;
+            }
+            if self.cancel_rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Result of applying an edit event
+#[derive(Debug, PartialEq)]
+pub enum EditOutcome {
+    /// The waiting turn will use the new text
+    Replaced,
+    /// Too late to replace — the turn is streaming (or done)
+    Started {
+        session_key: String,
+        running: bool,
+    },
+    Unknown,
+}
+
+/// Result of applying a recall event
+#[derive(Debug, PartialEq)]
+pub enum RecallOutcome {
+    /// Cancelled before the Sidecar saw it
+    CancelledWaiting,
+    /// Stream cancelled; the Sidecar's run should be stopped too
+    CancelledRunning {
+        session_key: String,
+    },
+    /// Already answered with these bot messages
+    Answered {
+        reply_ids: Vec<String>,
+    },
+    Unknown,
+}
+
+fn turn_key(chat_id: &str, message_id: &str) -> String {
+    format!("{}\u{1f}{}", chat_id, message_id)
+}
+
+#[derive(Default)]
+pub struct TurnRegistry {
+    turns: Mutex<HashMap<String, TurnEntry>>,
+    next_generation: AtomicU64,
+}
+
+impl TurnRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a newly accepted message (replacing an earlier turn for the same message)
+    pub fn register(
+        &self,
+        chat_id: &str,
+        message_id: &str,
+        session_key: &str,
+        text: &str,
+    ) -> TurnTicket {
+        let key = turn_key(chat_id, message_id);
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        prune(&mut turns);
+        turns.insert(
+            key.clone(),
+            TurnEntry {
+                generation,
+                session_key: session_key.to_string(),
+                phase: Phase::Waiting,
+                text: text.to_string(),
+                cancel_tx,
+                reply_ids: Vec::new(),
+                updated: Instant::now(),
+            },
+        );
+        TurnTicket {
+            key,
+            generation,
+            cancel_rx,
+        }
+    }
+
+    /// The turn got its slot: returns the (possibly edited) text, or None if it was recalled
+    pub fn begin(&self, ticket: &TurnTicket) -> Option<String> {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = turns
+            .get_mut(&ticket.key)
+            .filter(|e| e.generation == ticket.generation)?;
+        if *entry.cancel_tx.borrow() {
+            return None;
+        }
+        entry.phase = Phase::Running;
+        entry.updated = Instant::now();
+        Some(entry.text.clone())
+    }
+
+    /// Remember bot messages that carry this turn's reply
+    pub fn record_replies(&self, ticket: &TurnTicket, reply_ids: Vec<String>) {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = turns
+            .get_mut(&ticket.key)
+            .filter(|e| e.generation == ticket.generation)
+        {
+            entry.reply_ids.extend(reply_ids);
+        }
+    }
+
+    /// The turn ended; cancelled turns are dropped, answered ones remembered
+    pub fn finish(&self, ticket: &TurnTicket) {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = turns
+            .get_mut(&ticket.key)
+            .filter(|e| e.generation == ticket.generation)
+        else {
+            return;
+        };
+        if *entry.cancel_tx.borrow() {
+            turns.remove(&ticket.key);
+        } else {
+            entry.phase = Phase::Answered;
+            entry.updated = Instant::now();
+        }
+    }
+
+    /// Stop tracking a turn handed over to the inbound queue. Returns its latest
+    /// text, or None if it was recalled meanwhile.
+    pub fn release(&self, ticket: &TurnTicket) -> Option<String> {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        if turns
+            .get(&ticket.key)
+            .is_some_and(|e| e.generation == ticket.generation)
+        {
+            let entry = turns.remove(&ticket.key)?;
+            return (!*entry.cancel_tx.borrow()).then_some(entry.text);
+        }
+        None
+    }
+
+    pub fn apply_edit(&self, chat_id: &str, message_id: &str, text: &str) -> EditOutcome {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = turns.get_mut(&turn_key(chat_id, message_id)) else {
+            return EditOutcome::Unknown;
+        };
+        if *entry.cancel_tx.borrow() {
+            return EditOutcome::Unknown;
+        }
+        match entry.phase {
+            Phase::Waiting => {
+                entry.text = text.to_string();
+                EditOutcome::Replaced
+            }
+            phase => EditOutcome::Started {
+                session_key: entry.session_key.clone(),
+                running: phase == Phase::Running,
+            },
+        }
+    }
+
+    /// Cancel a waiting or running turn (edit re-run)
+    pub fn cancel(&self, chat_id: &str, message_id: &str) {
+        let turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = turns.get(&turn_key(chat_id, message_id)) {
+            let _ = entry.cancel_tx.send(true);
+        }
+    }
+
+    pub fn apply_recall(&self, chat_id: &str, message_id: &str) -> RecallOutcome {
+        let mut turns = self.turns.lock().unwrap_or_else(|e| e.into_inner());
+        let key = turn_key(chat_id, message_id);
+        let Some(entry) = turns.get(&key) else {
+            return RecallOutcome::Unknown;
+        };
+        match entry.phase {
+            Phase::Waiting => {
+                let _ = entry.cancel_tx.send(true);
+                RecallOutcome::CancelledWaiting
+            }
+            Phase::Running => {
+                let _ = entry.cancel_tx.send(true);
+                RecallOutcome::CancelledRunning {
+                    session_key: entry.session_key.clone(),
+                }
+            }
+            Phase::Answered => {
+                let reply_ids = turns.remove(&key).map(|e| e.reply_ids).unwrap_or_default();
+                RecallOutcome::Answered { reply_ids }
+            }
+        }
+    }
+}
+
+/// Drop old answered turns, then the oldest entries beyond the cap
+fn prune(turns: &mut HashMap<String, TurnEntry>) {
+    turns.retain(|_, e| e.phase != Phase::Answered || e.updated.elapsed() < ANSWERED_TTL);
+    while turns.len() >= MAX_TURNS {
+        let Some(oldest) = turns
+            .iter()
+            .filter(|(_, e)| e.phase == Phase::Answered)
+            .min_by_key(|(_, e)| e.updated)
+            .or_else(|| turns.iter().min_by_key(|(_, e)| e.updated))
+            .map(|(k, _)| k.clone())
+        else {
+            break;
+        };
+        turns.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_and_recall_follow_the_turn_phase() {
+        let registry = TurnRegistry::new();
+        let ticket = registry.register("c1", "m1", "k1", "helo");
+        assert_eq!(
+            registry.apply_edit("c1", "m1", "hello"),
+            EditOutcome::Replaced
+        );
+        assert_eq!(registry.begin(&ticket).as_deref(), Some("hello"));
+        assert_eq!(
+            registry.apply_edit("c1", "m1", "hello!"),
+            EditOutcome::Started {
+                session_key: "k1".to_string(),
+                running: true
+            }
+        );
+
+        registry.record_replies(&ticket, vec!["r1".to_string()]);
+        registry.finish(&ticket);
+        assert_eq!(
+            registry.apply_recall("c1", "m1"),
+            RecallOutcome::Answered {
+                reply_ids: vec!["r1".to_string()]
+            }
+        );
+        assert!(!ticket.is_cancelled());
+
+        // Recalled while waiting: the turn never starts
+        let ticket = registry.register("c1", "m2", "k1", "oops");
+        assert_eq!(
+            registry.apply_recall("c1", "m2"),
+            RecallOutcome::CancelledWaiting
+        );
+        assert!(ticket.is_cancelled());
+        assert_eq!(registry.begin(&ticket), None);
+        assert_eq!(registry.apply_recall("c1", "m9"), RecallOutcome::Unknown);
+    }
+
+    #[test]
+    fn rerun_is_not_finished_by_the_replaced_turn() {
+        let registry = TurnRegistry::new();
+        let old = registry.register("c1", "m1", "k1", "v1");
+        registry.begin(&old);
+        registry.cancel("c1", "m1");
+        assert!(old.is_cancelled());
+
+        let rerun = registry.register("c1", "m1", "k1", "v2");
+        registry.finish(&old);
+        assert_eq!(registry.begin(&rerun).as_deref(), Some("v2"));
+        assert_eq!(
+            registry.apply_recall("c1", "m1"),
+            RecallOutcome::CancelledRunning {
+                session_key: "k1".to_string()
+            }
+        );
+        assert!(rerun.is_cancelled());
+        assert_eq!(registry.release(&rerun), None);
+    }
+}
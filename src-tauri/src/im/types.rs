@@ -1,10 +1,12 @@
 // IM Bot integration types (Rust side)
 
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::time::Instant;
 
+use crate::ulog_warn;
+
 // ===== Platform & Status Enums =====
 
 /// IM platform type
@@ -13,6 +15,8 @@ pub enum ImPlatform {
     Telegram,
     Feishu,
     Dingtalk,
+    /// Loopback WebSocket on 127.0.0.1 (testing without a real IM)
+    Local,
 }
 
 impl Serialize for ImPlatform {
@@ -21,6 +25,7 @@ impl Serialize for ImPlatform {
             Self::Telegram => serializer.serialize_str("telegram"),
             Self::Feishu => serializer.serialize_str("feishu"),
             Self::Dingtalk => serializer.serialize_str("dingtalk"),
+            Self::Local => serializer.serialize_str("local"),
         }
     }
 }
@@ -32,9 +37,10 @@ impl<'de> Deserialize<'de> for ImPlatform {
             "telegram" => Ok(Self::Telegram),
             "feishu" => Ok(Self::Feishu),
             "dingtalk" => Ok(Self::Dingtalk),
+            "local" => Ok(Self::Local),
             _ => Err(serde::de::Error::unknown_variant(
                 &s,
-                &["telegram", "feishu", "dingtalk"],
+                &["telegram", "feishu", "dingtalk", "local"],
             )),
         }
     }
@@ -46,6 +52,7 @@ impl std::fmt::Display for ImPlatform {
             Self::Telegram => write!(f, "telegram"),
             Self::Feishu => write!(f, "feishu"),
             Self::Dingtalk => write!(f, "dingtalk"),
+            Self::Local => write!(f, "local"),
         }
     }
 }
@@ -85,21 +92,54 @@ pub struct ImMessage {
     pub is_mention: bool,
     /// Whether this is specifically a reply to bot's message
     pub reply_to_bot: bool,
+    /// New message, or an edit / recall of `message_id`
+    pub event: ImMessageEvent,
+}
+
+/// What an inbound event does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImMessageEvent {
+    New,
+    /// `message_id` was edited; `text` holds the new content
+    Edited,
+    /// `message_id` was recalled / deleted by its sender (`text` is empty)
+    Recalled,
 }
 
 impl ImMessage {
-    /// Canonical session key for routing (single source of truth for the format).
-    pub fn session_key(&self) -> String {
-        let source = match self.source_type {
-            ImSourceType::Private => "private",
-            ImSourceType::Group => "group",
-        };
-        format!("im:{}:{}:{}", self.platform, source, self.chat_id)
+    /// Recall notice for `message_id` (platforms only report which message went away)
+    pub fn recalled(platform: ImPlatform, chat_id: &str, message_id: &str) -> Self {
+        Self {
+            chat_id: chat_id.to_string(),
+            message_id: message_id.to_string(),
+            text: String::new(),
+            sender_id: String::new(),
+            sender_name: None,
+            source_type: ImSourceType::Private,
+            platform,
+            timestamp: chrono::Utc::now(),
+            is_mention: false,
+            reply_to_bot: false,
+            event: ImMessageEvent::Recalled,
+        }
     }
 }
 
 // ===== Config =====
 
+/// How much tool progress is shown while a reply is generated
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolActivityVerbosity {
+    /// Plain "Generating..." placeholder
+    Off,
+    /// Tool and its main target ("📄 Reading app.ts")
+    #[default]
+    Summary,
+    /// Full paths / commands and a step counter
+    Detailed,
+}
+
 /// IM Bot configuration passed to adapter at runtime.
 /// Merged from Agent + Channel config before adapter start.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -142,12 +182,57 @@ pub struct ImConfig {
     pub dingtalk_use_ai_card: Option<bool>,
     #[serde(default)]
     pub dingtalk_card_template_id: Option<String>,
+    /// Local loopback port (None/0 = pick a free port)
+    #[serde(default)]
+    pub local_port: Option<u16>,
     // Group permissions (persisted, passed to adapter on startup)
     #[serde(default)]
     pub group_permissions: Vec<GroupPermission>,
     // Group activation mode: "mention" or "always"
     #[serde(default)]
     pub group_activation: Option<String>,
+    // Multi-agent routing rules
+    #[serde(default)]
+    pub agent_routes: Vec<AgentRoute>,
+    // Channel persona (extra system prompt)
+    #[serde(default)]
+    pub persona: ChannelPersona,
+    /// Consecutive listen loop restarts before the channel gives up (None = default)
+    #[serde(default)]
+    pub max_listen_restarts: Option<u32>,
+    /// Allow proactive sends (desktop, scheduled tasks, agents) to known chats (None = allowed)
+    #[serde(default)]
+    pub allow_proactive_send: Option<bool>,
+    /// Re-run the turn when an already answered (or streaming) message is edited
+    #[serde(default)]
+    pub rerun_on_edit: Option<bool>,
+    /// Delete the bot's reply when the user recalls an answered message
+    #[serde(default)]
+    pub delete_reply_on_recall: Option<bool>,
+    /// Tool progress shown in the placeholder message (None = summary)
+    #[serde(default)]
+    pub tool_activity: Option<ToolActivityVerbosity>,
+    /// Send a short list of the tools used once the reply is done
+    #[serde(default)]
+    pub tool_activity_log: Option<bool>,
+    /// Append a token usage / cost footer to replies
+    #[serde(default)]
+    pub usage_footer: Option<bool>,
+    /// Hold final replies in the review queue until approved on the desktop
+    #[serde(default)]
+    pub review_before_send: Option<bool>,
+    /// Tell the user their reply is pending review (review_before_send only)
+    #[serde(default)]
+    pub review_notice: Option<bool>,
+    /// Share of turn slots relative to other busy channels (None = 1)
+    #[serde(default)]
+    pub scheduler_weight: Option<u32>,
+    /// Directories a chat may switch to with `/workspace` (from the agent config)
+    #[serde(default)]
+    pub workspace_allowlist: Vec<String>,
+    /// Users allowed to change chat settings such as `/workspace` (empty = any allowed user)
+    #[serde(default)]
+    pub admin_users: Vec<String>,
 }
 
 fn default_platform() -> ImPlatform {
@@ -167,33 +252,74 @@ pub struct PeerSession {
     pub last_active: Instant,
 }
 
-// ===== Buffer =====
+// ===== Inbound Queue =====
 
-/// Buffered message (when Sidecar is unavailable)
+/// Queued inbound message (accepted while its Sidecar was unavailable).
+/// Stored as a record in the inbound write-ahead log; fields added after the
+/// legacy `buffer.json` format default so old files still import.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BufferedMessage {
+    /// Queue entry id (unique per entry, used by WAL remove/attempt records)
+    #[serde(default)]
+    pub id: String,
     pub session_key: String,
     pub chat_id: String,
+    #[serde(default)]
+    pub message_id: String,
     pub text: String,
     pub sender_id: String,
     pub sender_name: Option<String>,
+    #[serde(default)]
+    pub source_type: Option<ImSourceType>,
+    /// Original message time (RFC 3339), used for TTL expiry
     pub timestamp: String,
+    /// Failed drain attempts so far
+    #[serde(default)]
+    pub attempts: u32,
 }
 
 impl BufferedMessage {
-    pub fn from_im_message(msg: &ImMessage) -> Self {
+    pub fn from_im_message(msg: &ImMessage, session_key: &str) -> Self {
         Self {
-            session_key: msg.session_key(),
+            id: uuid::Uuid::new_v4().to_string(),
+            session_key: session_key.to_string(),
             chat_id: msg.chat_id.clone(),
+            message_id: msg.message_id.clone(),
             text: msg.text.clone(),
             sender_id: msg.sender_id.clone(),
             sender_name: msg.sender_name.clone(),
+            source_type: Some(msg.source_type.clone()),
             timestamp: msg.timestamp.to_rfc3339(),
+            attempts: 0,
+        }
+    }
+
+    /// Rebuild the original message for re-processing.
+    pub fn to_im_message(&self, platform: ImPlatform) -> ImMessage {
+        ImMessage {
+            chat_id: self.chat_id.clone(),
+            message_id: self.message_id.clone(),
+            text: self.text.clone(),
+            sender_id: self.sender_id.clone(),
+            sender_name: self.sender_name.clone(),
+            source_type: self.source_type.clone().unwrap_or(ImSourceType::Private),
+            platform,
+            timestamp: self.received_at().unwrap_or_else(chrono::Utc::now),
+            is_mention: true,
+            reply_to_bot: false,
+            event: ImMessageEvent::New,
         }
     }
+
+    /// Parsed original message time
+    pub fn received_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|t| t.with_timezone(&chrono::Utc))
+    }
 }
 
-/// Persistent message buffer (serializable for disk persistence)
+/// Legacy message buffer file (`buffer.json`), imported once into the inbound queue
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct MessageBufferData {
     pub messages: VecDeque<BufferedMessage>,
@@ -211,6 +337,44 @@ pub struct ActiveSessionInfo {
     pub last_active: String,
 }
 
+/// A chat that has messaged the channel (target for proactive sends)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownChat {
+    pub chat_id: String,
+    pub chat_type: ImSourceType,
+    /// Last sender (private chats: the peer) — re-checked against the allowlist
+    #[serde(default)]
+    pub sender_id: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// RFC 3339 time of the last inbound message
+    pub last_active: String,
+}
+
+impl KnownChat {
+    pub fn from_message(msg: &ImMessage) -> Self {
+        let private = msg.source_type == ImSourceType::Private;
+        Self {
+            chat_id: msg.chat_id.clone(),
+            chat_type: msg.source_type.clone(),
+            sender_id: Some(msg.sender_id.clone()),
+            display_name: if private { msg.sender_name.clone() } else { None },
+            last_active: msg.timestamp.to_rfc3339(),
+        }
+    }
+}
+
+/// Known chats of one running channel (cmd_im_list_known_chats / sidecar send API)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelKnownChats {
+    pub agent_id: String,
+    pub channel_id: String,
+    pub platform: ImPlatform,
+    pub chats: Vec<KnownChat>,
+}
+
 /// Group permission status
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -240,8 +404,14 @@ pub struct ImBotStatusResponse {
     pub active_sessions: Vec<ActiveSessionInfo>,
     pub error_message: Option<String>,
     pub restart_count: u32,
+    /// Recent listen loop restarts (newest last)
+    pub restart_history: Vec<RestartRecord>,
     pub buffered_messages: usize,
+    /// Final replies waiting in the outbound queue for a retry
+    pub pending_deliveries: usize,
     pub group_permissions: Vec<GroupPermission>,
+    /// Token usage of the channel since it was first started
+    pub usage: UsageTotals,
 }
 
 // ===== Health State =====
@@ -257,10 +427,80 @@ pub struct ImHealthState {
     pub active_sessions: Vec<ActiveSessionInfo>,
     pub error_message: Option<String>,
     pub restart_count: u32,
+    #[serde(default)]
+    pub restart_history: Vec<RestartRecord>,
+    #[serde(default)]
+    pub known_chats: Vec<KnownChat>,
+    /// Token usage of the channel since it was first started
+    #[serde(default)]
+    pub usage: UsageTotals,
+    /// Token usage per session key
+    #[serde(default)]
+    pub session_usage: HashMap<String, UsageTotals>,
     pub buffered_messages: usize,
     pub last_persisted: String,
 }
 
+/// One supervised restart of a channel's listen loop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartRecord {
+    /// RFC 3339 time the exit was detected
+    pub at: String,
+    pub reason: String,
+    /// Backoff before the restart (seconds)
+    pub delay_secs: u64,
+}
+
+/// Token usage of one turn, from the Sidecar's `complete` event
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TurnUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_read_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_tokens: u64,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+}
+
+/// Accumulated token usage (a channel, session key, day or user)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+    pub turns: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_creation_tokens: u64,
+    pub cost_usd: f64,
+    pub duration_ms: u64,
+    /// RFC 3339 time of the last counted turn
+    #[serde(default)]
+    pub last_turn_at: Option<String>,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &TurnUsage, at: &str) {
+        self.turns += 1;
+        self.input_tokens += usage.input_tokens;
+        self.output_tokens += usage.output_tokens;
+        self.cache_read_tokens += usage.cache_read_tokens;
+        self.cache_creation_tokens += usage.cache_creation_tokens;
+        self.cost_usd += usage.cost_usd.unwrap_or(0.0);
+        self.duration_ms += usage.duration_ms.unwrap_or(0);
+        self.last_turn_at = Some(at.to_string());
+    }
+}
+
 impl Default for ImHealthState {
     fn default() -> Self {
         Self {
@@ -271,6 +511,10 @@ impl Default for ImHealthState {
             active_sessions: Vec::new(),
             error_message: None,
             restart_count: 0,
+            restart_history: Vec::new(),
+            known_chats: Vec::new(),
+            usage: UsageTotals::default(),
+            session_usage: HashMap::new(),
             buffered_messages: 0,
             last_persisted: chrono::Utc::now().to_rfc3339(),
         }
@@ -296,6 +540,8 @@ pub enum TelegramError {
     BotKicked,
     /// Bot token is invalid
     TokenUnauthorized,
+    /// Chat doesn't exist or the bot can't see it
+    ChatNotFound,
     /// Draft peer invalid (sendMessageDraft not supported for this chat)
     DraftPeerInvalid,
     /// Other API error
@@ -312,6 +558,7 @@ impl std::fmt::Display for TelegramError {
             Self::MessageTooLong => write!(f, "Message too long"),
             Self::BotKicked => write!(f, "Bot kicked from group"),
             Self::TokenUnauthorized => write!(f, "Token unauthorized"),
+            Self::ChatNotFound => write!(f, "Chat not found"),
             Self::DraftPeerInvalid => write!(f, "Draft peer invalid"),
             Self::Other(msg) => write!(f, "{}", msg),
         }
@@ -320,9 +567,98 @@ impl std::fmt::Display for TelegramError {
 
 impl std::error::Error for TelegramError {}
 
+impl TelegramError {
+    /// Stable label for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NetworkTimeout => "network_timeout",
+            Self::RateLimited(_) => "rate_limited",
+            Self::MarkdownParseError => "markdown_parse",
+            Self::MessageNotModified => "not_modified",
+            Self::MessageTooLong => "too_long",
+            Self::BotKicked => "bot_kicked",
+            Self::TokenUnauthorized => "unauthorized",
+            Self::ChatNotFound => "chat_not_found",
+            Self::DraftPeerInvalid => "draft_peer_invalid",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
 /// Convenience result alias for adapter operations
 pub type AdapterResult<T> = Result<T, String>;
 
+/// A failed message send, classified for the outbound queue
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendError {
+    pub message: String,
+    /// Seconds the platform asked us to wait before retrying
+    pub retry_after: Option<u64>,
+    /// Retrying the same message cannot succeed (bot removed, chat gone, message rejected)
+    pub permanent: bool,
+}
+
+impl SendError {
+    pub fn transient(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retry_after: None,
+            permanent: false,
+        }
+    }
+
+    pub fn permanent(message: impl Into<String>) -> Self {
+        Self {
+            permanent: true,
+            ..Self::transient(message)
+        }
+    }
+
+    pub fn rate_limited(message: impl Into<String>, retry_after: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::transient(message)
+        }
+    }
+
+    /// Classify a rejected HTTP response. Other 4xx statuses mean the request itself
+    /// was refused, so sending it again won't help.
+    pub fn from_status(status: u16, message: impl Into<String>) -> Self {
+        match status {
+            400..=499 if status != 408 && status != 429 => Self::permanent(message),
+            _ => Self::transient(message),
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Lets `?` hand a `SendError` to the String-based adapter API
+impl From<SendError> for String {
+    fn from(e: SendError) -> Self {
+        e.message
+    }
+}
+
+impl From<TelegramError> for SendError {
+    fn from(e: TelegramError) -> Self {
+        match e {
+            TelegramError::RateLimited(secs) => Self::rate_limited(e.to_string(), secs),
+            TelegramError::MessageTooLong
+            | TelegramError::BotKicked
+            | TelegramError::TokenUnauthorized
+            | TelegramError::ChatNotFound => Self::permanent(e.to_string()),
+            _ => Self::transient(e.to_string()),
+        }
+    }
+}
+
 /// Routing error variants
 #[derive(Debug)]
 pub enum RouteError {
@@ -346,6 +682,17 @@ impl std::fmt::Display for RouteError {
 
 impl std::error::Error for RouteError {}
 
+impl RouteError {
+    /// Stable label for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Setup(_) => "setup",
+            Self::Unavailable(_) => "unavailable",
+            Self::Response(..) => "response",
+        }
+    }
+}
+
 // ===== Agent + Channel Architecture =====
 
 /// Channel-level config overrides (None = inherit from Agent)
@@ -357,6 +704,76 @@ pub struct ChannelOverrides {
     pub model: Option<String>,
     pub permission_mode: Option<String>,
     pub tools_deny: Option<Vec<String>>,
+    /// Persona / rules appended to the system prompt (inline text)
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Persona file (absolute, or relative to the workspace); takes precedence over inline text
+    #[serde(default)]
+    pub system_prompt_file: Option<String>,
+}
+
+/// Channel persona: extra system prompt text for every session of a channel
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelPersona {
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub system_prompt_file: Option<String>,
+}
+
+impl ChannelPersona {
+    pub fn from_overrides(overrides: Option<&ChannelOverrides>) -> Self {
+        Self {
+            system_prompt: overrides.and_then(|o| o.system_prompt.clone()),
+            system_prompt_file: overrides.and_then(|o| o.system_prompt_file.clone()),
+        }
+    }
+
+    /// Prompt text to send to the Sidecar. The file is re-read on every call so edits
+    /// apply to the next message; if it can't be read the inline text is used.
+    pub fn resolve(&self, workspace_path: &str) -> Option<String> {
+        if let Some(file) = self.system_prompt_file.as_deref().filter(|f| !f.trim().is_empty()) {
+            let path = PathBuf::from(workspace_path).join(file.trim());
+            match std::fs::read_to_string(&path) {
+                Ok(text) if !text.trim().is_empty() => return Some(text),
+                Ok(_) => {}
+                Err(e) => ulog_warn!(
+                    "[im] Failed to read persona file {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        self.system_prompt
+            .clone()
+            .filter(|text| !text.trim().is_empty())
+    }
+}
+
+/// Routing rule that sends a channel's messages to another agent.
+/// Users address it as "@name ..." or "/ask name ..." (the choice sticks per chat).
+/// Unset fields come from `agent_id`'s agent config, then from the channel itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentRoute {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub workspace_path: Option<String>,
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub provider_env_json: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub permission_mode: Option<String>,
+    #[serde(default)]
+    pub mcp_servers_json: Option<String>,
 }
 
 /// Channel configuration within an Agent
@@ -390,6 +807,10 @@ pub struct ChannelConfigRust {
     #[serde(default)]
     pub dingtalk_card_template_id: Option<String>,
 
+    // Local loopback settings
+    #[serde(default)]
+    pub local_port: Option<u16>,
+
     // User management
     #[serde(default)]
     pub allowed_users: Vec<String>,
@@ -406,12 +827,60 @@ pub struct ChannelConfigRust {
     #[serde(default)]
     pub overrides: Option<ChannelOverrides>,
 
+    // Multi-agent routing rules (empty = every message goes to the owning Agent)
+    #[serde(default)]
+    pub agent_routes: Vec<AgentRoute>,
+
     #[serde(default)]
     pub setup_completed: Option<bool>,
 
     /// HTTP/SOCKS5 proxy URL (Telegram API needs proxy in China)
     #[serde(default)]
     pub proxy_url: Option<String>,
+
+    /// Consecutive listen loop restarts before giving up (None = default)
+    #[serde(default)]
+    pub max_listen_restarts: Option<u32>,
+
+    /// Allow proactive sends to known chats (None = allowed)
+    #[serde(default)]
+    pub allow_proactive_send: Option<bool>,
+
+    /// Re-run the turn when an answered message is edited (default: off)
+    #[serde(default)]
+    pub rerun_on_edit: Option<bool>,
+
+    /// Delete the bot's reply when the user recalls a message (default: off)
+    #[serde(default)]
+    pub delete_reply_on_recall: Option<bool>,
+
+    /// Tool progress in the placeholder message: off / summary / detailed (default: summary)
+    #[serde(default)]
+    pub tool_activity: Option<ToolActivityVerbosity>,
+
+    /// Send a short tool log after the reply (default: off)
+    #[serde(default)]
+    pub tool_activity_log: Option<bool>,
+
+    /// Append a token usage / cost footer to replies (default: off)
+    #[serde(default)]
+    pub usage_footer: Option<bool>,
+
+    /// Hold final replies for approval on the desktop before sending (default: off)
+    #[serde(default)]
+    pub review_before_send: Option<bool>,
+
+    /// Send a "pending review" notice while a reply waits for approval (default: off)
+    #[serde(default)]
+    pub review_notice: Option<bool>,
+
+    /// Weight in fair scheduling against other busy channels (default: 1)
+    #[serde(default)]
+    pub scheduler_weight: Option<u32>,
+
+    /// Users allowed to run admin commands like `/workspace` (empty = any allowed user)
+    #[serde(default)]
+    pub admin_users: Vec<String>,
 }
 
 /// Agent configuration (read from config.json agents[])
@@ -425,6 +894,9 @@ pub struct AgentConfigRust {
     pub enabled: bool,
 
     pub workspace_path: String,
+    /// Other directories IM chats may switch to with `/workspace`
+    #[serde(default)]
+    pub workspace_allowlist: Vec<String>,
 
     // AI config (Agent-level defaults)
     #[serde(default)]
@@ -485,6 +957,27 @@ pub struct HeartbeatConfig {
     /// Max chars for HEARTBEAT_OK detection (default: 300)
     #[serde(default)]
     pub ack_max_chars: Option<u32>,
+    /// Where "content" results go (nothing is delivered when empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliver_to: Vec<HeartbeatDelivery>,
+}
+
+/// Delivery target for heartbeat content
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HeartbeatDelivery {
+    /// A chat on one of the agent's own channels
+    #[serde(rename_all = "camelCase")]
+    Im { channel_id: String, chat_id: String },
+    /// Desktop notification (shown by the frontend)
+    Desktop,
+    /// JSON POST `{ agentId, text, at }`
+    #[serde(rename_all = "camelCase")]
+    Webhook {
+        url: String,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
 }
 
 fn default_hb_enabled() -> bool { true }
@@ -497,6 +990,7 @@ impl Default for HeartbeatConfig {
             interval_minutes: 30,
             active_hours: None,
             ack_max_chars: None,
+            deliver_to: Vec::new(),
         }
     }
 }
@@ -544,6 +1038,21 @@ impl Default for MemoryAutoUpdateConfig {
 }
 
 impl ChannelConfigRust {
+    /// Whether the platform credentials needed to start this channel are filled in.
+    pub fn has_credentials(&self) -> bool {
+        fn filled(v: &Option<String>) -> bool {
+            v.as_ref().is_some_and(|t| !t.is_empty())
+        }
+        match self.channel_type {
+            ImPlatform::Telegram => filled(&self.bot_token),
+            ImPlatform::Feishu => filled(&self.feishu_app_id) && filled(&self.feishu_app_secret),
+            ImPlatform::Dingtalk => {
+                filled(&self.dingtalk_client_id) && filled(&self.dingtalk_client_secret)
+            }
+            ImPlatform::Local => true,
+        }
+    }
+
     /// Convert to ImConfig for adapter startup.
     pub fn to_im_config(&self, agent: &AgentConfigRust) -> ImConfig {
         let overrides = self.overrides.as_ref();
@@ -576,8 +1085,23 @@ impl ChannelConfigRust {
             dingtalk_client_secret: self.dingtalk_client_secret.clone(),
             dingtalk_use_ai_card: self.dingtalk_use_ai_card,
             dingtalk_card_template_id: self.dingtalk_card_template_id.clone(),
+            local_port: self.local_port,
             group_permissions: self.group_permissions.clone(),
             group_activation: self.group_activation.clone(),
+            agent_routes: self.agent_routes.clone(),
+            persona: ChannelPersona::from_overrides(overrides),
+            max_listen_restarts: self.max_listen_restarts,
+            allow_proactive_send: self.allow_proactive_send,
+            rerun_on_edit: self.rerun_on_edit,
+            delete_reply_on_recall: self.delete_reply_on_recall,
+            tool_activity: self.tool_activity,
+            tool_activity_log: self.tool_activity_log,
+            usage_footer: self.usage_footer,
+            review_before_send: self.review_before_send,
+            review_notice: self.review_notice,
+            scheduler_weight: self.scheduler_weight,
+            workspace_allowlist: agent.workspace_allowlist.clone(),
+            admin_users: self.admin_users.clone(),
         }
     }
 }
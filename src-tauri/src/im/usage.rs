@@ -0,0 +1,357 @@
+// IM token usage — one ledger line per finished turn
+// (~/.soagents/agents/{agentId}/channels/{channelId}/usage.jsonl), the optional reply
+// footer ("📊 12.4k in / 350 out · $0.0123 · 8.4s") and the daily report behind
+// cmd_im_usage_report (totals per channel and per user).
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::health;
+use super::types::{ImMessage, TurnUsage, UsageTotals};
+use crate::ulog_warn;
+
+/// The usage log is compacted once it grows past this size
+const USAGE_LOG_MAX_BYTES: u64 = 8 * 1024 * 1024;
+/// Days of records kept when the usage log is compacted
+const USAGE_LOG_KEEP_DAYS: i64 = 90;
+/// Days covered by a report when the caller does not say
+const DEFAULT_REPORT_DAYS: u32 = 30;
+
+/// One finished turn, as written to the usage log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    /// RFC 3339 time the turn finished
+    pub at: String,
+    pub session_key: String,
+    pub sender_id: String,
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    #[serde(flatten)]
+    pub usage: TurnUsage,
+}
+
+impl UsageRecord {
+    pub fn new(msg: &ImMessage, session_key: &str, usage: TurnUsage, at: &str) -> Self {
+        Self {
+            at: at.to_string(),
+            session_key: session_key.to_string(),
+            sender_id: msg.sender_id.clone(),
+            sender_name: msg.sender_name.clone(),
+            usage,
+        }
+    }
+
+    /// Local calendar day of the turn
+    fn day(&self) -> Option<NaiveDate> {
+        DateTime::parse_from_rfc3339(&self.at)
+            .ok()
+            .map(|t| t.with_timezone(&Local).date_naive())
+    }
+}
+
+/// Daily totals of one channel
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelDailyUsage {
+    pub date: String,
+    pub agent_id: String,
+    pub channel_id: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// Daily totals of one user of a channel
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDailyUsage {
+    pub date: String,
+    pub agent_id: String,
+    pub channel_id: String,
+    pub sender_id: String,
+    pub sender_name: Option<String>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// Result of cmd_im_usage_report (rows sorted by date, then agent / channel / user)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    /// First day covered (local date, YYYY-MM-DD)
+    pub from: String,
+    /// Last day covered (today)
+    pub to: String,
+    pub channels: Vec<ChannelDailyUsage>,
+    pub users: Vec<UserDailyUsage>,
+}
+
+/// Append a finished turn to the channel's usage log
+pub fn record_turn(path: &Path, record: &UsageRecord) {
+    let Ok(line) = serde_json::to_string(record) else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let appended = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| writeln!(f, "{}", line));
+    if let Err(e) = appended {
+        ulog_warn!("[im] Failed to append usage log: {}", e);
+        return;
+    }
+    if std::fs::metadata(path).is_ok_and(|m| m.len() > USAGE_LOG_MAX_BYTES) {
+        compact_usage_log(path);
+    }
+}
+
+/// Drop records older than USAGE_LOG_KEEP_DAYS (atomic rewrite)
+fn compact_usage_log(path: &Path) {
+    let cutoff = Local::now().date_naive() - chrono::Duration::days(USAGE_LOG_KEEP_DAYS);
+    let records = read_usage_log(path);
+    let mut kept = String::new();
+    for record in records
+        .iter()
+        .filter(|r| r.day().is_some_and(|d| d >= cutoff))
+    {
+        if let Ok(line) = serde_json::to_string(record) {
+            kept.push_str(&line);
+            kept.push('\n');
+        }
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    if std::fs::write(&tmp, kept).is_ok() {
+        let _ = std::fs::rename(&tmp, path);
+    }
+}
+
+fn read_usage_log(path: &Path) -> Vec<UsageRecord> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<UsageRecord>(line).ok())
+        .collect()
+}
+
+// ── Footer ───────────────────────────────────────────────────────────────────
+
+/// One-line usage footer for a reply
+pub fn format_footer(usage: &TurnUsage) -> String {
+    let input = usage.input_tokens + usage.cache_read_tokens + usage.cache_creation_tokens;
+    let mut parts = vec![format!(
+        "{} in / {} out",
+        format_tokens(input),
+        format_tokens(usage.output_tokens)
+    )];
+    if let Some(cost) = usage.cost_usd {
+        parts.push(if cost < 1.0 {
+            format!("${:.4}", cost)
+        } else {
+            format!("${:.2}", cost)
+        });
+    }
+    if let Some(ms) = usage.duration_ms {
+        parts.push(format!("{:.1}s", ms as f64 / 1000.0));
+    }
+    if let Some(ref model) = usage.model {
+        parts.push(model.clone());
+    }
+    format!("📊 {}", parts.join(" · "))
+}
+
+fn format_tokens(count: u64) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{:.1}M", count as f64 / 1_000_000.0),
+    }
+}
+
+// ── Report ───────────────────────────────────────────────────────────────────
+
+/// Daily usage of all channels (or one agent / channel) over the last `days` days,
+/// read from the usage logs on disk so stopped channels are included.
+pub fn build_report(
+    agent_id: Option<&str>,
+    channel_id: Option<&str>,
+    days: Option<u32>,
+) -> UsageReport {
+    let days = days.unwrap_or(DEFAULT_REPORT_DAYS).max(1);
+    let to = Local::now().date_naive();
+    let from = to - chrono::Duration::days(i64::from(days) - 1);
+
+    let mut logs = Vec::new();
+    let agent_dirs = std::fs::read_dir(health::agents_data_dir())
+        .into_iter()
+        .flatten()
+        .flatten();
+    for agent_dir in agent_dirs {
+        let agent = agent_dir.file_name().to_string_lossy().to_string();
+        if agent_id.is_some_and(|id| id != agent) {
+            continue;
+        }
+        let channel_dirs = std::fs::read_dir(agent_dir.path().join("channels"))
+            .into_iter()
+            .flatten()
+            .flatten();
+        for channel_dir in channel_dirs {
+            let channel = channel_dir.file_name().to_string_lossy().to_string();
+            if channel_id.is_some_and(|id| id != channel) {
+                continue;
+            }
+            let path = channel_dir.path().join("usage.jsonl");
+            if path.exists() {
+                logs.push((agent.clone(), channel, read_usage_log(&path)));
+            }
+        }
+    }
+    summarize(&logs, from, to)
+}
+
+/// (day, agent, channel, sender)
+type UserDayKey<'a> = (NaiveDate, &'a str, &'a str, &'a str);
+
+/// Group records by day and channel / user, keeping days in `from..=to`
+fn summarize(
+    logs: &[(String, String, Vec<UsageRecord>)],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> UsageReport {
+    let mut channels: BTreeMap<(NaiveDate, &str, &str), UsageTotals> = BTreeMap::new();
+    let mut users: BTreeMap<UserDayKey, (Option<String>, UsageTotals)> = BTreeMap::new();
+    for (agent, channel, records) in logs {
+        for record in records {
+            let Some(day) = record.day().filter(|d| *d >= from && *d <= to) else {
+                continue;
+            };
+            channels
+                .entry((day, agent, channel))
+                .or_default()
+                .add(&record.usage, &record.at);
+            let user = users
+                .entry((day, agent, channel, &record.sender_id))
+                .or_default();
+            if record.sender_name.is_some() {
+                user.0 = record.sender_name.clone();
+            }
+            user.1.add(&record.usage, &record.at);
+        }
+    }
+
+    UsageReport {
+        from: from.to_string(),
+        to: to.to_string(),
+        channels: channels
+            .into_iter()
+            .map(|((day, agent, channel), totals)| ChannelDailyUsage {
+                date: day.to_string(),
+                agent_id: agent.to_string(),
+                channel_id: channel.to_string(),
+                totals,
+            })
+            .collect(),
+        users: users
+            .into_iter()
+            .map(
+                |((day, agent, channel, sender), (sender_name, totals))| UserDailyUsage {
+                    date: day.to_string(),
+                    agent_id: agent.to_string(),
+                    channel_id: channel.to_string(),
+                    sender_id: sender.to_string(),
+                    sender_name,
+                    totals,
+                },
+            )
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn record(day: u32, sender: &str, input: u64, cost: f64) -> UsageRecord {
+        let at = Local.with_ymd_and_hms(2026, 5, day, 12, 0, 0).unwrap();
+        UsageRecord {
+            at: at.to_rfc3339(),
+            session_key: format!("im:telegram:private:{}", sender),
+            sender_id: sender.to_string(),
+            sender_name: Some(format!("User {}", sender)),
+            usage: TurnUsage {
+                input_tokens: input,
+                output_tokens: 100,
+                cost_usd: Some(cost),
+                duration_ms: Some(2000),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_footer_format() {
+        let usage = TurnUsage {
+            input_tokens: 400,
+            output_tokens: 350,
+            cache_read_tokens: 12_000,
+            cache_creation_tokens: 0,
+            model: Some("claude-sonnet-4".to_string()),
+            duration_ms: Some(8_420),
+            cost_usd: Some(0.01234),
+        };
+        assert_eq!(
+            format_footer(&usage),
+            "📊 12.4k in / 350 out · $0.0123 · 8.4s · claude-sonnet-4"
+        );
+        assert_eq!(
+            format_footer(&TurnUsage {
+                output_tokens: 2_500_000,
+                ..Default::default()
+            }),
+            "📊 0 in / 2.5M out"
+        );
+    }
+
+    #[test]
+    fn test_record_round_trip_and_daily_summary() {
+        let dir = std::env::temp_dir().join(format!("soagents-usage-{}", std::process::id()));
+        let path = dir.join("usage.jsonl");
+        let _ = std::fs::remove_file(&path);
+        record_turn(&path, &record(1, "u1", 1000, 0.01));
+        record_turn(&path, &record(1, "u2", 500, 0.02));
+        record_turn(&path, &record(2, "u1", 200, 0.03));
+        record_turn(&path, &record(9, "u1", 200, 0.03));
+        let records = read_usage_log(&path);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].usage.input_tokens, 1000);
+
+        let logs = vec![("a1".to_string(), "c1".to_string(), records)];
+        let from = NaiveDate::from_ymd_opt(2026, 5, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2026, 5, 2).unwrap();
+        let report = summarize(&logs, from, to);
+
+        assert_eq!(report.channels.len(), 2);
+        assert_eq!(report.channels[0].date, "2026-05-01");
+        assert_eq!(report.channels[0].totals.turns, 2);
+        assert_eq!(report.channels[0].totals.input_tokens, 1500);
+        assert!((report.channels[0].totals.cost_usd - 0.03).abs() < 1e-9);
+        assert_eq!(report.channels[1].totals.turns, 1);
+
+        assert_eq!(report.users.len(), 3);
+        assert_eq!(report.users[0].sender_id, "u1");
+        assert_eq!(report.users[0].sender_name.as_deref(), Some("User u1"));
+        assert_eq!(report.users[1].sender_id, "u2");
+        assert_eq!(report.users[1].totals.duration_ms, 2000);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -46,6 +46,10 @@ pub(super) fn ext_to_mime(filename: &str) -> &str {
         "ogg" => "audio/ogg",
         "m4a" => "audio/m4a",
         "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" => "text/html",
+        "jsonl" => "application/x-ndjson",
         _ => "application/octet-stream",
     }
 }
@@ -64,3 +68,56 @@ pub(super) fn sanitize_filename(name: &str) -> String {
         cleaned.to_string()
     }
 }
+
+/// Hand-built `multipart/form-data` body (reqwest is built without its multipart feature).
+pub(super) struct MultipartForm {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartForm {
+    pub(super) fn new() -> Self {
+        Self {
+            boundary: format!("----soagents{}", uuid::Uuid::new_v4().simple()),
+            body: Vec::new(),
+        }
+    }
+
+    /// Append a plain text field.
+    pub(super) fn text(mut self, name: &str, value: &str) -> Self {
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                self.boundary, name, value
+            )
+            .as_bytes(),
+        );
+        self
+    }
+
+    /// Append a file field.
+    pub(super) fn file(mut self, name: &str, filename: &str, mime: &str, data: &[u8]) -> Self {
+        let filename = sanitize_filename(filename).replace('"', "_");
+        self.body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                 Content-Type: {}\r\n\r\n",
+                self.boundary, name, filename, mime
+            )
+            .as_bytes(),
+        );
+        self.body.extend_from_slice(data);
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Close the form. Returns `(content_type, body)`.
+    pub(super) fn finish(mut self) -> (String, Vec<u8>) {
+        self.body
+            .extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        (
+            format!("multipart/form-data; boundary={}", self.boundary),
+            self.body,
+        )
+    }
+}
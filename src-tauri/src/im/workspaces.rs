@@ -0,0 +1,175 @@
+// Per-chat workspace selection — lets a chat run in another project directory.
+// Admins pick one with `/workspace`, from the allowlist in the agent config; the choice
+// is kept per session key and survives restarts. SessionRouter consults it before
+// starting a Sidecar, so a switched chat gets a fresh Sidecar in the new directory.
+// Persist path convention: ~/.soagents/agents/{agentId}/channels/{channelId}/chat_workspaces.json
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ulog_info;
+
+pub struct ChatWorkspaces {
+    /// session_key → workspace path
+    selected: HashMap<String, String>,
+    persist_path: Option<PathBuf>,
+}
+
+impl ChatWorkspaces {
+    /// Load the choices from `persist_path` (None = in-memory only)
+    pub fn load(persist_path: Option<PathBuf>) -> Self {
+        let selected = persist_path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str::<HashMap<String, String>>(&s).ok())
+            .unwrap_or_default();
+        Self {
+            selected,
+            persist_path,
+        }
+    }
+
+    /// Workspace chosen for the session (None = the agent's default workspace)
+    pub fn get(&self, session_key: &str) -> Option<&str> {
+        self.selected.get(session_key).map(String::as_str)
+    }
+
+    /// Workspace chosen for the session, if it is still allowed
+    pub fn get_allowed(&self, session_key: &str, allowlist: &[String]) -> Option<&str> {
+        self.get(session_key)
+            .filter(|path| allowlist.iter().any(|a| same_path(a, path)))
+    }
+
+    /// Set (or with None clear) the session's workspace. Returns true if it changed.
+    pub fn set(&mut self, session_key: &str, workspace: Option<&str>) -> bool {
+        let changed = match workspace {
+            Some(path) => {
+                self.selected.insert(session_key.to_string(), path.to_string()).as_deref()
+                    != Some(path)
+            }
+            None => self.selected.remove(session_key).is_some(),
+        };
+        if changed {
+            ulog_info!(
+                "[im] Session {} now uses workspace {}",
+                session_key,
+                workspace.unwrap_or("(default)")
+            );
+            if let Some(path) = &self.persist_path {
+                save_selected(path, &self.selected);
+            }
+        }
+        changed
+    }
+}
+
+fn save_selected(path: &Path, selected: &HashMap<String, String>) {
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let tmp = path.with_extension("json.tmp");
+    if let Ok(s) = serde_json::to_string_pretty(selected) {
+        if std::fs::write(&tmp, &s).is_ok() {
+            let _ = std::fs::rename(&tmp, path);
+        }
+    }
+}
+
+fn same_path(a: &str, b: &str) -> bool {
+    a.trim_end_matches(['/', '\\']) == b.trim_end_matches(['/', '\\'])
+}
+
+/// Short name of a workspace for listings (its last path component)
+pub fn display_name(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    trimmed
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(trimmed)
+}
+
+/// Allowlist entry picked by `arg`: a 1-based index, the full path, or the
+/// directory name (case-insensitive).
+pub fn resolve_choice<'a>(allowlist: &'a [String], arg: &str) -> Result<&'a str, String> {
+    let arg = arg.trim();
+    if let Ok(n) = arg.parse::<usize>() {
+        return n
+            .checked_sub(1)
+            .and_then(|i| allowlist.get(i))
+            .map(String::as_str)
+            .ok_or_else(|| format!("No workspace #{}", n));
+    }
+    allowlist
+        .iter()
+        .find(|p| same_path(p, arg))
+        .or_else(|| {
+            allowlist
+                .iter()
+                .find(|p| display_name(p).eq_ignore_ascii_case(arg))
+        })
+        .map(String::as_str)
+        .ok_or_else(|| format!("Unknown workspace: {}", arg))
+}
+
+/// Numbered allowlist for `/workspace` replies, marking the current one
+pub fn format_list(allowlist: &[String], current: Option<&str>) -> String {
+    allowlist
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let marker = if current.is_some_and(|c| same_path(c, path)) {
+                " (current)"
+            } else {
+                ""
+            };
+            format!("{}. {} — {}{}", i + 1, display_name(path), path, marker)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist() -> Vec<String> {
+        vec![
+            "/home/me/projects/api".to_string(),
+            "/home/me/projects/Web/".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_resolve_choice() {
+        let list = allowlist();
+        assert_eq!(resolve_choice(&list, "1"), Ok("/home/me/projects/api"));
+        assert_eq!(resolve_choice(&list, "web"), Ok("/home/me/projects/Web/"));
+        assert_eq!(
+            resolve_choice(&list, "/home/me/projects/Web"),
+            Ok("/home/me/projects/Web/")
+        );
+        assert!(resolve_choice(&list, "0").is_err());
+        assert!(resolve_choice(&list, "3").is_err());
+        assert!(resolve_choice(&list, "/etc").is_err());
+    }
+
+    #[test]
+    fn test_selection_persists_and_respects_allowlist() {
+        let dir = std::env::temp_dir().join(format!("soagents-ws-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("chat_workspaces.json");
+        let key = "im:a:telegram:private:42";
+
+        let mut ws = ChatWorkspaces::load(Some(path.clone()));
+        assert!(ws.set(key, Some("/home/me/projects/api")));
+        assert!(!ws.set(key, Some("/home/me/projects/api")));
+
+        let ws = ChatWorkspaces::load(Some(path));
+        assert_eq!(ws.get(key), Some("/home/me/projects/api"));
+        assert_eq!(ws.get_allowed(key, &allowlist()), Some("/home/me/projects/api"));
+        // Removed from the allowlist → back to the default workspace
+        assert_eq!(ws.get_allowed(key, &allowlist()[1..]), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -3,6 +3,7 @@
 
 mod sidecar;
 mod commands;
+mod config_service;
 mod proxy;
 mod proxy_config;
 mod sse_proxy;
@@ -11,6 +12,7 @@ mod scheduled_task;
 mod local_http;
 mod tray;
 mod im;
+mod metrics;
 mod openclaw;
 mod process_cmd;
 mod system_binary;
@@ -101,12 +103,29 @@ pub fn run() {
             im::cmd_all_agent_channels_status,
             im::cmd_update_agent_channel_config,
             im::cmd_im_reset_session,
+            im::cmd_im_set_chat_workspace,
+            im::cmd_im_list_identities,
+            im::cmd_im_link_identity,
+            im::cmd_im_unlink_identity,
+            im::cmd_im_takeover_start,
+            im::cmd_im_takeover_send,
+            im::cmd_im_takeover_hand_back,
+            im::cmd_im_takeover_list,
+            im::cmd_im_review_list,
+            im::cmd_im_review_approve,
+            im::cmd_im_review_edit_and_send,
+            im::cmd_im_review_reject,
+            im::cmd_im_export_transcript,
+            im::cmd_im_send_message,
+            im::cmd_im_list_known_chats,
+            im::cmd_im_usage_report,
             im::cmd_im_verify_token,
             im::cmd_im_verify_feishu_credentials,
             im::cmd_im_verify_dingtalk_credentials,
             im::cmd_im_approve_group,
             im::cmd_im_reject_group,
             im::cmd_im_remove_group,
+            metrics::cmd_metrics_snapshot,
             commands::cmd_heartbeat_sync,
             commands::cmd_heartbeat_resume,
             commands::cmd_heartbeat_status,
@@ -150,9 +169,18 @@ pub fn run() {
                 log::error!("[App] Failed to setup system tray: {}", e);
             }
 
+            // Optional localhost Prometheus endpoint (config.json → metricsEndpoint)
+            metrics::spawn_metrics_endpoint();
+
+            // Proactive IM send endpoint for Sidecars (bound before any Sidecar starts)
+            im::proactive::spawn_send_endpoint(app.state::<im::ImManagerState>().inner().clone());
+
             // Auto-start enabled IM agent channels (4s delay)
             im::schedule_agent_auto_start(app.handle().clone());
 
+            // Hot-reload ~/.soagents/config.json (channels, heartbeat, proxy)
+            config_service::spawn_config_watcher(app.handle().clone());
+
             // Start heartbeat runners for enabled agents
             let hb_app = app.handle().clone();
             let hb_state = heartbeat_setup;
@@ -0,0 +1,439 @@
+//! In-process operational metrics — counters, gauges and latency histograms
+//! collected by the IM channels, sidecar manager and scheduled task runner.
+//!
+//! Exposed two ways:
+//!   1. `cmd_metrics_snapshot` Tauri command (structured JSON for the desktop UI)
+//!   2. Optional localhost `/metrics` endpoint in Prometheus text format,
+//!      enabled in `~/.soagents/config.json`:
+//!
+//! ```json
+//! { "metricsEndpoint": { "enabled": true, "port": 9464 } }
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::config_service;
+use crate::{ulog_info, ulog_warn};
+
+// ── Metric names ──
+
+pub const IM_MESSAGES_RECEIVED: &str = "soagents_im_messages_received_total";
+pub const IM_MESSAGES_SENT: &str = "soagents_im_messages_sent_total";
+pub const IM_FIRST_TOKEN_SECONDS: &str = "soagents_im_first_token_seconds";
+pub const IM_TURN_SECONDS: &str = "soagents_im_turn_duration_seconds";
+pub const IM_ERRORS: &str = "soagents_im_errors_total";
+pub const IM_PLATFORM_ERRORS: &str = "soagents_im_platform_api_errors_total";
+pub const IM_INBOUND_QUEUE_DEPTH: &str = "soagents_im_inbound_queue_depth";
+pub const IM_OUTBOUND_PENDING: &str = "soagents_im_outbound_pending";
+pub const IM_CONCURRENCY_WAIT_SECONDS: &str = "soagents_im_concurrency_wait_seconds";
+pub const IM_LISTEN_RESTARTS: &str = "soagents_im_listen_restarts_total";
+pub const SIDECAR_COLD_STARTS: &str = "soagents_sidecar_cold_starts_total";
+pub const SIDECAR_COLD_START_SECONDS: &str = "soagents_sidecar_cold_start_seconds";
+pub const SIDECAR_START_FAILURES: &str = "soagents_sidecar_start_failures_total";
+pub const SCHEDULED_TASK_RUNS: &str = "soagents_scheduled_task_runs_total";
+pub const SCHEDULED_TASK_SECONDS: &str = "soagents_scheduled_task_duration_seconds";
+
+/// HELP text per metric (Prometheus exposition)
+fn help_text(name: &str) -> &'static str {
+    match name {
+        IM_MESSAGES_RECEIVED => "Inbound IM messages accepted for processing",
+        IM_MESSAGES_SENT => "Final IM replies delivered to the platform",
+        IM_FIRST_TOKEN_SECONDS => "Time from turn start to the first streamed text",
+        IM_TURN_SECONDS => "End-to-end IM turn latency",
+        IM_ERRORS => "IM turn errors by type",
+        IM_PLATFORM_ERRORS => "Failed platform API calls by error kind",
+        IM_INBOUND_QUEUE_DEPTH => "Messages waiting in the inbound queue",
+        IM_OUTBOUND_PENDING => "Replies waiting in the outbound retry queue",
+        IM_CONCURRENCY_WAIT_SECONDS => "Time spent waiting for a concurrency slot",
+        IM_LISTEN_RESTARTS => "Listen loop restarts after an unexpected exit or panic",
+        SIDECAR_COLD_STARTS => "Sidecar processes started",
+        SIDECAR_COLD_START_SECONDS => "Time from spawn until a sidecar is healthy",
+        SIDECAR_START_FAILURES => "Sidecar processes that failed to start",
+        SCHEDULED_TASK_RUNS => "Scheduled task runs by status",
+        SCHEDULED_TASK_SECONDS => "Scheduled task run duration",
+        _ => "",
+    }
+}
+
+/// Upper bounds (seconds) shared by all latency histograms
+const LATENCY_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+];
+
+const DEFAULT_METRICS_PORT: u16 = 9464;
+
+/// Label set, e.g. `[("channel", "tg-main".into())]`
+pub type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// Non-cumulative count per LATENCY_BUCKETS entry (+Inf is `count`)
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS.len()],
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|b| value <= *b) {
+            self.buckets[idx] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// (upper bound, cumulative count) pairs, excluding +Inf
+    fn cumulative(&self) -> Vec<(f64, u64)> {
+        let mut acc = 0;
+        LATENCY_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(le, n)| {
+                acc += n;
+                (*le, acc)
+            })
+            .collect()
+    }
+}
+
+type SeriesKey = (&'static str, Labels);
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<SeriesKey, f64>,
+    gauges: BTreeMap<SeriesKey, f64>,
+    histograms: BTreeMap<SeriesKey, Histogram>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
+}
+
+fn with_registry<T>(f: impl FnOnce(&mut Registry) -> T) -> T {
+    let mut guard = registry().lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut guard)
+}
+
+fn series_key(name: &'static str, labels: &[(&'static str, String)]) -> SeriesKey {
+    let mut labels = labels.to_vec();
+    labels.sort();
+    (name, labels)
+}
+
+// ── Recording ──
+
+/// Increment a counter by one
+pub fn inc(name: &'static str, labels: &[(&'static str, String)]) {
+    let key = series_key(name, labels);
+    with_registry(|r| *r.counters.entry(key).or_insert(0.0) += 1.0);
+}
+
+/// Set a gauge to an absolute value
+pub fn set_gauge(name: &'static str, labels: &[(&'static str, String)], value: f64) {
+    let key = series_key(name, labels);
+    with_registry(|r| {
+        r.gauges.insert(key, value);
+    });
+}
+
+/// Record one observation (seconds) in a latency histogram
+pub fn observe(name: &'static str, labels: &[(&'static str, String)], seconds: f64) {
+    let key = series_key(name, labels);
+    with_registry(|r| {
+        r.histograms
+            .entry(key)
+            .or_insert_with(Histogram::new)
+            .observe(seconds)
+    });
+}
+
+// ── Snapshot (Tauri command) ──
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub count: u64,
+    pub sum: f64,
+    /// (upper bound in seconds, cumulative count)
+    pub buckets: Vec<(f64, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub counters: Vec<MetricSample>,
+    pub gauges: Vec<MetricSample>,
+    pub histograms: Vec<HistogramSample>,
+}
+
+fn label_map(labels: &Labels) -> BTreeMap<String, String> {
+    labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect()
+}
+
+pub fn snapshot() -> MetricsSnapshot {
+    with_registry(|r| {
+        let samples = |map: &BTreeMap<SeriesKey, f64>| {
+            map.iter()
+                .map(|((name, labels), value)| MetricSample {
+                    name: name.to_string(),
+                    labels: label_map(labels),
+                    value: *value,
+                })
+                .collect()
+        };
+        MetricsSnapshot {
+            counters: samples(&r.counters),
+            gauges: samples(&r.gauges),
+            histograms: r
+                .histograms
+                .iter()
+                .map(|((name, labels), h)| HistogramSample {
+                    name: name.to_string(),
+                    labels: label_map(labels),
+                    count: h.count,
+                    sum: h.sum,
+                    buckets: h.cumulative(),
+                })
+                .collect(),
+        }
+    })
+}
+
+// ── Prometheus text format ──
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(labels: &[(&'static str, String)], extra: Option<(&str, &str)>) -> String {
+    let mut parts: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+        .collect();
+    if let Some((k, v)) = extra {
+        parts.push(format!("{}=\"{}\"", k, v));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", parts.join(","))
+    }
+}
+
+fn write_header(out: &mut String, last: &mut Option<&'static str>, name: &'static str, kind: &str) {
+    if *last != Some(name) {
+        out.push_str(&format!("# HELP {} {}\n", name, help_text(name)));
+        out.push_str(&format!("# TYPE {} {}\n", name, kind));
+        *last = Some(name);
+    }
+}
+
+/// Render every series in Prometheus text exposition format (version 0.0.4)
+pub fn render_prometheus() -> String {
+    with_registry(|r| {
+        let mut out = String::new();
+        let mut last = None;
+        for ((name, labels), value) in &r.counters {
+            write_header(&mut out, &mut last, name, "counter");
+            out.push_str(&format!(
+                "{}{} {}\n",
+                name,
+                format_labels(labels, None),
+                value
+            ));
+        }
+        for ((name, labels), value) in &r.gauges {
+            write_header(&mut out, &mut last, name, "gauge");
+            out.push_str(&format!(
+                "{}{} {}\n",
+                name,
+                format_labels(labels, None),
+                value
+            ));
+        }
+        for ((name, labels), h) in &r.histograms {
+            write_header(&mut out, &mut last, name, "histogram");
+            for (le, count) in h.cumulative() {
+                let le = le.to_string();
+                out.push_str(&format!(
+                    "{}_bucket{} {}\n",
+                    name,
+                    format_labels(labels, Some(("le", &le))),
+                    count
+                ));
+            }
+            out.push_str(&format!(
+                "{}_bucket{} {}\n",
+                name,
+                format_labels(labels, Some(("le", "+Inf"))),
+                h.count
+            ));
+            out.push_str(&format!(
+                "{}_sum{} {}\n",
+                name,
+                format_labels(labels, None),
+                h.sum
+            ));
+            out.push_str(&format!(
+                "{}_count{} {}\n",
+                name,
+                format_labels(labels, None),
+                h.count
+            ));
+        }
+        out
+    })
+}
+
+// ── Localhost /metrics endpoint ──
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsEndpointSettings {
+    pub enabled: bool,
+    pub port: Option<u16>,
+}
+
+/// Endpoint settings from the config snapshot (None unless enabled)
+fn read_endpoint_settings() -> Option<MetricsEndpointSettings> {
+    config_service::current()
+        .metrics_endpoint
+        .clone()
+        .filter(|m| m.enabled)
+}
+
+/// Start the `/metrics` endpoint on 127.0.0.1 when enabled in config
+pub fn spawn_metrics_endpoint() {
+    let Some(settings) = read_endpoint_settings() else {
+        return;
+    };
+    let port = settings.port.unwrap_or(DEFAULT_METRICS_PORT);
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(l) => l,
+            Err(e) => {
+                ulog_warn!("[metrics] Failed to bind 127.0.0.1:{}: {}", port, e);
+                return;
+            }
+        };
+        ulog_info!(
+            "[metrics] Serving Prometheus metrics on http://127.0.0.1:{}/metrics",
+            port
+        );
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(serve_connection(stream));
+        }
+    });
+}
+
+async fn serve_connection(mut stream: tokio::net::TcpStream) {
+    // Request head only — the endpoint takes no body
+    let mut buf = [0u8; 2048];
+    let n = match tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+        .await
+    {
+        Ok(Ok(n)) => n,
+        _ => return,
+    };
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let (status, content_type, body) =
+        if method == "GET" && (path == "/metrics" || path.starts_with("/metrics?")) {
+            (
+                "200 OK",
+                "text/plain; version=0.0.4; charset=utf-8",
+                render_prometheus(),
+            )
+        } else {
+            (
+                "404 Not Found",
+                "text/plain; charset=utf-8",
+                "Not Found\n".to_string(),
+            )
+        };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+// ── Tauri command ──
+
+#[tauri::command]
+pub fn cmd_metrics_snapshot() -> MetricsSnapshot {
+    snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut h = Histogram::new();
+        h.observe(0.07);
+        h.observe(0.3);
+        h.observe(7200.0);
+        let buckets = h.cumulative();
+        assert_eq!(buckets[0], (0.05, 0));
+        assert_eq!(buckets[1], (0.1, 1));
+        assert_eq!(buckets[3], (0.5, 2));
+        assert_eq!(buckets.last().unwrap().1, 2);
+        assert_eq!(h.count, 3);
+    }
+
+    #[test]
+    fn prometheus_output_format() {
+        let labels: Labels = vec![("channel", "test-render-\"x\"".to_string())];
+        inc(IM_MESSAGES_RECEIVED, &labels);
+        inc(IM_MESSAGES_RECEIVED, &labels);
+        observe(IM_TURN_SECONDS, &labels, 1.5);
+        let text = render_prometheus();
+        assert!(text.contains("# TYPE soagents_im_messages_received_total counter"));
+        assert!(text
+            .contains("soagents_im_messages_received_total{channel=\"test-render-\\\"x\\\"\"} 2"));
+        assert!(text.contains(
+            "soagents_im_turn_duration_seconds_bucket{channel=\"test-render-\\\"x\\\"\",le=\"+Inf\"} 1"
+        ));
+    }
+}
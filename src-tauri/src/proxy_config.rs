@@ -12,11 +12,10 @@
 //! `cmd.env("HTTP_PROXY", ...)` / `cmd.env_remove(...)` is forbidden — it
 //! silently drifts when policy changes.
 //!
-//! Config is read from `~/.soagents/config.json` and edited via
-//! Settings → 通用 → 网络代理.
+//! Config is read from `~/.soagents/config.json` (via `config_service`, which
+//! also hot-reloads it) and edited via Settings → 通用 → 网络代理.
 
 use serde::Deserialize;
-use std::fs;
 use std::process::Command;
 
 const DEFAULT_PROXY_PROTOCOL: &str = "http";
@@ -47,7 +46,7 @@ pub const LOCALHOST_NO_PROXY: &str =
 ///   }
 /// }
 /// ```
-#[derive(Debug, Deserialize, Default, Clone)]
+#[derive(Debug, Deserialize, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ProxySettings {
     pub enabled: bool,
@@ -59,47 +58,13 @@ pub struct ProxySettings {
     pub port: Option<u16>,
 }
 
-#[derive(Debug, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
-struct PartialAppConfig {
-    proxy_settings: Option<ProxySettings>,
-}
-
-/// Read proxy settings from `~/.soagents/config.json`.
+/// Read proxy settings from `~/.soagents/config.json` (cached by `config_service`).
 /// Returns `Some` only when the user has *explicitly enabled* proxy.
 pub fn read_proxy_settings() -> Option<ProxySettings> {
-    let home = dirs::home_dir()?;
-    let config_path = home.join(".soagents").join("config.json");
-
-    let content = match fs::read_to_string(&config_path) {
-        Ok(c) => c,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
-        Err(e) => {
-            log::warn!(
-                "[proxy_config] Failed to read {:?}: {}. Check file permissions.",
-                config_path,
-                e
-            );
-            return None;
-        }
-    };
-
-    // Strip UTF-8 BOM if present (Windows editors sometimes inject one).
-    let content = content.strip_prefix('\u{FEFF}').unwrap_or(&content);
-
-    let config: PartialAppConfig = match serde_json::from_str(content) {
-        Ok(c) => c,
-        Err(e) => {
-            log::error!(
-                "[proxy_config] Invalid JSON in {:?}: {}. Check the config format.",
-                config_path,
-                e
-            );
-            return None;
-        }
-    };
-
-    config.proxy_settings.filter(|p| p.enabled)
+    crate::config_service::current()
+        .proxy_settings
+        .clone()
+        .filter(|p| p.enabled)
 }
 
 /// Build `protocol://host:port` from settings, validating protocol + port.
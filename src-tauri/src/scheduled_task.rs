@@ -44,6 +44,29 @@ impl Default for RunMode {
     }
 }
 
+/// IM chat that receives a task's results (through a running agent channel)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryTarget {
+    pub agent_id: String,
+    pub channel_id: String,
+    pub chat_id: String,
+    /// Send the result as a Markdown file instead of a text message
+    #[serde(default)]
+    pub as_attachment: bool,
+}
+
+/// Which runs get delivered to `deliver_to`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliverWhen {
+    #[default]
+    Always,
+    /// Only when the result (text or error) differs from the previous run
+    OnChange,
+    OnError,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EndConditions {
@@ -74,6 +97,9 @@ pub struct TaskState {
     pub last_status: Option<String>,
     pub consecutive_errors: u32,
     pub running_at_ms: Option<i64>,
+    /// Digest of the last run's result (for DeliverWhen::OnChange)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_result_digest: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +130,10 @@ pub struct ScheduledTask {
     pub execution_count: u32,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub timeout_minutes: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliver_to: Vec<DeliveryTarget>,
+    #[serde(default)]
+    pub deliver_when: DeliverWhen,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -126,6 +156,10 @@ pub struct ScheduledTaskInput {
     pub end_conditions: Option<EndConditions>,
     #[serde(default)]
     pub timeout_minutes: Option<u32>,
+    #[serde(default)]
+    pub deliver_to: Option<Vec<DeliveryTarget>>,
+    #[serde(default)]
+    pub deliver_when: Option<DeliverWhen>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -234,6 +268,7 @@ impl ScheduledTaskManager {
                 last_status: None,
                 consecutive_errors: 0,
                 running_at_ms: None,
+                last_result_digest: None,
             },
             provider_env: input.provider_env,
             model: input.model,
@@ -245,6 +280,8 @@ impl ScheduledTaskManager {
             persistent_session_id: None,
             execution_count: 0,
             timeout_minutes: input.timeout_minutes,
+            deliver_to: input.deliver_to.unwrap_or_default(),
+            deliver_when: input.deliver_when.unwrap_or_default(),
         };
 
         self.tasks.insert(id, task.clone());
@@ -270,6 +307,10 @@ impl ScheduledTaskManager {
         task.run_mode = input.run_mode.unwrap_or(task.run_mode);
         task.end_conditions = input.end_conditions;
         task.timeout_minutes = input.timeout_minutes;
+        if let Some(targets) = input.deliver_to {
+            task.deliver_to = targets;
+        }
+        task.deliver_when = input.deliver_when.unwrap_or(task.deliver_when);
 
         if input.enabled {
             task.state.next_run_at_ms = calculate_next_run_time(&input.schedule, task.state.last_run_at_ms)?;
@@ -844,6 +885,23 @@ async fn execute_task(state: ScheduledTaskState, task_id: String, trigger: Strin
         Err(e) => ("error".to_string(), Some(e.clone()), None, false),
     };
 
+    // Result text for IM delivery (final assistant message, or the error)
+    let result_text = match &result {
+        Ok((sid, _)) => crate::im::transcript::last_assistant_text(sid),
+        Err(e) => Some(e.clone()),
+    };
+    let result_digest = format!("{}:{:016x}", status, fnv1a64(result_text.as_deref().unwrap_or("")));
+    let mut pending_delivery: Option<ScheduledTask> = None;
+
+    let metric_labels: crate::metrics::Labels =
+        vec![("status", status.clone()), ("trigger", trigger.clone())];
+    crate::metrics::inc(crate::metrics::SCHEDULED_TASK_RUNS, &metric_labels);
+    crate::metrics::observe(
+        crate::metrics::SCHEDULED_TASK_SECONDS,
+        &metric_labels,
+        duration as f64 / 1000.0,
+    );
+
     {
         let mut mgr = state.write().await;
 
@@ -865,6 +923,19 @@ async fn execute_task(state: ScheduledTaskState, task_id: String, trigger: Strin
 
         // Update task state
         if let Some(task) = mgr.get_task_mut(&task_id) {
+            if !task.deliver_to.is_empty() {
+                let deliver = match task.deliver_when {
+                    DeliverWhen::Always => true,
+                    DeliverWhen::OnError => status == "error",
+                    DeliverWhen::OnChange => {
+                        task.state.last_result_digest.as_deref() != Some(result_digest.as_str())
+                    }
+                };
+                if deliver {
+                    pending_delivery = Some(task.clone());
+                }
+            }
+            task.state.last_result_digest = Some(result_digest);
             task.state.last_run_at_ms = Some(finished_at);
             task.state.last_status = Some(status.clone());
             task.state.running_at_ms = None;
@@ -954,6 +1025,76 @@ async fn execute_task(state: ScheduledTaskState, task_id: String, trigger: Strin
             }));
         }
     }
+
+    if let Some(task) = pending_delivery {
+        deliver_result(&app_handle, &task, &status, result_text.as_deref()).await;
+    }
+}
+
+// ── IM Delivery ──
+
+/// Send a finished run's result to the task's `deliver_to` chats.
+/// Channels that aren't running are skipped (logged) — the desktop still has the run record.
+async fn deliver_result(app_handle: &AppHandle, task: &ScheduledTask, status: &str, text: Option<&str>) {
+    let body = if status == "error" {
+        format!("⚠️ Scheduled task \"{}\" failed:\n{}", task.name, text.unwrap_or("unknown error"))
+    } else {
+        format!("📋 {}\n\n{}", task.name, text.unwrap_or("(no output)"))
+    };
+
+    let im_state: crate::im::ImManagerState = (*app_handle.state::<crate::im::ImManagerState>()).clone();
+    for target in &task.deliver_to {
+        let outlet = im_state.lock().await.outlet(&target.agent_id, &target.channel_id);
+        let Some(outlet) = outlet else {
+            log::warn!(
+                "[scheduled_task] Channel {}/{} not running, skipped delivery of task '{}'",
+                target.agent_id, target.channel_id, task.name
+            );
+            continue;
+        };
+
+        if target.as_attachment && status != "error" {
+            let filename = format!(
+                "{}-{}.md",
+                attachment_stem(&task.name),
+                Utc::now().format("%Y%m%d-%H%M")
+            );
+            let data = text.unwrap_or("").as_bytes().to_vec();
+            match outlet.send_file(&target.chat_id, &filename, data, Some(&format!("📋 {}", task.name))).await {
+                Ok(()) => continue,
+                Err(e) => log::warn!("[scheduled_task] Attachment delivery failed, sending text: {}", e),
+            }
+        }
+
+        if let Some(e) = outlet.send_text(&target.chat_id, &body).await.failure() {
+            log::warn!(
+                "[scheduled_task] Delivery of task '{}' result to {}/{} chat {} failed: {}",
+                task.name, target.agent_id, target.channel_id, target.chat_id, e
+            );
+            continue;
+        }
+        log::info!(
+            "[scheduled_task] Delivered task '{}' result to {}/{} chat {}",
+            task.name, target.agent_id, target.channel_id, target.chat_id
+        );
+    }
+}
+
+/// File-name-safe version of a task name
+fn attachment_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    let stem = stem.trim_matches('-');
+    if stem.is_empty() { "task-result".to_string() } else { stem.chars().take(60).collect() }
+}
+
+/// FNV-1a 64 — stable across builds (std's DefaultHasher isn't), used for result digests
+fn fnv1a64(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 async fn execute_with_sidecar(
@@ -31,6 +31,17 @@ pub enum SidecarOwner {
     Agent(String),
 }
 
+impl SidecarOwner {
+    /// Owner kind label for metrics
+    fn metric_kind(&self) -> &'static str {
+        match self {
+            Self::Session(_) => "session",
+            Self::BackgroundCompletion(_) => "background",
+            Self::Agent(_) => "agent",
+        }
+    }
+}
+
 /// Kill a child process and its entire process group.
 /// Non-blocking: sends SIGTERM, then spawns a background thread to wait and SIGKILL if needed.
 /// This avoids blocking the caller (important for Drop and UI responsiveness).
@@ -517,6 +528,18 @@ pub fn start_sidecar(
         guard.remove_instance(&sidecar_id);
     }
 
+    // Cold start from here on: label by owner kind for metrics
+    let metric_labels: crate::metrics::Labels = vec![(
+        "kind",
+        if sidecar_id == GLOBAL_SIDECAR_ID {
+            "global"
+        } else {
+            owner.as_ref().map_or("unowned", |o| o.metric_kind())
+        }
+        .to_string(),
+    )];
+    let spawn_started = std::time::Instant::now();
+
     let mut initial_owners = HashSet::new();
     if let Some(o) = owner {
         initial_owners.insert(o);
@@ -558,6 +581,15 @@ pub fn start_sidecar(
         .stderr(Stdio::piped())
         .stdin(Stdio::null());
 
+    // Proactive IM send API (used by the built-in IM tools), bound to this workspace
+    if let Some((url, token)) = agent_dir
+        .as_deref()
+        .and_then(crate::im::proactive::endpoint_env)
+    {
+        cmd.env("SOAGENTS_IM_API_URL", url)
+            .env("SOAGENTS_IM_API_TOKEN", token);
+    }
+
     // Unix: make child a process group leader so kill(-PGID) kills entire tree
     #[cfg(unix)]
     {
@@ -571,7 +603,10 @@ pub fn start_sidecar(
     crate::proxy_config::apply_to_subprocess(&mut cmd);
 
     let mut child = cmd.spawn()
-        .map_err(|e| format!("Failed to spawn bun process: {}", e))?;
+        .map_err(|e| {
+            crate::metrics::inc(crate::metrics::SIDECAR_START_FAILURES, &metric_labels);
+            format!("Failed to spawn bun process: {}", e)
+        })?;
 
     // Capture stdout
     if let Some(stdout) = child.stdout.take() {
@@ -598,6 +633,7 @@ pub fn start_sidecar(
     if let Ok(Some(status)) = child.try_wait() {
         thread::sleep(Duration::from_millis(100)); // let stderr thread capture output
         log::error!("[sidecar] Process exited immediately with status: {:?}", status);
+        crate::metrics::inc(crate::metrics::SIDECAR_START_FAILURES, &metric_labels);
         return Err(format!(
             "Sidecar '{}' exited immediately with status: {:?}",
             sidecar_id, status
@@ -642,10 +678,17 @@ pub fn start_sidecar(
                 inst.healthy = true;
             }
             log::info!("[sidecar] Sidecar '{}' is healthy on port {}", sidecar_id, port);
+            crate::metrics::inc(crate::metrics::SIDECAR_COLD_STARTS, &metric_labels);
+            crate::metrics::observe(
+                crate::metrics::SIDECAR_COLD_START_SECONDS,
+                &metric_labels,
+                spawn_started.elapsed().as_secs_f64(),
+            );
             Ok(port)
         }
         Err(e) => {
             log::error!("[sidecar] Health check failed for '{}': {}", sidecar_id, e);
+            crate::metrics::inc(crate::metrics::SIDECAR_START_FAILURES, &metric_labels);
             let mut guard = manager.lock().map_err(|_| e.clone())?;
             guard.remove_instance(&sidecar_id); // Drop kills process
             Err(e)
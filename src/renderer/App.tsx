@@ -235,11 +235,24 @@ export default function App() {
       refreshSidebarSessions();
     }).then((fn) => { unlistenBgComplete = fn; });
 
+    // Heartbeat content with a desktop delivery target
+    let unlistenHeartbeat: (() => void) | undefined;
+    listen<{ agentId: string; text: string }>('heartbeat:notification', (event) => {
+      if (!mountedRef.current || typeof Notification === 'undefined') return;
+      const show = () => new Notification('SoAgents', { body: event.payload.text.slice(0, 300) });
+      if (Notification.permission === 'granted') {
+        show();
+      } else if (Notification.permission !== 'denied') {
+        void Notification.requestPermission().then((p) => { if (p === 'granted') show(); });
+      }
+    }).then((fn) => { unlistenHeartbeat = fn; });
+
     return () => {
       mountedRef.current = false;
       if (retryTimeoutRef.current) clearTimeout(retryTimeoutRef.current);
       unlistenRestarted?.();
       unlistenBgComplete?.();
+      unlistenHeartbeat?.();
     };
   }, [refreshSidebarSessions]);
 
@@ -204,6 +204,11 @@ export default function ChannelConfigPanel({
               {status.bufferedMessages} 条消息已缓冲（等待 Sidecar 恢复后重放）
             </p>
           )}
+          {(status.pendingDeliveries ?? 0) > 0 && (
+            <p className="text-[12px] text-[var(--ink-tertiary)]">
+              {status.pendingDeliveries} 条回复待投递（发送失败，正在自动重试）
+            </p>
+          )}
         </>
       )}
 
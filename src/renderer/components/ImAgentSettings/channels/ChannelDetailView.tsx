@@ -127,6 +127,7 @@ function PlatformIcon({ type }: { type: string }) {
 const platformName = (type: string) => {
   if (type === 'feishu') return '飞书';
   if (type === 'dingtalk') return '钉钉';
+  if (type === 'local') return '本地测试';
   return 'Telegram';
 };
 
@@ -329,6 +330,11 @@ export default function ChannelDetailView({
           {status.bufferedMessages} 条消息已缓冲（等待 Sidecar 恢复后重放）
         </p>
       )}
+      {status && (status.pendingDeliveries ?? 0) > 0 && (
+        <p className="text-[12px] text-[var(--ink-tertiary)]">
+          {status.pendingDeliveries} 条回复待投递（发送失败，正在自动重试）
+        </p>
+      )}
 
       {/* ══ Section 1: Credentials ══ */}
       <AccordionSection
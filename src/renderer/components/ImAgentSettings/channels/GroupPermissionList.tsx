@@ -12,6 +12,7 @@ interface GroupPermissionListProps {
 const platformLabel = (platform: string) => {
   if (platform === 'telegram') return 'Telegram';
   if (platform === 'dingtalk') return '钉钉';
+  if (platform === 'local') return '本地测试';
   return '飞书';
 };
 
@@ -1,8 +1,9 @@
 import { invoke } from '@tauri-apps/api/core';
-import type { AgentConfig, ChannelConfig } from '../../shared/types/agentConfig';
+import type { AgentConfig, AgentRoute, ChannelConfig, ChannelOverrides } from '../../shared/types/agentConfig';
 import type { AppConfig } from '../../shared/types/config';
 import type { WorkspaceEntry } from '../../shared/types/workspace';
-import type { ImBotStatus } from '../../shared/types/im';
+import type { ChannelKnownChats, ExportedTranscript, ImBotStatus, ImPerson, ImPlatformBuiltin, ImReviewItem, ImTakeoverMessage, ImTakeoverSession, ImUsageReport, TranscriptFormat } from '../../shared/types/im';
+import type { MetricsSnapshot } from '../../shared/types/metrics';
 import type { PermissionMode } from '../../shared/types/permission';
 import { resolveEffectiveConfig } from '../../shared/types/agentConfig';
 import { atomicModifyConfig, loadAppConfig } from './configService';
@@ -381,6 +382,7 @@ export async function startAgentChannel(
       name: agent.name,
       enabled: agent.enabled,
       workspacePath: agent.workspacePath,
+      workspaceAllowlist: agent.workspaceAllowlist || [],
       providerId: effective.providerId,
       model: effective.model,
       providerEnvJson: effective.providerEnvJson,
@@ -405,11 +407,24 @@ export async function startAgentChannel(
       dingtalkClientSecret: channel.dingtalkClientSecret,
       dingtalkUseAiCard: channel.dingtalkUseAiCard,
       dingtalkCardTemplateId: channel.dingtalkCardTemplateId,
+      localPort: channel.localPort,
       allowedUsers: channel.allowedUsers || [],
       groupPermissions: channel.groupPermissions || [],
       groupActivation: channel.groupActivation,
       proxyUrl: channel.proxyUrl,
+      maxListenRestarts: channel.maxListenRestarts,
+      allowProactiveSend: channel.allowProactiveSend,
+      rerunOnEdit: channel.rerunOnEdit,
+      deleteReplyOnRecall: channel.deleteReplyOnRecall,
+      toolActivity: channel.toolActivity,
+      toolActivityLog: channel.toolActivityLog,
+      usageFooter: channel.usageFooter,
+      reviewBeforeSend: channel.reviewBeforeSend,
+      reviewNotice: channel.reviewNotice,
+      schedulerWeight: channel.schedulerWeight,
+      adminUsers: channel.adminUsers || [],
       overrides: channel.overrides,
+      agentRoutes: channel.agentRoutes || [],
       setupCompleted: channel.setupCompleted,
     },
   });
@@ -422,6 +437,32 @@ export async function stopAgentChannel(
   await invoke('cmd_stop_agent_channel', { agentId, channelId });
 }
 
+/** Hot-reload a running channel's overrides (currently the persona / system prompt) */
+export async function updateChannelOverrides(
+  agentId: string,
+  channelId: string,
+  overrides: ChannelOverrides,
+): Promise<void> {
+  await invoke('cmd_update_agent_channel_config', {
+    agentId,
+    channelId,
+    configJson: JSON.stringify({ overrides }),
+  });
+}
+
+/** Hot-reload a running channel's multi-agent routing rules */
+export async function updateChannelAgentRoutes(
+  agentId: string,
+  channelId: string,
+  agentRoutes: AgentRoute[],
+): Promise<void> {
+  await invoke('cmd_update_agent_channel_config', {
+    agentId,
+    channelId,
+    configJson: JSON.stringify({ agentRoutes }),
+  });
+}
+
 export async function getChannelStatus(
   agentId: string,
   channelId: string,
@@ -433,6 +474,134 @@ export async function getAllChannelsStatus(): Promise<Record<string, ImBotStatus
   return invoke('cmd_all_agent_channels_status');
 }
 
+/** Export an IM session's transcript into the channel's exports folder */
+export async function exportTranscript(
+  agentId: string,
+  channelId: string,
+  sessionKey: string,
+  format: TranscriptFormat = 'md',
+): Promise<ExportedTranscript> {
+  return invoke('cmd_im_export_transcript', { agentId, channelId, sessionKey, format });
+}
+
+/** Point an IM session at one of the agent's allowed workspaces (undefined = default workspace) */
+export async function setImChatWorkspace(
+  agentId: string,
+  channelId: string,
+  sessionKey: string,
+  workspace?: string,
+): Promise<void> {
+  return invoke('cmd_im_set_chat_workspace', { agentId, channelId, sessionKey, workspace });
+}
+
+/** Persons with linked IM accounts (their private chats share one session) */
+export async function listImIdentities(): Promise<ImPerson[]> {
+  return invoke('cmd_im_list_identities');
+}
+
+/** Link an IM account to an existing person, or to a new person called `name` */
+export async function linkImIdentity(
+  platform: ImPlatformBuiltin,
+  userId: string,
+  personId?: string,
+  name?: string,
+): Promise<ImPerson> {
+  return invoke('cmd_im_link_identity', { personId, name, platform, userId });
+}
+
+export async function unlinkImIdentity(platform: ImPlatformBuiltin, userId: string): Promise<void> {
+  return invoke('cmd_im_unlink_identity', { platform, userId });
+}
+
+/** Take over an IM session: the agent pauses and messages arrive as `im:takeover-message` events */
+export async function startImTakeover(
+  agentId: string,
+  channelId: string,
+  sessionKey: string,
+  chatId: string,
+): Promise<void> {
+  return invoke('cmd_im_takeover_start', { agentId, channelId, sessionKey, chatId });
+}
+
+/** Reply as the bot in a taken-over session */
+export async function sendImTakeoverMessage(
+  agentId: string,
+  channelId: string,
+  sessionKey: string,
+  text: string,
+): Promise<ImTakeoverMessage> {
+  return invoke('cmd_im_takeover_send', { agentId, channelId, sessionKey, text });
+}
+
+/** Hand the session back to the agent; it sees the operator exchange with the next message */
+export async function handBackImTakeover(
+  agentId: string,
+  channelId: string,
+  sessionKey: string,
+): Promise<void> {
+  return invoke('cmd_im_takeover_hand_back', { agentId, channelId, sessionKey });
+}
+
+export async function listImTakeovers(agentId: string, channelId: string): Promise<ImTakeoverSession[]> {
+  return invoke('cmd_im_takeover_list', { agentId, channelId });
+}
+
+/** Replies waiting for approval on a review-before-send channel (oldest first) */
+export async function listImReviews(agentId: string, channelId: string): Promise<ImReviewItem[]> {
+  return invoke('cmd_im_review_list', { agentId, channelId });
+}
+
+export async function approveImReview(agentId: string, channelId: string, id: string): Promise<void> {
+  return invoke('cmd_im_review_approve', { agentId, channelId, id });
+}
+
+/** Send an edited version of a queued reply instead of the draft */
+export async function editAndSendImReview(
+  agentId: string,
+  channelId: string,
+  id: string,
+  text: string,
+): Promise<void> {
+  return invoke('cmd_im_review_edit_and_send', { agentId, channelId, id, text });
+}
+
+export async function rejectImReview(agentId: string, channelId: string, id: string): Promise<void> {
+  return invoke('cmd_im_review_reject', { agentId, channelId, id });
+}
+
+/** Send a message (and optional file attachments, absolute paths) to a known chat */
+export async function sendImMessage(
+  agentId: string,
+  channelId: string,
+  chatId: string,
+  text: string,
+  attachments?: string[],
+): Promise<void> {
+  return invoke('cmd_im_send_message', { agentId, channelId, chatId, text, attachments });
+}
+
+/** Chats that have messaged running channels, optionally for one agent / channel */
+export async function listKnownChats(
+  agentId?: string,
+  channelId?: string,
+): Promise<ChannelKnownChats[]> {
+  return invoke('cmd_im_list_known_chats', { agentId, channelId });
+}
+
+/** Daily token usage per channel and per user over the last `days` days (default 30) */
+export async function getImUsageReport(
+  agentId?: string,
+  channelId?: string,
+  days?: number,
+): Promise<ImUsageReport> {
+  return invoke('cmd_im_usage_report', { agentId, channelId, days });
+}
+
+/** Operational metrics (messages, latency, errors, queue depth) for all channels */
+export async function getMetricsSnapshot(): Promise<MetricsSnapshot> {
+  return invoke('cmd_metrics_snapshot');
+}
+
 export async function verifyToken(
   platform: string,
   token: string,
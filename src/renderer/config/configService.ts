@@ -1,7 +1,7 @@
 // Config service for managing app configuration using Tauri FS plugin
 // Falls back to localStorage in browser development mode
 
-import type { AppConfig } from '../../shared/types/config';
+import type { AppConfig, ConfigChangedEvent } from '../../shared/types/config';
 import { DEFAULT_CONFIG } from '../../shared/providers';
 import { isTauri } from '../utils/env';
 
@@ -269,3 +269,20 @@ async function _writeAppConfigLocked(config: AppConfig): Promise<void> {
   const configPath = await join(dir, CONFIG_FILE);
   await safeWriteJson(configPath, config);
 }
+
+// ============= Change Events =============
+
+/**
+ * Subscribe to backend hot-reload events (fired after config.json changes on disk
+ * and running channels / heartbeats / proxy have been reconciled).
+ * Returns an unsubscribe function; no-op in browser mode.
+ */
+export async function onConfigChanged(
+  handler: (event: ConfigChangedEvent) => void,
+): Promise<() => void> {
+  if (!isTauri()) {
+    return () => {};
+  }
+  const { listen } = await import('@tauri-apps/api/event');
+  return listen<ConfigChangedEvent>('config:changed', (e) => handler(e.payload));
+}
@@ -392,6 +392,11 @@ export function getSdkSessionId(sessionId: string): string | undefined {
   return sessions.find(s => s.id === sessionId)?.sdkSessionId;
 }
 
+export function getSessionSource(sessionId: string): string | undefined {
+  if (!isValidId(sessionId)) return undefined;
+  return readIndex().find(s => s.id === sessionId)?.source;
+}
+
 export function updateSessionSource(sessionId: string, source: string): void {
   if (!isValidId(sessionId)) return;
   withLock(() => {
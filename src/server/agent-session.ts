@@ -20,6 +20,7 @@ import './tools/gemini-image-tool';
 import './tools/edge-tts-tool';
 import './tools/scheduled-task-tools';
 import { getScheduledTaskContext, scheduledTaskToolsServer, setCurrentSessionId } from './tools/scheduled-task-tools';
+import { imToolsServer, isImApiAvailable } from './tools/im-tools';
 
 interface ChatImage {
   name: string;
@@ -58,14 +59,32 @@ export interface OpenAiBridgeConfig {
  * 未来接入 Cron / IM 等场景时，在此扩展为多层架构。
  */
 function buildSystemPrompt(agentDir: string): string {
-  return [
+  const lines = [
     '<soagents-identity>',
     '你正运行在 SoAgents —— 一款基于 Claude Agent SDK 的桌面端 AI Agent 应用中。',
     '</soagents-identity>',
     '',
     `当前工作目录为：${agentDir}`,
     '所有创建、修改或写入的文件，必须放置在此目录或其子目录内，不得操作此目录之外的文件。',
-  ].join('\n');
+  ];
+  if (currentChannelSystemPrompt) {
+    lines.push('', '<channel-persona>', currentChannelSystemPrompt, '</channel-persona>');
+  }
+  return lines.join('\n');
+}
+
+// ── IM 频道人设（由 Rust 通过 /api/im/system-prompt 或 /api/im/chat 推送）──
+
+let currentChannelSystemPrompt: string | null = null;
+
+/**
+ * 设置 IM 频道的附加系统提示词。活跃 Session 在下一条消息时检测到变化并重启。
+ */
+export function setChannelSystemPrompt(prompt: string | null | undefined): void {
+  const next = prompt?.trim() ? prompt : null;
+  if (next === currentChannelSystemPrompt) return;
+  currentChannelSystemPrompt = next;
+  console.log(`[IM] Channel persona ${next ? `updated (${next.length} chars)` : 'cleared'}`);
 }
 
 /** 当前 OpenAI bridge 配置（模块级，供 bridge handler 读取） */
@@ -127,6 +146,7 @@ interface BuildMcpServersInput {
   serverArgsMap: Record<string, string[]>;               // from ConfigStore.readConfig().mcpServerArgs
   sessionId?: string;   // for builtin MCP context
   workspace?: string;   // for builtin MCP context
+  imToolsOptIn?: boolean; // workspace explicitly enabled im-tools outside IM sessions
 }
 
 function buildSdkMcpServers(input: BuildMcpServersInput): Record<string, unknown> {
@@ -140,6 +160,12 @@ function buildSdkMcpServers(input: BuildMcpServersInput): Record<string, unknown
       result['scheduled-task-tools'] = scheduledTaskToolsServer;
     }
   }
+  // Proactive IM sends — only for sessions started from an IM channel, or workspaces
+  // that opted in, and only when Rust injected the send endpoint
+  const isImSession = !!input.sessionId && !!SessionStore.getSessionSource(input.sessionId);
+  if (isImApiAvailable() && (isImSession || input.imToolsOptIn)) {
+    result['im-tools'] = imToolsServer;
+  }
 
   // --- Pattern 2: Builtin registry MCPs (command='__builtin__') ---
   // These are user-toggled in Settings but run in-process via the registry.
@@ -287,6 +313,23 @@ function stripPlaywrightResult(toolName: string | undefined, content: string): s
   return `[Playwright result truncated: ${content.length} chars]`;
 }
 
+/**
+ * Compact tool input for IM status lines: short string / number / boolean fields only,
+ * so large payloads (file contents, patches) never travel over the IM stream.
+ */
+function compactToolInput(input: unknown): Record<string, string | number | boolean> {
+  const compact: Record<string, string | number | boolean> = {};
+  if (!input || typeof input !== 'object') return compact;
+  for (const [key, value] of Object.entries(input as Record<string, unknown>)) {
+    if (typeof value === 'string') {
+      compact[key] = value.length > 300 ? `${value.slice(0, 300)}…` : value;
+    } else if (typeof value === 'number' || typeof value === 'boolean') {
+      compact[key] = value;
+    }
+  }
+  return compact;
+}
+
 /**
  * 前端推送有效 MCP 配置。若配置变化且有活跃 Session，触发重启。
  */
@@ -541,7 +584,8 @@ class SessionRunner {
   }> = [];
 
   // ── IM stream callback ──
-  private imStreamCallback: ((event: 'delta' | 'block-end' | 'complete' | 'error' | 'activity', data: string) => void) | null = null;
+  // 'tool-use' / 'tool-result' carry JSON ({ id, name, input } / { id, name, isError }) for IM status lines
+  private imStreamCallback: ((event: 'delta' | 'block-end' | 'complete' | 'error' | 'activity' | 'tool-use' | 'tool-result', data: string) => void) | null = null;
   private imTextBlockIndices = new Set<number>();
   // Cross-turn guard: set to true when imStreamCallback is nulled or replaced during a turn.
   // Reset before each new yield. Prevents stale turn's events from consuming a new SSE stream.
@@ -562,6 +606,9 @@ class SessionRunner {
   // ── MCP 动态重启 ──
   private mcpRestartPending = false;
 
+  // 当前 Session 启动时使用的频道人设（用于检测变化）
+  private activeChannelSystemPrompt: string | null = null;
+
   // ── MCP 预热 ──
   private preWarmTimer: ReturnType<typeof setTimeout> | null = null;
   private preWarmRetryCount = 0;
@@ -834,6 +881,7 @@ class SessionRunner {
             serverArgsMap: ConfigStore.readConfig().mcpServerArgs ?? {},
             sessionId: this.sessionId,
             workspace: config.agentDir,
+            imToolsOptIn: config.mcpEnabledServerIds?.includes('im-tools'),
           })
         : undefined;
 
@@ -847,9 +895,16 @@ class SessionRunner {
         if (parts.length < 3) return { allowed: false, reason: '无效的 MCP 工具名称' };
         const serverId = parts[1];
 
-        // Trusted built-in MCPs: always allow (e.g., scheduled-task-tools)
+        // Trusted built-in MCPs: always allow (e.g., scheduled-task-tools).
         const TRUSTED_MCP_IDS = new Set<string>(['scheduled-task-tools']);
         if (TRUSTED_MCP_IDS.has(serverId)) return { allowed: true };
+        // Context-injected im-tools passes this check only when it was injected;
+        // it still goes through the permission mode below (it sends messages out).
+        if (serverId === 'im-tools') {
+          return mcpServers && 'im-tools' in mcpServers
+            ? { allowed: true }
+            : { allowed: false, reason: 'MCP 服务「im-tools」未启用' };
+        }
 
         if (enabledMcpServerIds.size === 0) return { allowed: false, reason: 'MCP 工具已被禁用' };
         if (enabledMcpServerIds.has(serverId)) return { allowed: true };
@@ -1013,6 +1068,7 @@ class SessionRunner {
         ? canUseTool
         : undefined;
 
+      this.activeChannelSystemPrompt = currentChannelSystemPrompt;
       const q = query({
         prompt: this.messageGenerator(),
         options: {
@@ -1166,7 +1222,7 @@ class SessionRunner {
         console.log(`${logTag} message_stop`);
       }
     } else if (msg.type === 'assistant') {
-      const betaMsg = msg.message as { content: Array<{ type: string; text?: string }> };
+      const betaMsg = msg.message as { content: Array<{ type: string; text?: string; id?: string; name?: string; input?: unknown }> };
       // SDK 为每条 assistant 消息分配一个 UUID — Rewind 的 resumeSessionAt 需要它。
       // 一个回合可能产生多条（thinking → text），总是记录最新的（SoAgents 合并为
       // 一条持久化的 assistant message，turn 结束时写入 sdkUuid）。
@@ -1182,6 +1238,13 @@ class SessionRunner {
             if (!this.imCallbackNulledDuringTurn) this.imStreamCallback?.('delta', block.text);
             console.log(`${logTag} assistant fallback broadcast: ${block.text.length} chars`);
           }
+        } else if (block.type === 'tool_use' && !this.imCallbackNulledDuringTurn) {
+          // Full tool input is only known once the assistant message is assembled
+          this.imStreamCallback?.('tool-use', JSON.stringify({
+            id: block.id ?? '',
+            name: block.name ?? '',
+            input: compactToolInput(block.input),
+          }));
         }
       }
     } else if (msg.type === 'user') {
@@ -1211,6 +1274,13 @@ class SessionRunner {
             content: stripPlaywrightResult(toolName, rawContent),
             isError: block.is_error ?? false,
           });
+          if (!this.imCallbackNulledDuringTurn) {
+            this.imStreamCallback?.('tool-result', JSON.stringify({
+              id: toolId,
+              name: toolName ?? '',
+              isError: block.is_error ?? false,
+            }));
+          }
           this.toolNameMap.delete(toolId);
         }
       }
@@ -1224,6 +1294,7 @@ class SessionRunner {
 
       // 提取 token usage：优先 modelUsage（per-model），fallback usage（aggregate）
       const resultMsg = msg as SDKMessage & {
+        total_cost_usd?: number;
         usage?: { input_tokens?: number; output_tokens?: number; cache_read_input_tokens?: number; cache_creation_input_tokens?: number };
         modelUsage?: Record<string, { inputTokens?: number; outputTokens?: number; cacheReadInputTokens?: number; cacheCreationInputTokens?: number }>;
       };
@@ -1258,9 +1329,17 @@ class SessionRunner {
 
       // 保存助手消息（含 usage）并通知前端
       this.saveTurnAssistantContent(activeSessionId, durationMs);
-      // IM stream: notify complete (skip if callback was replaced/nulled during this turn)
+      // IM stream: notify complete with the turn's usage (skip if callback was replaced/nulled during this turn)
       if (this.imStreamCallback && !this.imCallbackNulledDuringTurn) {
-        this.imStreamCallback('complete', '');
+        this.imStreamCallback('complete', JSON.stringify({
+          inputTokens: this.turnUsage.inputTokens,
+          outputTokens: this.turnUsage.outputTokens,
+          cacheReadTokens: this.turnUsage.cacheReadTokens,
+          cacheCreationTokens: this.turnUsage.cacheCreationTokens,
+          model: this.turnUsage.model,
+          durationMs,
+          costUsd: resultMsg.total_cost_usd,
+        }));
         this.imStreamCallback = null;
       }
       this.imTextBlockIndices.clear();
@@ -1359,7 +1438,13 @@ class SessionRunner {
       console.log(`[SessionRunner:${this.sessionId.slice(0, 8)}] PermissionMode changed: ${this.currentPermissionMode} → ${resolvedMode}, session restart required`);
     }
 
-    return providerChanged || modelChanged || modeChanged;
+    // 频道人设变更
+    const personaChanged = currentChannelSystemPrompt !== this.activeChannelSystemPrompt;
+    if (personaChanged) {
+      console.log(`[SessionRunner:${this.sessionId.slice(0, 8)}] Channel persona changed, session restart required`);
+    }
+
+    return providerChanged || modelChanged || modeChanged || personaChanged;
   }
 
   // ── 公共 API ──
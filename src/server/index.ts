@@ -1,5 +1,5 @@
 import { broadcast, createSseHandler, setLogHistoryProvider } from './sse';
-import { getOrCreateRunner, getRunner, getCurrentSessionId, resetState, removeRunner, isRunning, getPendingState, setProxyConfig, respondExitPlanMode, respondEnterPlanMode, setMcpServers, stripYamlFrontmatter, waitForSessionIdle, enqueueUserMessage, initSocksBridgeFromEnv } from './agent-session';
+import { getOrCreateRunner, getRunner, getCurrentSessionId, resetState, removeRunner, isRunning, getPendingState, setProxyConfig, respondExitPlanMode, respondEnterPlanMode, setMcpServers, setChannelSystemPrompt, stripYamlFrontmatter, waitForSessionIdle, enqueueUserMessage, initSocksBridgeFromEnv } from './agent-session';
 import * as SessionStore from './SessionStore';
 import * as ConfigStore from './ConfigStore';
 import * as MCPConfigStore from './MCPConfigStore';
@@ -155,6 +155,7 @@ Bun.serve({
           model?: string;
           sessionId?: string;
           metadata?: { source: string; sourceId: string; senderName?: string };
+          systemPrompt?: string | null;
         };
 
         if (!payload.message?.trim()) {
@@ -207,6 +208,11 @@ Bun.serve({
         }
         const runner = getOrCreateRunner(sessionId);
 
+        // Channel persona travels with every message so config edits apply on the next turn
+        if ('systemPrompt' in payload) {
+          setChannelSystemPrompt(payload.systemPrompt);
+        }
+
         // Tag session source from IM metadata
         const imSource = payload.metadata?.source;
         if (imSource) {
@@ -260,11 +266,17 @@ Bun.serve({
                   sendEvent({ type: 'block-end', text: imAccText });
                   imAccText = '';
                 }
-                sendEvent({ type: 'complete', sessionId: getCurrentSessionId() });
+                // data: turn usage JSON (empty when the turn ended without a result)
+                let turnUsage: unknown;
+                try { turnUsage = data ? JSON.parse(data) : undefined; } catch { turnUsage = undefined; }
+                sendEvent({ type: 'complete', sessionId: getCurrentSessionId(), turnUsage });
                 broadcast('im:response_sent', { sessionId });
                 closeStream();
               } else if (event === 'activity') {
-                sendEvent({ type: 'activity' });
+                sendEvent({ type: 'activity', kind: data || undefined });
+              } else if (event === 'tool-use' || event === 'tool-result') {
+                // Tool progress for IM status lines (kind: tool_use / tool_result)
+                sendEvent({ type: 'activity', kind: event.replace('-', '_'), tool: JSON.parse(data) });
               } else if (event === 'error') {
                 sendEvent({ type: 'error', error: data });
                 closeStream();
@@ -801,6 +813,12 @@ Bun.serve({
       return Response.json({ servers: serversWithStatus, enabledIds });
     }
 
+    if (req.method === 'POST' && url.pathname === '/api/im/system-prompt') {
+      const body = await req.json() as { systemPrompt?: string | null };
+      setChannelSystemPrompt(body.systemPrompt);
+      return Response.json({ ok: true });
+    }
+
     if (req.method === 'POST' && url.pathname === '/api/mcp/set') {
       const body = await req.json() as { servers: import('../shared/types/mcp').McpServerDefinition[] };
       setMcpServers(body.servers ?? []);
@@ -0,0 +1,126 @@
+// src/server/tools/im-tools.ts
+// In-process MCP server letting the agent start conversations on IM channels.
+// Injected via Pattern 1 (context-injected) in buildSdkMcpServers() for IM sessions
+// (or workspaces listing 'im-tools' in mcpEnabledServerIds) when Rust exposed its
+// proactive send endpoint (SOAGENTS_IM_API_URL / SOAGENTS_IM_API_TOKEN). Calls go
+// through the session's permission mode like any other tool.
+//
+// Rust enforces the channel policy and allowlist — only chats that have messaged
+// the bot (or allowlisted users) can be reached, and only if the channel allows it.
+
+import { isAbsolute, resolve } from 'path';
+import { createSdkMcpServer, tool } from '@anthropic-ai/claude-agent-sdk';
+import { z } from 'zod/v4';
+
+type CallToolResult = {
+  content: Array<{ type: 'text'; text: string }>;
+  isError?: boolean;
+};
+
+// ===== Endpoint =====
+
+export function isImApiAvailable(): boolean {
+  return !!process.env.SOAGENTS_IM_API_URL && !!process.env.SOAGENTS_IM_API_TOKEN;
+}
+
+async function callImApi(method: 'GET' | 'POST', path: string, body?: unknown): Promise<CallToolResult> {
+  const baseUrl = process.env.SOAGENTS_IM_API_URL;
+  const token = process.env.SOAGENTS_IM_API_TOKEN;
+  if (!baseUrl || !token) {
+    return { isError: true, content: [{ type: 'text', text: 'IM send API is not available in this app instance.' }] };
+  }
+  try {
+    const resp = await fetch(`${baseUrl}${path}`, {
+      method,
+      headers: {
+        Authorization: `Bearer ${token}`,
+        ...(body ? { 'Content-Type': 'application/json' } : {}),
+      },
+      body: body ? JSON.stringify(body) : undefined,
+    });
+    const text = await resp.text();
+    if (!resp.ok) {
+      let message = text;
+      try {
+        message = (JSON.parse(text) as { error?: string }).error ?? text;
+      } catch {
+        // keep raw body
+      }
+      return { isError: true, content: [{ type: 'text', text: `IM send failed: ${message}` }] };
+    }
+    return { content: [{ type: 'text', text }] };
+  } catch (err) {
+    return { isError: true, content: [{ type: 'text', text: `IM send API unreachable: ${String(err)}` }] };
+  }
+}
+
+// ===== Tool Handlers =====
+
+async function listImChatsHandler(args: { agentId?: string; channelId?: string }): Promise<CallToolResult> {
+  const params = new URLSearchParams();
+  if (args.agentId) params.set('agentId', args.agentId);
+  if (args.channelId) params.set('channelId', args.channelId);
+  const query = params.toString();
+  return callImApi('GET', `/im/chats${query ? `?${query}` : ''}`);
+}
+
+async function sendImMessageHandler(args: {
+  agentId: string;
+  channelId: string;
+  chatId: string;
+  text: string;
+  attachments?: string[];
+}): Promise<CallToolResult> {
+  // Relative attachment paths are resolved against the workspace (Sidecar cwd)
+  const attachments = (args.attachments ?? []).map((p) => (isAbsolute(p) ? p : resolve(process.cwd(), p)));
+  const result = await callImApi('POST', '/im/send', { ...args, attachments });
+  if (result.isError) return result;
+  let status = 'sent';
+  try {
+    status = (JSON.parse(result.content[0]?.text ?? '{}') as { status?: string }).status ?? 'sent';
+  } catch {
+    // older endpoint without a JSON body — treat as sent
+  }
+  console.log(`[im-tools] Message to ${args.agentId}/${args.channelId} chat ${args.chatId}: ${status}`);
+  const text =
+    status === 'queued'
+      ? 'Message queued: the platform did not accept it yet, it will be retried automatically.'
+      : status === 'heldForReview'
+        ? 'Message held for review: this channel reviews messages before sending, it goes out once an operator approves it.'
+        : 'Message sent.';
+  return { content: [{ type: 'text', text }] };
+}
+
+// ===== MCP Server =====
+
+export function createImToolsServer() {
+  return createSdkMcpServer({
+    name: 'im-tools',
+    version: '1.0.0',
+    tools: [
+      tool(
+        'list_im_chats',
+        'List IM chats (Telegram / Feishu / DingTalk ...) that have talked to running agent channels. Returns agentId, channelId and chats (chatId, chatType, displayName, lastActive) — use these IDs with send_im_message.',
+        {
+          agentId: z.string().optional().describe('Only list chats of this agent.'),
+          channelId: z.string().optional().describe('Only list chats of this channel.'),
+        },
+        listImChatsHandler,
+      ),
+      tool(
+        'send_im_message',
+        'Proactively send a message to an IM chat (one returned by list_im_chats). Use for notifications or follow-ups the user asked for; do not use it to answer the current conversation.',
+        {
+          agentId: z.string().describe('Agent that owns the channel.'),
+          channelId: z.string().describe('Channel to send through.'),
+          chatId: z.string().describe('Target chat ID.'),
+          text: z.string().describe('Message text (Markdown).'),
+          attachments: z.array(z.string()).optional().describe('Files to upload after the text (workspace-relative or absolute paths).'),
+        },
+        sendImMessageHandler,
+      ),
+    ],
+  });
+}
+
+export const imToolsServer = createImToolsServer();
@@ -17,6 +17,26 @@ export interface ChannelOverrides {
   model?: string;
   permissionMode?: string;
   toolsDeny?: string[];
+  /** Persona / rules appended to the system prompt for this channel */
+  systemPrompt?: string;
+  /** Persona file (absolute or workspace-relative); preferred over systemPrompt when readable */
+  systemPromptFile?: string;
+}
+
+/**
+ * Routes a channel's messages to another agent ("@name ..." or "/ask name ...").
+ * Unset fields come from agentId's agent, then from the channel itself.
+ */
+export interface AgentRoute {
+  name: string;
+  aliases?: string[];
+  agentId?: string;
+  workspacePath?: string;
+  providerId?: string;
+  providerEnvJson?: string;
+  model?: string;
+  permissionMode?: string;
+  mcpServersJson?: string;
 }
 
 export interface ChannelConfig {
@@ -40,9 +60,39 @@ export interface ChannelConfig {
   groupPermissions?: GroupPermission[];
   groupActivation?: GroupActivation;
 
+  // Local loopback (type 'local'): ws://127.0.0.1:{localPort}, unset/0 = any free port.
+  // Clients authenticate with `Authorization: Bearer {botToken}` (required for local channels)
+  localPort?: number;
+
   // Proxy
   proxyUrl?: string;
 
+  /** Consecutive listen loop restarts before the channel gives up (default 10) */
+  maxListenRestarts?: number;
+
+  /** 允许主动发送（桌面端 / 定时任务 / Agent 工具）到已知会话，默认允许 */
+  allowProactiveSend?: boolean;
+
+  /** 用户编辑已回答（或生成中）的消息时重新执行该轮，默认关闭 */
+  rerunOnEdit?: boolean;
+  /** 用户撤回已回答的消息时删除 Bot 的回复，默认关闭 */
+  deleteReplyOnRecall?: boolean;
+
+  /** 生成回复时在占位消息中显示工具进度：off / summary / detailed，默认 summary */
+  toolActivity?: 'off' | 'summary' | 'detailed';
+  /** 回复结束后发送简短的工具调用记录，默认关闭 */
+  toolActivityLog?: boolean;
+  /** 在回复末尾附加 token 用量 / 费用 / 耗时，默认关闭 */
+  usageFooter?: boolean;
+  /** 最终回复先进入桌面端审核队列，批准后才发送（流式草稿不外发），默认关闭 */
+  reviewBeforeSend?: boolean;
+  /** 回复等待审核时通知用户，默认关闭 */
+  reviewNotice?: boolean;
+  /** 多个渠道同时繁忙时分得的并发份额权重，默认 1 */
+  schedulerWeight?: number;
+  /** 可执行 /workspace 等管理命令的用户 ID，为空时所有允许的用户均可 */
+  adminUsers?: string[];
+
   // OpenClaw plugin fields — only set when type starts with "openclaw:".
   // Present for any channel backed by a Plugin Bridge (WeChat / WeCom / QQ / Feishu-enhanced etc).
   /** Plugin ID (e.g. "qqbot", "openclaw-weixin"). Redundant with `type` but easier to consume. */
@@ -60,6 +110,8 @@ export interface ChannelConfig {
 
   // Per-channel overrides
   overrides?: ChannelOverrides;
+  // Multi-agent routing rules (empty = all messages go to this agent)
+  agentRoutes?: AgentRoute[];
   setupCompleted?: boolean;
 }
 
@@ -72,6 +124,8 @@ export interface AgentConfig {
 
   // Core: Workspace
   workspacePath: string;
+  /** IM 会话可通过 /workspace 切换到的其他目录 */
+  workspaceAllowlist?: string[];
 
   // AI Configuration (defaults for all channels + desktop sessions)
   providerId?: string;
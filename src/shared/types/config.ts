@@ -71,6 +71,28 @@ export interface ModelAliases {
   haiku?: string;
 }
 
+// ── Metrics Endpoint ──
+
+/** Optional localhost Prometheus endpoint (http://127.0.0.1:{port}/metrics) */
+export interface MetricsEndpointSettings {
+  enabled: boolean;
+  port?: number;
+}
+
+/** IM 调度：所有渠道共享的 AI 并发上限，渠道间按 schedulerWeight 公平分配 */
+export interface ImSchedulerSettings {
+  /** 全局并发轮次上限，默认 8 */
+  maxConcurrent?: number;
+  /** 单个渠道的并发上限，默认不限（仅受全局上限约束） */
+  perChannel?: number;
+  /** 同一渠道内单个用户的并发上限，默认 2 */
+  perUser?: number;
+  /** 私聊优先于群聊，默认开启 */
+  prioritizePrivate?: boolean;
+  /** 渠道管理员（adminUsers）优先，默认开启 */
+  prioritizeAdmins?: boolean;
+}
+
 // ── Proxy Settings ──
 
 export type ProxyProtocol = 'http' | 'socks5';
@@ -142,6 +164,8 @@ export interface AppConfig {
   minimizeToTray?: boolean;
   defaultWorkspacePath?: string;
   proxySettings?: ProxySettings;
+  metricsEndpoint?: MetricsEndpointSettings;
+  imScheduler?: ImSchedulerSettings;
   showDevTools?: boolean;
   agents?: AgentConfig[];
 }
@@ -157,3 +181,24 @@ export interface ProviderEnv {
   upstreamFormat?: 'chat_completions' | 'responses';
   modelAliases?: ModelAliases;
 }
+
+/** 后端检测到 config.json 变更后发出的 `config:changed` 事件 */
+export interface ConfigChannelRef {
+  agentId: string;
+  channelId: string;
+}
+
+export interface ConfigChangedEvent {
+  channelsAdded: ConfigChannelRef[];
+  channelsRemoved: ConfigChannelRef[];
+  channelsChanged: ConfigChannelRef[];
+  /** Agent IDs whose heartbeat / memory auto-update settings changed */
+  heartbeatChanged: string[];
+  proxyChanged: boolean;
+  schedulerChanged: boolean;
+  /** Channels the backend (re)started, stopped or hot-patched while reconciling */
+  channelsStarted: ConfigChannelRef[];
+  channelsStopped: ConfigChannelRef[];
+  channelsUpdated: ConfigChannelRef[];
+  errors: string[];
+}
@@ -1,7 +1,8 @@
 // IM platform and status types
 
 /** Rust-native adapters — baked into src-tauri/src/im/ */
-export type ImPlatformBuiltin = 'telegram' | 'feishu' | 'dingtalk';
+/** 'local' = loopback WebSocket on 127.0.0.1, for testing without a real IM */
+export type ImPlatformBuiltin = 'telegram' | 'feishu' | 'dingtalk' | 'local';
 
 /** OpenClaw plugin channels — loaded dynamically via Plugin Bridge.
  *  The string is the pluginId (e.g. "qqbot", "openclaw-weixin").
@@ -44,8 +45,122 @@ export interface ImBotStatus {
   activeSessions: ImActiveSession[];
   errorMessage?: string;
   restartCount: number;
+  /** Recent listen loop restarts (newest last) */
+  restartHistory?: ImRestartRecord[];
   bufferedMessages: number;
+  /** Final replies waiting in the outbound queue for a retry */
+  pendingDeliveries?: number;
   groupPermissions?: GroupPermission[];
+  /** 渠道累计 token 用量 */
+  usage?: ImUsageTotals;
+}
+
+/** 累计 token 用量（渠道 / 会话 / 日 / 用户） */
+export interface ImUsageTotals {
+  turns: number;
+  inputTokens: number;
+  outputTokens: number;
+  cacheReadTokens: number;
+  cacheCreationTokens: number;
+  costUsd: number;
+  durationMs: number;
+  lastTurnAt?: string;
+}
+
+export interface ImChannelDailyUsage extends ImUsageTotals {
+  date: string; // YYYY-MM-DD（本地时区）
+  agentId: string;
+  channelId: string;
+}
+
+export interface ImUserDailyUsage extends ImChannelDailyUsage {
+  senderId: string;
+  senderName?: string;
+}
+
+/** Result of cmd_im_usage_report */
+export interface ImUsageReport {
+  from: string;
+  to: string;
+  channels: ImChannelDailyUsage[];
+  users: ImUserDailyUsage[];
+}
+
+/** 曾向渠道发过消息的会话（主动发送的目标） */
+export interface KnownChat {
+  chatId: string;
+  chatType: 'private' | 'group';
+  senderId?: string;
+  displayName?: string;
+  lastActive: string;
+}
+
+export interface ChannelKnownChats {
+  agentId: string;
+  channelId: string;
+  platform: ImPlatform;
+  chats: KnownChat[]; // 最近活跃在前
+}
+
+/** 跨平台身份：同一个人的多个 IM 账号，私聊共享同一会话 */
+export interface ImLinkedAccount {
+  platform: ImPlatformBuiltin;
+  userId: string;
+  displayName?: string;
+  linkedAt: string;
+}
+
+export interface ImPerson {
+  id: string;
+  name: string;
+  accounts: ImLinkedAccount[];
+}
+
+/** 人工接管：接管期间 AI 暂停，消息推送到桌面端，由操作员以 Bot 身份回复 */
+export interface ImTakeoverMessage {
+  role: 'user' | 'operator';
+  senderName?: string;
+  text: string;
+  at: string;
+}
+
+export interface ImTakeoverSession {
+  sessionKey: string;
+  chatId: string;
+  startedAt: string;
+  messages: ImTakeoverMessage[];
+}
+
+/** `im:takeover-message` 事件 */
+export interface ImTakeoverEvent extends ImTakeoverMessage {
+  agentId: string;
+  channelId: string;
+  sessionKey: string;
+  chatId: string;
+}
+
+/** 待审核的回复（reviewBeforeSend 渠道），批准后才发送给用户 */
+export interface ImReviewItem {
+  id: string;
+  sessionKey: string;
+  chatId: string;
+  messageId: string;
+  senderName?: string;
+  question: string;
+  draft: string;
+  createdAt: string;
+}
+
+/** `im:review-queued` 事件 */
+export interface ImReviewEvent extends ImReviewItem {
+  agentId: string;
+  channelId: string;
+}
+
+export interface ImRestartRecord {
+  at: string;
+  reason: string;
+  delaySecs: number;
 }
 
 export interface ImActiveSession {
@@ -55,6 +170,16 @@ export interface ImActiveSession {
   lastActive: string;
 }
 
+export type TranscriptFormat = 'md' | 'html' | 'jsonl';
+
+/** Result of cmd_im_export_transcript */
+export interface ExportedTranscript {
+  path: string;
+  fileName: string;
+  sessionId: string;
+  messageCount: number;
+}
+
 export interface GroupPermission {
   groupId: string;
   groupName: string;
@@ -68,6 +193,33 @@ export interface HeartbeatConfig {
   intervalMinutes: number;
   activeHours?: { start: string; end: string; timezone: string };
   ackMaxChars?: number;
+  /** "content" 结果投递目标；为空则不投递 */
+  deliverTo?: HeartbeatDelivery[];
+}
+
+export type HeartbeatDelivery =
+  | { type: 'im'; channelId: string; chatId: string }
+  | { type: 'desktop' }
+  | { type: 'webhook'; url: string; headers?: Record<string, string> };
+
+/** 一次心跳内容投递记录（状态视图） */
+export interface HeartbeatDeliveryRecord {
+  at: string;
+  target: string; // "im:{channelId}/{chatId}" | "desktop" | "webhook:{host}"
+  ok: boolean;
+  error: string | null;
+  preview: string;
+}
+
+export interface HeartbeatRunStatus {
+  agentId: string;
+  running: boolean;
+  paused: boolean;
+  lastRunAt: string | null;
+  lastResult: 'success' | 'error' | 'skipped' | null;
+  lastError: string | null;
+  consecutiveErrors: number;
+  recentDeliveries: HeartbeatDeliveryRecord[]; // 最新在前
 }
 
 export const DEFAULT_HEARTBEAT_CONFIG: HeartbeatConfig = {
@@ -0,0 +1,22 @@
+/** Counter or gauge series from `cmd_metrics_snapshot` */
+export interface MetricSample {
+  name: string;
+  labels: Record<string, string>;
+  value: number;
+}
+
+/** Latency histogram series (seconds) */
+export interface HistogramSample {
+  name: string;
+  labels: Record<string, string>;
+  count: number;
+  sum: number;
+  /** [upper bound in seconds, cumulative count] */
+  buckets: [number, number][];
+}
+
+export interface MetricsSnapshot {
+  counters: MetricSample[];
+  gauges: MetricSample[];
+  histograms: HistogramSample[];
+}
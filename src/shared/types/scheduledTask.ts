@@ -2,6 +2,16 @@ import type { ProviderEnv } from './config';
 
 export type RunMode = 'single_session' | 'new_session';
 
+/** 运行结果投递到的 IM 会话（通过已运行的 Agent 渠道） */
+export interface DeliveryTarget {
+  agentId: string;
+  channelId: string;
+  chatId: string;
+  asAttachment?: boolean; // 以 Markdown 文件发送
+}
+
+export type DeliverWhen = 'always' | 'on_change' | 'on_error';
+
 export interface EndConditions {
   deadline?: string;
   maxExecutions?: number;
@@ -22,6 +32,7 @@ export interface TaskState {
   lastStatus: 'success' | 'error' | 'running' | null;
   consecutiveErrors: number;
   runningAtMs: number | null;
+  lastResultDigest?: string;
 }
 
 export interface ScheduledTask {
@@ -40,6 +51,8 @@ export interface ScheduledTask {
   persistentSessionId?: string | null;
   executionCount: number;
   timeoutMinutes?: number;
+  deliverTo?: DeliveryTarget[];
+  deliverWhen?: DeliverWhen;
   createdAtMs: number;
   updatedAtMs: number;
 }
@@ -56,6 +69,8 @@ export interface ScheduledTaskInput {
   runMode?: RunMode;
   endConditions?: EndConditions;
   timeoutMinutes?: number;
+  deliverTo?: DeliveryTarget[];
+  deliverWhen?: DeliverWhen;
 }
 
 export interface ScheduledTaskRun {